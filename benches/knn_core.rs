@@ -0,0 +1,102 @@
+// ------------------------------------------ KNN CORE BENCHMARKS -----------------------------------------------
+// Times the math of one prediction (distance to every train point, selection of the k nearest, vote) over dataset sizes and
+// dimensions, so a change to the distance or selection code that slows predictions down is noticed. Run with:
+//     cargo bench --bench knn_core
+// The crate is built as a cdylib only, which benches can't link to, so the core module is compiled in directly (it does not
+// depend on near_sdk). The timing uses std only: the median of several samples of a batch of predictions.
+// The ranking columns time the k nearest found from Euclidean distances (a square root per train point) and from squared
+// distances (roots of the k nearest only), as the predictions rank them, to show the saving of the squared ranking.
+#[path = "../src/knn_core.rs"]
+#[allow(dead_code)]
+mod knn_core;
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const SIZES: [usize; 3] = [10, 1_000, 10_000]; // # of train points
+const DIMS: [usize; 3] = [2, 8, 32]; // # of features
+const K: usize = 5;
+const SAMPLES: usize = 11;
+const SAMPLE_TIME: Duration = Duration::from_millis(50); // minimum duration of a sample
+
+// Deterministic pseudo-random numbers (SplitMix64), so every run times the same datasets.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+fn random_point(state: &mut u64, dim: usize) -> Vec<f64> {
+    (0..dim).map(|_| (splitmix64(state) % 10_000) as f64 / 100.0).collect()
+}
+
+// One prediction: distances to all train points, k nearest, majority vote.
+fn predict(train: &[Vec<f64>], labels: &[u8], test: &[f64]) -> u8 {
+    let distances: Vec<f64> = train.iter().map(|row| knn_core::euclidean(knn_core::abs_diffs(row, test, &[]))).collect();
+    let (indices, _) = knn_core::select_k_smallest(&distances, K);
+    let k_labels: Vec<u8> = indices.iter().map(|ii| labels[*ii]).collect();
+    knn_core::majority_vote(&k_labels)
+}
+
+// k nearest by their Euclidean distances, with a square root per train point.
+fn rank_sqrt(train: &[Vec<f64>], test: &[f64]) -> (Vec<usize>, Vec<f64>) {
+    let distances: Vec<f64> = train.iter().map(|row| knn_core::euclidean(knn_core::abs_diffs(row, test, &[]))).collect();
+    knn_core::select_k_smallest(&distances, K)
+}
+
+// k nearest by their squared Euclidean distances (same order), with the square roots of the k nearest only.
+fn rank_squared(train: &[Vec<f64>], test: &[f64]) -> (Vec<usize>, Vec<f64>) {
+    let squared: Vec<f64> = train.iter().map(|row| knn_core::squared_euclidean(knn_core::abs_diffs(row, test, &[]))).collect();
+    let (indices, squared) = knn_core::select_k_smallest(&squared, K);
+    (indices, squared.into_iter().map(f64::sqrt).collect())
+}
+
+// Median time of one call of f, over SAMPLES samples of a batch of calls lasting at least SAMPLE_TIME.
+fn time_per_call<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    let mut batch: u32 = 0;
+    while start.elapsed() < SAMPLE_TIME { // size of the batch
+        f();
+        batch += 1;
+    }
+    let mut samples: Vec<Duration> = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..batch {
+                f();
+            }
+            start.elapsed() / batch
+        })
+        .collect();
+    samples.sort();
+    samples[SAMPLES / 2]
+}
+
+fn main() {
+    let mut state: u64 = 42;
+    println!("{:>8} {:>5} {:>14} {:>18} {:>14} {:>14}", "rows", "dim", "predict", "sort_and_argsort", "rank_sqrt", "rank_squared");
+    for n in SIZES {
+        for dim in DIMS {
+            let train: Vec<Vec<f64>> = (0..n).map(|_| random_point(&mut state, dim)).collect();
+            let labels: Vec<u8> = (0..n).map(|_| (splitmix64(&mut state) % 2) as u8).collect();
+            let test = random_point(&mut state, dim);
+            let distances: Vec<f64> = train.iter().map(|row| knn_core::euclidean(knn_core::abs_diffs(row, &test, &[]))).collect();
+            let predict_time = time_per_call(|| {
+                black_box(predict(black_box(&train), black_box(&labels), black_box(&test)));
+            });
+            let sort_time = time_per_call(|| {
+                black_box(knn_core::sort_and_argsort(black_box(&distances)));
+            });
+            assert_eq!(rank_squared(&train, &test).0, rank_sqrt(&train, &test).0, "Expected the same k nearest.");
+            let sqrt_time = time_per_call(|| {
+                black_box(rank_sqrt(black_box(&train), black_box(&test)));
+            });
+            let squared_time = time_per_call(|| {
+                black_box(rank_squared(black_box(&train), black_box(&test)));
+            });
+            println!("{:>8} {:>5} {:>14?} {:>18?} {:>14?} {:>14?}", n, dim, predict_time, sort_time, sqrt_time, squared_time);
+        }
+    }
+}
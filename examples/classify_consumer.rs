@@ -0,0 +1,108 @@
+// ------------------------------------------ EXAMPLE: CALLING THE CLASSIFIER FROM A CONTRACT -------------------
+// A minimal contract composing with the KNN contract: request_class calls classify_for on it, and the callback on_classified
+// stores the predicted class of the point (or the reason it could not be classified). Build it for the blockchain with:
+//     cargo build --example classify_consumer --target wasm32-unknown-unknown --release
+// and deploy target/wasm32-unknown-unknown/release/examples/classify_consumer.wasm, e.g. with
+//     near deploy consumer.myacc.testnet --wasmFile target/wasm32-unknown-unknown/release/examples/classify_consumer.wasm --initFunction 'new' --initArgs '{"knn_account": "knn_nft.myacc.testnet"}'
+//     near call consumer.myacc.testnet request_class '{"dataset": "cancer", "point": [13.9, 1.9]}' --accountId myacc.testnet --gas 200000000000000
+//     near view consumer.myacc.testnet get_last_class
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::{self, Value};
+use near_sdk::{env, ext_contract, near_bindgen, AccountId, Gas, PanicOnDefault, Promise, PromiseResult};
+
+const GAS_FOR_CLASSIFY: Gas = Gas(100_000_000_000_000); // 100 Tgas for classify_for (depends on the size of the dataset)
+const GAS_FOR_ON_CLASSIFIED: Gas = Gas(10_000_000_000_000); // 10 Tgas for on_classified
+
+// The fields of the KNN contract's PredictionResult used here (the other fields are ignored when parsing).
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Prediction {
+    pub label: u8,
+    pub confidence: f64,
+}
+
+// Interface of the KNN contract.
+#[ext_contract(ext_knn)]
+trait KnnClassifier {
+    fn classify_for(&mut self, dataset: String, point: Vec<f64>, k_override: Option<u8>) -> Result<Prediction, Value>;
+}
+
+#[ext_contract(ext_self)]
+trait SelfCallbacks {
+    fn on_classified(&mut self, dataset: String);
+}
+
+// Outcome of the latest request.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Outcome {
+    Class { dataset: String, label: u8, confidence: f64 },
+    Failed { dataset: String, reason: String },
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct ClassifyConsumer {
+    knn_account: AccountId,
+    last: Option<Outcome>,
+}
+
+#[near_bindgen]
+impl ClassifyConsumer {
+    #[init]
+    pub fn new(knn_account: AccountId) -> Self {
+        Self { knn_account, last: None }
+    }
+
+    // Asks the KNN contract for the class of the point, forwarding the attached deposit as the prediction fee.
+    #[payable]
+    pub fn request_class(&mut self, dataset: String, point: Vec<f64>) -> Promise {
+        ext_knn::classify_for(dataset.clone(), point, None, self.knn_account.clone(), env::attached_deposit(), GAS_FOR_CLASSIFY)
+            .then(ext_self::on_classified(dataset, env::current_account_id(), 0, GAS_FOR_ON_CLASSIFIED))
+    }
+
+    // Callback of request_class: reads the Result returned by classify_for.
+    #[private]
+    pub fn on_classified(&mut self, dataset: String) -> Outcome {
+        let outcome = match env::promise_result(0) {
+            PromiseResult::Successful(value) => match serde_json::from_slice::<Result<Prediction, Value>>(&value) {
+                Ok(Ok(prediction)) => Outcome::Class { dataset, label: prediction.label, confidence: prediction.confidence },
+                Ok(Err(error)) => Outcome::Failed { dataset, reason: error.to_string() }, // e.g. {"DimensionMismatch": {...}}
+                Err(e) => Outcome::Failed { dataset, reason: e.to_string() },
+            },
+            _ => Outcome::Failed { dataset, reason: "classify_for failed".to_string() },
+        };
+        self.last = Some(outcome.clone());
+        outcome
+    }
+
+    pub fn get_last_class(&self) -> Option<Outcome> {
+        self.last.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn callback_context(value: &str) {
+        let mut builder = VMContextBuilder::new();
+        builder.predecessor_account_id(accounts(0)).current_account_id(accounts(0));
+        testing_env!(builder.build(), Default::default(), Default::default(), Default::default(), vec![PromiseResult::Successful(value.as_bytes().to_vec())]);
+    }
+
+    #[test]
+    fn test_on_classified() { // the Result returned by classify_for is parsed, both Ok and Err
+        callback_context("");
+        let mut contract = ClassifyConsumer::new(accounts(1));
+        callback_context(r#"{"Ok": {"label": 1, "confidence": 0.6666666666666666, "neighbor_indices": [2, 8, 5]}}"#);
+        let outcome = contract.on_classified("cancer".to_string());
+        assert_eq!(outcome, Outcome::Class { dataset: "cancer".to_string(), label: 1, confidence: 0.6666666666666666 }, "Expected equality.");
+        callback_context(r#"{"Err": {"DimensionMismatch": {"expected": 2, "actual": 3}}}"#);
+        contract.on_classified("cancer".to_string());
+        assert!(matches!(contract.get_last_class(), Some(Outcome::Failed { reason, .. }) if reason.contains("DimensionMismatch")), "Expected the error.");
+    }
+}
@@ -0,0 +1,62 @@
+use crate::*;
+
+// ------------------------------------------ ACCESS LISTS -----------------------------------------------------
+// The owner can restrict the service to known accounts, or ban abusers. Accounts on the denylist cannot make predictions or
+// write to datasets. When the allowlist is enabled, only the accounts on it (and the owner) can. Predictions are checked
+// for the account they are made for (e.g. the sender of a fungible token payment), dataset methods for their caller.
+// The view methods are not restricted: they do not change the state, and anyone can read it.
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can turn the allowlist on (only listed accounts are served) or off (every account not denied is served).
+    pub fn set_allowlist_enabled(&mut self, enabled: bool) {
+        self.assert_owner();
+        self.allowlist_enabled = enabled;
+    }
+
+    pub fn is_allowlist_enabled(&self) -> bool {
+        self.allowlist_enabled
+    }
+
+    // Only the owner can add accounts to the allowlist or remove them.
+    pub fn allow_account(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.allowlist.insert(&account_id);
+    }
+
+    pub fn remove_allowed_account(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.allowlist.remove(&account_id);
+    }
+
+    // Only the owner can ban accounts or lift their ban.
+    pub fn deny_account(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.denylist.insert(&account_id);
+    }
+
+    pub fn remove_denied_account(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.denylist.remove(&account_id);
+    }
+
+    // Whether the account can make predictions and write to datasets.
+    pub fn is_account_allowed(&self, account_id: AccountId) -> bool {
+        self.check_access(&account_id).is_ok()
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). AccessDenied unless the lists let the account use the contract.
+    pub(crate) fn check_access(&self, account_id: &AccountId) -> Result<(), KnnError> {
+        let listed = !self.allowlist_enabled || self.allowlist.contains(account_id);
+        if *account_id != self.owner_id && (self.denylist.contains(account_id) || !listed) {
+            return Err(KnnError::AccessDenied { account_id: account_id.clone() });
+        }
+        Ok(())
+    }
+
+    // Callable from methods only (not user). Guards the dataset methods.
+    pub(crate) fn assert_caller_access(&self) {
+        self.check_access(&env::predecessor_account_id()).unwrap_or_else(|e| e.panic());
+    }
+}
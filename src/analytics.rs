@@ -0,0 +1,61 @@
+use crate::*;
+
+// ------------------------------------------ USAGE ANALYTICS ---------------------------------------------------
+// Counters of the recorded predictions (run_analysis and the calls going through it), kept on chain so that operators and token
+// holders can track the adoption of the model without an off-chain indexer: in total, per dataset, per predicted class, the
+// number of distinct callers and the predictions of each UTC day. get_usage_stats returns the days of the last USAGE_DAYS.
+const NANOSECONDS_PER_DAY: u64 = 86_400 * 1_000_000_000;
+const USAGE_DAYS: u64 = 30;
+
+// Counters that fit in the contract's state (the per dataset and per day counters are in collections).
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct UsageCounters {
+    unique_callers: u64,
+    per_class: BTreeMap<u8, u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DailyUsage {
+    pub day: u64, // days since 1970-01-01 (UTC)
+    pub predictions: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UsageStats {
+    pub total_predictions: u64,
+    pub unique_callers: u64,
+    pub per_dataset: Vec<(String, u64)>,
+    pub per_class: Vec<(u8, u64)>,
+    pub daily: Vec<DailyUsage>, // days of the last USAGE_DAYS with predictions, oldest first
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    pub fn get_usage_stats(&self) -> UsageStats {
+        let today = env::block_timestamp() / NANOSECONDS_PER_DAY;
+        UsageStats {
+            total_predictions: self.predictions.len(),
+            unique_callers: self.usage_counters.unique_callers,
+            per_dataset: self.dataset_predictions.to_vec(),
+            per_class: self.usage_counters.per_class.iter().map(|(label, n)| (*label, *n)).collect(),
+            daily: (today.saturating_sub(USAGE_DAYS - 1)..=today)
+                .filter_map(|day| self.daily_predictions.get(&day).map(|predictions| DailyUsage { day, predictions }))
+                .collect(),
+        }
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Counts a prediction about to be recorded in the history.
+    pub(crate) fn count_usage(&mut self, dataset: &String, label: u8, caller: &AccountId) {
+        if !self.predictions_by_account.contains_key(caller) {
+            self.usage_counters.unique_callers += 1;
+        }
+        *self.usage_counters.per_class.entry(label).or_insert(0) += 1;
+        self.dataset_predictions.insert(dataset, &(self.dataset_predictions.get(dataset).unwrap_or(0) + 1));
+        let day = env::block_timestamp() / NANOSECONDS_PER_DAY;
+        self.daily_predictions.insert(&day, &(self.daily_predictions.get(&day).unwrap_or(0) + 1));
+    }
+}
@@ -0,0 +1,80 @@
+use crate::*;
+
+// ------------------------------------------ ANOMALY DETECTION -------------------------------------------------
+// The train points of a dataset also tell how unusual a point is: its anomaly score is the mean distance to its k nearest train
+// points (k of the dataset, else the contract's; all the points if the dataset has fewer), found with the same search as the
+// predictions (the dataset's metric, scaling and feature weights, and k-d tree if any). Points far from all the train points
+// score high, whatever the labels, so that classification and regression datasets are anomaly detectors as well. Points outside
+// the range of the train points are scored rather than rejected (see set_range_check), since they are the likely outliers.
+// A raw distance threshold suits data of one density; the Local Outlier Factor compares the density around the point with the
+// density around its k nearest train points instead, so that a point is an outlier relative to its neighbourhood, in dense and
+// sparse regions alike. The local reachability density of a point is 1 / the mean reachability distance to its k nearest points,
+// max(k-distance(o), d(p, o)), with k-distance(o) the distance of o to its own k-th nearest train point (other than itself);
+// the LOF of a point is the mean density of its neighbours divided by its own density: about 1 inside a cluster, well above 1
+// for an outlier. The neighbourhoods are found with the same search as anomaly_score, k at most the number of train points - 1.
+const LRD_EPSILON: f64 = 1e-10; // added to the mean reachability distances, so that duplicate points have a finite density
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Mean distance of the point to its k nearest train points, in the units of the distances of the dataset.
+    pub fn anomaly_score(&self, dataset: String, point: Vec<f64>) -> Result<f64, KnnError> {
+        let data = self.get_dataset(&dataset)?;
+        let metric = self.search_metric(&dataset, &data, &point)?;
+        let k = (data.k.unwrap_or(self.param_k) as u64).min(data.train.len());
+        let (_, distances) = self.dataset_neighbors(&data, &point, &metric, k as usize, &mut StageGas::default());
+        Ok(distances.iter().sum::<f64>() / distances.len() as f64)
+    }
+
+    // Whether the anomaly score of the point exceeds the threshold.
+    pub fn is_outlier(&self, dataset: String, point: Vec<f64>, threshold: f64) -> Result<bool, KnnError> {
+        Ok(self.anomaly_score(dataset, point)? > threshold)
+    }
+
+    // Local Outlier Factor of the point among the train points of the dataset (at least 2).
+    pub fn lof_score(&self, dataset: String, point: Vec<f64>) -> Result<f64, KnnError> {
+        let data = self.get_dataset(&dataset)?;
+        let metric = self.search_metric(&dataset, &data, &point)?;
+        let k = (data.k.unwrap_or(self.param_k) as u64).min(data.train.len() - 1) as usize;
+        if k == 0 {
+            return Err(KnnError::KTooLarge { k: 1, n_points: 1 });
+        }
+        let (indices, distances) = self.dataset_neighbors(&data, &point, &metric, k, &mut StageGas::default());
+        let mut neighborhoods: BTreeMap<usize, (Vec<usize>, Vec<f64>)> = BTreeMap::new(); // of the neighbours and their neighbours
+        for ii in &indices {
+            let neighbors = self.train_neighbors(&data, &metric, *ii, k);
+            for jj in neighbors.0.iter().chain(std::iter::once(ii)) {
+                if !neighborhoods.contains_key(jj) {
+                    let neighborhood = if jj == ii { neighbors.clone() } else { self.train_neighbors(&data, &metric, *jj, k) };
+                    neighborhoods.insert(*jj, neighborhood);
+                }
+            }
+        }
+        let density = |indices: &[usize], distances: &[f64]| {
+            let reach: f64 = indices.iter().zip(distances).map(|(o, d)| neighborhoods[o].1.last().unwrap().max(*d)).sum();
+            1.0 / (reach / indices.len() as f64 + LRD_EPSILON)
+        };
+        let neighbor_density: f64 = indices.iter().map(|ii| density(&neighborhoods[ii].0, &neighborhoods[ii].1)).sum::<f64>() / k as f64;
+        Ok(neighbor_density / density(&indices, &distances))
+    }
+
+    // Callable from methods only (not user). The k nearest train points of the train point ii, other than itself (nearest first),
+    // and their distances.
+    fn train_neighbors(&self, data: &Dataset, metric: &PointMetric, ii: usize, k: usize) -> (Vec<usize>, Vec<f64>) {
+        let (indices, distances) = self.dataset_neighbors(data, &data.train.get(ii as u64).unwrap(), metric, k + 1, &mut StageGas::default());
+        let itself = indices.iter().position(|jj| *jj == ii).unwrap_or(k); // not found: duplicates of it rank first, drops the furthest
+        indices.into_iter().zip(distances).enumerate().filter(|(position, _)| *position != itself).map(|(_, neighbor)| neighbor).unzip()
+    }
+
+    // Callable from methods only (not user). Checks the point can be compared with the train points of the dataset, whatever their
+    // targets, and returns the metric of the dataset (else the contract's).
+    fn search_metric(&self, name: &str, dataset: &Dataset, point: &[f64]) -> Result<PointMetric, KnnError> {
+        check_dimension(dataset, point)?;
+        check_finite(point)?;
+        if dataset.train.is_empty() {
+            return Err(KnnError::EmptyDataset { name: name.to_string() });
+        }
+        let metric = dataset.metric.unwrap_or(self.distance_metric);
+        self.check_metric(&metric)?;
+        dataset.point_metric(metric)
+    }
+}
@@ -0,0 +1,190 @@
+use crate::*;
+
+// ------------------------------------------ LABELING BOUNTIES -------------------------------------------------
+// A dataset can be grown with points nobody has labeled yet: an account allowed to write to the dataset escrows a bounty (in NEAR)
+// with the unlabeled points. Until labeling_ends_at, labelers submit one class per point (submitting again replaces their labels).
+// Until challenge_ends_at, the creator of the bounty or the owner can challenge the labels of a labeler, who is then not accepted.
+// After that, anyone can settle the bounty: each point gets the class most of the accepted labelers gave it (the lowest on a tie),
+// the labeled points are appended to the dataset as contributed by the creator, and the bounty is split equally among the
+// accepted labelers (the creator is refunded if none was accepted, and gets the remainder of the split). The storage of the
+// bounty, paid on creation, is freed for the points; points needing more storage than the bounty freed have it paid from the
+// bounty. Points the dataset can no longer take (removed, re-created with another schema, frozen or at a storage limit) are not
+// appended, and the labelers are paid all the same. Until the first labels are submitted, the creator can cancel the bounty.
+const NANOSECONDS_PER_HOUR: u64 = 3_600 * 1_000_000_000;
+
+// Labels a labeler submitted for the points of a bounty.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LabelSubmission {
+    pub labeler: AccountId,
+    pub labels: Vec<u8>, // one class per point
+    pub challenged: bool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Bounty {
+    pub creator: AccountId,
+    pub dataset: String,
+    pub points: Vec<Vec<f64>>, // unlabeled points, in their stored form
+    pub reward: U128, // yoctoNEAR escrowed for the labelers
+    pub labeling_ends_at: U64, // block timestamp (nanoseconds)
+    pub challenge_ends_at: U64,
+    pub submissions: Vec<LabelSubmission>,
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Opens a bounty for labeling the points, which must match the schema of the classification dataset. The attached deposit,
+    // minus the storage of the bounty, is the reward. Returns the id of the bounty.
+    #[payable]
+    pub fn create_bounty(&mut self, dataset: String, points: Vec<Vec<f64>>, labeling_hours: u32, challenge_hours: u32) -> u64 {
+        self.assert_not_paused();
+        self.assert_can_write(&dataset); // the points are appended on the creator's behalf
+        let data = self.get_dataset(&dataset).unwrap_or_else(|e| e.panic());
+        if !data.values.is_empty() {
+            KnnError::TargetMismatch { name: dataset }.panic();
+        }
+        check_unfrozen(&dataset, &data).unwrap_or_else(|e| e.panic());
+        assert!(!points.is_empty(), "A bounty needs at least one point to label.");
+        let points: Vec<Vec<f64>> = points.into_iter().map(|point| self.stored_point(point).unwrap_or_else(|e| e.panic())).collect();
+        for point in &points {
+            check_dimension(&data, point).unwrap_or_else(|e| e.panic());
+        }
+        let initial_storage = env::storage_usage();
+        let labeling_ends_at = env::block_timestamp() + u64::from(labeling_hours) * NANOSECONDS_PER_HOUR;
+        let mut bounty = Bounty {
+            creator: env::predecessor_account_id(),
+            dataset,
+            points,
+            reward: U128(0),
+            labeling_ends_at: U64(labeling_ends_at),
+            challenge_ends_at: U64(labeling_ends_at + u64::from(challenge_hours) * NANOSECONDS_PER_HOUR),
+            submissions: Vec::new(),
+        };
+        let bounty_id = self.next_bounty_id;
+        self.bounties.insert(&bounty_id, &bounty);
+        let storage_cost = Balance::from(env::storage_usage() - initial_storage) * env::storage_byte_cost();
+        let attached = env::attached_deposit();
+        assert!(attached > storage_cost, "Must attach more than {} yoctoNEAR: the storage of the bounty, and the reward.", storage_cost);
+        bounty.reward = U128(attached - storage_cost);
+        self.bounties.insert(&bounty_id, &bounty); // same size
+        self.next_bounty_id += 1;
+        bounty_id
+    }
+
+    pub fn get_bounty(&self, bounty_id: u64) -> Option<Bounty> {
+        self.bounties.get(&bounty_id)
+    }
+
+    // Submits the caller's classes of the points of the bounty, replacing their previous ones. The attached deposit must cover
+    // the storage of the labels, the surplus is refunded.
+    #[payable]
+    pub fn submit_labels(&mut self, bounty_id: u64, labels: Vec<u8>) {
+        self.assert_not_paused();
+        let mut bounty = self.bounties.get(&bounty_id).unwrap_or_else(|| KnnError::UnknownBounty { bounty_id }.panic());
+        if env::block_timestamp() >= bounty.labeling_ends_at.0 {
+            KnnError::BountyClosed { bounty_id }.panic();
+        }
+        if labels.len() != bounty.points.len() {
+            KnnError::InvalidLabels { expected: bounty.points.len() as u32, actual: labels.len() as u32 }.panic();
+        }
+        let labeler = env::predecessor_account_id();
+        self.check_access(&labeler).unwrap_or_else(|e| e.panic());
+        let initial_storage = env::storage_usage();
+        bounty.submissions.retain(|submission| submission.labeler != labeler);
+        bounty.submissions.push(LabelSubmission { labeler, labels, challenged: false });
+        self.bounties.insert(&bounty_id, &bounty);
+        self.charge_storage(initial_storage, 0);
+    }
+
+    // Only the creator of the bounty or the owner can challenge the labels of a labeler, until the challenge window ends.
+    pub fn challenge_labels(&mut self, bounty_id: u64, labeler: AccountId) -> Result<(), KnnError> {
+        let mut bounty = self.bounties.get(&bounty_id).ok_or(KnnError::UnknownBounty { bounty_id })?;
+        let caller = env::predecessor_account_id();
+        if caller != bounty.creator && caller != self.owner_id {
+            return Err(KnnError::Unauthorized { account_id: caller });
+        }
+        if env::block_timestamp() >= bounty.challenge_ends_at.0 {
+            return Err(KnnError::BountyClosed { bounty_id });
+        }
+        let submission = bounty.submissions.iter_mut().find(|submission| submission.labeler == labeler);
+        submission.ok_or(KnnError::NoSubmission { labeler })?.challenged = true;
+        self.bounties.insert(&bounty_id, &bounty);
+        Ok(())
+    }
+
+    // Settles the bounty once its challenge window is over: appends the labeled points to the dataset and pays the accepted
+    // labelers. Returns the number of points appended.
+    pub fn settle_bounty(&mut self, bounty_id: u64) -> Result<u64, KnnError> {
+        self.assert_not_paused();
+        let bounty = self.bounties.get(&bounty_id).ok_or(KnnError::UnknownBounty { bounty_id })?;
+        if env::block_timestamp() < bounty.challenge_ends_at.0 {
+            return Err(KnnError::ChallengeWindowOpen { bounty_id, ends_at: bounty.challenge_ends_at });
+        }
+        let accepted: Vec<&LabelSubmission> = bounty.submissions.iter().filter(|submission| !submission.challenged).collect();
+        if accepted.is_empty() {
+            self.bounties.remove(&bounty_id);
+            Promise::new(bounty.creator).transfer(bounty.reward.0);
+            return Ok(0);
+        }
+        let labels: Vec<u8> = (0..bounty.points.len())
+            .map(|ii| knn_core::majority_vote(&accepted.iter().map(|submission| submission.labels[ii]).collect::<Vec<u8>>()))
+            .collect();
+        let data = self.get_dataset(&bounty.dataset).ok().filter(|data| data.values.is_empty() && !data.frozen && check_dimension(data, &bounty.points[0]).is_ok());
+        let initial_storage = env::storage_usage();
+        self.bounties.remove(&bounty_id);
+        let freed_storage = initial_storage - env::storage_usage();
+        let mut n_points = 0;
+        let mut extra_storage = 0;
+        if let Some(mut data) = data {
+            let initial_storage = env::storage_usage();
+            let rows = bounty.points.len() as u64;
+            let (start, version, content_hash, storage_bytes) = (data.train.len(), data.version, data.content_hash.clone(), data.storage_bytes);
+            upload::push_training_rows(&bounty.dataset, &mut data, &bounty.points, labels, None);
+            self.store_dataset(&bounty.dataset, &data);
+            if self.check_storage_limits(&mut data, rows, initial_storage).is_ok() {
+                n_points = rows;
+                self.record_contribution(&bounty.dataset, &mut data, &bounty.creator, n_points);
+            } else { // over a storage limit: the points are removed again, the dataset as it was
+                data.remove_rows(start, rows);
+                data.version = version;
+                data.content_hash = content_hash;
+                data.storage_bytes = storage_bytes;
+            }
+            self.store_dataset(&bounty.dataset, &data);
+            extra_storage = env::storage_usage().saturating_sub(initial_storage).saturating_sub(freed_storage);
+        }
+        let reward = bounty.reward.0.saturating_sub(Balance::from(extra_storage) * env::storage_byte_cost());
+        let share = reward / accepted.len() as Balance;
+        if share > 0 {
+            for submission in &accepted {
+                Promise::new(submission.labeler.clone()).transfer(share);
+            }
+        }
+        let remainder = reward - share * accepted.len() as Balance;
+        if remainder > 0 {
+            Promise::new(bounty.creator.clone()).transfer(remainder);
+        }
+        self.log(LogLevel::Info, "bounty_settled", json!({ "bounty_id": bounty_id, "points": n_points, "labelers": accepted.len(), "share": U128(share) }));
+        Ok(n_points)
+    }
+
+    // Only the creator of a bounty can cancel it, as long as no labels were submitted: the reward and the storage of the bounty
+    // are refunded. Returns the reward refunded.
+    pub fn cancel_bounty(&mut self, bounty_id: u64) -> Result<U128, KnnError> {
+        let bounty = self.bounties.get(&bounty_id).ok_or(KnnError::UnknownBounty { bounty_id })?;
+        let caller = env::predecessor_account_id();
+        if caller != bounty.creator {
+            return Err(KnnError::Unauthorized { account_id: caller });
+        }
+        if !bounty.submissions.is_empty() {
+            return Err(KnnError::BountyHasSubmissions { bounty_id });
+        }
+        let initial_storage = env::storage_usage();
+        self.bounties.remove(&bounty_id);
+        let freed = Balance::from(initial_storage - env::storage_usage()) * env::storage_byte_cost();
+        Promise::new(bounty.creator).transfer(bounty.reward.0 + freed);
+        Ok(bounty.reward)
+    }
+}
@@ -0,0 +1,77 @@
+use crate::*;
+
+// ------------------------------------------ RESULT CACHE ------------------------------------------------------
+// Many users may probe the same canonical points. With the cache enabled, the classification of a recorded prediction (run_analysis
+// and the calls going through it) is kept under the hash of the dataset, the point rounded to `decimals` decimal places and the
+// options of the call, so that a repeated query skips the neighbour search. Points rounding to the same values get the result of
+// the first one. The hash also covers the state of the dataset (its rows and configuration) and of the contract that the
+// classification depends on, so a change of either is never answered from the cache. The confidence threshold and privacy noise
+// are applied to cached results like to fresh ones. At most `capacity` results are kept: the oldest is evicted for a new one.
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CacheConfig {
+    pub decimals: u8, // the features are rounded to this many decimal places
+    pub capacity: u32, // maximum number of cached results
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can enable (or resize) the cache, or disable it (None). Either empties it.
+    pub fn set_result_cache(&mut self, config: Option<CacheConfig>) {
+        self.assert_owner();
+        for key in self.cache_slots.iter() {
+            self.result_cache.remove(&key);
+        }
+        self.cache_slots.clear();
+        self.cache_next_slot = 0;
+        self.cache_config = config.filter(|config| config.capacity > 0);
+    }
+
+    pub fn get_result_cache(&self) -> Option<CacheConfig> {
+        self.cache_config
+    }
+
+    // Number of results in the cache.
+    pub fn get_cache_size(&self) -> u64 {
+        self.cache_slots.len()
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Like classify_dataset_metered, answering from the cache when it holds the result and
+    // caching it otherwise.
+    pub(crate) fn classify_cached(&mut self, name: &str, dataset: &Dataset, test_point: &[f64], metric: Option<DistanceMetric>, k_override: Option<u8>, gas: &mut StageGas) -> Result<PredictionResult, KnnError> {
+        let config = match self.cache_config {
+            Some(config) => config,
+            None => return self.classify_dataset_metered(name, dataset, test_point, metric, k_override, gas),
+        };
+        check_finite(test_point)?; // NaN and infinite features would round to a key of finite ones
+        let key = self.cache_key(name, dataset, test_point, metric, k_override, config.decimals);
+        if let Some(ans) = self.result_cache.get(&key) {
+            self.log(LogLevel::Debug, "cache_hit", json!({ "dataset": name }));
+            return Ok(ans);
+        }
+        let ans = self.classify_dataset_metered(name, dataset, test_point, metric, k_override, gas)?;
+        if self.cache_slots.len() < u64::from(config.capacity) {
+            self.cache_slots.push(&key);
+        } else { // full: the slot of the oldest result
+            let evicted = self.cache_slots.replace(self.cache_next_slot, &key);
+            self.result_cache.remove(&evicted);
+            self.cache_next_slot = (self.cache_next_slot + 1) % u64::from(config.capacity);
+        }
+        self.result_cache.insert(&key, &ans);
+        Ok(ans)
+    }
+
+    fn cache_key(&self, name: &str, dataset: &Dataset, test_point: &[f64], metric: Option<DistanceMetric>, k_override: Option<u8>, decimals: u8) -> Vec<u8> {
+        let scale = 10f64.powi(i32::from(decimals));
+        let rounded: Vec<i64> = test_point.iter().map(|x| (x * scale).round() as i64).collect();
+        let query = (name, rounded, metric, k_override).try_to_vec().unwrap();
+        let dataset_state = (dataset.created_at, dataset.version, dataset.train.len(), &dataset.stats, &dataset.class_counts, dataset.metric, dataset.scaling, &dataset.feature_weights, dataset.k, dataset.class_weighted, dataset.range_tolerance, (&dataset.label_names, &dataset.categorical_features, dataset.covariance.is_some(), dataset.decision_rule, dataset.default_model, &dataset.logistic))
+            .try_to_vec()
+            .unwrap();
+        let contract_state = (self.param_k, self.voting_scheme, self.tie_break, self.numeric_mode, self.distance_metric).try_to_vec().unwrap();
+        env::sha256(&[query, dataset_state, contract_state].concat())
+    }
+}
@@ -0,0 +1,47 @@
+use crate::*;
+
+// ------------------------------------------ CATEGORICAL FEATURES ----------------------------------------------
+// Some features are categories encoded as numbers (e.g. a colour: 0 red, 1 green, 2 blue), whose differences mean nothing: blue is
+// not further from red than green is. The owner or the creator of a dataset can mark such features categorical. The distances then
+// compare them like the Hamming distance does: they differ by 0 if the points have the same value and by 1 otherwise, in place of
+// |a - b| (e.g. in sqrt(sum(d^2)) for Euclidean), whatever the scaling and feature weights (a weight of 0 still ignores a feature).
+// For datasets mixing both kinds of features the Gower metric averages the differences, the numeric features being scaled by their
+// range so that every feature differs by 0 to 1; the Hamming metric compares every feature as a category.
+// With categorical features (or these metrics) the neighbours are found by scanning the train points, not with the k-d tree.
+
+impl Dataset {
+    // The metric as applied to the points of the dataset, once checked against its number of features (and for Mahalanobis its
+    // inverse covariance, see mahalanobis.rs).
+    pub(crate) fn point_metric(&self, metric: DistanceMetric) -> Result<PointMetric, KnnError> {
+        metric.check_dimension(self.n_features)?;
+        let mut categorical = Vec::new();
+        if !self.categorical_features.is_empty() {
+            categorical = vec![false; self.n_features as usize];
+            for feature in &self.categorical_features {
+                categorical[*feature as usize] = true;
+            }
+        }
+        let inverse_covariance = if metric == DistanceMetric::Mahalanobis { self.inverse_covariance()? } else { Vec::new() };
+        Ok(metric.measure(categorical, inverse_covariance))
+    }
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner or the creator of a dataset can mark its categorical features (by index, replacing the previous ones; none:
+    // all the features are numeric).
+    pub fn set_categorical_features(&mut self, dataset: String, features: Vec<u32>) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset)?;
+        self.assert_dataset_admin(&data);
+        if let Some(feature) = features.iter().find(|feature| **feature >= data.n_features) {
+            return Err(KnnError::UnknownFeature { feature: *feature, n_features: data.n_features });
+        }
+        let mut features = features;
+        features.sort_unstable();
+        features.dedup();
+        data.categorical_features = features;
+        self.store_dataset(&dataset, &data);
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use crate::*;
+use crate::classifier::Classifier;
+
+// ------------------------------------------ NEAREST CENTROID --------------------------------------------------
+// A cheap alternative to KNN for large datasets: each class is summarised by its centroid, the mean of its train points, and a
+// test point is given the class of the nearest centroid, which costs O(classes) distances per prediction instead of O(rows).
+// Once the model is registered for a dataset (see register_model), the dataset keeps the sum of the train points of every class,
+// which uploads extend with their rows (removing rows sums them all again). The centroids go through the dataset's scaling and
+// feature weights, and are compared with the metric of the call, else the dataset's, else the contract's, like the train points
+// in KNN. The confidence is the share of the winning class in the inverse distances to the centroids (1 if the test point is
+// a centroid); the distance to the winning centroid is returned as the neighbour distance, without neighbour indices.
+
+// Sum and number of the train points of every class, over the first n_rows rows of the dataset.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default, PartialEq, Debug)]
+pub struct ClassSums {
+    n_rows: u64,
+    sums: BTreeMap<u8, (u64, Vec<f64>)>,
+}
+
+pub(crate) struct NearestCentroid;
+
+impl Classifier for NearestCentroid {
+    fn fit(&self, dataset: &mut Dataset) {
+        let mut class_sums = dataset.class_sums.take().unwrap_or_default();
+        for ii in class_sums.n_rows..dataset.train.len() {
+            let (point, label) = (dataset.train.get(ii).unwrap(), dataset.target.get(ii).unwrap());
+            let (count, sum) = class_sums.sums.entry(label).or_insert_with(|| (0, vec![0.0; point.len()]));
+            *count += 1;
+            for (s, x) in sum.iter_mut().zip(&point) {
+                *s += x;
+            }
+        }
+        class_sums.n_rows = dataset.train.len();
+        dataset.class_sums = Some(class_sums);
+    }
+
+    fn classify(&self, contract: &KnnMachineLearning, name: &str, dataset: &Dataset, query: &Query, gas: &mut StageGas) -> Result<PredictionResult, KnnError> {
+        let metric = contract.classification_metric(name, dataset, query.test_point, query.metric)?;
+        let start = env::used_gas().0;
+        let point = dataset.transform(query.test_point, &metric);
+        let distances: Vec<(u8, f64)> = dataset
+            .class_sums
+            .iter()
+            .flat_map(|class_sums| &class_sums.sums)
+            .map(|(label, (count, sum))| {
+                let centroid: Vec<f64> = sum.iter().map(|s| s / *count as f64).collect();
+                (*label, metric.distance_in(contract.numeric_mode, &dataset.transform(&centroid, &metric), &point))
+            })
+            .collect();
+        gas.distance += env::used_gas().0 - start;
+        let (label, distance) = distances.iter().copied().reduce(|best, next| if next.1 < best.1 { next } else { best }).unwrap(); // a dataset to classify with has a class
+        let weights: Vec<f64> = distances.iter().map(|(_, d)| 1.0 / d).collect();
+        let labels: Vec<u8> = distances.iter().map(|(label, _)| *label).collect();
+        Ok(dataset.named(PredictionResult {
+            label,
+            label_name: None, // see Dataset::named
+            confidence: knn_core::vote_share(&labels, &weights, label),
+            neighbor_indices: Vec::new(),
+            neighbor_distances: vec![distance],
+        }))
+    }
+}
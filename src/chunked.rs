@@ -0,0 +1,78 @@
+use crate::*;
+use near_sdk::serde_json;
+use near_sdk::PromiseResult;
+
+// ------------------------------------------ CHUNKED CLASSIFICATION --------------------------------------------
+// A prediction on a large dataset can need more gas than a single function call may use. classify_chunked splits the
+// train points into chunks: each chunk is scanned by a call of the contract to itself (process_chunk, executed as its own
+// receipt, possibly in a later block), which returns the k nearest points of its chunk. A callback (on_chunks_processed)
+// then merges the candidates of all chunks into the k nearest neighbours and votes.
+const GAS_FOR_CHUNKS_CALLBACK: Gas = Gas(20_000_000_000_000); // 20 Tgas for on_chunks_processed
+const GAS_FOR_SCHEDULING: Gas = Gas(15_000_000_000_000); // 15 Tgas kept by classify_chunked to create each promise
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Classifies the test point like classify, with the distances computed in chunks of at most chunk_size train points.
+    // The gas attached to the call (minus what the callback needs) is shared equally by the chunks.
+    // The result (a Result<PredictionResult, KnnError>) is the one of on_chunks_processed. Invalid arguments abort the call.
+    pub fn classify_chunked(&self, dataset: String, point: Vec<f64>, chunk_size: u64) -> Promise {
+        self.assert_not_paused();
+        let data = self.get_dataset(&dataset).unwrap_or_else(|e| e.panic());
+        self.classification_metric(&dataset, &data, &point, None).unwrap_or_else(|e| e.panic());
+        let k = data.k.unwrap_or(self.param_k);
+        let n_points = data.train.len();
+        let n_chunks = if chunk_size > 0 { n_points.div_ceil(chunk_size) } else { 0 };
+        let reserved = GAS_FOR_CHUNKS_CALLBACK.0 + (n_chunks + 1) * GAS_FOR_SCHEDULING.0;
+        let available = env::prepaid_gas().0.saturating_sub(env::used_gas().0 + reserved);
+        if n_chunks == 0 || available == 0 { // too many chunks for the attached gas
+            KnnError::InvalidChunkSize.panic();
+        }
+        let gas_per_chunk = Gas(available / n_chunks);
+        let chunks = (0..n_chunks).map(|ii| {
+            let from_index = ii * chunk_size;
+            let to_index = std::cmp::min(from_index + chunk_size, n_points);
+            ext_self::process_chunk(dataset.clone(), point.clone(), (from_index, to_index), k, env::current_account_id(), 0, gas_per_chunk)
+        });
+        chunks
+            .reduce(|all, chunk| all.and(chunk))
+            .unwrap() // a dataset to classify with has at least one train point, hence one chunk
+            .then(ext_self::on_chunks_processed(dataset, k, env::current_account_id(), 0, GAS_FOR_CHUNKS_CALLBACK))
+    }
+
+    // Step of classify_chunked: indices and distances of the k nearest train points to the test point among those with an index
+    // in the range [from_index, to_index), nearest first.
+    #[private]
+    pub fn process_chunk(&self, dataset: String, point: Vec<f64>, range: (u64, u64), k: u8) -> Vec<(u64, f64)> {
+        let (from_index, to_index) = range;
+        let data = self.get_dataset(&dataset).unwrap_or_else(|e| e.panic());
+        let metric = data.point_metric(data.metric.unwrap_or(self.distance_metric)).unwrap_or_else(|e| e.panic());
+        let params = data.feature_transform(&metric);
+        let chunk: Vec<Vec<f64>> = (from_index..std::cmp::min(to_index, data.train.len()))
+            .filter_map(|ii| data.train.get(ii))
+            .map(|train_point| apply_transform(&train_point, &params))
+            .collect();
+        let (indices, distances) = self.k_nearest(&chunk, &apply_transform(&point, &params), &metric, k as usize);
+        indices.into_iter().map(|ii| from_index + ii as u64).zip(distances).collect()
+    }
+
+    // Last step of classify_chunked: votes with the k nearest of the candidates returned by the chunks.
+    #[private]
+    pub fn on_chunks_processed(&self, dataset: String, k: u8) -> Result<PredictionResult, KnnError> {
+        let data = self.get_dataset(&dataset)?;
+        let mut candidates: Vec<(usize, f64)> = Vec::new();
+        for chunk in 0..env::promise_results_count() {
+            match env::promise_result(chunk) {
+                PromiseResult::Successful(bytes) => {
+                    let nearest: Vec<(u64, f64)> = serde_json::from_slice(&bytes).map_err(|_| KnnError::ChunkFailed { chunk })?;
+                    candidates.extend(nearest.into_iter().map(|(ii, d)| (ii as usize, d)));
+                }
+                _ => return Err(KnnError::ChunkFailed { chunk }),
+            }
+        }
+        candidates.sort_unstable_by(cmp_pairs);
+        candidates.truncate(k as usize);
+        let (indices, distances): (Vec<usize>, Vec<f64>) = candidates.into_iter().unzip();
+        let labels: Vec<u8> = indices.iter().filter_map(|&ii| data.target.get(ii as u64)).collect();
+        self.check_confidence(data.named(self.vote(labels, indices, distances, data.vote_class_counts(), data.voting_rule().as_ref())?), data.class_weighted)
+    }
+}
@@ -0,0 +1,127 @@
+use crate::*;
+use crate::centroid::NearestCentroid;
+use crate::logistic::LogisticRegression;
+use crate::naive_bayes::NaiveBayes;
+
+// ------------------------------------------ CLASSIFIER REGISTRY -----------------------------------------------
+// KNN is one of the models a dataset can be classified with. Every model implements the Classifier trait: it is fitted to the
+// rows of the dataset (when registered, and again as rows are uploaded or removed, keeping what it learns in the dataset) and
+// classifies test points with it. The owner or the creator of a dataset registers the models it supports (KNN always is) and
+// picks the default one of its predictions (run_analysis, classify and the calls going through them); classify_with selects any
+// registered model at call time. A new algorithm implements Classifier and gets an Algorithm variant, built into it by
+// Algorithm::classifier.
+
+// Models the datasets can be classified with.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Algorithm {
+    Knn, // vote of the k nearest train points
+    NearestCentroid, // class of the nearest class mean, see centroid.rs
+    NaiveBayes, // class of the highest Gaussian naive Bayes posterior, see naive_bayes.rs
+    LogisticRegression, // class of the highest probability of a logistic regression trained on chain, see logistic.rs
+}
+
+// Arguments of a prediction, each model using those that apply to it.
+pub(crate) struct Query<'a> {
+    pub test_point: &'a [f64],
+    pub metric: Option<DistanceMetric>, // distance metric of the call (KNN)
+    pub k_override: Option<u8>, // number of neighbours of the call (KNN)
+}
+
+pub(crate) trait Classifier {
+    // Updates what the model keeps in the dataset from its rows (nothing by default: the model uses the rows as they are).
+    fn fit(&self, _dataset: &mut Dataset) {}
+
+    // Class of the test point (once checked against the dataset) and its confidence, adding the gas used by each stage to gas.
+    fn classify(&self, contract: &KnnMachineLearning, name: &str, dataset: &Dataset, query: &Query, gas: &mut StageGas) -> Result<PredictionResult, KnnError>;
+}
+
+struct Knn;
+
+impl Classifier for Knn {
+    fn classify(&self, contract: &KnnMachineLearning, name: &str, dataset: &Dataset, query: &Query, gas: &mut StageGas) -> Result<PredictionResult, KnnError> {
+        contract.classify_knn(name, dataset, query.test_point, query.metric, query.k_override, gas)
+    }
+}
+
+impl Algorithm {
+    pub(crate) fn classifier(self) -> Box<dyn Classifier> {
+        match self {
+            Algorithm::Knn => Box::new(Knn),
+            Algorithm::NearestCentroid => Box::new(NearestCentroid),
+            Algorithm::NaiveBayes => Box::new(NaiveBayes),
+            Algorithm::LogisticRegression => Box::new(LogisticRegression),
+        }
+    }
+}
+
+impl Dataset {
+    // Models registered for the dataset, KNN first.
+    pub(crate) fn models(&self) -> Vec<Algorithm> {
+        std::iter::once(Algorithm::Knn).chain(self.models.iter().copied()).collect()
+    }
+
+    // Model of the predictions on the dataset.
+    pub(crate) fn default_classifier(&self) -> Box<dyn Classifier> {
+        self.default_model.unwrap_or(Algorithm::Knn).classifier()
+    }
+
+    // Fits the registered models again, once rows are added or removed.
+    pub(crate) fn refit_models(&mut self) {
+        for model in self.models.clone() {
+            model.classifier().fit(self);
+        }
+    }
+}
+
+// Checks the model is registered for the dataset.
+pub(crate) fn check_registered(name: &str, dataset: &Dataset, algorithm: Algorithm) -> Result<(), KnnError> {
+    if !dataset.models().contains(&algorithm) {
+        return Err(KnnError::UnregisteredModel { dataset: name.to_string(), algorithm });
+    }
+    Ok(())
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner or the creator of a classification dataset can register a model for it, which fits it to the rows.
+    pub fn register_model(&mut self, dataset: String, algorithm: Algorithm) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset)?;
+        self.assert_dataset_admin(&data);
+        if !data.values.is_empty() { // holds numeric values, not classes
+            return Err(KnnError::TargetMismatch { name: dataset });
+        }
+        if !data.models().contains(&algorithm) {
+            data.models.push(algorithm);
+            algorithm.classifier().fit(&mut data);
+            self.store_dataset(&dataset, &data);
+        }
+        Ok(())
+    }
+
+    // Only the owner or the creator of a dataset can pick the model of its predictions among the registered ones (None: KNN).
+    pub fn set_default_model(&mut self, dataset: String, algorithm: Option<Algorithm>) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset)?;
+        self.assert_dataset_admin(&data);
+        if let Some(algorithm) = algorithm {
+            check_registered(&dataset, &data, algorithm)?;
+        }
+        data.default_model = algorithm;
+        self.store_dataset(&dataset, &data);
+        Ok(())
+    }
+
+    pub fn get_models(&self, dataset: String) -> Result<Vec<Algorithm>, KnnError> {
+        Ok(self.get_dataset(&dataset)?.models())
+    }
+
+    // Same as classify, with a registered model of the call instead of the dataset's default one.
+    pub fn classify_with(&self, data_set: String, test_point: Vec<f64>, algorithm: Algorithm) -> Result<PredictionResult, KnnError> {
+        let mut dataset = self.get_dataset(&data_set)?; // a copy, the stored dataset keeps its default model
+        check_registered(&data_set, &dataset, algorithm)?;
+        dataset.default_model = Some(algorithm);
+        self.check_confidence(self.classify_dataset(&data_set, &dataset, &test_point, None, None)?, dataset.class_weighted)
+    }
+}
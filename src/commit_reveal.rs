@@ -0,0 +1,70 @@
+use crate::*;
+use events::{CommitData, RevealData};
+use near_sdk::json_types::Base64VecU8;
+
+// ------------------------------------------ COMMIT-REVEAL PREDICTIONS ----------------------------------------
+// A test point sent to run_analysis is public as soon as the transaction is submitted. A caller can instead first commit to it:
+// commit_prediction stores the hash of the request (dataset, test point and a secret salt) on chain, and the point is only
+// sent, and classified, when the caller chooses to reveal it with reveal_and_classify. The commitment is a sha256 hash of
+// borsh((data_set, test_point, salt)), computed by the caller (see commitment_hash); the salt keeps it from being guessed.
+// Each account has one pending commitment, which is consumed by its reveal.
+const HASH_LEN: usize = 32;
+
+// A commitment to a prediction request, not revealed yet.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PredictionCommitment {
+    pub hash: Base64VecU8,
+    pub block_height: U64, // block of the commitment
+}
+
+fn request_hash(data_set: &str, test_point: &[f64], salt: &str) -> Vec<u8> {
+    env::sha256(&(data_set, test_point.to_vec(), salt).try_to_vec().unwrap())
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Commits the caller to a prediction request by its hash (replaces the caller's pending commitment, if any).
+    pub fn commit_prediction(&mut self, hash: Base64VecU8) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        if hash.0.len() != HASH_LEN {
+            return Err(KnnError::InvalidCommitment);
+        }
+        let caller = env::predecessor_account_id();
+        KnnEvent::KnnCommit(vec![CommitData { caller: &caller, commitment: &hash }]).emit();
+        self.commitments.insert(&caller, &PredictionCommitment { hash, block_height: U64(env::block_height()) });
+        Ok(())
+    }
+
+    // Reveals the request the caller committed to and classifies the test point like run_analysis (the attached deposit must
+    // cover the prediction fee). The commitment is consumed once the prediction is made. Errors panic when a deposit is attached,
+    // so that it is returned.
+    #[payable]
+    pub fn reveal_and_classify(&mut self, data_set: String, test_point: Vec<f64>, salt: String) -> Result<PredictionResult, KnnError> {
+        let caller = env::predecessor_account_id();
+        let revealed = match self.commitments.get(&caller) {
+            None => Err(KnnError::NoCommitment { account_id: caller.clone() }),
+            Some(commitment) if request_hash(&data_set, &test_point, &salt) != commitment.hash.0 => Err(KnnError::CommitmentMismatch),
+            Some(commitment) => Ok(commitment),
+        };
+        let commitment = match revealed {
+            Err(e) if env::attached_deposit() > 0 => e.panic(), // revert, so that the deposit is returned
+            result => result?,
+        };
+        let ans = self.run_analysis(data_set, test_point, None, None, None)?;
+        self.commitments.remove(&caller);
+        let prediction_id = self.predictions.len() - 1; // recorded by run_analysis
+        KnnEvent::KnnReveal(vec![RevealData { caller: &caller, commitment: &commitment.hash, prediction_id }]).emit();
+        Ok(ans)
+    }
+
+    pub fn get_commitment(&self, account_id: AccountId) -> Option<PredictionCommitment> {
+        self.commitments.get(&account_id)
+    }
+
+    // Commitment of a request, to check a client's hashing against (the arguments of a view call are not recorded on chain,
+    // but they are seen by the RPC node serving it: compute the commitments of private points locally).
+    pub fn commitment_hash(&self, data_set: String, test_point: Vec<f64>, salt: String) -> Base64VecU8 {
+        Base64VecU8(request_hash(&data_set, &test_point, &salt))
+    }
+}
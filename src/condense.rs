@@ -0,0 +1,88 @@
+use crate::*;
+
+// ------------------------------------------ CONDENSED NEAREST NEIGHBOUR ---------------------------------------
+// Every prediction computes the distance to every train point, so its gas grows with the rows. Most rows far from the class
+// boundaries do not change any prediction of the nearest neighbour: Hart's Condensed Nearest Neighbour (CNN) algorithm keeps a
+// prototype set, starting with the first row, and adds every row that the nearest prototype misclassifies, pass after pass over
+// the rows until a pass adds none. Every row is then classified correctly by its nearest prototype, which approximately preserves
+// the decision boundary of 1-NN with a fraction of the rows. condense_dataset stores the prototypes as a derived dataset named
+// "name-condensed", with the configuration of the dataset (schema, metric, scaling, feature weights and covariance tracking) and k = 1, the k the
+// prototypes are selected for; it is a dataset like the others, created by the caller, and does not follow later changes of the
+// original (remove it and condense again). The distances are those of the predictions on the original dataset.
+
+// Name of the condensed dataset of a dataset.
+fn condensed_name(name: &str) -> String {
+    format!("{}-condensed", name)
+}
+
+// Indices (ascending) of the prototypes of the points and their classes, by the CNN algorithm.
+fn prototypes(points: &[Vec<f64>], targets: &[u8], metric: &PointMetric, mode: NumericMode) -> Vec<usize> {
+    let mut kept = vec![false; points.len()];
+    let mut prototypes = vec![0];
+    kept[0] = true;
+    loop {
+        let n_prototypes = prototypes.len();
+        for ii in 0..points.len() {
+            if kept[ii] {
+                continue;
+            }
+            let nearest = prototypes.iter().map(|jj| (*jj, metric.rank_distance(mode, &points[*jj], &points[ii]))).fold((0, f64::INFINITY), |best, next| if next.1 < best.1 { next } else { best });
+            if targets[nearest.0] != targets[ii] {
+                prototypes.push(ii);
+                kept[ii] = true;
+            }
+        }
+        if prototypes.len() == n_prototypes {
+            break;
+        }
+    }
+    prototypes.sort_unstable();
+    prototypes
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner or the creator of a classification dataset can condense it into "name-condensed" (which must not exist yet).
+    // Payable: the caller covers the storage of the prototypes (the surplus is refunded). Errors panic so that nothing is stored.
+    // Returns the number of prototypes.
+    #[payable]
+    pub fn condense_dataset(&mut self, name: String) -> u64 {
+        self.assert_not_paused();
+        let data = self.get_dataset(&name).unwrap_or_else(|e| e.panic());
+        self.assert_dataset_admin(&data);
+        let derived = condensed_name(&name);
+        if is_snapshot(&derived) { // snapshots have no derived datasets
+            KnnError::InvalidDatasetName { name: derived }.panic();
+        }
+        if self.get_dataset(&derived).is_ok() || self.offchain_datasets.contains_key(&derived) {
+            KnnError::DatasetExists { name: derived }.panic();
+        }
+        if data.train.is_empty() {
+            KnnError::EmptyDataset { name }.panic();
+        }
+        if !data.values.is_empty() { // holds numeric values, not classes
+            KnnError::TargetMismatch { name }.panic();
+        }
+        let metric = data.point_metric(data.metric.unwrap_or(self.distance_metric)).unwrap_or_else(|e| e.panic());
+        let kept = prototypes(&data.transformed_train(&metric), &data.target.to_vec(), &metric, self.numeric_mode);
+        let initial_storage = env::storage_usage(); // bytes used before the prototypes are stored
+        let mut condensed = Dataset::new(&derived, data.n_features, env::predecessor_account_id());
+        condensed.feature_names = data.feature_names.clone();
+        condensed.label_names = data.label_names.clone();
+        condensed.metric = data.metric;
+        condensed.scaling = data.scaling;
+        condensed.feature_weights = data.feature_weights.clone();
+        condensed.categorical_features = data.categorical_features.clone();
+        condensed.k = Some(1);
+        let points: Vec<Vec<f64>> = kept.iter().map(|ii| data.train.get(*ii as u64).unwrap()).collect();
+        let labels = kept.iter().map(|ii| data.target.get(*ii as u64).unwrap()).collect();
+        upload::push_training_rows(&derived, &mut condensed, &points, labels, None);
+        if data.covariance.is_some() { // of the prototypes, for the Mahalanobis metric
+            condensed.track_covariance();
+        }
+        self.store_dataset(&derived, &condensed);
+        self.enforce_storage_limits(&derived, &mut condensed, points.len() as u64, initial_storage).unwrap_or_else(|e| e.panic());
+        self.charge_storage(initial_storage, 0);
+        points.len() as u64
+    }
+}
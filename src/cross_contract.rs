@@ -0,0 +1,22 @@
+use crate::*;
+
+// ------------------------------------------ CROSS-CONTRACT CLASSIFICATION -------------------------------------
+// Other contracts use the classifier with classify_for: the result is the value of the promise of the call, which the calling
+// contract reads in its callback, e.g. with near-sdk:
+//     #[ext_contract(ext_knn)]
+//     trait KnnClassifier {
+//         fn classify_for(&mut self, dataset: String, point: Vec<f64>, k_override: Option<u8>) -> Result<PredictionResult, KnnError>;
+//     }
+//     ext_knn::classify_for(dataset, point, None, knn_account, fee, gas).then(ext_self::on_classified(...))
+// See examples/classify_consumer.rs for a complete calling contract. The value is the JSON of the Result, i.e. {"Ok": {...}} or
+// {"Err": {...}}: a prediction that cannot be made still resolves the promise (with no deposit attached), so the calling contract
+// can handle the structured error. With a deposit attached, an error fails the call instead, so that the deposit is returned.
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Classifies the point for the calling contract (or account) like run_analysis: the prediction fee must be attached (the surplus
+    // is refunded), and the prediction is recorded in the history as made by the caller. No NFT is minted.
+    #[payable]
+    pub fn classify_for(&mut self, dataset: String, point: Vec<f64>, k_override: Option<u8>) -> Result<PredictionResult, KnnError> {
+        self.run_analysis(dataset, point, None, k_override, None)
+    }
+}
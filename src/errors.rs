@@ -0,0 +1,204 @@
+use near_sdk::serde::{Deserialize, Serialize}; // JSON serialization so callers receive a structured failure reason
+use near_sdk::json_types::{U128, U64};
+use near_sdk::AccountId;
+use crate::classifier::Algorithm;
+use crate::quota::QuotaPeriod;
+use std::fmt;
+
+// ------------------------------------------ CONTRACT ERRORS ---------------------------------------------------
+// Reasons a contract method can fail. Public methods return Result<_, KnnError>, which is serialized to JSON for the caller,
+// e.g. {"Err": {"UnknownDataset": {"name": "iris"}}} (the near-sdk version used has no #[handle_result], so an Err does not fail the transaction).
+// Methods that move deposits panic with the error's message instead (see panic below).
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum KnnError {
+    InvalidK { k: u8 }, // k must be a positive odd number between 1 and 15
+    KTooLarge { k: u8, n_points: u64 }, // more neighbours requested than the dataset has train points
+    UnknownDataset { name: String }, // no dataset stored under this name
+    DimensionMismatch { expected: u32, actual: u32 }, // point does not have as many features as the dataset's schema
+    EmptyDataset { name: String }, // dataset has no train points to compare against
+    TargetMismatch { name: String }, // dataset holds the other kind of targets (classes vs numeric values)
+    InvalidMetric { p: f64 }, // Minkowski p below 1 is not a distance
+    InsufficientDeposit { required: U128, attached: U128 }, // attached deposit (yoctoNEAR) does not cover the prediction fee
+    InsufficientTreasury { requested: U128, available: U128 }, // withdrawal of more fees than were collected
+    Unauthorized { account_id: AccountId }, // caller lacks the permission the method requires (e.g. not the owner)
+    InvalidFeatureWeights, // feature weights must be finite, non-negative and not all 0
+    InvalidFolds { n_folds: u64, n_points: u64 }, // cross-validation needs between 2 folds and one fold per train point
+    NoCandidateK, // tune_k needs at least one candidate k
+    InvalidTestSet { n_points: u32, n_labels: u32 }, // test set needs one label per point, and at least one point
+    TiedVote { labels: Vec<u8> }, // classes sharing the top vote, when the tie-break policy is to abstain
+    Abstained { label: u8, confidence: f64, threshold: f64 }, // most voted class has a smaller share of the votes than the confidence threshold
+    InvalidThreshold { threshold: f64 }, // confidence threshold must be between 0 and 1
+    NoNeighborsInRadius { radius: f64 }, // no train point within the radius of the test point to vote
+    InvalidChunkSize, // chunks of a chunked classification need at least one train point, and few enough chunks for the attached gas
+    ChunkFailed { chunk: u64 }, // a chunk of a chunked classification did not return its nearest points (e.g. out of gas)
+    UnsupportedFixedPointMetric { p: f64 }, // the fixed-point mode computes Minkowski distances for p = 1 and p = 2 only
+    StorageLimitExceeded { limit: String, maximum: u64, actual: u64 }, // upload would exceed a storage limit set by the owner (named as in StorageLimits)
+    NotRegistered { account_id: AccountId }, // account has no storage balance (see storage_deposit)
+    InsufficientStorageBalance { required: U128, available: U128 }, // storage balance (yoctoNEAR) too low for the storage or the withdrawal
+    StorageInUse { account_id: AccountId }, // account cannot unregister while it has datasets
+    Paused, // the owner paused the contract
+    InvalidCsv { line: u64, reason: String }, // uploaded CSV text could not be parsed into train points (line 1 is the header)
+    InvalidPayload { reason: String }, // uploaded Borsh payload could not be decoded into train points
+    DatasetExists { name: String }, // a dataset is already stored under this name
+    UploadInProgress { name: String }, // account already has a chunked upload in progress (of this dataset)
+    NoUploadInProgress { account_id: AccountId }, // account has no chunked upload in progress (see begin_upload)
+    UploadRowCount { expected: u64, actual: u64 }, // chunks of an upload hold more rows than announced, or fewer on commit
+    InvalidCommitment, // Merkle root of an off-chain dataset (or a prediction commitment) must be a 32 byte sha256 hash
+    InvalidProof { index: u64 }, // supplied row is not proven to be the row at this index of the off-chain dataset
+    NoCommitment { account_id: AccountId }, // account has no prediction request committed to (see commit_prediction)
+    CommitmentMismatch, // revealed request does not hash to the account's commitment
+    InvalidEpsilon { epsilon: f64 }, // privacy budget must be positive and finite
+    InvalidRewardShare { share_bps: u16 }, // share of the fees in basis points cannot exceed 10000
+    InvalidRoyalties { total_bps: u32 }, // royalties of the prediction NFTs cannot exceed 5000 basis points in total
+    ModelTokenExists { name: String }, // the NFT of the dataset was already minted
+    UnsupportedToken { token_id: AccountId }, // fungible token not accepted for the prediction fees
+    InvalidRequest { reason: String }, // msg of a fungible token transfer is not a prediction request
+    OracleFailed { oracle_account: AccountId }, // oracle did not return the features of the test point
+    AccessDenied { account_id: AccountId }, // account is denied, or not on the allowlist while it is enabled
+    QuotaExceeded { max_calls: u32, period: QuotaPeriod }, // account already made the maximum number of predictions of the period
+    UnknownTier { name: String }, // no subscription tier offered under this name
+    InvalidTier { name: String }, // subscription tier must limit the predictions or the duration
+    AccessPassRequired { account_id: AccountId }, // predictions are token-gated and the account holds no verified pass
+    UnknownPrediction { prediction_id: u64 }, // no prediction with this id in the history
+    OutcomeReported { prediction_id: u64 }, // the outcome of the prediction was already reported
+    UnknownBounty { bounty_id: u64 }, // no open labeling bounty with this id
+    BountyClosed { bounty_id: u64 }, // labeling (or challenge) window of the bounty is over
+    ChallengeWindowOpen { bounty_id: u64, ends_at: U64 }, // bounty cannot be settled before its challenge window ends
+    InvalidLabels { expected: u32, actual: u32 }, // labels submitted to a bounty must give one class per point
+    NoSubmission { labeler: AccountId }, // account submitted no labels to the bounty
+    UnknownBatch { batch_id: u64 }, // no staked batch with this id (or its stake was released)
+    SlashWindowOver { batch_id: u64 }, // batch can no longer be slashed
+    SlashWindowOpen { batch_id: u64, ends_at: U64 }, // stake of the batch cannot be released before its slash window ends
+    GovernanceRequired, // under a council, the change is only made by proposal
+    InvalidCouncil { members: u32, threshold: u32 }, // threshold of a council must be between 1 and its number of members
+    NotCouncilMember { account_id: AccountId }, // only council members can propose and approve
+    UnknownProposal { proposal_id: u64 }, // no proposal with this id
+    ProposalExecuted { proposal_id: u64 }, // the proposal was already carried out
+    TimelockRequired, // under a timelock, the change is only made by a scheduled action
+    UnknownAction { action_id: u64 }, // no scheduled action with this id (or it was executed or cancelled)
+    ActionNotReady { action_id: u64, ready_at: U64 }, // scheduled action cannot be executed before this block height
+    NonFiniteFeature { feature: u32 }, // features must be finite numbers (not NaN or infinite)
+    NonFiniteValue, // numeric targets must be finite numbers
+    OutOfRange { feature: u32, value: f64, min: f64, max: f64 }, // test point far outside the range of the train points, see set_range_check
+    InvalidTolerance { tolerance: f64 }, // range tolerance must be finite and non-negative
+    SnapshotImmutable { name: String }, // snapshots of datasets cannot be changed or removed
+    SnapshotExists { name: String }, // a snapshot of a removed dataset of the same name holds this version
+    InvalidDatasetName { name: String }, // '@' is reserved for the names of snapshots ("name@version")
+    DatasetFrozen { name: String }, // rows cannot be added to or removed from a frozen dataset
+    TooManyLabels { name: String }, // a dataset names at most 256 classes (labels 0 to 255)
+    UnknownLabel { dataset: String, label_name: String }, // the dataset has no class of this name
+    EmptyLabelSet, // a multi-label train point needs at least one class
+    UnknownFeature { feature: u32, n_features: u32 }, // features are numbered from 0 to the number of features of the dataset - 1
+    MetricDimension { expected: u32, actual: u32 }, // the metric only applies to points of `expected` features (Haversine: latitude and longitude)
+    NoInverseCovariance, // Mahalanobis needs the dataset to keep the covariance of its train points, and the covariance to be invertible
+    UnregisteredModel { dataset: String, algorithm: Algorithm }, // the model is not registered for the dataset (see register_model)
+    InvalidLearningRate { learning_rate: f64 }, // the learning rate of a gradient descent step must be finite and positive
+    InvalidClusterCount { k: u32, n_points: u64 }, // k-means needs between 1 and n_points clusters
+    NoClusters { dataset: String }, // the dataset is not clustered (see fit_kmeans)
+    BountyHasSubmissions { bounty_id: u64 }, // a bounty with submitted labels cannot be cancelled
+    InsufficientGas { required: U64, prepaid: U64 }, // the gas prepaid does not cover the oracle call and the prediction after it
+    UriTooLong { length: u32, max_length: u32 }, // the uri of an off-chain dataset is too long to store
+}
+
+impl fmt::Display for KnnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KnnError::InvalidK { k } => write!(f, "k must be positive and odd between 1 and 15! Got {}.", k),
+            KnnError::KTooLarge { k, n_points } => write!(f, "k = {} exceeds the {} train points of the dataset.", k, n_points),
+            KnnError::UnknownDataset { name } => write!(f, "Dataset '{}' not found. Re-specify.", name),
+            KnnError::DimensionMismatch { expected, actual } => write!(f, "Point must have {} features to match the dataset, got {}.", expected, actual),
+            KnnError::EmptyDataset { name } => write!(f, "Dataset '{}' has no train points.", name),
+            KnnError::TargetMismatch { name } => write!(f, "Dataset '{}' holds the other kind of targets (classes vs numeric values).", name),
+            KnnError::InvalidMetric { p } => write!(f, "Minkowski p must be at least 1! Got {}.", p),
+            KnnError::InsufficientDeposit { required, attached } => write!(f, "Must attach at least {} yoctoNEAR, got {}.", required.0, attached.0),
+            KnnError::InsufficientTreasury { requested, available } => write!(f, "Cannot withdraw {} yoctoNEAR, only {} collected.", requested.0, available.0),
+            KnnError::Unauthorized { account_id } => write!(f, "Account {} is not allowed to call this method.", account_id),
+            KnnError::InvalidFeatureWeights => write!(f, "Feature weights must be finite and non-negative, and not all 0."),
+            KnnError::InvalidFolds { n_folds, n_points } => write!(f, "Number of folds must be between 2 and the {} train points! Got {}.", n_points, n_folds),
+            KnnError::NoCandidateK => write!(f, "At least one candidate k is needed."),
+            KnnError::InvalidTestSet { n_points, n_labels } => write!(f, "Test set needs one label per point and at least one point, got {} points and {} labels.", n_points, n_labels),
+            KnnError::TiedVote { labels } => write!(f, "Vote tied between the classes {:?}, no class predicted.", labels),
+            KnnError::Abstained { label, confidence, threshold } => write!(f, "Abstained: class {} has confidence {} below the threshold {}.", label, confidence, threshold),
+            KnnError::InvalidThreshold { threshold } => write!(f, "Confidence threshold must be between 0 and 1! Got {}.", threshold),
+            KnnError::NoNeighborsInRadius { radius } => write!(f, "No train point within a radius of {} of the test point.", radius),
+            KnnError::InvalidChunkSize => write!(f, "Chunk size must be at least 1, with few enough chunks for the attached gas."),
+            KnnError::ChunkFailed { chunk } => write!(f, "Chunk {} of the classification failed.", chunk),
+            KnnError::UnsupportedFixedPointMetric { p } => write!(f, "Minkowski p = {} is not supported in the fixed-point mode (only p = 1 and p = 2).", p),
+            KnnError::StorageLimitExceeded { limit, maximum, actual } => write!(f, "Storage limit {} of {} exceeded: {} after the upload.", limit, maximum, actual),
+            KnnError::NotRegistered { account_id } => write!(f, "Account {} is not registered for storage.", account_id),
+            KnnError::InsufficientStorageBalance { required, available } => write!(f, "Storage balance too low: {} yoctoNEAR required, {} available.", required.0, available.0),
+            KnnError::StorageInUse { account_id } => write!(f, "Account {} still has datasets, remove them before unregistering.", account_id),
+            KnnError::Paused => write!(f, "The contract is paused."),
+            KnnError::InvalidCsv { line, reason } => write!(f, "Invalid CSV at line {}: {}.", line, reason),
+            KnnError::InvalidPayload { reason } => write!(f, "Invalid Borsh payload: {}.", reason),
+            KnnError::DatasetExists { name } => write!(f, "Dataset '{}' already exists.", name),
+            KnnError::UploadInProgress { name } => write!(f, "Upload of dataset '{}' in progress, commit or abort it first.", name),
+            KnnError::NoUploadInProgress { account_id } => write!(f, "Account {} has no upload in progress.", account_id),
+            KnnError::UploadRowCount { expected, actual } => write!(f, "Upload announced {} rows, got {}.", expected, actual),
+            KnnError::InvalidCommitment => write!(f, "Commitment must be a 32 byte sha256 hash."),
+            KnnError::InvalidProof { index } => write!(f, "Row {} is not proven to belong to the dataset.", index),
+            KnnError::NoCommitment { account_id } => write!(f, "Account {} has no prediction commitment to reveal.", account_id),
+            KnnError::CommitmentMismatch => write!(f, "Revealed request does not match the commitment."),
+            KnnError::InvalidEpsilon { epsilon } => write!(f, "Privacy budget epsilon must be positive! Got {}.", epsilon),
+            KnnError::InvalidRewardShare { share_bps } => write!(f, "Reward share must be between 0 and 10000 basis points! Got {}.", share_bps),
+            KnnError::InvalidRoyalties { total_bps } => write!(f, "Royalties cannot exceed 5000 basis points in total! Got {}.", total_bps),
+            KnnError::ModelTokenExists { name } => write!(f, "The NFT of the {} dataset was already minted.", name),
+            KnnError::UnsupportedToken { token_id } => write!(f, "Token {} is not accepted for the prediction fees.", token_id),
+            KnnError::InvalidRequest { reason } => write!(f, "Invalid prediction request: {}.", reason),
+            KnnError::OracleFailed { oracle_account } => write!(f, "Oracle {} did not return the features of the test point.", oracle_account),
+            KnnError::AccessDenied { account_id } => write!(f, "Account {} is not allowed to use the contract.", account_id),
+            KnnError::QuotaExceeded { max_calls, period } => write!(f, "Quota of {} predictions per {:?} exceeded.", max_calls, period),
+            KnnError::UnknownTier { name } => write!(f, "No subscription tier named {}.", name),
+            KnnError::InvalidTier { name } => write!(f, "Subscription tier {} must limit the predictions, the duration or both.", name),
+            KnnError::AccessPassRequired { account_id } => write!(f, "Account {} has no verified access pass, see verify_access.", account_id),
+            KnnError::UnknownPrediction { prediction_id } => write!(f, "No prediction {} in the history.", prediction_id),
+            KnnError::OutcomeReported { prediction_id } => write!(f, "The outcome of prediction {} was already reported.", prediction_id),
+            KnnError::UnknownBounty { bounty_id } => write!(f, "No open labeling bounty {}.", bounty_id),
+            KnnError::BountyClosed { bounty_id } => write!(f, "The window for this call on bounty {} is over.", bounty_id),
+            KnnError::ChallengeWindowOpen { bounty_id, ends_at } => write!(f, "Bounty {} can be settled once its challenge window ends at {}.", bounty_id, ends_at.0),
+            KnnError::InvalidLabels { expected, actual } => write!(f, "Expected {} labels, one per point of the bounty, got {}.", expected, actual),
+            KnnError::NoSubmission { labeler } => write!(f, "Account {} submitted no labels to the bounty.", labeler),
+            KnnError::UnknownBatch { batch_id } => write!(f, "No staked batch {}.", batch_id),
+            KnnError::SlashWindowOver { batch_id } => write!(f, "The slash window of batch {} is over.", batch_id),
+            KnnError::SlashWindowOpen { batch_id, ends_at } => write!(f, "The stake of batch {} can be released once its slash window ends at {}.", batch_id, ends_at.0),
+            KnnError::GovernanceRequired => write!(f, "The contract is governed by a council: propose this change with propose_action."),
+            KnnError::InvalidCouncil { members, threshold } => write!(f, "Threshold {} must be between 1 and the {} members of the council.", threshold, members),
+            KnnError::NotCouncilMember { account_id } => write!(f, "Account {} is not a member of the council.", account_id),
+            KnnError::UnknownProposal { proposal_id } => write!(f, "No proposal {}.", proposal_id),
+            KnnError::ProposalExecuted { proposal_id } => write!(f, "Proposal {} was already carried out.", proposal_id),
+            KnnError::TimelockRequired => write!(f, "Changes are timelocked: schedule this change with schedule_action."),
+            KnnError::UnknownAction { action_id } => write!(f, "No scheduled action {}.", action_id),
+            KnnError::ActionNotReady { action_id, ready_at } => write!(f, "Scheduled action {} can be executed from block {}.", action_id, ready_at.0),
+            KnnError::NonFiniteFeature { feature } => write!(f, "Feature {} is not a finite number.", feature),
+            KnnError::NonFiniteValue => write!(f, "The target value is not a finite number."),
+            KnnError::OutOfRange { feature, value, min, max } => write!(f, "Feature {} ({}) is too far outside the range [{}, {}] of the train points.", feature, value, min, max),
+            KnnError::InvalidTolerance { tolerance } => write!(f, "Range tolerance must be finite and non-negative, got {}.", tolerance),
+            KnnError::SnapshotImmutable { name } => write!(f, "Snapshot '{}' cannot be changed.", name),
+            KnnError::SnapshotExists { name } => write!(f, "Snapshot '{}' already holds another dataset.", name),
+            KnnError::InvalidDatasetName { name } => write!(f, "Dataset name '{}' cannot contain '@'.", name),
+            KnnError::DatasetFrozen { name } => write!(f, "Dataset '{}' is frozen.", name),
+            KnnError::TooManyLabels { name } => write!(f, "No label left for class '{}': a dataset has at most 256 classes.", name),
+            KnnError::UnknownLabel { dataset, label_name } => write!(f, "Dataset '{}' has no class named '{}'.", dataset, label_name),
+            KnnError::EmptyLabelSet => write!(f, "A train point needs at least one class."),
+            KnnError::UnknownFeature { feature, n_features } => write!(f, "Feature {} does not exist, the dataset has {} features.", feature, n_features),
+            KnnError::MetricDimension { expected, actual } => write!(f, "The metric applies to points of {} features, the dataset has {}.", expected, actual),
+            KnnError::NoInverseCovariance => write!(f, "The dataset has no inverse covariance for the Mahalanobis metric: see set_mahalanobis."),
+            KnnError::UnregisteredModel { dataset, algorithm } => write!(f, "Model {:?} is not registered for dataset '{}'.", algorithm, dataset),
+            KnnError::InvalidLearningRate { learning_rate } => write!(f, "Learning rate must be finite and positive, got {}.", learning_rate),
+            KnnError::InvalidClusterCount { k, n_points } => write!(f, "The number of clusters must be between 1 and the {} train points, got {}.", n_points, k),
+            KnnError::NoClusters { dataset } => write!(f, "Dataset '{}' is not clustered: see fit_kmeans.", dataset),
+            KnnError::BountyHasSubmissions { bounty_id } => write!(f, "Bounty {} has submitted labels and cannot be cancelled.", bounty_id),
+            KnnError::InsufficientGas { required, prepaid } => write!(f, "At least {} gas must be prepaid, got {}.", required.0, prepaid.0),
+            KnnError::UriTooLong { length, max_length } => write!(f, "The uri can have at most {} bytes, got {}.", max_length, length),
+        }
+    }
+}
+
+impl KnnError {
+    // Aborts the call with the error message (near_bindgen's panic hook reports it to the caller).
+    // Used where the transaction must be reverted (e.g. so an attached deposit is returned).
+    pub fn panic(&self) -> ! {
+        panic!("{}", self)
+    }
+}
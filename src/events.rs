@@ -0,0 +1,127 @@
+use near_sdk::serde::Serialize;
+use near_sdk::json_types::{Base64VecU8, U64};
+use near_sdk::serde_json;
+use near_sdk::{env, AccountId};
+use crate::governance::AdminAction;
+
+// ------------------------------------------ CONTRACT EVENTS ---------------------------------------------------
+// Standardized (NEP-297) event logs: `EVENT_JSON:{"standard":"knn","version":"1.0.0","event":...,"data":[...]}`
+// so that indexers and explorers can track contract usage without parsing free-form log strings.
+const EVENT_STANDARD: &str = "knn";
+const EVENT_VERSION: &str = "1.0.0";
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+pub enum KnnEvent<'a> {
+    KnnPrediction(Vec<PredictionData<'a>>),
+    KnnTuneK(Vec<TuneKData<'a>>),
+    KnnImbalance(Vec<ImbalanceData<'a>>),
+    KnnCommit(Vec<CommitData<'a>>),
+    KnnReveal(Vec<RevealData<'a>>),
+    KnnOutcome(Vec<OutcomeData<'a>>),
+    KnnProposalCreated(Vec<ProposalData<'a>>),
+    KnnProposalApproved(Vec<ProposalData<'a>>),
+    KnnProposalExecuted(Vec<ProposalData<'a>>),
+    KnnActionScheduled(Vec<ScheduledActionData<'a>>),
+    KnnActionExecuted(Vec<ScheduledActionData<'a>>),
+    KnnActionCancelled(Vec<ScheduledActionData<'a>>),
+}
+
+// A test point was classified with run_analysis.
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PredictionData<'a> {
+    pub dataset: &'a str,
+    pub test_point: &'a [f64],
+    pub predicted_class: u8,
+    pub caller: &'a AccountId,
+}
+
+// The k of a dataset was picked with tune_k: leave-one-out accuracy of each candidate k, as [k, accuracy] pairs.
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TuneKData<'a> {
+    pub dataset: &'a str,
+    pub scores: &'a [(u8, f64)],
+    pub best_k: u8,
+}
+
+// A dataset became imbalanced as points were added: one class holds more than 90% of its train points (see set_class_weighting).
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ImbalanceData<'a> {
+    pub dataset: &'a str,
+    pub label: u8, // majority class
+    pub share: f64, // its share of the train points
+    pub n_rows: u64,
+}
+
+// An account committed to a prediction request by its hash (see commit_reveal.rs).
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CommitData<'a> {
+    pub caller: &'a AccountId,
+    pub commitment: &'a Base64VecU8,
+}
+
+// A committed request was revealed and classified: the prediction recorded under prediction_id.
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RevealData<'a> {
+    pub caller: &'a AccountId,
+    pub commitment: &'a Base64VecU8,
+    pub prediction_id: u64,
+}
+
+// The true class of a prediction was reported (see feedback.rs): a true_label other than predicted_class disputes the prediction.
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OutcomeData<'a> {
+    pub prediction_id: u64,
+    pub dataset: &'a str,
+    pub predicted_class: u8,
+    pub true_label: u8,
+    pub reporter: &'a AccountId,
+}
+
+// A governance proposal was created, approved or carried out (see governance.rs), by account_id.
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProposalData<'a> {
+    pub proposal_id: u64,
+    pub action: &'a AdminAction,
+    pub account_id: &'a AccountId,
+}
+
+// A timelocked action was scheduled, executed or cancelled (see timelock.rs), by account_id.
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ScheduledActionData<'a> {
+    pub action_id: u64,
+    pub action: &'a AdminAction,
+    pub ready_at: U64, // block height
+    pub account_id: &'a AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)] // puts the "event" and "data" fields next to standard and version
+    event: &'a KnnEvent<'a>,
+}
+
+impl KnnEvent<'_> {
+    // Log line of the event, e.g. EVENT_JSON:{"standard":"knn","version":"1.0.0","event":"knn_prediction","data":[...]}
+    pub fn to_log_string(&self) -> String {
+        let log = EventLog { standard: EVENT_STANDARD, version: EVENT_VERSION, event: self };
+        format!("EVENT_JSON:{}", serde_json::to_string(&log).unwrap())
+    }
+
+    pub fn emit(&self) {
+        env::log_str(&self.to_log_string());
+    }
+}
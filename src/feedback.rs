@@ -0,0 +1,64 @@
+use crate::*;
+use events::OutcomeData;
+
+// ------------------------------------------ GROUND-TRUTH FEEDBACK ---------------------------------------------
+// The accuracy of a model on its own train points says little of how it does on the points it is asked to classify. Once the
+// real outcome of a prediction is known (e.g. the diagnosis of the patient), the caller of the prediction, or the owner, can
+// report it with report_outcome, once per prediction. The contract keeps the live accuracy of each dataset over the reported
+// outcomes, and emits an event for each (a wrong prediction disputes the model). Outcomes of predictions made before the
+// dataset was (re)created don't count, and removing the dataset resets its live accuracy.
+
+// Accuracy of the predictions of a dataset whose outcome was reported.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LiveAccuracy {
+    pub n_reported: u64,
+    pub n_correct: u64,
+    pub accuracy: f64, // n_correct / n_reported
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Records the true class of a prediction of the history. Returns whether the prediction was correct.
+    pub fn report_outcome(&mut self, prediction_id: u64, true_label: u8) -> Result<bool, KnnError> {
+        self.assert_not_paused();
+        let record = self.predictions.get(prediction_id).ok_or(KnnError::UnknownPrediction { prediction_id })?;
+        let reporter = env::predecessor_account_id();
+        if reporter != record.caller && reporter != self.owner_id {
+            return Err(KnnError::Unauthorized { account_id: reporter });
+        }
+        if self.outcomes.contains_key(&prediction_id) {
+            return Err(KnnError::OutcomeReported { prediction_id });
+        }
+        let dataset = self.get_dataset(&record.dataset)?;
+        if record.timestamp.0 < dataset.created_at {
+            return Err(KnnError::UnknownDataset { name: record.dataset }); // the dataset was removed since
+        }
+        let correct = record.result.label == true_label;
+        let mut live = self.live_accuracy.get(&record.dataset).unwrap_or_default();
+        live.n_reported += 1;
+        live.n_correct += u64::from(correct);
+        live.accuracy = live.n_correct as f64 / live.n_reported as f64;
+        self.live_accuracy.insert(&record.dataset, &live);
+        self.outcomes.insert(&prediction_id, &true_label);
+        KnnEvent::KnnOutcome(vec![OutcomeData {
+            prediction_id,
+            dataset: &record.dataset,
+            predicted_class: record.result.label,
+            true_label,
+            reporter: &reporter,
+        }])
+        .emit();
+        Ok(correct)
+    }
+
+    // True class reported for a prediction, if any.
+    pub fn get_outcome(&self, prediction_id: u64) -> Option<u8> {
+        self.outcomes.get(&prediction_id)
+    }
+
+    // Accuracy of the dataset over the reported outcomes (None before the first one).
+    pub fn get_live_accuracy(&self, dataset: String) -> Option<LiveAccuracy> {
+        self.live_accuracy.get(&dataset)
+    }
+}
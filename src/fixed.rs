@@ -0,0 +1,56 @@
+use crate::DistanceMetric;
+
+// ------------------------------------------ FIXED-POINT ARITHMETIC -------------------------------------------
+// In the FixedPoint numeric mode coordinates are integers in units of 1e-6 (i64 scaled by 1e6), and distances are computed
+// with integer arithmetic only, so every node computes bit-identical distances and there are no rounding disputes.
+// Distances are converted back to f64 (a multiple of 1e-6) when returned.
+pub const FIXED_POINT_SCALE: i64 = 1_000_000;
+
+// Nearest fixed-point value of x (saturates at the bounds of i64).
+pub fn to_fixed(x: f64) -> i64 {
+    (x * FIXED_POINT_SCALE as f64).round() as i64
+}
+
+pub fn from_fixed(x: i64) -> f64 {
+    x as f64 / FIXED_POINT_SCALE as f64
+}
+
+// Rounds every coordinate of the point to the fixed-point grid (multiples of 1e-6).
+pub fn quantize(point: &[f64]) -> Vec<f64> {
+    point.iter().map(|x| from_fixed(to_fixed(*x))).collect()
+}
+
+// Whether the metric can be computed with integer arithmetic: Minkowski only for p = 1 and p = 2 (as Manhattan and Euclidean).
+pub fn supports(metric: &DistanceMetric) -> bool {
+    !matches!(metric, DistanceMetric::Minkowski(p) if *p != 1.0 && *p != 2.0)
+}
+
+// Distance between 2 points with fixed-point arithmetic (None: the metric is not supported). Euclidean takes the integer
+// square root (rounded down) of the sum of squared differences, which is accumulated in i128 so it does not overflow.
+// Categorical features differ by 0 or 1 (see knn_core::abs_diffs), and Gower divides the sum of the differences (rounding down).
+pub fn distance(metric: &DistanceMetric, a: &[f64], b: &[f64], categorical: &[bool]) -> Option<f64> {
+    let abs_diffs = a.iter().zip(b).enumerate().map(|(ii, (x, y))| {
+        let d = (i128::from(to_fixed(*x)) - i128::from(to_fixed(*y))).unsigned_abs();
+        match categorical.get(ii) {
+            Some(true) => u128::from(d != 0) * FIXED_POINT_SCALE as u128,
+            _ => d,
+        }
+    });
+    let d = match metric {
+        DistanceMetric::Euclidean => abs_diffs.map(|d| d * d).sum::<u128>().isqrt(),
+        DistanceMetric::Minkowski(p) if *p == 2.0 => abs_diffs.map(|d| d * d).sum::<u128>().isqrt(),
+        DistanceMetric::Manhattan => abs_diffs.sum(),
+        DistanceMetric::Minkowski(p) if *p == 1.0 => abs_diffs.sum(),
+        DistanceMetric::Chebyshev => abs_diffs.max().unwrap_or(0),
+        DistanceMetric::Minkowski(_) => return None,
+        DistanceMetric::Hamming => abs_diffs.filter(|d| *d != 0).count() as u128 * FIXED_POINT_SCALE as u128,
+        DistanceMetric::Gower => abs_diffs.sum::<u128>() / a.len().max(1) as u128,
+        DistanceMetric::Haversine | DistanceMetric::Cosine | DistanceMetric::Mahalanobis => return None, // trigonometry, division and matrices, computed with f64
+    };
+    Some(from_fixed(d as i64))
+}
+
+// Fixed-point difference of 2 coordinates, a lower bound of the fixed-point distance of points with these coordinates.
+pub fn gap(a: f64, b: f64) -> f64 {
+    from_fixed((i128::from(to_fixed(a)) - i128::from(to_fixed(b))).unsigned_abs() as i64)
+}
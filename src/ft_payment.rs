@@ -0,0 +1,118 @@
+use crate::*;
+use near_contract_standards::fungible_token::core_impl::ext_fungible_token;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::serde_json;
+use near_sdk::PromiseOrValue;
+
+// ------------------------------------------ FUNGIBLE TOKEN PAYMENTS (NEP-141) ---------------------------------
+// Prediction fees can also be paid in a fungible token whitelisted by the owner (e.g. wNEAR or USDC), each with its own fee in
+// the units of the token. The caller sends at least the fee to the contract with ft_transfer_call, whose msg is the prediction
+// request as JSON, e.g. {"data_set": "cancer", "test_point": [13.9, 1.9]}. The contract classifies the point (the result is in
+// the knn_prediction event and the prediction history) and reports the surplus as unused, so the token contract refunds it.
+// If the request cannot be served ft_on_transfer panics, and the token contract refunds the whole amount.
+// The storage the prediction writes (its record in the history, the usage counters and cache) is paid in NEAR: it is drawn from
+// the sender's storage balance (see storage.rs), and the request is rejected if the sender has not pre-paid enough.
+// Fees paid in tokens go to a treasury per token: the contributors' rewards and the model NFTs are paid in NEAR only.
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000); // 10 Tgas for ft_transfer on the token contract
+const GAS_FOR_FT_WITHDRAW_CALLBACK: Gas = Gas(5_000_000_000_000); // 5 Tgas for on_ft_fees_withdrawn
+
+// Prediction requested in the msg of ft_transfer_call, with the arguments of run_analysis (no NFT: its storage is paid in NEAR).
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtPredictionRequest {
+    data_set: String,
+    test_point: Vec<f64>,
+    metric: Option<DistanceMetric>,
+    k_override: Option<u8>,
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for KnnMachineLearning {
+    // Called by the token contract on ft_transfer_call: amount of the token was sent by sender_id to pay for the prediction in msg.
+    // Returns the amount not used, which the token contract refunds to the sender.
+    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+        self.assert_not_paused();
+        let token_id = env::predecessor_account_id();
+        let fee = self.ft_prediction_fees.get(&token_id).unwrap_or_else(|| KnnError::UnsupportedToken { token_id: token_id.clone() }.panic());
+        if amount.0 < fee {
+            KnnError::InsufficientDeposit { required: U128(fee), attached: amount }.panic();
+        }
+        let request: FtPredictionRequest = serde_json::from_str(&msg).unwrap_or_else(|e| KnnError::InvalidRequest { reason: e.to_string() }.panic());
+        let initial_storage = env::storage_usage(); // bytes used before the prediction is recorded
+        self.predict_and_record(&request.data_set, request.test_point, request.metric, request.k_override, sender_id.clone())
+            .unwrap_or_else(|e| e.panic());
+        let storage_cost = Balance::from(env::storage_usage().saturating_sub(initial_storage)) * env::storage_byte_cost();
+        if storage_cost > 0 {
+            self.lock_storage_balance(&sender_id, storage_cost);
+        }
+        let collected = self.ft_fees_collected.get(&token_id).unwrap_or(0);
+        self.ft_fees_collected.insert(&token_id, &(collected + fee));
+        PromiseOrValue::Value(U128(amount.0 - fee))
+    }
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can accept a fungible token for the prediction fees, with the fee in its smallest units (None stops accepting it).
+    pub fn set_ft_fee(&mut self, token_id: AccountId, fee: Option<U128>) {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.store_ft_fee(&token_id, fee);
+    }
+
+    // Fee of a prediction paid in the token, None if the token is not accepted.
+    pub fn get_ft_fee(&self, token_id: AccountId) -> Option<U128> {
+        self.ft_prediction_fees.get(&token_id).map(U128)
+    }
+
+    // Fees collected in the token that can still be withdrawn.
+    pub fn get_ft_treasury_balance(&self, token_id: AccountId) -> U128 {
+        U128(self.ft_fees_collected.get(&token_id).unwrap_or(0))
+    }
+
+    // Sends amount of the fees collected in the token to `to` (which must be registered with the token contract). Only the owner
+    // can withdraw. Like withdraw_fees, the treasury is debited before the transfer and credited back by on_ft_fees_withdrawn if it fails.
+    pub fn withdraw_ft_fees(&mut self, token_id: AccountId, amount: U128, to: AccountId) -> Result<(), KnnError> {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.transfer_ft_fees(token_id, amount, to)
+    }
+
+    // Callback of withdraw_ft_fees: returns the amount to the token's treasury when the transfer did not go through.
+    #[private]
+    pub fn on_ft_fees_withdrawn(&mut self, token_id: AccountId, amount: U128) {
+        if !is_promise_success() {
+            let collected = self.ft_fees_collected.get(&token_id).unwrap_or(0);
+            self.ft_fees_collected.insert(&token_id, &(collected + amount.0));
+        }
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Sets the fee in the token for set_ft_fee or a SetFtFee action.
+    pub(crate) fn store_ft_fee(&mut self, token_id: &AccountId, fee: Option<U128>) {
+        match fee {
+            Some(fee) => self.ft_prediction_fees.insert(token_id, &fee.0),
+            None => self.ft_prediction_fees.remove(token_id),
+        };
+    }
+
+    // Callable from methods only (not user). Checks the treasury of the token holds the amount.
+    pub(crate) fn check_ft_treasury(&self, token_id: &AccountId, amount: U128) -> Result<(), KnnError> {
+        let available = self.ft_fees_collected.get(token_id).unwrap_or(0);
+        if amount.0 > available {
+            return Err(KnnError::InsufficientTreasury { requested: amount, available: U128(available) });
+        }
+        Ok(())
+    }
+
+    // Callable from methods only (not user). Withdraws fees in the token for withdraw_ft_fees or a WithdrawFtFees action.
+    pub(crate) fn transfer_ft_fees(&mut self, token_id: AccountId, amount: U128, to: AccountId) -> Result<(), KnnError> {
+        self.check_ft_treasury(&token_id, amount)?;
+        let available = self.ft_fees_collected.get(&token_id).unwrap_or(0);
+        self.ft_fees_collected.insert(&token_id, &(available - amount.0));
+        ext_fungible_token::ft_transfer(to, amount, None, token_id.clone(), 1, GAS_FOR_FT_TRANSFER) // NEP-141 transfers require 1 yoctoNEAR
+            .then(ext_self::on_ft_fees_withdrawn(token_id, amount, env::current_account_id(), 0, GAS_FOR_FT_WITHDRAW_CALLBACK));
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+use crate::*;
+use events::ProposalData;
+
+// ------------------------------------------ GOVERNANCE --------------------------------------------------------
+// The owner can be any account, a DAO contract included (see propose_owner). For a single owner key, sensitive changes can
+// instead be put under a council: once the owner sets one, the changes of the predictions (the default k, voting scheme, tie
+// break, confidence threshold, numeric mode and distance metric, and the metric of a dataset), of the fees and treasuries (the
+// prediction fee, the fees in tokens, the subscription tiers, the reward share, the royalties and the withdrawals in NEAR and
+// in tokens), of the data rules (the storage limits, the stake policy and the slashing of batches, the removal of datasets by
+// anyone but their creator), and of the council itself are only made by proposals of its members. A proposal is carried out as
+// soon as `threshold` of the current members approved it (its proposer included), or queued if a timelock is set (see
+// timelock.rs). Each governance action emits an event (knn_proposal_created, knn_proposal_approved, knn_proposal_executed).
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Council {
+    pub members: Vec<AccountId>,
+    pub threshold: u32, // approvals needed to carry out a proposal
+}
+
+// A sensitive change of the configuration.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum AdminAction {
+    SetPredictionFee { fee: U128 },
+    SetK { k: u8 },
+    SetVotingScheme { voting_scheme: VotingScheme },
+    RemoveDataset { name: String },
+    SetCouncil { council: Option<Council> },
+    SetTimelock { blocks: u64 },
+    SetTieBreak { tie_break: TieBreak },
+    SetConfidenceThreshold { threshold: f64 },
+    SetDistanceMetric { metric: DistanceMetric },
+    SetRewardShare { share_bps: u16 },
+    SetRoyalties { royalties: Royalties },
+    SetStakePolicy { policy: Option<StakePolicy> },
+    SlashBatch { batch_id: u64 },
+    WithdrawFees { amount: U128, to: AccountId },
+    SetFtFee { token_id: AccountId, fee: Option<U128> },
+    WithdrawFtFees { token_id: AccountId, amount: U128, to: AccountId },
+    SetSubscriptionTier { name: String, tier: Option<SubscriptionTier> },
+    SetNumericMode { mode: NumericMode },
+    SetStorageLimits { limits: StorageLimits },
+    SetDatasetMetric { dataset: String, metric: Option<DistanceMetric> },
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Proposal {
+    pub proposer: AccountId,
+    pub action: AdminAction,
+    pub approvals: Vec<AccountId>,
+    pub executed: bool,
+}
+
+impl Council {
+    fn validate(&self) -> Result<(), KnnError> {
+        if self.threshold == 0 || self.threshold as usize > self.members.len() {
+            return Err(KnnError::InvalidCouncil { members: self.members.len() as u32, threshold: self.threshold });
+        }
+        Ok(())
+    }
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can put the contract under a council, after which changing it takes a SetCouncil proposal.
+    pub fn set_council(&mut self, council: Council) -> Result<(), KnnError> {
+        self.assert_owner();
+        self.assert_ungoverned();
+        council.validate()?;
+        self.council = Some(council);
+        Ok(())
+    }
+
+    pub fn get_council(&self) -> Option<Council> {
+        self.council.clone()
+    }
+
+    // Only council members can propose a change, which counts as their approval. Returns the id of the proposal. If that
+    // approval carries it out and the change fails, the call panics so that no proposal (nor its event) is left behind.
+    pub fn propose_action(&mut self, action: AdminAction) -> Result<u64, KnnError> {
+        let proposer = self.assert_council_member()?;
+        self.validate_action(&action)?;
+        let proposal_id = self.next_proposal_id;
+        self.next_proposal_id += 1;
+        KnnEvent::KnnProposalCreated(vec![ProposalData { proposal_id, action: &action, account_id: &proposer }]).emit();
+        self.proposals.insert(&proposal_id, &Proposal { proposer, action, approvals: Vec::new(), executed: false });
+        self.approve_proposal(proposal_id).unwrap_or_else(|e| e.panic());
+        Ok(proposal_id)
+    }
+
+    // Only council members can approve a proposal. Returns whether the proposal was carried out.
+    pub fn approve_proposal(&mut self, proposal_id: u64) -> Result<bool, KnnError> {
+        let member = self.assert_council_member()?;
+        let mut proposal = self.proposals.get(&proposal_id).ok_or(KnnError::UnknownProposal { proposal_id })?;
+        if proposal.executed {
+            return Err(KnnError::ProposalExecuted { proposal_id });
+        }
+        if !proposal.approvals.contains(&member) {
+            proposal.approvals.push(member.clone());
+        }
+        let members = self.council.as_ref().map(|council| council.members.clone()).unwrap_or_default();
+        let approvals = proposal.approvals.iter().filter(|account_id| members.contains(account_id)).count();
+        let threshold = self.council.as_ref().map_or(0, |council| council.threshold) as usize;
+        if approvals >= threshold && self.timelock_blocks > 0 {
+            self.schedule(proposal.action.clone(), proposal.proposer.clone());
+            proposal.executed = true;
+        } else if approvals >= threshold {
+            self.apply_action(proposal.action.clone())?; // the approval is not recorded if the change fails
+            proposal.executed = true;
+        }
+        self.proposals.insert(&proposal_id, &proposal);
+        KnnEvent::KnnProposalApproved(vec![ProposalData { proposal_id, action: &proposal.action, account_id: &member }]).emit();
+        if proposal.executed {
+            KnnEvent::KnnProposalExecuted(vec![ProposalData { proposal_id, action: &proposal.action, account_id: &member }]).emit();
+        }
+        Ok(proposal.executed)
+    }
+
+    pub fn get_proposal(&self, proposal_id: u64) -> Option<Proposal> {
+        self.proposals.get(&proposal_id)
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Guards the sensitive owner methods: under a council, only its proposals make these
+    // changes, under a timelock only scheduled actions.
+    pub(crate) fn assert_ungoverned(&self) {
+        if self.council.is_some() {
+            KnnError::GovernanceRequired.panic();
+        }
+        if self.timelock_blocks > 0 {
+            KnnError::TimelockRequired.panic();
+        }
+    }
+
+    // Callable from methods only (not user). Rejects actions that could not be carried out.
+    pub(crate) fn validate_action(&self, action: &AdminAction) -> Result<(), KnnError> {
+        match action {
+            AdminAction::SetK { k } => validate_k(*k),
+            AdminAction::SetCouncil { council: Some(council) } => council.validate(),
+            AdminAction::RemoveDataset { name } => check_unfrozen(name, &self.get_dataset(name)?),
+            AdminAction::SetConfidenceThreshold { threshold } => validate_threshold(*threshold),
+            AdminAction::SetDistanceMetric { metric } => metric.validate(),
+            AdminAction::SetRewardShare { share_bps } => rewards::validate_reward_share(*share_bps),
+            AdminAction::SetRoyalties { royalties } => royalties.validate(),
+            AdminAction::SlashBatch { batch_id } => self.slashable_batch(*batch_id).map(|_| ()),
+            AdminAction::WithdrawFees { amount, .. } if amount.0 > self.fees_collected => {
+                Err(KnnError::InsufficientTreasury { requested: *amount, available: U128(self.fees_collected) })
+            }
+            AdminAction::WithdrawFtFees { token_id, amount, .. } => self.check_ft_treasury(token_id, *amount),
+            AdminAction::SetSubscriptionTier { name, tier: Some(tier) } => subscription::validate_tier(name, tier),
+            AdminAction::SetDatasetMetric { dataset, metric } => self.dataset_with_metric(dataset, *metric).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    fn assert_council_member(&self) -> Result<AccountId, KnnError> {
+        let caller = env::predecessor_account_id();
+        match &self.council {
+            Some(council) if council.members.contains(&caller) => Ok(caller),
+            _ => Err(KnnError::NotCouncilMember { account_id: caller }),
+        }
+    }
+
+    // Callable from methods only (not user). Makes the change of an approved proposal.
+    pub(crate) fn apply_action(&mut self, action: AdminAction) -> Result<(), KnnError> {
+        match action {
+            AdminAction::SetPredictionFee { fee } => self.prediction_fee = fee.0,
+            AdminAction::SetK { k } => {
+                validate_k(k)?;
+                self.param_k = k;
+            }
+            AdminAction::SetVotingScheme { voting_scheme } => self.voting_scheme = voting_scheme,
+            AdminAction::RemoveDataset { name } => {
+                let data = self.get_dataset(&name)?;
+                check_unfrozen(&name, &data)?;
+                self.delete_dataset_refunded(&name, data);
+            }
+            AdminAction::SetCouncil { council } => self.council = council,
+            AdminAction::SetTimelock { blocks } => self.timelock_blocks = blocks,
+            AdminAction::SetTieBreak { tie_break } => self.tie_break = tie_break,
+            AdminAction::SetConfidenceThreshold { threshold } => {
+                validate_threshold(threshold)?;
+                self.confidence_threshold = threshold;
+            }
+            AdminAction::SetDistanceMetric { metric } => {
+                metric.validate()?;
+                self.distance_metric = metric;
+            }
+            AdminAction::SetRewardShare { share_bps } => {
+                rewards::validate_reward_share(share_bps)?;
+                self.reward_share_bps = share_bps;
+            }
+            AdminAction::SetRoyalties { royalties } => {
+                royalties.validate()?;
+                self.royalties = royalties;
+            }
+            AdminAction::SetStakePolicy { policy } => self.stake_policy = policy,
+            AdminAction::SlashBatch { batch_id } => {
+                self.slash(batch_id)?;
+            }
+            AdminAction::WithdrawFees { amount, to } => self.transfer_fees(amount, to)?,
+            AdminAction::SetFtFee { token_id, fee } => self.store_ft_fee(&token_id, fee),
+            AdminAction::WithdrawFtFees { token_id, amount, to } => self.transfer_ft_fees(token_id, amount, to)?,
+            AdminAction::SetSubscriptionTier { name, tier } => self.store_subscription_tier(name, tier)?,
+            AdminAction::SetNumericMode { mode } => self.numeric_mode = mode,
+            AdminAction::SetStorageLimits { limits } => self.storage_limits = limits,
+            AdminAction::SetDatasetMetric { dataset, metric } => {
+                let data = self.dataset_with_metric(&dataset, metric)?;
+                self.store_dataset(&dataset, &data);
+            }
+        }
+        Ok(())
+    }
+}
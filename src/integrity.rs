@@ -0,0 +1,62 @@
+use crate::*;
+
+// ------------------------------------------ DATASET INTEGRITY -------------------------------------------------
+// Each dataset keeps a content hash of its rows, so that anyone can check the stored data matches a published dataset (with
+// verify_dataset, or against get_dataset_meta). It chains the rows in order: starting from sha256 of nothing,
+// hash = sha256(hash ++ borsh(row)), where a row is (point, class) for a classification dataset ((point, classes) for a point of
+// several classes, see multilabel.rs) and (point, value) for a regression dataset. Appended rows are chained as they are stored (for a chunked upload, as its chunks are staged); removing
+// rows recomputes the hash.
+// The owner or the creator of a dataset can also freeze it, for good: rows can then no longer be added to it or removed from it
+// (nor the dataset itself), so that consumers can rely on a stable model.
+
+impl Dataset {
+    // Chains a row just appended into the content hash.
+    pub(crate) fn hash_row<T: BorshSerialize>(&mut self, point: &[f64], target: T) {
+        let row = (point, target).try_to_vec().unwrap();
+        self.content_hash = env::sha256(&[&self.content_hash[..], &row].concat());
+    }
+
+    // Content hash of all the rows of the dataset.
+    pub(crate) fn compute_content_hash(&self) -> Vec<u8> {
+        let mut hash = env::sha256(&[]);
+        for (ii, point) in self.train.iter().enumerate() {
+            let row = match (self.label_sets.get(ii as u64), self.target.get(ii as u64)) {
+                (Some(labels), _) if labels.len() > 1 => (point, labels).try_to_vec(),
+                (_, Some(label)) => (point, label).try_to_vec(),
+                _ => (point, self.values.get(ii as u64).unwrap_or_default()).try_to_vec(),
+            };
+            hash = env::sha256(&[hash, row.unwrap()].concat());
+        }
+        hash
+    }
+}
+
+// Rows cannot be added to or removed from a frozen dataset.
+pub(crate) fn check_unfrozen(name: &str, data: &Dataset) -> Result<(), KnnError> {
+    if data.frozen {
+        return Err(KnnError::DatasetFrozen { name: name.to_string() });
+    }
+    Ok(())
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Whether the rows of the dataset have the expected content hash.
+    pub fn verify_dataset(&self, name: String, expected_hash: Base64VecU8) -> Result<bool, KnnError> {
+        Ok(self.get_dataset(&name)?.content_hash == expected_hash.0)
+    }
+
+    // Only the owner or the creator of a dataset can freeze it (this cannot be undone). Returns the content hash of its rows.
+    pub fn freeze_dataset(&mut self, name: String) -> Result<Base64VecU8, KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&name)?;
+        if is_snapshot(&name) {
+            return Err(KnnError::SnapshotImmutable { name });
+        }
+        self.assert_dataset_admin(&data);
+        check_unfrozen(&name, &data)?;
+        data.frozen = true;
+        self.store_dataset(&name, &data);
+        Ok(Base64VecU8(data.content_hash))
+    }
+}
@@ -0,0 +1,104 @@
+use crate::*;
+
+// ------------------------------------------ K-D TREE SPATIAL INDEX -------------------------------------------
+// Scanning every train point costs O(n d) gas per prediction, too much for datasets beyond a few hundred rows.
+// A k-d tree splits the train points on one feature per level (the feature cycles with the depth), so that the search
+// only visits the branches that may hold one of the k nearest neighbours.
+// The nodes are stored in a Vector of the dataset, and a node only refers to its train point by index: a search reads
+// the nodes and train points it visits, not the whole dataset.
+// The tree is built on the stored (raw) coordinates. Scaling and feature weights multiply every feature by a non-negative
+// factor, which keeps the order of the points along each feature, so the same tree serves any scaling and weights.
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, Debug)]
+pub struct KdNode {
+    point: u64, // index of the train point in the dataset
+    axis: u32, // feature the node splits on: points with a smaller value are on the left, the others on the right
+    left: Option<u64>, // index of the child nodes in the tree (None: no child)
+    right: Option<u64>,
+}
+
+// Nodes of a balanced tree of the points, in preorder (the root is node 0).
+pub(crate) fn build(points: &[Vec<f64>]) -> Vec<KdNode> {
+    let mut indices: Vec<usize> = (0..points.len()).collect();
+    let mut nodes = Vec::with_capacity(points.len());
+    build_subtree(points, &mut indices, 0, &mut nodes);
+    nodes
+}
+
+// Splits the points on the median of the axis of this depth, appends the node and then its subtrees. Returns the index of the node.
+fn build_subtree(points: &[Vec<f64>], indices: &mut [usize], depth: usize, nodes: &mut Vec<KdNode>) -> Option<u64> {
+    if indices.is_empty() {
+        return None;
+    }
+    let axis = depth % points[indices[0]].len();
+    indices.sort_unstable_by(|a, b| cmp_pairs(&(*a, points[*a][axis]), &(*b, points[*b][axis])));
+    let median = indices.len() / 2; // points equal to the median may be on either side, the search checks both sides of a tie
+    let id = nodes.len();
+    nodes.push(KdNode { point: indices[median] as u64, axis: axis as u32, left: None, right: None });
+    let (left, rest) = indices.split_at_mut(median);
+    nodes[id].left = build_subtree(points, left, depth + 1, nodes);
+    nodes[id].right = build_subtree(points, &mut rest[1..], depth + 1, nodes);
+    Some(id as u64)
+}
+
+impl Dataset {
+    // Adds train point `index` (already pushed to the train points) as a leaf of the tree. The tree is not rebalanced: build_index does that.
+    pub(crate) fn insert_into_index(&mut self, index: u64, point: &[f64]) {
+        let tree = match &mut self.kd_tree {
+            Some(tree) => tree,
+            None => return,
+        };
+        let new_id = tree.len();
+        let mut axis = 0;
+        if new_id > 0 {
+            let mut id = 0;
+            loop {
+                let mut node = tree.get(id).unwrap();
+                let split = self.train.get(node.point).unwrap()[node.axis as usize];
+                let child = if point[node.axis as usize] < split { &mut node.left } else { &mut node.right };
+                match *child {
+                    Some(child_id) => id = child_id,
+                    None => {
+                        *child = Some(new_id);
+                        tree.replace(id, &node);
+                        axis = (node.axis + 1) % self.n_features;
+                        break;
+                    }
+                }
+            }
+        }
+        tree.push(&KdNode { point: index, axis, left: None, right: None });
+    }
+
+    // Indices of the k nearest train points to pt (nearest first) and their distances, found with the tree.
+    // Same result as a scan of all the train points: equal distances are ordered by index, so ties are only pruned when strictly farther.
+    pub(crate) fn indexed_nearest(&self, tree: &Vector<KdNode>, pt: &[f64], metric: &PointMetric, mode: NumericMode, k: usize) -> (Vec<usize>, Vec<f64>) {
+        let params = self.feature_transform(metric);
+        let pt = apply_transform(pt, &params);
+        let mut best: Vec<(usize, f64)> = Vec::with_capacity(k + 1); // (index, distance) of the nearest points found so far, nearest first
+        let mut stack: Vec<(u64, f64)> = if tree.is_empty() || k == 0 { Vec::new() } else { vec![(0, 0.0)] }; // (node, lower bound of the distances in its subtree)
+        while let Some((id, bound)) = stack.pop() {
+            if best.len() == k && bound > best[k - 1].1 {
+                continue; // no point of the subtree can be nearer than the k found
+            }
+            let node = tree.get(id).unwrap();
+            let point = apply_transform(&self.train.get(node.point).unwrap(), &params);
+            let candidate = (node.point as usize, metric.distance_in(mode, &point, &pt));
+            if best.len() < k || cmp_pairs(&candidate, &best[k - 1]) == Ordering::Less {
+                let position = best.partition_point(|other| cmp_pairs(other, &candidate) == Ordering::Less);
+                best.insert(position, candidate);
+                best.truncate(k);
+            }
+            // Any point across the split is at least as far as the split along its axis (true for every Minkowski distance)
+            let axis = node.axis as usize;
+            let (near, far) = if pt[axis] < point[axis] { (node.left, node.right) } else { (node.right, node.left) };
+            if let Some(far) = far {
+                stack.push((far, bound.max(mode.gap(pt[axis], point[axis]))));
+            }
+            if let Some(near) = near {
+                stack.push((near, bound)); // searched first
+            }
+        }
+        best.into_iter().unzip()
+    }
+}
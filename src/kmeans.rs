@@ -0,0 +1,107 @@
+use crate::*;
+
+// ------------------------------------------ K-MEANS CLUSTERING ------------------------------------------------
+// Unsupervised clustering of the train points, whatever their labels (e.g. segments of the customer dataset): fit_kmeans splits
+// them into k clusters by Lloyd's algorithm, each point assigned to the nearest centroid and each centroid moved to the mean of
+// its points, until the assignments no longer change or after max_iters iterations. The centroids start at k train points
+// spread apart (the first row, then repeatedly the row furthest from the centroids chosen so far), so that the clustering is
+// deterministic. The Euclidean distances are computed with the dataset's scaling and feature weights; the centroids are stored
+// as raw features, and are scaled again by assign_cluster, so that they keep up with the scaling as rows are uploaded. An empty
+// cluster keeps its centroid. fit_kmeans can be called again to cluster the rows as they are.
+
+// Clusters of the train points of a dataset, as found by fit_kmeans.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct KMeans {
+    pub centroids: Vec<Vec<f64>>, // mean of the train points of every cluster (raw features)
+    pub sizes: Vec<u64>, // number of train points of every cluster
+    pub iterations: u32, // iterations run, fewer than max_iters if the assignments settled
+    pub inertia: f64, // sum of the squared distances of the train points to the nearest centroid (scaled units)
+}
+
+// Index of the nearest centroid and the squared distance to it.
+fn nearest_centroid(point: &[f64], centroids: &[Vec<f64>]) -> (usize, f64) {
+    centroids
+        .iter()
+        .map(|centroid| knn_core::squared_euclidean(knn_core::abs_diffs(point, centroid, &[])))
+        .enumerate()
+        .fold((0, f64::INFINITY), |best, next| if next.1 < best.1 { next } else { best })
+}
+
+// Lloyd's algorithm on the points (at least k) as transformed by transform: centroids (not transformed), assignments and
+// iterations run. The transform is affine, so the transformed mean of points is the mean of the transformed points.
+fn lloyd(points: &[Vec<f64>], transform: impl Fn(&[f64]) -> Vec<f64>, k: usize, max_iters: u32) -> (Vec<Vec<f64>>, Vec<usize>, u32) {
+    let transformed: Vec<Vec<f64>> = points.iter().map(|point| transform(point)).collect();
+    let mut centroids = vec![points[0].clone()];
+    let mut scaled = vec![transformed[0].clone()];
+    while centroids.len() < k {
+        let furthest = (0..points.len()).map(|ii| (ii, nearest_centroid(&transformed[ii], &scaled).1)).fold((0, -1.0), |best, next| if next.1 > best.1 { next } else { best });
+        centroids.push(points[furthest.0].clone());
+        scaled.push(transformed[furthest.0].clone());
+    }
+    let mut assignments: Vec<usize> = transformed.iter().map(|point| nearest_centroid(point, &scaled).0).collect();
+    let mut iterations = 0;
+    while iterations < max_iters {
+        iterations += 1;
+        for (cluster, centroid) in centroids.iter_mut().enumerate() {
+            let members: Vec<&Vec<f64>> = points.iter().zip(&assignments).filter(|(_, a)| **a == cluster).map(|(point, _)| point).collect();
+            if !members.is_empty() {
+                *centroid = (0..centroid.len()).map(|jj| members.iter().map(|point| point[jj]).sum::<f64>() / members.len() as f64).collect();
+                scaled[cluster] = transform(centroid);
+            }
+        }
+        let next: Vec<usize> = transformed.iter().map(|point| nearest_centroid(point, &scaled).0).collect();
+        if next == assignments {
+            break;
+        }
+        assignments = next;
+    }
+    (centroids, assignments, iterations)
+}
+
+// Metric the points are transformed for: the dataset's scaling and feature weights of the Euclidean distance.
+fn cluster_metric() -> PointMetric {
+    DistanceMetric::Euclidean.into()
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner or the creator of a dataset can cluster its train points into k clusters (between 1 and the number of train
+    // points), with at most max_iters iterations. The clusters are stored with the dataset and returned.
+    pub fn fit_kmeans(&mut self, dataset: String, k: u32, max_iters: u32) -> Result<KMeans, KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset)?;
+        self.assert_dataset_admin(&data);
+        let n_points = data.train.len();
+        if k == 0 || u64::from(k) > n_points {
+            return Err(KnnError::InvalidClusterCount { k, n_points });
+        }
+        let metric = cluster_metric();
+        let (centroids, assignments, iterations) = lloyd(&data.train.to_vec(), |point| data.transform(point, &metric), k as usize, max_iters);
+        let mut sizes = vec![0; k as usize];
+        for cluster in &assignments {
+            sizes[*cluster] += 1;
+        }
+        let scaled: Vec<Vec<f64>> = centroids.iter().map(|centroid| data.transform(centroid, &metric)).collect();
+        let inertia = data.transformed_train(&metric).iter().map(|point| nearest_centroid(point, &scaled).1).sum();
+        let kmeans = KMeans { centroids, sizes, iterations, inertia };
+        data.kmeans = Some(kmeans.clone());
+        self.store_dataset(&dataset, &data);
+        Ok(kmeans)
+    }
+
+    // Index of the cluster of the nearest centroid to the point, once the dataset is clustered (see fit_kmeans).
+    pub fn assign_cluster(&self, dataset: String, point: Vec<f64>) -> Result<u32, KnnError> {
+        let data = self.get_dataset(&dataset)?;
+        check_dimension(&data, &point)?;
+        check_finite(&point)?;
+        let kmeans = data.kmeans.as_ref().ok_or(KnnError::NoClusters { dataset: dataset.clone() })?;
+        let metric = cluster_metric();
+        let centroids: Vec<Vec<f64>> = kmeans.centroids.iter().map(|centroid| data.transform(centroid, &metric)).collect();
+        Ok(nearest_centroid(&data.transform(&point, &metric), &centroids).0 as u32)
+    }
+
+    pub fn get_kmeans(&self, dataset: String) -> Result<Option<KMeans>, KnnError> {
+        Ok(self.get_dataset(&dataset)?.kmeans)
+    }
+}
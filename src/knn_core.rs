@@ -0,0 +1,195 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+// ------------------------------------------ KNN CORE ALGORITHM ------------------------------------------------
+// The math of the KNN algorithm: distances, selection of the k nearest and votes. It does not depend on near_sdk (nor on the
+// contract's state), so it runs and is tested natively like any Rust code; the contract picks the metric, weights and policies
+// from its configuration and calls these functions.
+// Only std's collections and f64 math (sqrt, powf, trigonometry) are used.
+
+// |a - b| for each dimension of 2 points of the same dimensionality. The differences of categorical features (categorical[ii] true)
+// are 0 if the points have the same value and 1 otherwise. The metrics below take these differences.
+pub fn abs_diffs<'a>(a: &'a [f64], b: &'a [f64], categorical: &'a [bool]) -> impl Iterator<Item = f64> + 'a {
+    a.iter().zip(b).enumerate().map(move |(ii, (x, y))| match categorical.get(ii) {
+        Some(true) => if x == y { 0.0 } else { 1.0 },
+        _ => (x - y).abs(),
+    })
+}
+
+// L2 norm: sqrt(sum((a - b)^2)).
+pub fn euclidean(diffs: impl Iterator<Item = f64>) -> f64 {
+    squared_euclidean(diffs).sqrt()
+}
+
+// sum((a - b)^2): ranks points like the Euclidean distance, without the square root.
+pub fn squared_euclidean(diffs: impl Iterator<Item = f64>) -> f64 {
+    diffs.map(|d| d.powi(2)).sum()
+}
+
+// L1 norm: sum(|a - b|).
+pub fn manhattan(diffs: impl Iterator<Item = f64>) -> f64 {
+    diffs.sum()
+}
+
+// L-infinity norm: max(|a - b|).
+pub fn chebyshev(diffs: impl Iterator<Item = f64>) -> f64 {
+    diffs.fold(0.0, f64::max)
+}
+
+// Lp norm: (sum(|a - b|^p))^(1/p).
+pub fn minkowski(diffs: impl Iterator<Item = f64>, p: f64) -> f64 {
+    minkowski_sum(diffs, p).powf(1.0 / p)
+}
+
+// sum(|a - b|^p): ranks points like the Minkowski distance, without the root.
+pub fn minkowski_sum(diffs: impl Iterator<Item = f64>, p: f64) -> f64 {
+    diffs.map(|d| d.powf(p)).sum()
+}
+
+// Number of dimensions in which the points differ.
+pub fn hamming(diffs: impl Iterator<Item = f64>) -> f64 {
+    diffs.filter(|d| *d != 0.0).count() as f64
+}
+
+// Mean of the differences (between 0 and 1 for features scaled by their range, and for categorical features).
+pub fn gower(diffs: impl Iterator<Item = f64>) -> f64 {
+    let (sum, n) = diffs.fold((0.0, 0), |(sum, n), d| (sum + d, n + 1));
+    if n == 0 { 0.0 } else { sum / n as f64 }
+}
+
+// 1 - cos(angle between a and b): 0 for points in the same direction (whatever their norms), 1 for orthogonal points and 2 for
+// opposite points. A point at the origin has no direction: its distance to any point is 1.
+pub fn cosine(a: &[f64], b: &[f64]) -> f64 {
+    let (dot, norm_a, norm_b) = a.iter().zip(b).fold((0.0, 0.0, 0.0), |(dot, norm_a, norm_b), (x, y)| (dot + x * y, norm_a + x * x, norm_b + y * y));
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 1.0;
+    }
+    (1.0 - dot / (norm_a * norm_b).sqrt()).clamp(0.0, 2.0) // clamp: rounding may take the cosine slightly beyond [-1, 1]
+}
+
+// (a - b)^T m (a - b) for a square matrix m (row-major): the squared Mahalanobis distance for the inverse covariance matrix.
+pub fn squared_mahalanobis(a: &[f64], b: &[f64], m: &[f64]) -> f64 {
+    let diffs: Vec<f64> = a.iter().zip(b).map(|(x, y)| x - y).collect();
+    let n = diffs.len();
+    let sum: f64 = (0..n).map(|ii| diffs[ii] * (0..n).map(|jj| m[ii * n + jj] * diffs[jj]).sum::<f64>()).sum();
+    sum.max(0.0) // rounding may take the square of a tiny distance below 0
+}
+
+// Mean radius of the Earth, in km.
+pub const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+// Great-circle distance (km) between 2 points given as (latitude, longitude) in degrees, with the haversine formula.
+pub fn haversine(a: &[f64], b: &[f64]) -> f64 {
+    let (lat_a, lat_b) = (a[0].to_radians(), b[0].to_radians());
+    let (d_lat, d_lon) = ((b[0] - a[0]).to_radians(), (b[1] - a[1]).to_radians());
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin() // min: rounding may take h slightly above 1 for antipodal points
+}
+
+// Orders (index, value) pairs by value; equal values are ordered by index so the order of train points with equal distances is deterministic.
+pub fn cmp_pairs(a: &(usize, f64), b: &(usize, f64)) -> Ordering {
+    a.1.partial_cmp(&b.1).unwrap().then(a.0.cmp(&b.0))
+}
+
+// Parameters: vec nx1. Returns the indices of the elements in ascending order of their values (argsort), and the sorted values.
+// Sorts (index, value) pairs directly, so each index appears exactly once even when values are equal (e.g. duplicate distances).
+pub fn sort_and_argsort(vec: &[f64]) -> (Vec<usize>, Vec<f64>) {
+    let mut pairs: Vec<(usize, f64)> = vec.iter().copied().enumerate().collect(); // (index, value), avoids handing over ownership
+    pairs.sort_unstable_by(cmp_pairs);
+    pairs.into_iter().unzip() // split into argsort() indices and sorted values
+}
+
+// Parameters: vec nx1, k. Returns indices and values of the k smallest elements (in ascending order).
+// Partial selection: only the k smallest are sorted, so the cost scales with n + k log k rather than a full sort with an index lookup per element.
+pub fn select_k_smallest(vec: &[f64], k: usize) -> (Vec<usize>, Vec<f64>) {
+    if k >= vec.len() { // nothing to select, every element is needed
+        return sort_and_argsort(vec);
+    }
+    let mut pairs: Vec<(usize, f64)> = vec.iter().copied().enumerate().collect(); // (index, value)
+    pairs.select_nth_unstable_by(k, cmp_pairs); // k smallest elements end up (unordered) before position k
+    pairs.truncate(k);
+    pairs.sort_unstable_by(cmp_pairs);
+    pairs.into_iter().unzip()
+}
+
+// Parameters: vec kx1 of class labels (any u8 value, so 3+ classes are supported). Label with the most votes, the lowest on a tie.
+pub fn majority_vote(labels: &[u8]) -> u8 {
+    let mut counts: BTreeMap<u8, usize> = BTreeMap::new(); // # of votes per class label (ordered by label)
+    for label in labels {
+        *counts.entry(*label).or_insert(0) += 1;
+    }
+    // Label with the most votes wins; on a tie the lowest label wins (as labels are visited in ascending order)
+    let mut winner: (u8, usize) = (0, 0); // (label, # of votes)
+    for (label, n) in counts {
+        if n > winner.1 {
+            winner = (label, n);
+        }
+    }
+    winner.0
+}
+
+// Parameters: vec kx1 of class labels, vec kx1 of their vote weights. Label with the highest sum of weights, the lowest on a tie.
+pub fn weighted_vote(labels: &[u8], weights: &[f64]) -> u8 {
+    let mut scores: BTreeMap<u8, f64> = BTreeMap::new(); // sum of vote weights per class label (ordered by label)
+    for (label, w) in labels.iter().zip(weights) {
+        *scores.entry(*label).or_insert(0.0) += w;
+    }
+    // Label with the highest score wins; on a tie the lowest label wins
+    let mut winner: (u8, f64) = (0, f64::NEG_INFINITY); // (label, score)
+    for (label, score) in scores {
+        if score > winner.1 {
+            winner = (label, score);
+        }
+    }
+    winner.0
+}
+
+// Share of the vote weights (between 0 and 1) of every label among the neighbours, by ascending label.
+pub fn class_shares(labels: &[u8], weights: &[f64]) -> Vec<(u8, f64)> {
+    let weights = exact_match_weights(weights);
+    let mut totals: BTreeMap<u8, f64> = BTreeMap::new();
+    for (label, w) in labels.iter().zip(&weights) {
+        *totals.entry(*label).or_insert(0.0) += w;
+    }
+    let total: f64 = weights.iter().sum();
+    totals.into_iter().map(|(label, w)| (label, w / total)).collect()
+}
+
+// Share of the vote weights (between 0 and 1) of every label among the neighbours of a multi-label dataset, by ascending label:
+// each neighbour votes with its full weight for every one of its labels, so the shares need not sum to 1.
+pub fn label_set_shares(label_sets: &[Vec<u8>], weights: &[f64]) -> Vec<(u8, f64)> {
+    let weights = exact_match_weights(weights);
+    let mut totals: BTreeMap<u8, f64> = BTreeMap::new();
+    for (labels, w) in label_sets.iter().zip(&weights) {
+        for label in labels {
+            *totals.entry(*label).or_insert(0.0) += w;
+        }
+    }
+    let total: f64 = weights.iter().sum();
+    totals.into_iter().map(|(label, w)| (label, w / total)).collect()
+}
+
+// Exact matches (infinite weight) outweigh all other neighbours, so if there are any only they count (one vote each).
+fn exact_match_weights(weights: &[f64]) -> Vec<f64> {
+    if weights.iter().any(|w| w.is_infinite()) {
+        weights.iter().map(|w| if w.is_infinite() { 1.0 } else { 0.0 }).collect()
+    } else {
+        weights.to_vec()
+    }
+}
+
+// Share of the vote weights (between 0 and 1) that went to the winning label.
+pub fn vote_share(labels: &[u8], weights: &[f64], winner: u8) -> f64 {
+    class_shares(labels, weights).into_iter().find(|(label, _)| *label == winner).map_or(0.0, |(_, share)| share)
+}
+
+// Weighted average of the neighbours' values (the plain average when all weights are 1). Exact matches (infinite weight) outweigh
+// all other neighbours: if there are any, their values alone are averaged.
+pub fn weighted_average(values: &[f64], weights: &[f64]) -> f64 {
+    let exact: Vec<f64> = values.iter().zip(weights).filter(|(_, w)| w.is_infinite()).map(|(v, _)| *v).collect();
+    if !exact.is_empty() {
+        return exact.iter().sum::<f64>() / exact.len() as f64;
+    }
+    let weighted_sum: f64 = values.iter().zip(weights).map(|(v, w)| v * w).sum();
+    weighted_sum / weights.iter().sum::<f64>()
+}
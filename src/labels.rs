@@ -0,0 +1,49 @@
+use crate::*;
+
+// ------------------------------------------ NAMED CLASSES -----------------------------------------------------
+// Classes are stored as u8 labels; the label names of a dataset (see set_dataset_schema) map them to human-readable names,
+// label 0 to the first name and so on. Train points can be added with the name of their class: a name the dataset does not
+// know yet is given the next label. The predictions on the dataset return the name of the predicted class with the label.
+const MAX_LABELS: usize = 256; // a label is a u8
+
+impl Dataset {
+    // The prediction with the name of its class, if the dataset names it.
+    pub(crate) fn named(&self, ans: PredictionResult) -> PredictionResult {
+        PredictionResult { label_name: self.label_names.get(usize::from(ans.label)).cloned(), ..ans }
+    }
+}
+
+// Label of the class name among the names of a dataset, appending the name if it is new.
+fn label_of(label_names: &mut Vec<String>, name: &str) -> Result<u8, KnnError> {
+    let label = match label_names.iter().position(|known| known == name) {
+        Some(label) => label,
+        None if label_names.len() < MAX_LABELS => {
+            label_names.push(name.to_string());
+            label_names.len() - 1
+        }
+        None => return Err(KnnError::TooManyLabels { name: name.to_string() }),
+    };
+    Ok(label as u8)
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Same as add_training_point, with the name of the point's class instead of its label.
+    #[payable]
+    pub fn add_named_training_point(&mut self, dataset: String, point: Vec<f64>, label_name: String) {
+        self.assert_not_paused();
+        self.assert_can_write(&dataset);
+        let mut label_names = self.get_dataset(&dataset).map(|data| data.label_names).unwrap_or_default();
+        let label = label_of(&mut label_names, &label_name).unwrap_or_else(|e| e.panic());
+        self.append_training_rows(&dataset, TrainingRows { feature_names: Vec::new(), label_names: Some(label_names), points: vec![point], labels: vec![label], label_sets: None });
+    }
+
+    // Label of a class of the dataset given its name.
+    pub fn get_label(&self, dataset: String, label_name: String) -> Result<u8, KnnError> {
+        let data = self.get_dataset(&dataset)?;
+        match data.label_names.iter().position(|known| *known == label_name) {
+            Some(label) => Ok(label as u8),
+            None => Err(KnnError::UnknownLabel { dataset, label_name }),
+        }
+    }
+}
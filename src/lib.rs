@@ -1,14 +1,823 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize}; // imports involving serialization are used to bundle the code/storage so that it's ready for the blockchain.
-use near_sdk::{env, near_bindgen};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedMap, Vector};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize}; // JSON (de)serialization of arguments and return values
+use near_sdk::serde_json::json;
+use near_sdk::{env, ext_contract, is_promise_success, near_bindgen, AccountId, Balance, BorshStorageKey, Gas, Promise};
+use near_contract_standards::non_fungible_token::NonFungibleToken;
+
+mod access;
+mod analytics;
+mod anomaly;
+mod bounty;
+mod cache;
+mod categorical;
+mod centroid;
+mod chunked;
+mod classifier;
+mod commit_reveal;
+mod condense;
+mod cross_contract;
+mod errors;
+mod events;
+mod feedback;
+mod fixed;
+mod ft_payment;
+mod governance;
+mod integrity;
+mod kdtree;
+mod kmeans;
+mod labels;
+mod logging;
+mod logistic;
+mod knn_core;
+mod mahalanobis;
+mod metric;
+mod migrate;
+mod multilabel;
+mod naive_bayes;
+mod nft;
+mod offchain;
+mod oracle;
+mod privacy;
+mod quota;
+mod rewards;
+mod source_metadata;
+mod staking;
+mod storage;
+mod subscription;
+mod timelock;
+mod token_gate;
+mod upload;
+mod versioning;
+mod voting;
+pub use errors::KnnError;
+use events::{ImbalanceData, KnnEvent, PredictionData, TuneKData};
+use analytics::UsageCounters;
+use bounty::Bounty;
+use cache::CacheConfig;
+use centroid::ClassSums;
+use classifier::{Algorithm, Query};
+use commit_reveal::PredictionCommitment;
+use feedback::LiveAccuracy;
+use governance::{Council, Proposal};
+use integrity::check_unfrozen;
+use kdtree::KdNode;
+use kmeans::KMeans;
+use logging::LogLevel;
+use logistic::LogisticModel;
+use mahalanobis::Covariance;
+use metric::{MetricInput, PointMetric};
+use naive_bayes::ClassStats;
+use nft::Royalties;
+use offchain::OffchainDataset;
+use oracle::OracleRequest;
+use quota::{CallCount, CallQuota};
+use rewards::Contribution;
+use staking::{StakePolicy, StakedBatch};
+use knn_core::cmp_pairs;
+use storage::StorageAccount;
+use subscription::{Subscription, SubscriptionTier};
+use timelock::ScheduledAction;
+use token_gate::{AccessPass, TokenGate};
+use upload::{PendingUpload, TrainingRows};
+use versioning::is_snapshot;
+use voting::{DecisionRule, VotingRule};
+
+const GAS_FOR_WITHDRAW_CALLBACK: Gas = Gas(5_000_000_000_000); // 5 Tgas for on_fees_withdrawn
+const IMBALANCE_THRESHOLD: f64 = 0.9; // a dataset is imbalanced when one class has more than this share of the train points
+const MIN_ROWS_FOR_IMBALANCE: u64 = 10; // smaller datasets are not checked for imbalance
+
+// Callbacks of the contract to itself (results of the promises it creates).
+#[ext_contract(ext_self)]
+trait SelfCallbacks {
+    fn on_fees_withdrawn(&mut self, amount: U128);
+    fn on_rewards_claimed(&mut self, account_id: AccountId, amount: U128);
+    fn on_ft_fees_withdrawn(&mut self, token_id: AccountId, amount: U128);
+    fn on_access_verified(&mut self, account_id: AccountId, gate_id: u64) -> bool;
+    fn on_oracle_features(&mut self, request: OracleRequest) -> Result<PredictionResult, KnnError>;
+    fn process_chunk(&self, dataset: String, point: Vec<f64>, range: (u64, u64), k: u8) -> Vec<(u64, f64)>;
+    fn on_chunks_processed(&self, dataset: String, k: u8) -> Result<PredictionResult, KnnError>;
+}
 
 
 // ------------------------------------ VARIABLES OUTSIDE OF CONTRACT (NO STAKING) -----------------------------
 // Outside struct therefore won't be on the blockchain and thus won't require staking of NEAR tokens from developer's account
 // Toy data for cancer dataset, and for customer data set. Arrays with train data  10x2 and target classes 10x1 (i.e. class that data point belongs to)
-const TOY_CANCER_TRAIN: &'static [[f64; 2]; 10] = &[[1.4, 14.2], [7.3, 3.6], [15.8, 2.0], [7.0, 9.1], [13.9, 5.7], [16.6, 2.1], [18.1, 4.5], [8.1, 11.1], [11.9, 1.9], [12.8, 15.7]];
-const TOY_CANCER_TARGET: &'static [u8] = &[0, 1, 1, 1, 0, 0, 1, 0, 1, 0];
-const TOY_CUSTOMER_TRAIN: &'static [[f64; 2]; 10] = &[[11.4, 4.2], [17.3, 13.6], [5.8, 22.0], [7.0, 1.1], [13.9, 5.7], [16.6, 9.1], [8.1, 1.5], [1.1, 11.1], [2.9, 19.9], [22.8, 15.7]];
-const TOY_CUSTOMER_TARGET: &'static [u8] = &[1, 0, 0, 1, 1, 0, 1, 1, 1, 0];
+// These are only used to seed the 'cancer' and 'customer' datasets into contract storage upon initialisation (see seed_toy_datasets).
+const TOY_CANCER_TRAIN: &[[f64; 2]; 10] = &[[1.4, 14.2], [7.3, 3.6], [15.8, 2.0], [7.0, 9.1], [13.9, 5.7], [16.6, 2.1], [18.1, 4.5], [8.1, 11.1], [11.9, 1.9], [12.8, 15.7]];
+const TOY_CANCER_TARGET: &[u8] = &[0, 1, 1, 1, 0, 0, 1, 0, 1, 0];
+const TOY_CUSTOMER_TRAIN: &[[f64; 2]; 10] = &[[11.4, 4.2], [17.3, 13.6], [5.8, 22.0], [7.0, 1.1], [13.9, 5.7], [16.6, 9.1], [8.1, 1.5], [1.1, 11.1], [2.9, 19.9], [22.8, 15.7]];
+const TOY_CUSTOMER_TARGET: &[u8] = &[1, 0, 0, 1, 1, 0, 1, 1, 1, 0];
+
+// Keys under which the persistent collections are stored (each collection needs its own unique prefix).
+#[derive(BorshStorageKey, BorshSerialize)]
+enum StorageKey {
+    Datasets,
+    DatasetTrain { dataset_hash: Vec<u8> }, // hash of the dataset name keeps the prefix short and unique per dataset
+    DatasetTarget { dataset_hash: Vec<u8> },
+    DatasetValues { dataset_hash: Vec<u8> },
+    Predictions,
+    PredictionsByAccount,
+    AccountPredictions { account_hash: Vec<u8> },
+    NftOwners,
+    NftMetadata,
+    NftEnumeration,
+    NftApprovals,
+    Roles,
+    AccountDatasets,
+    AccountDatasetsInner { account_hash: Vec<u8> },
+    DatasetKdTree { dataset_hash: Vec<u8> },
+    GasStats,
+    AccountUsage,
+    StorageAccounts,
+    Uploads,
+    OffchainDatasets,
+    Commitments,
+    Contributions,
+    ContributedDatasets,
+    Rewards,
+    ClosedRewardPools,
+    TokenRoyalties,
+    FtPredictionFees,
+    FtFeesCollected,
+    Allowlist,
+    Denylist,
+    CallCounts,
+    SubscriptionTiers,
+    Subscriptions,
+    AccessPasses,
+    Outcomes,
+    LiveAccuracy,
+    Bounties,
+    StakedBatches,
+    Proposals,
+    ScheduledActions,
+    DatasetPredictions,
+    DailyPredictions,
+    ResultCache,
+    CacheSlots,
+    Snapshots,
+    DatasetLabelSets { dataset_hash: Vec<u8> },
+}
+
+// A dataset stored on the blockchain: train points (n x n_features) and their target classes (n x 1).
+// A regression dataset stores numeric target values (n x 1) instead of target classes.
+// Points are kept in a Vector so new ones can be appended without re-writing the whole dataset.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Dataset {
+    n_features: u32, // schema of the dataset: number of features (columns) every point must have
+    metric: Option<DistanceMetric>, // distance metric to use for this dataset (None: use the contract's metric)
+    train: Vector<Vec<f64>>,
+    target: Vector<u8>,
+    label_sets: Vector<Vec<u8>>, // all the classes of every train point of a multi-label dataset (empty otherwise), see multilabel.rs
+    values: Vector<f64>, // numeric targets (regression datasets only)
+    creator: AccountId, // account that added the first point, may remove the dataset
+    created_at: u64, // block timestamp (nanoseconds) of the creation
+    feature_names: Vec<String>, // names of the features (columns), empty if not described
+    label_names: Vec<String>, // names of the classes 0, 1, ..., empty if not described
+    scaling: Scaling, // transform applied to the features before distances are computed
+    stats: FeatureStats, // per feature statistics of the train points, kept up to date as points are added
+    feature_weights: Option<Vec<f64>>, // importance of each feature in the distances (None: all features count equally)
+    categorical_features: Vec<u32>, // features compared as categories in the distances, ascending (see categorical.rs)
+    covariance: Option<Covariance>, // covariance of the train points for the Mahalanobis metric, see mahalanobis.rs (None: not kept)
+    k: Option<u8>, // number of nearest neighbours for this dataset, e.g. found by tune_k (None: use the contract's k)
+    kd_tree: Option<Vector<KdNode>>, // spatial index of the train points, see kdtree.rs (None: predictions scan all the train points)
+    class_counts: BTreeMap<u8, u64>, // number of train points of every class, kept up to date as points are added
+    class_weighted: bool, // whether each vote is divided by the number of train points of its class, see set_class_weighting
+    decision_rule: Option<DecisionRule>, // how the vote of the neighbours decides the class, see voting.rs (None: Weighted)
+    models: Vec<Algorithm>, // models registered for the dataset besides KNN, see classifier.rs
+    default_model: Option<Algorithm>, // registered model of the predictions on the dataset (None: KNN)
+    class_sums: Option<ClassSums>, // sums of the train points of every class for the nearest centroid model, see centroid.rs (None: not registered)
+    class_stats: Option<ClassStats>, // feature statistics of every class for the naive Bayes model, see naive_bayes.rs (None: not registered)
+    logistic: Option<LogisticModel>, // weights of the logistic regression, trained by train_epoch, see logistic.rs (None: not registered)
+    kmeans: Option<KMeans>, // clusters of the train points, see kmeans.rs (None: not clustered)
+    range_tolerance: Option<f64>, // test points further outside the range of the train points are rejected, see set_range_check (None: not checked)
+    version: u64, // bumped by every change of the rows, see versioning.rs
+    snapshots: Vec<u64>, // versions with a snapshot, oldest first
+    frozen: bool, // whether rows can no longer be added or removed, see integrity.rs
+    content_hash: Vec<u8>, // sha256 chain of the rows, see integrity.rs
+    storage_bytes: u64, // storage used by the points, targets and index of the dataset (as measured when they were stored)
+    contributed_rows: u64, // train points added by accounts that are rewarded for them (see rewards.rs)
+    reward_per_row: u128, // contributors' share of the prediction fees earned per train point since the creation (scaled, see rewards.rs)
+}
+
+// How the features are rescaled so that features with a large range do not dominate the distances.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Scaling {
+    None, // raw features
+    MinMax, // (x - min) / (max - min): every feature of the train points within [0, 1]
+    ZScore, // (x - mean) / standard deviation: every feature of the train points with mean 0 and variance 1
+}
+
+// Minimum, maximum, sum and sum of squares of each feature over the train points: the scaling parameters derive from these.
+#[derive(BorshDeserialize, BorshSerialize, Clone, PartialEq, Debug)]
+pub struct FeatureStats {
+    min: Vec<f64>,
+    max: Vec<f64>,
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+}
+
+impl FeatureStats {
+    fn new(n_features: usize) -> Self {
+        Self {
+            min: vec![f64::INFINITY; n_features],
+            max: vec![f64::NEG_INFINITY; n_features],
+            sum: vec![0.0; n_features],
+            sum_sq: vec![0.0; n_features],
+        }
+    }
+
+    fn add(&mut self, point: &[f64]) {
+        for (ii, x) in point.iter().enumerate() {
+            self.min[ii] = self.min[ii].min(*x);
+            self.max[ii] = self.max[ii].max(*x);
+            self.sum[ii] += x;
+            self.sum_sq[ii] += x * x;
+        }
+    }
+
+    // (offset, factor) of each feature for the given scaling of n points: x is transformed to (x - offset) * factor, factor = 1 / scale.
+    // A feature without spread (e.g. a single point) keeps a scale of 1, so it does not divide by 0.
+    fn params(&self, scaling: Scaling, n: u64) -> Vec<(f64, f64)> {
+        let n = n as f64;
+        (0..self.sum.len())
+            .map(|ii| {
+                let (offset, scale) = match scaling {
+                    Scaling::None => (0.0, 1.0),
+                    Scaling::MinMax => (self.min[ii], self.max[ii] - self.min[ii]),
+                    Scaling::ZScore => {
+                        let mean = self.sum[ii] / n;
+                        (mean, (self.sum_sq[ii] / n - mean * mean).max(0.0).sqrt()) // population standard deviation
+                    }
+                };
+                (offset, if scale > 0.0 { 1.0 / scale } else { 1.0 })
+            })
+            .collect()
+    }
+}
+
+impl Dataset {
+    fn new(name: &str, n_features: u32, creator: AccountId) -> Self {
+        Self::with_hash(env::sha256(name.as_bytes()), n_features, creator)
+    }
+
+    // Dataset whose collections are stored under prefixes derived from dataset_hash (the hash of its name for Dataset::new).
+    fn with_hash(dataset_hash: Vec<u8>, n_features: u32, creator: AccountId) -> Self {
+        Self {
+            creator,
+            created_at: env::block_timestamp(),
+            feature_names: Vec::new(),
+            label_names: Vec::new(),
+            scaling: Scaling::None,
+            stats: FeatureStats::new(n_features as usize),
+            feature_weights: None,
+            categorical_features: Vec::new(),
+            covariance: None,
+            k: None,
+            kd_tree: None,
+            class_counts: BTreeMap::new(),
+            class_weighted: false,
+            decision_rule: None,
+            models: Vec::new(),
+            default_model: None,
+            class_sums: None,
+            class_stats: None,
+            logistic: None,
+            kmeans: None,
+            range_tolerance: None,
+            version: 0,
+            snapshots: Vec::new(),
+            frozen: false,
+            content_hash: env::sha256(&[]), // of no rows
+            storage_bytes: 0,
+            contributed_rows: 0,
+            reward_per_row: 0,
+            n_features,
+            metric: None,
+            train: Vector::new(StorageKey::DatasetTrain { dataset_hash: dataset_hash.clone() }),
+            target: Vector::new(StorageKey::DatasetTarget { dataset_hash: dataset_hash.clone() }),
+            label_sets: Vector::new(StorageKey::DatasetLabelSets { dataset_hash: dataset_hash.clone() }),
+            values: Vector::new(StorageKey::DatasetValues { dataset_hash }),
+        }
+    }
+
+    // Appends a train point (its target is pushed separately), updates the feature statistics, the covariance sums and the
+    // spatial index if any.
+    fn push_point(&mut self, point: &[f64]) {
+        self.train.push(&point.to_vec());
+        self.stats.add(point);
+        if let Some(covariance) = &mut self.covariance {
+            covariance.add(point);
+        }
+        self.insert_into_index(self.train.len() - 1, point);
+    }
+
+    // Appends the class of a train point (pushed with push_point) and counts it.
+    fn push_label(&mut self, label: u8) {
+        if !self.label_sets.is_empty() { // multi-label dataset
+            self.label_sets.push(&vec![label]);
+        }
+        self.target.push(&label);
+        *self.class_counts.entry(label).or_insert(0) += 1;
+    }
+
+    // Removes the rows start..start + n (points and targets), recomputing the feature statistics, class counts and covariance if
+    // any, and rebuilding the spatial index if any. The rows after them move up by n.
+    fn remove_rows(&mut self, start: u64, n: u64) {
+        self.version += 1;
+        remove_range(&mut self.train, start, n);
+        remove_range(&mut self.target, start, n);
+        remove_range(&mut self.label_sets, start, n);
+        remove_range(&mut self.values, start, n);
+        let points = self.train.to_vec();
+        self.stats = FeatureStats::new(self.n_features as usize);
+        for point in &points {
+            self.stats.add(point);
+        }
+        self.class_counts.clear();
+        for label in self.target.iter() {
+            *self.class_counts.entry(label).or_insert(0) += 1;
+        }
+        if self.covariance.is_some() {
+            self.track_covariance();
+        }
+        self.class_sums = None; // summed again from the remaining rows
+        self.class_stats = None;
+        self.refit_models();
+        if let Some(tree) = &mut self.kd_tree {
+            tree.clear();
+            for node in kdtree::build(&points) {
+                tree.push(&node);
+            }
+        }
+        self.content_hash = self.compute_content_hash();
+    }
+
+    // Majority class and its share of the train points if the dataset is imbalanced (more than IMBALANCE_THRESHOLD of the points
+    // in one class, for datasets of at least MIN_ROWS_FOR_IMBALANCE points).
+    fn dominant_class(&self) -> Option<(u8, f64)> {
+        let n = self.target.len();
+        let (label, count) = self.class_counts.iter().max_by_key(|(_, count)| **count)?;
+        let share = *count as f64 / n as f64;
+        if n >= MIN_ROWS_FOR_IMBALANCE && share > IMBALANCE_THRESHOLD { Some((*label, share)) } else { None }
+    }
+
+    // Checks every feature of the test point lies within the range of the train points, widened by range_tolerance times the range
+    // on both sides (not checked if range_tolerance is None).
+    fn check_range(&self, point: &[f64]) -> Result<(), KnnError> {
+        let tolerance = match self.range_tolerance {
+            Some(tolerance) => tolerance,
+            None => return Ok(()),
+        };
+        for (feature, value) in point.iter().enumerate() {
+            let (min, max) = (self.stats.min[feature], self.stats.max[feature]);
+            let margin = tolerance * (max - min);
+            if *value < min - margin || *value > max + margin {
+                return Err(KnnError::OutOfRange { feature: feature as u32, value: *value, min, max });
+            }
+        }
+        Ok(())
+    }
+
+    // Class counts the votes are divided by (None: votes are not class weighted).
+    fn vote_class_counts(&self) -> Option<&BTreeMap<u8, u64>> {
+        if self.class_weighted { Some(&self.class_counts) } else { None }
+    }
+
+    // Voting rule of the predictions on the dataset.
+    fn voting_rule(&self) -> Box<dyn VotingRule> {
+        self.decision_rule.unwrap_or(DecisionRule::Weighted).rule()
+    }
+
+    // (offset, factor) of each feature: x is transformed to (x - offset) * factor before distances are computed with the metric.
+    // The factor combines the scaling and the feature weights (None: features are used as they are), unless the metric takes
+    // the features scaled by their range (Gower) or raw (e.g. Haversine, as scaled degrees are no longer angles).
+    fn feature_transform(&self, metric: &PointMetric) -> Option<Vec<(f64, f64)>> {
+        let scaling = match metric.input() {
+            MetricInput::Scaled => self.scaling,
+            MetricInput::RangeScaled => Scaling::MinMax,
+            MetricInput::Raw => return None,
+        };
+        if scaling == Scaling::None && self.feature_weights.is_none() {
+            return None;
+        }
+        let mut params = self.stats.params(scaling, self.train.len());
+        if let Some(weights) = &self.feature_weights {
+            for ((_, factor), w) in params.iter_mut().zip(weights) {
+                *factor *= metric.weight_factor(*w);
+            }
+        }
+        Some(params)
+    }
+
+    // Applies the dataset's scaling and feature weights to a point.
+    fn transform(&self, point: &[f64], metric: &PointMetric) -> Vec<f64> {
+        apply_transform(point, &self.feature_transform(metric))
+    }
+
+    // Train points as the distances are computed on, i.e. scaled and weighted.
+    fn transformed_train(&self, metric: &PointMetric) -> Vec<Vec<f64>> {
+        let params = self.feature_transform(metric);
+        match params {
+            Some(_) => self.train.iter().map(|point| apply_transform(&point, &params)).collect(),
+            None => self.train.to_vec(),
+        }
+    }
+}
+
+// Removes the elements start..start + n of a vector (if it has them), moving up the elements after them.
+fn remove_range<T: BorshSerialize + BorshDeserialize>(vector: &mut Vector<T>, start: u64, n: u64) {
+    let tail: Vec<T> = (start.saturating_add(n)..vector.len()).filter_map(|ii| vector.get(ii)).collect();
+    while vector.len() > start {
+        vector.pop();
+    }
+    vector.extend(tail);
+}
+
+// Transforms a point to (x - offset) * factor feature by feature, see Dataset::feature_transform (None: the point is unchanged).
+fn apply_transform(point: &[f64], params: &Option<Vec<(f64, f64)>>) -> Vec<f64> {
+    match params {
+        Some(params) => point.iter().zip(params).map(|(x, (offset, factor))| (x - offset) * factor).collect(),
+        None => point.to_vec(),
+    }
+}
+
+// How the distance between a train point and the test point is measured (by the Metric it is built into, see metric.rs).
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum DistanceMetric {
+    Euclidean, // L2 norm: sqrt(sum((a - b)^2))
+    Manhattan, // L1 norm: sum(|a - b|)
+    Chebyshev, // L-infinity norm: max(|a - b|)
+    Minkowski(f64), // Lp norm: (sum(|a - b|^p))^(1/p), p must be >= 1
+    Hamming, // number of features in which the points differ, every feature being compared as a category (see categorical.rs)
+    Gower, // mean(|a - b|) of the features scaled by their range, categorical features differing by 0 or 1 (see categorical.rs)
+    Haversine, // great-circle distance in km between (latitude, longitude) points in degrees, for datasets of these 2 features
+    Cosine, // 1 - cos(angle between a and b), from 0 (same direction) to 2 (opposite directions): compares directions, not norms
+    Mahalanobis, // sqrt((a - b)^T S^-1 (a - b)), S the covariance of the train points, for datasets keeping it (see mahalanobis.rs)
+}
+
+impl DistanceMetric {
+    // Checks the metric is a valid distance (Minkowski is only a metric for p >= 1).
+    fn validate(&self) -> Result<(), KnnError> {
+        match self {
+            DistanceMetric::Minkowski(p) if *p < 1.0 => Err(KnnError::InvalidMetric { p: *p }),
+            _ => Ok(()),
+        }
+    }
+
+    // Checks the metric applies to points of n_features features (Haversine to latitude and longitude only).
+    fn check_dimension(&self, n_features: u32) -> Result<(), KnnError> {
+        match self {
+            DistanceMetric::Haversine if n_features != 2 => Err(KnnError::MetricDimension { expected: 2, actual: n_features }),
+            _ => Ok(()),
+        }
+    }
+}
+
+// How the k nearest neighbours vote for the class of the test point.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VotingScheme {
+    Uniform, // every neighbour has one vote
+    InverseDistance, // each neighbour's vote is weighted by 1/d
+    InverseSquaredDistance, // each neighbour's vote is weighted by 1/d^2
+}
+
+impl VotingScheme {
+    // Weight of a neighbour's vote given its distance d to the test point (d = 0 gives an infinite weight, i.e. an exact match decides the class).
+    fn weight(&self, d: f64) -> f64 {
+        match self {
+            VotingScheme::Uniform => 1.0,
+            VotingScheme::InverseDistance => 1.0 / d,
+            VotingScheme::InverseSquaredDistance => 1.0 / d.powi(2),
+        }
+    }
+}
+
+// What decides the class when several classes share the top vote of the k nearest neighbours (possible with 3+ classes or weighted votes).
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum TieBreak {
+    NearestNeighbor, // class of the nearest neighbour among the tied classes
+    LowestLabel, // lowest of the tied labels
+    Random, // one of the tied classes at random (seeded from the block's random seed)
+    Abstain, // no class is predicted, the call returns a TiedVote error
+}
+
+// Arithmetic the distances are computed with.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum NumericMode {
+    Float, // f64
+    FixedPoint, // integers in units of 1e-6, see fixed.rs
+}
+
+impl NumericMode {
+    // Difference of 2 coordinates along an axis, a lower bound of the distance between points with these coordinates (used to prune the k-d tree).
+    fn gap(&self, a: f64, b: f64) -> f64 {
+        match self {
+            NumericMode::Float => (a - b).abs(),
+            NumericMode::FixedPoint => fixed::gap(a, b),
+        }
+    }
+}
+
+// Permissions an account can be granted on top of what the public can do. The owner has all of them.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Role {
+    Admin, // grants and revokes roles, and provides data
+    DataProvider, // adds training data
+    Predictor, // makes predictions (as does the public)
+}
+
+// Description of a dataset (its schema and origin), as returned by get_dataset_meta and list_datasets.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DatasetMeta {
+    pub name: String,
+    pub n_features: u32,
+    pub feature_names: Vec<String>,
+    pub label_names: Vec<String>,
+    pub creator: AccountId,
+    pub created_at: U64,
+    pub n_rows: u64, // number of train points
+    pub metric: Option<DistanceMetric>, // default metric of the predictions on the dataset (see set_dataset_metric)
+    pub scaling: Scaling,
+    pub feature_weights: Option<Vec<f64>>,
+    pub categorical_features: Vec<u32>, // features compared as categories (see set_categorical_features)
+    pub k: Option<u8>,
+    pub indexed: bool, // whether predictions search a k-d tree (see build_index)
+    pub storage_bytes: u64, // storage used by the dataset, counted against the storage limits
+    pub version: u64, // bumped by every change of the rows
+    pub snapshots: Vec<u64>, // versions that can be used as "name@version"
+    pub frozen: bool, // whether rows can no longer be added or removed (see freeze_dataset)
+    pub content_hash: Base64VecU8, // sha256 chain of the rows (see verify_dataset)
+}
+
+impl DatasetMeta {
+    fn from_dataset(name: &str, dataset: &Dataset) -> Self {
+        Self {
+            name: name.to_string(),
+            n_features: dataset.n_features,
+            feature_names: dataset.feature_names.clone(),
+            label_names: dataset.label_names.clone(),
+            creator: dataset.creator.clone(),
+            created_at: U64(dataset.created_at),
+            n_rows: dataset.train.len(),
+            metric: dataset.metric,
+            scaling: dataset.scaling,
+            feature_weights: dataset.feature_weights.clone(),
+            categorical_features: dataset.categorical_features.clone(),
+            k: dataset.k,
+            indexed: dataset.kd_tree.is_some(),
+            storage_bytes: dataset.storage_bytes,
+            version: dataset.version,
+            snapshots: dataset.snapshots.clone(),
+            frozen: dataset.frozen,
+            content_hash: Base64VecU8(dataset.content_hash.clone()),
+        }
+    }
+}
+
+// Quality of the predictions on a labelled test set (see evaluate).
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EvaluationMetrics {
+    pub accuracy: f64, // share of test points classified correctly
+    pub per_class: Vec<ClassMetrics>, // for every class among the test labels and predictions, by ascending label
+}
+
+// Precision, recall and F1 score of one class (0 where undefined, e.g. precision of a class never predicted).
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClassMetrics {
+    pub label: u8,
+    pub precision: f64, // share of the points predicted as the class that belong to it
+    pub recall: f64, // share of the points of the class predicted as such
+    pub f1: f64, // harmonic mean of precision and recall
+    pub support: u32, // number of test points of the class
+}
+
+// Statistics of a dataset computed on chain (see describe_dataset), to sanity-check uploaded data and pick a scaling.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DatasetDescription {
+    pub name: String,
+    pub n_rows: u64,
+    pub features: Vec<FeatureSummary>, // one per feature, in the order of the columns
+    pub class_counts: Vec<(u8, u64)>, // number of train points of every class, by ascending label (empty for regression datasets)
+}
+
+// Statistics of one feature over the train points.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeatureSummary {
+    pub name: Option<String>, // from the dataset's schema (None if not described)
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std: f64, // population standard deviation, as used by the ZScore scaling
+}
+
+impl DatasetDescription {
+    fn from_dataset(name: &str, dataset: &Dataset) -> Self {
+        let n = dataset.train.len() as f64;
+        let stats = &dataset.stats;
+        let features = (0..dataset.n_features as usize)
+            .map(|ii| {
+                let mean = stats.sum[ii] / n;
+                FeatureSummary {
+                    name: dataset.feature_names.get(ii).cloned(),
+                    min: stats.min[ii],
+                    max: stats.max[ii],
+                    mean,
+                    std: (stats.sum_sq[ii] / n - mean * mean).max(0.0).sqrt(),
+                }
+            })
+            .collect();
+        let class_counts = dataset.class_counts.iter().map(|(label, count)| (*label, *count)).collect();
+        Self { name: name.to_string(), n_rows: dataset.train.len(), features, class_counts }
+    }
+}
+
+// Number of train points of a class and their share of the dataset, as returned by class_distribution.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClassShare {
+    pub label: u8,
+    pub count: u64,
+    pub share: f64, // between 0 and 1
+}
+
+// Outcome of a k-fold cross-validation: accuracy (between 0 and 1) of each fold and their mean.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct KFoldResult {
+    pub fold_accuracies: Vec<f64>,
+    pub mean_accuracy: f64,
+}
+
+// Outcome of a classification: the predicted class, how strong the vote was and which train points drove it.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PredictionResult {
+    pub label: u8, // predicted class
+    pub label_name: Option<String>, // its name, if the dataset names its classes
+    pub confidence: f64, // share of the (weighted) votes that went to the predicted class, between 0 and 1
+    pub neighbor_indices: Vec<u64>, // indices of the k nearest train points in the dataset (nearest first)
+    pub neighbor_distances: Vec<f64>, // their distances to the test point
+}
+
+// A train point near a test point, as returned by nearest_neighbors.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Neighbor {
+    pub index: u64, // index of the train point in the dataset
+    pub point: Vec<f64>, // its coordinates as stored (before scaling)
+    pub label: u8, // its class
+    pub distance: f64, // its distance to the test point
+}
+
+// Gas used by the stages of the latest run_analysis prediction, for operators to size datasets and k against the gas limits.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasReport {
+    pub dataset: String,
+    pub distance_gas: U64, // computing the distances to the train points (with a k-d tree: the whole neighbour search)
+    pub sort_gas: U64, // selecting the k nearest
+    pub vote_gas: U64, // voting for the class
+    pub total_gas: U64, // gas used by the call up to the prediction, including loading the dataset
+}
+
+// Gas used by the run_analysis predictions on a dataset.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasStats {
+    pub n_predictions: u64,
+    pub total_gas: U64,
+    pub average_gas: U64,
+}
+
+// Maximum size of the datasets, set by the owner so that uploads cannot bloat the contract's storage or grow a dataset beyond
+// what a prediction can scan within the gas limits (None: no limit). Account limits cover all the datasets created by an account.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageLimits {
+    pub max_rows_per_dataset: Option<u64>,
+    pub max_bytes_per_dataset: Option<u64>,
+    pub max_rows_per_account: Option<u64>,
+    pub max_bytes_per_account: Option<u64>,
+}
+
+// Train points and storage bytes of the datasets created by an account (the datasets it is refunded for when they are removed).
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountUsage {
+    pub rows: u64,
+    pub bytes: u64,
+}
+
+// Gas of the stages of a classification, see GasReport.
+#[derive(Default)]
+struct StageGas {
+    distance: u64,
+    sort: u64,
+    vote: u64,
+}
+
+// A prediction made with run_analysis, kept on the blockchain for auditability.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PredictionRecord {
+    pub id: u64, // index in the prediction history
+    pub caller: AccountId,
+    pub dataset: String,
+    pub test_point: Vec<f64>,
+    pub result: PredictionResult,
+    pub timestamp: U64, // block timestamp in nanoseconds (U64 serializes as a string, as JSON numbers can't hold it exactly)
+    pub block_height: U64,
+}
+
+// Algo requirement: k must be a positive odd number between 1 and 15.
+fn validate_k(k: u8) -> Result<(), KnnError> {
+    if (k % 2 == 1) & (k <= 15) {
+        Ok(())
+    } else {
+        Err(KnnError::InvalidK { k })
+    }
+}
+
+// The confidence threshold must be between 0 and 1.
+fn validate_threshold(threshold: f64) -> Result<(), KnnError> {
+    if (0.0..=1.0).contains(&threshold) {
+        Ok(())
+    } else {
+        Err(KnnError::InvalidThreshold { threshold })
+    }
+}
+
+// A point must have as many features as the dataset's schema.
+fn check_dimension(dataset: &Dataset, point: &[f64]) -> Result<(), KnnError> {
+    if point.len() == dataset.n_features as usize {
+        Ok(())
+    } else {
+        Err(KnnError::DimensionMismatch { expected: dataset.n_features, actual: point.len() as u32 })
+    }
+}
+
+// Features must be finite numbers: a NaN or infinite feature makes the distances meaningless.
+fn check_finite(point: &[f64]) -> Result<(), KnnError> {
+    match point.iter().position(|x| !x.is_finite()) {
+        Some(feature) => Err(KnnError::NonFiniteFeature { feature: feature as u32 }),
+        None => Ok(()),
+    }
+}
+
+// Random number from the first 8 bytes of the block's random seed (the same for every call in the block).
+fn block_random() -> u64 {
+    env::random_seed().iter().take(8).fold(0, |acc, byte| (acc << 8) | u64::from(*byte))
+}
+
+// Next number of the splitmix64 pseudo-random generator (deterministic for a given initial state).
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Shuffles the items in place (Fisher-Yates), deterministically for a given seed.
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    for ii in (1..items.len()).rev() {
+        let jj = (splitmix64(&mut state) % (ii as u64 + 1)) as usize;
+        items.swap(ii, jj);
+    }
+}
+
+// Datasets named "account/name" (e.g. "alice.testnet/churn") live in the namespace of the account.
+// Returns the account and the name of the dataset within the namespace, None for the shared datasets (e.g. "cancer").
+fn split_namespace(name: &str) -> Option<(AccountId, &str)> {
+    let (account, local_name) = name.split_once('/')?;
+    let account_id: AccountId = account.parse().ok()?;
+    Some((account_id, local_name))
+}
+
+// Descriptions of at most limit datasets of a map starting at from_index (namespace: prefix of the names, e.g. "alice.testnet/").
+fn dataset_page(datasets: &UnorderedMap<String, Dataset>, namespace: &str, from_index: u64, limit: u64) -> Vec<DatasetMeta> {
+    let (names, values) = (datasets.keys_as_vector(), datasets.values_as_vector());
+    (from_index..std::cmp::min(from_index.saturating_add(limit), names.len()))
+        .filter_map(|ii| Some(DatasetMeta::from_dataset(&format!("{}{}", namespace, names.get(ii)?), &values.get(ii)?)))
+        .collect()
+}
 
 //When writing smart contracts, the pattern is to have a struct with an associated impl where you write the core logic into functions.
 // ------------------------------------------ CONTRACT STATE --------------------------------------------------
@@ -16,17 +825,157 @@ const TOY_CUSTOMER_TARGET: &'static [u8] = &[1, 0, 0, 1, 1, 0, 1, 1, 1, 0];
 #[derive(BorshDeserialize, BorshSerialize)] // deleted Default since have 'default constructor' below
 pub struct KnnMachineLearning { // name of my Contract K Nearest Neighbours Classification Algorithm
     param_k: u8, // number of nearest neighbours (MUST BE odd value between 1 and 15) 
-    // u8 is suitable since it takes unsigned values (0,255) and k has at the lowest value 1, and at the highest approx 15.
+    // u8 is suitable since it takes unsigned values (0,255) and k has the lowest value 1, and at the highest approx 15.
+    datasets: UnorderedMap<String, Dataset>, // dataset name -> dataset (stored on the blockchain, hence requires staking)
+    voting_scheme: VotingScheme, // flat (uniform) or distance weighted votes of the k nearest neighbours
+    tie_break: TieBreak, // how a tied vote is decided
+    numeric_mode: NumericMode, // f64 or fixed-point distances
+    confidence_threshold: f64, // run_analysis abstains when the winning class has a smaller share of the votes (0: never abstains)
+    distance_metric: DistanceMetric, // metric used unless the dataset or the call specifies another one
+    predictions: Vector<PredictionRecord>, // history of all predictions made with run_analysis
+    predictions_by_account: LookupMap<AccountId, Vector<u64>>, // caller -> ids of their predictions
+    tokens: NonFungibleToken, // NFTs of predictions (NEP-171), see nft.rs
+    prediction_fee: Balance, // yoctoNEAR to attach to every run_analysis call (0: free)
+    fees_collected: Balance, // prediction fees accumulated by the contract and not withdrawn yet (the treasury)
+    owner_id: AccountId, // account allowed to configure the contract and withdraw fees
+    proposed_owner: Option<AccountId>, // new owner proposed by the owner, until they accept
+    roles: LookupMap<AccountId, Role>, // trusted accounts and what they may do
+    account_datasets: LookupMap<AccountId, UnorderedMap<String, Dataset>>, // datasets in the namespace of an account ("alice.testnet/churn")
+    last_gas_report: Option<GasReport>, // gas used by the latest run_analysis prediction
+    gas_stats: LookupMap<String, GasStats>, // dataset name -> gas used by its run_analysis predictions
+    storage_limits: StorageLimits, // maximum size of the datasets, per dataset and per account
+    account_usage: LookupMap<AccountId, AccountUsage>, // creator -> size of its datasets (the toy datasets are not counted)
+    storage_accounts: LookupMap<AccountId, StorageAccount>, // storage balances pre-paid by data contributors (NEP-145), see storage.rs
+    paused: bool, // circuit breaker: while paused, predictions, uploads, dataset changes and storage deposits are rejected
+    uploads: LookupMap<AccountId, PendingUpload>, // uploader -> dataset being uploaded in chunks, see upload.rs
+    offchain_datasets: LookupMap<String, OffchainDataset>, // dataset name -> commitment to rows stored off chain, see offchain.rs
+    commitments: LookupMap<AccountId, PredictionCommitment>, // caller -> prediction request committed to and not revealed yet, see commit_reveal.rs
+    privacy_epsilon: Option<f64>, // privacy budget of the noise added to vote fractions and class counts (None: no noise), see privacy.rs
+    reward_share_bps: u16, // share of every prediction fee credited to the contributors of the dataset (basis points), see rewards.rs
+    contributions: LookupMap<(String, AccountId), Contribution>, // (dataset name, contributor) -> rows contributed
+    contributed_datasets: LookupMap<AccountId, Vec<String>>, // contributor -> datasets it has rows (and rewards) in
+    rewards: LookupMap<AccountId, Balance>, // contributor -> settled rewards not claimed yet (yoctoNEAR)
+    closed_reward_pools: LookupMap<(String, u64), u128>, // (name, created_at) of a removed dataset -> its final reward per row
+    royalties: Royalties, // shares of the resales of prediction NFTs minted from now on, see nft.rs
+    token_royalties: LookupMap<String, BTreeMap<AccountId, u16>>, // token id -> shares of its resales (basis points) by account
+    ft_prediction_fees: LookupMap<AccountId, Balance>, // fungible token accepted for the prediction fees -> fee in its units, see ft_payment.rs
+    ft_fees_collected: LookupMap<AccountId, Balance>, // fungible token -> fees collected in it and not withdrawn yet
+    allowlist_enabled: bool, // only the accounts on the allowlist are served, see access.rs
+    allowlist: LookupSet<AccountId>,
+    denylist: LookupSet<AccountId>, // banned accounts
+    call_quota: Option<CallQuota>, // maximum predictions per account and period, see quota.rs
+    call_counts: LookupMap<AccountId, CallCount>, // account -> its predictions in the current period
+    subscription_tiers: UnorderedMap<String, SubscriptionTier>, // tiers offered by the owner by name, see subscription.rs
+    subscriptions: LookupMap<AccountId, Subscription>,
+    token_gate: Option<TokenGate>, // token the predictions are gated on, see token_gate.rs
+    gate_id: u64, // incremented when the gate changes, which voids the passes
+    access_passes: LookupMap<AccountId, AccessPass>, // account -> its cached verification
+    outcomes: LookupMap<u64, u8>, // prediction id -> true class reported, see feedback.rs
+    live_accuracy: LookupMap<String, LiveAccuracy>, // dataset -> accuracy over the reported outcomes
+    bounties: LookupMap<u64, Bounty>, // open labeling bounties by id, see bounty.rs
+    next_bounty_id: u64,
+    stake_policy: Option<StakePolicy>, // stake required on the batches of rows, see staking.rs
+    staked_batches: UnorderedMap<u64, StakedBatch>, // batches whose stake is not released yet, by id
+    next_batch_id: u64,
+    council: Option<Council>, // members whose proposals make the sensitive changes, see governance.rs
+    proposals: LookupMap<u64, Proposal>,
+    next_proposal_id: u64,
+    timelock_blocks: u64, // delay of the sensitive changes (0: none), see timelock.rs
+    scheduled_actions: UnorderedMap<u64, ScheduledAction>, // actions waiting for their delay, by id
+    next_action_id: u64,
+    usage_counters: UsageCounters, // see analytics.rs
+    dataset_predictions: UnorderedMap<String, u64>, // dataset -> predictions made with it
+    daily_predictions: LookupMap<u64, u64>, // day (since 1970-01-01) -> predictions made that day
+    cache_config: Option<CacheConfig>, // see cache.rs (None: results are not cached)
+    result_cache: LookupMap<Vec<u8>, PredictionResult>, // hash of the query -> its result
+    cache_slots: Vector<Vec<u8>>, // hashes of the cached queries, a ring of at most `capacity` slots
+    cache_next_slot: u64, // slot of the oldest result once the ring is full
+    log_level: LogLevel, // least severe log lines logged, see logging.rs
+    snapshots: LookupMap<String, Dataset>, // "name@version" -> immutable copy of the dataset, see versioning.rs
 }
 
 // ------------------------------------------ CONTRACT METHODS -------------------------------------------------
 // 'Default constructor'. Allows to instantiate the struct by giving only the non-default values: let p = KnnMachineLearning {var: 10, ..Default::default()};
 impl Default for KnnMachineLearning {
     fn default() -> KnnMachineLearning {
-        KnnMachineLearning {
+        let mut contract = KnnMachineLearning {
             param_k: 5, // typical k value is 3, 5, or 7. Therefore making a default popular choice of k=5.
             // Here staking will be required as the information is stored on the blockchain.
-        }
+            datasets: UnorderedMap::new(StorageKey::Datasets),
+            voting_scheme: VotingScheme::Uniform, // classic KNN: each neighbour has one vote
+            tie_break: TieBreak::LowestLabel,
+            numeric_mode: NumericMode::Float,
+            confidence_threshold: 0.0,
+            distance_metric: DistanceMetric::Euclidean,
+            predictions: Vector::new(StorageKey::Predictions),
+            predictions_by_account: LookupMap::new(StorageKey::PredictionsByAccount),
+            tokens: NonFungibleToken::new(
+                StorageKey::NftOwners,
+                env::current_account_id(), // owner of the NFT contract
+                Some(StorageKey::NftMetadata),
+                Some(StorageKey::NftEnumeration),
+                Some(StorageKey::NftApprovals), // the enumeration views of near-contract-standards read the approvals of each token
+            ),
+            prediction_fee: 0, // free until the owner sets a fee
+            fees_collected: 0,
+            owner_id: env::current_account_id(),
+            proposed_owner: None,
+            roles: LookupMap::new(StorageKey::Roles),
+            account_datasets: LookupMap::new(StorageKey::AccountDatasets),
+            last_gas_report: None,
+            gas_stats: LookupMap::new(StorageKey::GasStats),
+            storage_limits: StorageLimits::default(), // unlimited until the owner sets limits
+            account_usage: LookupMap::new(StorageKey::AccountUsage),
+            storage_accounts: LookupMap::new(StorageKey::StorageAccounts),
+            paused: false,
+            uploads: LookupMap::new(StorageKey::Uploads),
+            offchain_datasets: LookupMap::new(StorageKey::OffchainDatasets),
+            commitments: LookupMap::new(StorageKey::Commitments),
+            privacy_epsilon: None,
+            reward_share_bps: 0,
+            contributions: LookupMap::new(StorageKey::Contributions),
+            contributed_datasets: LookupMap::new(StorageKey::ContributedDatasets),
+            rewards: LookupMap::new(StorageKey::Rewards),
+            closed_reward_pools: LookupMap::new(StorageKey::ClosedRewardPools),
+            royalties: Royalties::default(),
+            token_royalties: LookupMap::new(StorageKey::TokenRoyalties),
+            ft_prediction_fees: LookupMap::new(StorageKey::FtPredictionFees),
+            ft_fees_collected: LookupMap::new(StorageKey::FtFeesCollected),
+            allowlist_enabled: false,
+            allowlist: LookupSet::new(StorageKey::Allowlist),
+            denylist: LookupSet::new(StorageKey::Denylist),
+            call_quota: None,
+            call_counts: LookupMap::new(StorageKey::CallCounts),
+            subscription_tiers: UnorderedMap::new(StorageKey::SubscriptionTiers),
+            subscriptions: LookupMap::new(StorageKey::Subscriptions),
+            token_gate: None,
+            gate_id: 0,
+            access_passes: LookupMap::new(StorageKey::AccessPasses),
+            outcomes: LookupMap::new(StorageKey::Outcomes),
+            live_accuracy: LookupMap::new(StorageKey::LiveAccuracy),
+            bounties: LookupMap::new(StorageKey::Bounties),
+            next_bounty_id: 0,
+            stake_policy: None,
+            staked_batches: UnorderedMap::new(StorageKey::StakedBatches),
+            next_batch_id: 0,
+            council: None,
+            proposals: LookupMap::new(StorageKey::Proposals),
+            next_proposal_id: 0,
+            timelock_blocks: 0,
+            scheduled_actions: UnorderedMap::new(StorageKey::ScheduledActions),
+            next_action_id: 0,
+            usage_counters: UsageCounters::default(),
+            dataset_predictions: UnorderedMap::new(StorageKey::DatasetPredictions),
+            daily_predictions: LookupMap::new(StorageKey::DailyPredictions),
+            cache_config: None,
+            result_cache: LookupMap::new(StorageKey::ResultCache),
+            cache_slots: Vector::new(StorageKey::CacheSlots),
+            cache_next_slot: 0,
+            log_level: LogLevel::Info,
+            snapshots: LookupMap::new(StorageKey::Snapshots),
+        };
+        contract.seed_toy_datasets();
+        contract
     }
 }
 
@@ -35,163 +984,3458 @@ impl Default for KnnMachineLearning {
 impl KnnMachineLearning {
     #[init]
     // This is a public method which is exported to the contract i.e. anyone can call it. 
-    pub fn new(k: u8) -> Self { // could set another k value during depolyment using Batch Action. 
-        assert_eq!((k % 2 != 0) & (k > 0) & (k <= 15), true, "k must be positive and odd between 1 and 35!"); // Algo requirement: ensure k is positive odd number between 1 and 15
+    pub fn new(k: u8, voting_scheme: Option<VotingScheme>, owner_id: Option<AccountId>) -> Self { // could set another k value (and voting scheme) during depolyment using Batch Action. 
+        // Some SDK versions let init run again over an existing state, which would reset k and wipe the datasets: only migrate
+        // (#[init(ignore_state)], reserved to the contract account) may replace the stored state.
+        assert!(!env::state_exists(), "The contract has already been initialized.");
+        validate_k(k).unwrap_or_else(|e| e.panic()); // Algo requirement: ensure k is positive odd number between 1 and 15 (init must return the contract, so panic)
+        migrate::write_state_version(); // the state starts with the current layout, see migrate.rs
         Self {
             param_k : k,
+            voting_scheme: voting_scheme.unwrap_or(VotingScheme::Uniform),
+            owner_id: owner_id.unwrap_or_else(env::predecessor_account_id), // the deployer, unless another owner is given
+            ..Default::default() // storage collections and toy datasets
         }
     }
 
     // near_sdk: method is VIEW if &self; method is CHANGE if &mut self.
     // CHANGE methods serialize the main contract structure at the end and store the new value into storage.
     // Made this mutable to allow change of state in the contract. (Data scope should ensure it is destroyed and thus (hopefully) not stored into staked memory)
-    pub fn run_analysis(&mut self, data_set: &String, test_point: &[f64; 2]) -> u8 { // dataset has 2 columns/features, hence test point needs to have same dimensionality. 
-        // Dataset can either be 'cancer' or 'customer as provided by the user.
-        let mut ans: u8 = 0;
-        if data_set == "cancer" {
-            env::log_str("Working with cancer dataset.");
-            // call fn to do the calculations with CANCER toy data
-            ans = self.classify_test_point(&TOY_CANCER_TRAIN, &TOY_CANCER_TARGET, &test_point); // borrow data and test point to fn classify_test_point
-        } else if data_set == "customer" {
-            env::log_str("Working with customer dataset.");
-            // call fn to do the calculations with CUSTOMER toy data
-            ans = self.classify_test_point(&TOY_CUSTOMER_TRAIN, &TOY_CUSTOMER_TARGET, &test_point);
-        } else {
-            env::log_str("Data can either be: 'cancer' or 'customer' data. Re-specify.");
+    #[payable]
+    pub fn run_analysis(&mut self, data_set: String, test_point: Vec<f64>, metric: Option<DistanceMetric>, k_override: Option<u8>, mint_nft: Option<bool>) -> Result<PredictionResult, KnnError> { // test point needs to have same dimensionality as the dataset. 
+        self.assert_not_paused();
+        // Dataset can be 'cancer', 'customer' or any other dataset added with add_training_point.
+        // k_override: use another k just for this call (e.g. to experiment), the contract's k stays unchanged.
+        // mint_nft: mint the prediction as an NFT owned by the caller (see nft.rs).
+        // The attached deposit must cover the prediction fee and the storage written by the prediction (its record in the history,
+        // the usage counters and cache, and the NFT when minting), the surplus is refunded.
+        // Callers with an active subscription pay no fee (see subscription.rs).
+        let caller = env::predecessor_account_id();
+        let subscribed = self.is_subscribed(&caller);
+        let fee = if subscribed { 0 } else { self.prediction_fee };
+        let attached = env::attached_deposit();
+        if attached < fee {
+            KnnError::InsufficientDeposit { required: U128(fee), attached: U128(attached) }.panic();
+        }
+        let initial_storage = env::storage_usage(); // bytes used before the prediction is recorded
+        let (id, ans) = match self.predict_and_record(&data_set, test_point.clone(), metric, k_override, caller.clone()) {
+            Err(e) if attached > 0 => e.panic(), // revert, so that the deposit is returned
+            result => result?,
         };
-        println!("The test point class is: {}", ans);
-        ans
-    }
-    
-    // Fn callable from inside contract methods only, not by user. Parameters: array 10x2, array 10x1, array 2x1.
-    fn classify_test_point(&self, arr_train: &[[f64; 2]; 10], arr_target: &[u8], pt: &[f64; 2]) -> u8 {
-        // Get L2 norm (Euclidean) distances from test point to all train data points
-        let dist = self.calc_euclidean_dist(&arr_train, &pt);
-        // Sort distances in ascending order. Obtain argsort() of that action and re-order corresponding target labels (keep train point distances and target classes aligned).
-        let (indices, _sorted_distances) = self.sort_and_argsort(&dist);
-        // Based on indices obtained from argsort() re-order targets
-        let sorted_targets = indices.into_iter().map(|x| arr_target[x]).collect::<Vec<u8>>();
-        // Obtain the classes of k nearest neighbours (distances were sorted in ascending order, so take first k elements from sorted_targets)
-        let first_k: Vec<u8> = sorted_targets[0..(self.param_k as usize)].to_vec();
-        // Count number of classes with label 1 vs label 0, go with majority
-        let n_1: usize = first_k.iter().filter(|&n| *n == 1).count(); // # of 1s 
-        let n_0: usize = first_k.iter().filter(|&n| *n == 0).count(); // # of 0s
-        if n_1 > n_0 {
-            1
+        if subscribed {
+            self.use_subscription(&caller);
         } else {
-            0
+            self.collect_fee(&data_set, fee);
         }
+        if mint_nft.unwrap_or(false) {
+            self.mint_prediction(id, caller, &data_set, &test_point, &ans);
+        }
+        self.charge_storage(initial_storage, fee);
+        Ok(ans)
     }
 
-    // Callable from methods only (not user). Params: array 10x2, array 2x1.
-    fn calc_euclidean_dist(&self, arr_train: &[[f64; 2]; 10], pt: &[f64; 2]) -> Vec<f64> {
-        let mut dist: Vec<f64> = Vec::new(); // store distanes 
-        for obs in arr_train { // for each observation in train dataset i.e. obs=[x, y]
-            let mut sum_sq_diff: f64 = 0.0; // sum of squared differences 
-            for ii in 0..obs.len(){ // go over each dim of the train point obs (note: sequence stop index is decremented by 1 automatically therefore 0 to len is correct)
-                sum_sq_diff += (obs[ii] - pt[ii]).powi(2);  // square the diff and add 
-            }
-            // Once the sum is completed obtain the Euclidean distance
-            dist.push(sum_sq_diff.sqrt());
+    // Prediction history (oldest first), paginated: at most limit records starting at from_index.
+    pub fn get_predictions(&self, from_index: u64, limit: u64) -> Vec<PredictionRecord> {
+        (from_index..std::cmp::min(from_index.saturating_add(limit), self.predictions.len()))
+            .filter_map(|id| self.predictions.get(id))
+            .collect()
+    }
+
+    // Predictions made by one account (oldest first), paginated like get_predictions.
+    pub fn get_predictions_by_account(&self, account_id: AccountId, from_index: u64, limit: u64) -> Vec<PredictionRecord> {
+        match self.predictions_by_account.get(&account_id) {
+            Some(ids) => (from_index..std::cmp::min(from_index.saturating_add(limit), ids.len()))
+                .filter_map(|ii| ids.get(ii))
+                .filter_map(|id| self.predictions.get(id))
+                .collect(),
+            None => Vec::new(),
         }
-        dist
     }
 
-    // Callable from methods only (not user). Parameters: vec 10x1.
-    fn sort_and_argsort(&self, vec: &Vec<f64>) -> (Vec<usize>, Vec<f64>) {
-        let v_original = vec.clone(); // avoid handing over owenership
-        let mut v = vec.clone(); // avoid handing over owenership
-        // sort v in-place
-        v.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        // implement argsort() equivalent
-        let mut inds = Vec::new();
-        for ii in 0..v.len() {
-            let ans = v_original.iter().position(|&r| r == v[ii]).unwrap();  
-            inds.push(ans);
+    // Radius neighbours classification: all train points within radius of the test point vote, instead of a fixed number k.
+    // The radius is in the units the distances are computed in, i.e. after the dataset's scaling and feature weights.
+    pub fn classify_radius(&self, dataset: String, point: Vec<f64>, radius: f64) -> Result<PredictionResult, KnnError> {
+        let data = self.get_dataset(&dataset)?;
+        let metric = self.classification_metric(&dataset, &data, &point, None)?;
+        let dist = self.calc_dist(&data.transformed_train(&metric), &data.transform(&point, &metric), &metric);
+        let (indices, distances) = knn_core::sort_and_argsort(&dist); // nearest first
+        let (indices, distances): (Vec<usize>, Vec<f64>) = indices.into_iter().zip(distances).filter(|(_, d)| *d <= radius).unzip();
+        if indices.is_empty() {
+            return Err(KnnError::NoNeighborsInRadius { radius });
         }
-        (inds, v) // return 2 variables
+        let labels: Vec<u8> = indices.iter().filter_map(|&ii| data.target.get(ii as u64)).collect();
+        self.check_confidence(data.named(self.vote(labels, indices, distances, data.vote_class_counts(), data.voting_rule().as_ref())?), data.class_weighted)
     }
-}
 
+    // The k nearest train points of the test point (nearest first; all of them if the dataset has fewer than k), so users can see
+    // why a class was chosen. Distances are computed like in classify, i.e. after the dataset's scaling and feature weights.
+    // With privacy noise enabled the list is empty, like the neighbours of the predictions (see privacy.rs).
+    pub fn nearest_neighbors(&self, dataset: String, point: Vec<f64>, k: u8) -> Result<Vec<Neighbor>, KnnError> {
+        let data = self.get_dataset(&dataset)?;
+        let metric = self.classification_metric(&dataset, &data, &point, None)?;
+        if self.privacy_epsilon.is_some() { // the exact votes could be recomputed from the neighbours
+            return Ok(Vec::new());
+        }
+        let (indices, distances) = self.dataset_neighbors(&data, &point, &metric, k as usize, &mut StageGas::default());
+        Ok(indices
+            .into_iter()
+            .zip(distances)
+            .filter_map(|(ii, distance)| {
+                let index = ii as u64;
+                Some(Neighbor { index, point: data.train.get(index)?, label: data.target.get(index)?, distance })
+            })
+            .collect())
+    }
 
-// ---------------------------------------------- TESTS ----------------------------------------------------------
-#[cfg(test)]
-mod tests { // start of unit tests
-    use super::*;
-    use near_sdk::test_utils::{get_logs, VMContextBuilder};
-    use near_sdk::{testing_env, AccountId};
+    // Probability of every class that received votes: its share of the (possibly distance weighted) votes of the k nearest neighbours,
+    // by ascending label. Allows decisions on a threshold rather than on the most voted class alone.
+    pub fn predict_proba(&self, dataset: String, point: Vec<f64>) -> Result<Vec<(u8, f64)>, KnnError> {
+        let data = self.get_dataset(&dataset)?;
+        let ans = self.classify_dataset(&dataset, &data, &point, None, None)?;
+        let labels: Vec<u8> = ans.neighbor_indices.iter().filter_map(|&ii| data.target.get(ii)).collect();
+        let weights = self.vote_weights(&labels, &ans.neighbor_distances, data.vote_class_counts());
+        let query = ans.try_to_vec().unwrap();
+        Ok(knn_core::class_shares(&labels, &weights)
+            .into_iter()
+            .map(|(label, share)| (label, self.noisy_share(share, labels.len(), data.class_weighted, &[&query[..], &[label]].concat())))
+            .collect())
+    }
 
-    // Set up a mock context. Provide a `predecessor` here, it'll modify the default context.
-    fn get_context(predecessor: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder.predecessor_account_id(predecessor);
-        builder
+    // Same classification as run_analysis, but as a VIEW method (&self) so predictions can be obtained for free with `near view`.
+    pub fn classify(&self, data_set: String, test_point: Vec<f64>, metric: Option<DistanceMetric>, k_override: Option<u8>) -> Result<PredictionResult, KnnError> {
+        let dataset = self.get_dataset(&data_set)?;
+        self.check_confidence(self.classify_dataset(&data_set, &dataset, &test_point, metric, k_override)?, dataset.class_weighted)
     }
 
-    // TESTS HERE
-    #[test] 
-    fn test_default_k() { // Check that the default k value is 5
-        let contract = KnnMachineLearning::default(); 
-        assert_eq!(contract.param_k == 5, true, "Expected default value for k=3") 
+    // KNN regression: instead of voting, averages the numeric targets of the k nearest neighbours of the test point.
+    // With a distance weighted voting scheme the average is weighted the same way (1/d or 1/d^2). Does not change state, so it is a VIEW method.
+    pub fn predict_value(&self, data_set: String, test_point: Vec<f64>, metric: Option<DistanceMetric>) -> Result<f64, KnnError> {
+        let dataset = self.get_dataset(&data_set)?;
+        check_dimension(&dataset, &test_point)?;
+        check_finite(&test_point)?;
+        if dataset.train.is_empty() {
+            return Err(KnnError::EmptyDataset { name: data_set });
+        }
+        dataset.check_range(&test_point)?;
+        if dataset.values.is_empty() { // holds target classes, not numeric values
+            return Err(KnnError::TargetMismatch { name: data_set });
+        }
+        let metric = metric.or(dataset.metric).unwrap_or(self.distance_metric);
+        self.check_metric(&metric)?;
+        let metric = dataset.point_metric(metric)?;
+        Ok(self.regress_test_point(&dataset.transformed_train(&metric), &dataset.values.to_vec(), &dataset.transform(&test_point, &metric), &metric, dataset.k.unwrap_or(self.param_k) as usize))
     }
-    
-    #[test]
-    fn test_new_k() { // Check that initialisation of k upon deployment satisfies requirements of being +ve, odd number between 1 and 15
-        KnnMachineLearning::new(3); // assert present inside new code
+
+    // Leave-one-out cross-validation: classifies every train point of the dataset with the k nearest of the other train points,
+    // returns the share of points classified correctly (accuracy between 0 and 1). Uses the dataset's (or the contract's) metric.
+    pub fn evaluate_loocv(&self, dataset: String, k: u8) -> Result<f64, KnnError> {
+        let (data, metric) = self.evaluation_data(&dataset, k, None)?;
+        let (points, targets) = (data.transformed_train(&metric), data.target.to_vec());
+        let n_correct: f64 = (0..points.len()).map(|ii| self.holdout_accuracy(&points, &targets, &[ii], &metric, k as usize)).sum();
+        Ok(n_correct / points.len() as f64)
     }
 
-    #[test]
-    fn test_run_analysis() { // run_analysis is the top level method. Here will test that datset name was correctly specified
-        let mut contract = KnnMachineLearning::new(3);
-        let test_point: [f64; 2] = [2.2, 14.0]; // vector with 2 entries
-        contract.run_analysis(&"cancer".to_string(), &test_point);
-        contract.run_analysis(&"customer".to_string(), &test_point);
-        contract.run_analysis(&"wrong dataset".to_string(), &test_point);
-        assert_eq!( //Asserts that two expressions are equal to each other 
-            get_logs(), 
-            ["Working with cancer dataset.", "Working with customer dataset.", "Data can either be: 'cancer' or 'customer' data. Re-specify."],
-            "Expected a successful log."
-        );
+    // k-fold cross-validation: the train points are shuffled (deterministically, from the seed or else the block's random seed) and
+    // dealt into n_folds folds, the points of each fold are classified with the k_neighbors nearest points of the other folds.
+    // Returns the accuracy of each fold and their mean.
+    pub fn evaluate_kfold(&self, dataset: String, k_neighbors: u8, n_folds: u64, seed: Option<U64>) -> Result<KFoldResult, KnnError> {
+        let (data, metric) = self.evaluation_data(&dataset, k_neighbors, Some(n_folds))?;
+        let (points, targets) = (data.transformed_train(&metric), data.target.to_vec());
+        let mut order: Vec<usize> = (0..points.len()).collect();
+        let seed = seed.map(|seed| seed.0).unwrap_or_else(block_random);
+        shuffle(&mut order, seed);
+        let fold_accuracies: Vec<f64> = (0..n_folds as usize)
+            .map(|fold| {
+                let holdout: Vec<usize> = order.iter().skip(fold).step_by(n_folds as usize).copied().collect();
+                self.holdout_accuracy(&points, &targets, &holdout, &metric, k_neighbors as usize)
+            })
+            .collect();
+        let mean_accuracy = fold_accuracies.iter().sum::<f64>() / fold_accuracies.len() as f64;
+        Ok(KFoldResult { fold_accuracies, mean_accuracy })
     }
 
-    #[test]
-    fn test_calc_euclidean_dist() { // check knn algo's sub-tasks work correctly
-        let contract = KnnMachineLearning::new(3);
-        let test_point: [f64; 2] = [15.8, 2.0]; // vector with 2 entries
-        let d = contract.calc_euclidean_dist(&TOY_CANCER_TRAIN, &test_point);
-        let mut rounded_d = Vec::new();
-        for elem in d {
-            rounded_d.push((elem * 100.0).round() / 100.0);
+    // Picks the k of the dataset among the candidates: the one with the best leave-one-out accuracy (the smallest such k on a tie).
+    // The k is stored for the dataset (owner or creator of the dataset only) and the scores of all candidates are emitted as an event.
+    pub fn tune_k(&mut self, dataset: String, candidate_ks: Vec<u8>) -> Result<u8, KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset)?;
+        self.assert_dataset_admin(&data);
+        if candidate_ks.is_empty() {
+            return Err(KnnError::NoCandidateK);
         }
-        assert_eq!(rounded_d, vec![18.87, 8.65, 0.00, 11.31, 4.16, 0.81, 3.40, 11.92, 3.90, 14.02], "Expected equality."); // Correct answer obtained from the correct code in Python.
+        let mut scores: Vec<(u8, f64)> = Vec::new();
+        for k in candidate_ks {
+            scores.push((k, self.evaluate_loocv(dataset.clone(), k)?));
+        }
+        let mut best = scores[0];
+        for score in &scores {
+            if score.1 > best.1 || (score.1 == best.1 && score.0 < best.0) {
+                best = *score;
+            }
+        }
+        data.k = Some(best.0);
+        self.store_dataset(&dataset, &data);
+        KnnEvent::KnnTuneK(vec![TuneKData { dataset: &dataset, scores: &scores, best_k: best.0 }]).emit();
+        Ok(best.0)
     }
 
-    #[test]
-    fn test_sort_and_argsort() { // check knn algo's sub-tasks work correctly
-        let contract = KnnMachineLearning::new(3);
-        let v = vec![1.1, 7.1, 4.1, 2.1]; // vector of floats
-        let (i, v_sorted) = contract.sort_and_argsort(&v);
-        assert_eq!(i, vec![0, 3, 2, 1], "Expected equality."); //Asserts that two expressions are equal to each other 
-        assert_eq!(v_sorted, vec![1.1, 2.1, 4.1, 7.1], "Expected equality."); // Correct answer can be obtained by visual inspection.
+    // Classifies labelled test points (e.g. held out from the train data) and measures the quality of the predictions:
+    // accuracy, and precision, recall, F1 score and support of every class.
+    pub fn evaluate(&self, dataset: String, test_points: Vec<Vec<f64>>, test_labels: Vec<u8>) -> Result<EvaluationMetrics, KnnError> {
+        let predicted = self.predict_test_set(&dataset, &test_points, &test_labels)?;
+        let n_correct = predicted.iter().zip(&test_labels).filter(|(p, t)| p == t).count();
+        let mut labels: Vec<u8> = test_labels.iter().chain(&predicted).copied().collect();
+        labels.sort_unstable();
+        labels.dedup();
+        let per_class = labels
+            .into_iter()
+            .map(|label| {
+                let count = |f: &dyn Fn(u8, u8) -> bool| predicted.iter().zip(&test_labels).filter(|(&p, &t)| f(p, t)).count() as f64;
+                let tp = count(&|p, t| p == label && t == label);
+                let n_predicted = count(&|p, _| p == label);
+                let support = count(&|_, t| t == label);
+                let precision = if n_predicted > 0.0 { tp / n_predicted } else { 0.0 };
+                let recall = if support > 0.0 { tp / support } else { 0.0 };
+                let f1 = if precision + recall > 0.0 { 2.0 * precision * recall / (precision + recall) } else { 0.0 };
+                ClassMetrics { label, precision, recall, f1, support: support as u32 }
+            })
+            .collect();
+        Ok(EvaluationMetrics { accuracy: n_correct as f64 / test_labels.len() as f64, per_class })
     }
 
-    #[test]
-    fn test_classify_test_point(){ // check single test data point and 10 test data points for class results.
-        let contract = KnnMachineLearning::new(3);
-        // Test a single data point
-        let test_point: [f64; 2] = [13.9, 1.9]; // vector with 2 entries
-        let ans = contract.classify_test_point(&TOY_CANCER_TRAIN, &TOY_CANCER_TARGET, &test_point);
-        assert_eq!(ans, 1, "Expected equality."); // This data point should be classified as 1, established from Python code.
-        // Test 10 data points: the data points from the training set (note: they will not ALL be classified correctly as algo has some error; expected result given below as tested in Python)
-        let test_points = TOY_CANCER_TRAIN.clone(); // array with 10 entries   
-        let mut pred_class = vec![0; (test_points.len() as u8).into()]; // store predicted class labels.
-        let mut count = 0;
-        for pt in test_points { // go over test points (note each is 2x1)
-            let ans = contract.classify_test_point(&TOY_CANCER_TRAIN, &TOY_CANCER_TARGET, &pt);
-            pred_class[count] = ans; // store predicted class one at a time (for each test point)
-            count += 1;
+    // Confusion matrix of the predictions on a labelled test set: entry [i][j] counts the test points of class i predicted as class j.
+    // Classes are 0 up to the largest label among the test labels and the predictions.
+    pub fn confusion_matrix(&self, dataset: String, test_points: Vec<Vec<f64>>, test_labels: Vec<u8>) -> Result<Vec<Vec<u32>>, KnnError> {
+        let predicted = self.predict_test_set(&dataset, &test_points, &test_labels)?;
+        let n_classes = *test_labels.iter().chain(&predicted).max().unwrap() as usize + 1; // test set is not empty
+        let mut matrix = vec![vec![0; n_classes]; n_classes];
+        for (&actual, &pred) in test_labels.iter().zip(&predicted) {
+            matrix[actual as usize][pred as usize] += 1;
         }
-        assert_eq!(pred_class, vec![0, 1, 1, 1, 1, 1, 1, 0, 1, 0], "Expected equality."); // correct classes (obtained with code in Python)
+        Ok(matrix)
+    }
+
+    // Appends one train point and its class to a dataset (a new dataset is created if the name is not yet known, its schema taken from the point).
+    // Payable: the caller covers the storage the new point occupies, any surplus of the attached deposit is refunded.
+    // Errors panic (rather than being returned) so that the transaction is reverted and the attached deposit goes back to the caller.
+    #[payable]
+    pub fn add_training_point(&mut self, dataset: String, point: Vec<f64>, label: u8) {
+        self.assert_not_paused();
+        self.assert_can_write(&dataset); // only trusted accounts add training data (or the account owning the namespace)
+        self.append_training_rows(&dataset, TrainingRows { feature_names: Vec::new(), label_names: None, points: vec![point], labels: vec![label], label_sets: None });
+    }
+
+    // Same as add_training_point but for regression datasets: the point comes with a numeric target value instead of a class.
+    #[payable]
+    pub fn add_regression_point(&mut self, dataset: String, point: Vec<f64>, value: f64) {
+        self.assert_not_paused();
+        self.assert_can_write(&dataset);
+        let initial_storage = env::storage_usage(); // bytes used before the point is stored
+        let point = self.stored_point(point).unwrap_or_else(|e| e.panic());
+        if !value.is_finite() {
+            KnnError::NonFiniteValue.panic();
+        }
+        let mut data = self.dataset_for_point(&dataset, &point).unwrap_or_else(|e| e.panic());
+        check_unfrozen(&dataset, &data).unwrap_or_else(|e| e.panic());
+        if !data.target.is_empty() { // holds target classes, use add_training_point
+            KnnError::TargetMismatch { name: dataset }.panic();
+        }
+        data.push_point(&point);
+        data.values.push(&value);
+        data.hash_row(&point, value);
+        data.update_inverse_covariance();
+        data.version += 1;
+        self.record_contribution(&dataset, &mut data, &env::predecessor_account_id(), 1);
+        self.store_dataset(&dataset, &data); // re-insert so that the updated Vector lengths are stored too
+        self.enforce_storage_limits(&dataset, &mut data, 1, initial_storage).unwrap_or_else(|e| e.panic());
+        let stake = self.stake_batch(&dataset, &data, 1);
+        self.charge_storage(initial_storage, stake);
+    }
+
+    // Only the owner can change the number of nearest neighbours (same requirements as in new).
+    pub fn set_k(&mut self, k: u8) -> Result<(), KnnError> {
+        self.assert_owner();
+        self.assert_ungoverned();
+        validate_k(k)?;
+        self.param_k = k;
+        Ok(())
+    }
+
+    pub fn get_k(&self) -> u8 {
+        self.param_k
+    }
+
+    // Only the owner can change how the neighbours vote.
+    pub fn set_voting_scheme(&mut self, voting_scheme: VotingScheme) {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.voting_scheme = voting_scheme;
+    }
+
+    pub fn get_voting_scheme(&self) -> VotingScheme {
+        self.voting_scheme
+    }
+
+    // Only the owner can change how tied votes are decided.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.tie_break = tie_break;
+    }
+
+    pub fn get_tie_break(&self) -> TieBreak {
+        self.tie_break
+    }
+
+    // Only the owner can switch between f64 and fixed-point distances. In the fixed-point mode, points added are also stored rounded to 1e-6.
+    pub fn set_numeric_mode(&mut self, mode: NumericMode) {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.numeric_mode = mode;
+    }
+
+    pub fn get_numeric_mode(&self) -> NumericMode {
+        self.numeric_mode
+    }
+
+    // Only the owner can change the confidence below which predictions abstain (between 0 and 1, 0: never abstain).
+    pub fn set_confidence_threshold(&mut self, threshold: f64) -> Result<(), KnnError> {
+        self.assert_owner();
+        self.assert_ungoverned();
+        validate_threshold(threshold)?;
+        self.confidence_threshold = threshold;
+        Ok(())
+    }
+
+    pub fn get_confidence_threshold(&self) -> f64 {
+        self.confidence_threshold
+    }
+
+    // Only the owner can change the default distance metric.
+    pub fn set_distance_metric(&mut self, metric: DistanceMetric) -> Result<(), KnnError> {
+        self.assert_owner();
+        self.assert_ungoverned();
+        metric.validate()?;
+        self.distance_metric = metric;
+        Ok(())
+    }
+
+    pub fn get_distance_metric(&self) -> DistanceMetric {
+        self.distance_metric
+    }
+
+    // Fee in yoctoNEAR for each run_analysis call (0 makes predictions free). Only the owner can change it.
+    pub fn set_prediction_fee(&mut self, fee: U128) {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.prediction_fee = fee.0;
+    }
+
+    pub fn get_prediction_fee(&self) -> U128 {
+        U128(self.prediction_fee)
+    }
+
+    // Sends `amount` yoctoNEAR of the collected fees to `to`. Only the owner can withdraw.
+    // The treasury is debited before the transfer and credited back by on_fees_withdrawn if the transfer fails.
+    pub fn withdraw_fees(&mut self, amount: U128, to: AccountId) -> Result<(), KnnError> {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.transfer_fees(amount, to)
+    }
+
+    // Callable from methods only (not user). Withdraws fees for withdraw_fees or a WithdrawFees action.
+    fn transfer_fees(&mut self, amount: U128, to: AccountId) -> Result<(), KnnError> {
+        if amount.0 > self.fees_collected {
+            return Err(KnnError::InsufficientTreasury { requested: amount, available: U128(self.fees_collected) });
+        }
+        self.fees_collected -= amount.0;
+        Promise::new(to)
+            .transfer(amount.0)
+            .then(ext_self::on_fees_withdrawn(amount, env::current_account_id(), 0, GAS_FOR_WITHDRAW_CALLBACK));
+        Ok(())
+    }
+
+    // The owner and admins can grant a role to an account (replacing its previous role) or revoke it.
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+        self.assert_role(&[Role::Admin]);
+        self.roles.insert(&account_id, &role);
+    }
+
+    pub fn revoke_role(&mut self, account_id: AccountId) {
+        self.assert_role(&[Role::Admin]);
+        self.roles.remove(&account_id);
+    }
+
+    pub fn get_role(&self, account_id: AccountId) -> Option<Role> {
+        self.roles.get(&account_id)
+    }
+
+    pub fn get_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    // Ownership changes hands in two steps, so it cannot be given to a mistyped account:
+    // the owner proposes a new owner (None withdraws the proposal), then the proposed account accepts.
+    pub fn propose_owner(&mut self, new_owner: Option<AccountId>) {
+        self.assert_owner();
+        self.proposed_owner = new_owner;
+    }
+
+    pub fn accept_owner(&mut self) {
+        let caller = env::predecessor_account_id();
+        if self.proposed_owner.as_ref() != Some(&caller) {
+            KnnError::Unauthorized { account_id: caller }.panic();
+        }
+        self.owner_id = caller;
+        self.proposed_owner = None;
+    }
+
+    pub fn get_proposed_owner(&self) -> Option<AccountId> {
+        self.proposed_owner.clone()
+    }
+
+    // Callback of withdraw_fees: returns the amount to the treasury when the transfer did not go through (e.g. unknown account).
+    #[private]
+    pub fn on_fees_withdrawn(&mut self, amount: U128) {
+        if !is_promise_success() {
+            self.fees_collected += amount.0;
+        }
+    }
+
+    // Gas used by the stages of the latest run_analysis prediction (None before the first one).
+    pub fn last_gas_report(&self) -> Option<GasReport> {
+        self.last_gas_report.clone()
+    }
+
+    // Only the owner can pause the contract, e.g. when a data-quality or billing issue is discovered: predictions (run_analysis and
+    // classify_chunked), uploads, changes to the datasets and storage deposits and withdrawals are rejected until unpause.
+    // The owner's configuration methods stay available, so that the issue can be fixed while paused.
+    pub fn pause(&mut self) {
+        self.assert_owner();
+        self.paused = true;
+        self.log(LogLevel::Warn, "contract_paused", json!({}));
+    }
+
+    pub fn unpause(&mut self) {
+        self.assert_owner();
+        self.paused = false;
+        self.log(LogLevel::Info, "contract_unpaused", json!({}));
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Only the owner can limit the size of the datasets (see StorageLimits). Limits apply to later uploads, datasets already larger are kept.
+    pub fn set_storage_limits(&mut self, limits: StorageLimits) {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.storage_limits = limits;
+    }
+
+    pub fn get_storage_limits(&self) -> StorageLimits {
+        self.storage_limits
+    }
+
+    // Train points and storage bytes of the datasets created by an account, counted against the account limits.
+    pub fn get_account_usage(&self, account_id: AccountId) -> AccountUsage {
+        self.account_usage.get(&account_id).unwrap_or_default()
+    }
+
+    // Gas used by the run_analysis predictions on a dataset, in total and on average (None if there were none).
+    pub fn get_gas_stats(&self, dataset: String) -> Option<GasStats> {
+        self.gas_stats.get(&dataset)
+    }
+
+    // Fees collected from predictions that can still be withdrawn.
+    pub fn get_treasury_balance(&self) -> U128 {
+        U128(self.fees_collected)
+    }
+
+    // Schema and origin of a dataset.
+    pub fn get_dataset_meta(&self, name: String) -> Result<DatasetMeta, KnnError> {
+        let dataset = self.get_dataset(&name)?;
+        Ok(DatasetMeta::from_dataset(&name, &dataset))
+    }
+
+    // Per feature minimum, maximum, mean and standard deviation, and per class counts of the train points of a dataset.
+    pub fn describe_dataset(&self, name: String) -> Result<DatasetDescription, KnnError> {
+        let dataset = self.get_dataset(&name)?;
+        if dataset.train.is_empty() {
+            return Err(KnnError::EmptyDataset { name });
+        }
+        Ok(DatasetDescription { class_counts: self.class_counts(&name, &dataset), ..DatasetDescription::from_dataset(&name, &dataset) })
+    }
+
+    // Number and share of the train points of every class of a dataset, by ascending label (empty for regression datasets).
+    pub fn class_distribution(&self, dataset: String) -> Result<Vec<ClassShare>, KnnError> {
+        let data = self.get_dataset(&dataset)?;
+        let counts = self.class_counts(&dataset, &data);
+        let n: u64 = counts.iter().map(|(_, count)| count).sum();
+        Ok(counts.into_iter().map(|(label, count)| ClassShare { label, count, share: if n > 0 { count as f64 / n as f64 } else { 0.0 } }).collect())
+    }
+
+    // Lists the shared datasets, or the datasets in the namespace of account_id, paginated: at most limit datasets starting at from_index.
+    pub fn list_datasets(&self, from_index: u64, limit: u64, account_id: Option<AccountId>) -> Vec<DatasetMeta> {
+        match account_id {
+            Some(account_id) => match self.account_datasets.get(&account_id) {
+                Some(datasets) => dataset_page(&datasets, &format!("{}/", account_id), from_index, limit),
+                None => Vec::new(),
+            },
+            None => dataset_page(&self.datasets, "", from_index, limit),
+        }
+    }
+
+    // Describes the features (columns) and classes of a dataset, by the owner or the creator of the dataset.
+    // The number of feature names must match the dataset's schema, label names are given for the classes 0, 1, ...
+    pub fn set_dataset_schema(&mut self, name: String, feature_names: Vec<String>, label_names: Vec<String>) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&name)?;
+        self.assert_dataset_admin(&data);
+        if !feature_names.is_empty() && feature_names.len() != data.n_features as usize {
+            return Err(KnnError::DimensionMismatch { expected: data.n_features, actual: feature_names.len() as u32 });
+        }
+        data.feature_names = feature_names;
+        data.label_names = label_names;
+        self.store_dataset(&name, &data);
+        Ok(())
+    }
+
+    // Weights of the features of a dataset in the distances, e.g. to downweight noisy features (owner or creator of the dataset only).
+    // One non-negative weight per feature, not all 0 (None: all features count equally).
+    pub fn set_feature_weights(&mut self, dataset: String, weights: Option<Vec<f64>>) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset)?;
+        self.assert_dataset_admin(&data);
+        if let Some(weights) = &weights {
+            if weights.len() != data.n_features as usize {
+                return Err(KnnError::DimensionMismatch { expected: data.n_features, actual: weights.len() as u32 });
+            }
+            if weights.iter().any(|w| !w.is_finite() || *w < 0.0) || weights.iter().all(|w| *w == 0.0) {
+                return Err(KnnError::InvalidFeatureWeights);
+            }
+        }
+        data.feature_weights = weights;
+        self.store_dataset(&dataset, &data);
+        Ok(())
+    }
+
+    // Class-weighted voting for an imbalanced dataset (owner or creator of the dataset only): each neighbour's vote (weighted by
+    // the voting scheme) is divided by the number of train points of its class, so that a majority class does not win by its size alone.
+    pub fn set_class_weighting(&mut self, dataset: String, enabled: bool) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset)?;
+        self.assert_dataset_admin(&data);
+        data.class_weighted = enabled;
+        self.store_dataset(&dataset, &data);
+        Ok(())
+    }
+
+    // Rejects test points far outside the range of the train points, where the nearest neighbours say little about the point (owner
+    // or creator of the dataset only): a feature more than tolerance times its range below the minimum or above the maximum of the
+    // train points fails with OutOfRange, e.g. tolerance 0 for the range itself (None: not checked).
+    pub fn set_range_check(&mut self, dataset: String, tolerance: Option<f64>) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset)?;
+        self.assert_dataset_admin(&data);
+        if let Some(tolerance) = tolerance.filter(|t| !t.is_finite() || *t < 0.0) {
+            return Err(KnnError::InvalidTolerance { tolerance });
+        }
+        data.range_tolerance = tolerance;
+        self.store_dataset(&dataset, &data);
+        Ok(())
+    }
+
+    // Picks how the features of a dataset are scaled (owner or creator of the dataset only). The scaling parameters follow the
+    // train points (they are derived from statistics updated with every new point) and are applied to test points as well.
+    pub fn set_dataset_scaling(&mut self, name: String, scaling: Scaling) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&name)?;
+        self.assert_dataset_admin(&data);
+        data.scaling = scaling;
+        self.store_dataset(&name, &data);
+        Ok(())
+    }
+
+    // Deletes a dataset with all its points and targets. Only the owner or the creator of the dataset can remove it (under a
+    // council, the owner removes the datasets of others by proposal). The freed storage stake is refunded to the creator (who
+    // paid for the storage of the points).
+    pub fn remove_dataset(&mut self, name: String) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let data = self.get_dataset(&name)?;
+        self.assert_dataset_admin(&data);
+        if env::predecessor_account_id() != data.creator {
+            self.assert_ungoverned();
+        }
+        check_unfrozen(&name, &data)?;
+        self.delete_dataset_refunded(&name, data);
+        Ok(())
+    }
+
+    // Builds (or rebuilds, balanced) the k-d tree of a dataset, so that predictions search it instead of scanning every train point.
+    // Points added later are inserted into the tree. Only the owner or the creator of the dataset can index it, and must attach
+    // a deposit covering the storage of the tree (the surplus is refunded). Returns the number of points indexed.
+    #[payable]
+    pub fn build_index(&mut self, dataset: String) -> u64 {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset).unwrap_or_else(|e| e.panic());
+        self.assert_dataset_admin(&data);
+        let initial_storage = env::storage_usage();
+        let mut tree = data.kd_tree.take().unwrap_or_else(|| Vector::new(StorageKey::DatasetKdTree { dataset_hash: env::sha256(dataset.as_bytes()) }));
+        tree.clear();
+        for node in kdtree::build(&data.train.to_vec()) {
+            tree.push(&node);
+        }
+        let n_indexed = tree.len();
+        data.kd_tree = Some(tree);
+        self.store_dataset(&dataset, &data);
+        self.enforce_storage_limits(&dataset, &mut data, 0, initial_storage).unwrap_or_else(|e| e.panic());
+        self.charge_storage(initial_storage, 0);
+        n_indexed
+    }
+
+    // Only the owner can pick the metric of a dataset (None: fall back to the contract's metric).
+    pub fn set_dataset_metric(&mut self, dataset: String, metric: Option<DistanceMetric>) -> Result<(), KnnError> {
+        self.assert_owner();
+        self.assert_ungoverned();
+        let data = self.dataset_with_metric(&dataset, metric)?;
+        self.store_dataset(&dataset, &data);
+        Ok(())
+    }
+
+    // Callable from methods only (not user). The dataset with the metric (checked against its features), to be stored by the caller.
+    fn dataset_with_metric(&self, dataset: &String, metric: Option<DistanceMetric>) -> Result<Dataset, KnnError> {
+        let mut data = self.get_dataset(dataset)?;
+        if let Some(m) = &metric {
+            m.validate()?;
+            m.check_dimension(data.n_features)?;
+        }
+        data.metric = metric;
+        Ok(data)
+    }
+
+    // Callable from methods only (not user). Classifies the test point, logs the prediction and records it in the history
+    // as made by caller. Returns the id of the prediction in the history together with the result.
+    pub(crate) fn predict_and_record(&mut self, data_set: &String, test_point: Vec<f64>, metric: Option<DistanceMetric>, k_override: Option<u8>, caller: AccountId) -> Result<(u64, PredictionResult), KnnError> {
+        self.check_access(&caller)?;
+        self.check_token_gate(&caller)?;
+        self.check_call_quota(&caller)?;
+        let dataset = self.get_dataset(data_set)?;
+        self.log(LogLevel::Info, "dataset_selected", json!({ "dataset": data_set }));
+        let mut gas = StageGas::default();
+        let ans = self.classify_cached(data_set, &dataset, &test_point, metric, k_override, &mut gas)?;
+        let ans = self.check_confidence(ans, dataset.class_weighted)?;
+        self.record_gas(data_set, gas);
+        self.record_call(&caller);
+        self.log(LogLevel::Debug, "prediction", json!({ "dataset": data_set, "label": ans.label }));
+        KnnEvent::KnnPrediction(vec![PredictionData {
+            dataset: data_set,
+            test_point: &test_point,
+            predicted_class: ans.label,
+            caller: &caller,
+        }])
+        .emit();
+        self.count_usage(data_set, ans.label, &caller);
+        let id = self.record_prediction(caller, data_set.clone(), test_point, ans.clone());
+        Ok((id, ans))
+    }
+
+
+    // Callable from methods only (not user). Stores the gas report of a prediction and adds it to the gas statistics of the dataset.
+    fn record_gas(&mut self, dataset: &String, gas: StageGas) {
+        let total_gas = env::used_gas().0;
+        let mut stats = self.gas_stats.get(dataset).unwrap_or(GasStats { n_predictions: 0, total_gas: U64(0), average_gas: U64(0) });
+        stats.n_predictions += 1;
+        stats.total_gas = U64(stats.total_gas.0 + total_gas);
+        stats.average_gas = U64(stats.total_gas.0 / stats.n_predictions);
+        self.gas_stats.insert(dataset, &stats);
+        self.last_gas_report = Some(GasReport {
+            dataset: dataset.clone(),
+            distance_gas: U64(gas.distance),
+            sort_gas: U64(gas.sort),
+            vote_gas: U64(gas.vote),
+            total_gas: U64(total_gas),
+        });
+    }
+
+    // Callable from methods only (not user). Number of train points of every class of a dataset by ascending label, with the privacy noise if enabled.
+    fn class_counts(&self, name: &str, dataset: &Dataset) -> Vec<(u8, u64)> {
+        dataset.class_counts.iter().map(|(label, count)| (*label, self.noisy_count(*count, &[name.as_bytes(), &[*label]].concat()))).collect()
+    }
+
+    // Callable from methods only (not user). Adds the privacy noise (if enabled, see privacy.rs; class_weighted: whether the votes were
+    // divided by the class sizes), then abstains (Abstained error) rather than forcing a label when the confidence is below the threshold.
+    fn check_confidence(&self, ans: PredictionResult, class_weighted: bool) -> Result<PredictionResult, KnnError> {
+        let ans = self.noisy_result(ans, class_weighted);
+        if ans.confidence < self.confidence_threshold {
+            return Err(KnnError::Abstained { label: ans.label, confidence: ans.confidence, threshold: self.confidence_threshold });
+        }
+        Ok(ans)
+    }
+
+    // Callable from methods only (not user). Appends a prediction to the history and to the caller's list of predictions, returns its id.
+    fn record_prediction(&mut self, caller: AccountId, dataset: String, test_point: Vec<f64>, result: PredictionResult) -> u64 {
+        let id = self.predictions.len();
+        let mut ids = self.predictions_by_account.get(&caller).unwrap_or_else(|| {
+            Vector::new(StorageKey::AccountPredictions { account_hash: env::sha256(caller.as_bytes()) })
+        });
+        ids.push(&id);
+        self.predictions_by_account.insert(&caller, &ids);
+        self.predictions.push(&PredictionRecord {
+            id,
+            caller,
+            dataset,
+            test_point,
+            result,
+            timestamp: U64(env::block_timestamp()),
+            block_height: U64(env::block_height()),
+        });
+        id
+    }
+
+    // Callable from methods only (not user). Guards the methods reserved to the owner (configuration, fees, deletion of data).
+    fn assert_owner(&self) {
+        let caller = env::predecessor_account_id();
+        if caller != self.owner_id {
+            KnnError::Unauthorized { account_id: caller }.panic();
+        }
+    }
+
+    // Callable from methods only (not user). Guards the change methods open to users while the contract is paused.
+    fn assert_not_paused(&self) {
+        if self.paused {
+            KnnError::Paused.panic();
+        }
+    }
+
+    // Callable from methods only (not user). Guards the methods reserved to accounts with one of the given roles (or the owner).
+    fn assert_role(&self, allowed: &[Role]) {
+        let caller = env::predecessor_account_id();
+        let permitted = caller == self.owner_id || self.roles.get(&caller).is_some_and(|role| allowed.contains(&role));
+        if !permitted {
+            KnnError::Unauthorized { account_id: caller }.panic();
+        }
+    }
+
+    // Callable from methods only (not user). Guards the methods managing a dataset, reserved to the owner and the creator of the dataset.
+    fn assert_dataset_admin(&self, dataset: &Dataset) {
+        self.assert_caller_access();
+        let caller = env::predecessor_account_id();
+        if caller != self.owner_id && caller != dataset.creator {
+            KnnError::Unauthorized { account_id: caller }.panic();
+        }
+    }
+
+    // Callable from methods only (not user). Guards the methods writing to a dataset: datasets in the namespace of an account
+    // can only be written by that account, the other datasets by trusted accounts.
+    fn assert_can_write(&self, name: &str) {
+        self.assert_caller_access();
+        match split_namespace(name) {
+            Some((account_id, _)) => {
+                let caller = env::predecessor_account_id();
+                if caller != account_id {
+                    KnnError::Unauthorized { account_id: caller }.panic();
+                }
+            }
+            None => self.assert_role(&[Role::Admin, Role::DataProvider]),
+        }
+    }
+
+    // Callable from methods only (not user). Looks up a stored dataset by name (in the namespace of an account if the name is "account/name",
+    // a snapshot if it is "name@version").
+    fn get_dataset(&self, name: &String) -> Result<Dataset, KnnError> {
+        if is_snapshot(name) {
+            return self.snapshots.get(name).ok_or_else(|| KnnError::UnknownDataset { name: name.clone() });
+        }
+        let dataset = match split_namespace(name) {
+            Some((account_id, local_name)) => self.account_datasets.get(&account_id).and_then(|datasets| datasets.get(&local_name.to_string())),
+            None => self.datasets.get(name),
+        };
+        dataset.ok_or_else(|| KnnError::UnknownDataset { name: name.clone() })
+    }
+
+    // Callable from methods only (not user). Stores a dataset under its name, creating the namespace of the account if needed.
+    // Panics for a snapshot, which cannot be changed.
+    fn store_dataset(&mut self, name: &str, dataset: &Dataset) {
+        if is_snapshot(name) {
+            KnnError::SnapshotImmutable { name: name.to_string() }.panic();
+        }
+        match split_namespace(name) {
+            Some((account_id, local_name)) => {
+                let mut datasets = self.account_datasets.get(&account_id).unwrap_or_else(|| {
+                    UnorderedMap::new(StorageKey::AccountDatasetsInner { account_hash: env::sha256(account_id.as_bytes()) })
+                });
+                datasets.insert(&local_name.to_string(), dataset);
+                self.account_datasets.insert(&account_id, &datasets); // re-insert so that the updated map length is stored too
+            }
+            None => {
+                self.datasets.insert(&name.to_string(), dataset);
+            }
+        }
+    }
+
+    // Callable from methods only (not user). Counts the rows added to a dataset (already stored) and the storage used since initial_storage
+    // against the dataset and its creator, and fails if that exceeds a storage limit (the caller panics to revert the upload).
+    fn enforce_storage_limits(&mut self, name: &str, dataset: &mut Dataset, rows_added: u64, initial_storage: u64) -> Result<(), KnnError> {
+        self.check_storage_limits(dataset, rows_added, initial_storage)?;
+        self.store_dataset(name, dataset); // same size: only storage_bytes changed
+        Ok(())
+    }
+
+    // Callable from methods only (not user). Same as enforce_storage_limits, for a dataset stored elsewhere (the caller stores it again).
+    fn check_storage_limits(&mut self, dataset: &mut Dataset, rows_added: u64, initial_storage: u64) -> Result<(), KnnError> {
+        let storage = env::storage_usage(); // may be below initial_storage, e.g. when an index is rebuilt smaller
+        let mut usage = self.account_usage.get(&dataset.creator).unwrap_or_default();
+        usage.rows += rows_added;
+        usage.bytes = (usage.bytes + storage).saturating_sub(initial_storage);
+        dataset.storage_bytes = (dataset.storage_bytes + storage).saturating_sub(initial_storage);
+        let limits = self.storage_limits;
+        for (limit, maximum, actual) in [
+            ("max_rows_per_dataset", limits.max_rows_per_dataset, dataset.train.len()),
+            ("max_bytes_per_dataset", limits.max_bytes_per_dataset, dataset.storage_bytes),
+            ("max_rows_per_account", limits.max_rows_per_account, usage.rows),
+            ("max_bytes_per_account", limits.max_bytes_per_account, usage.bytes),
+        ] {
+            match maximum {
+                Some(maximum) if actual > maximum => return Err(KnnError::StorageLimitExceeded { limit: limit.to_string(), maximum, actual }),
+                _ => {}
+            }
+        }
+        self.account_usage.insert(&dataset.creator, &usage);
+        Ok(())
+    }
+
+    // Callable from methods only (not user). Deletes the points, targets and index of a dataset from storage, and no longer counts
+    // them in the usage of its creator (the entry of the dataset itself is kept).
+    fn clear_dataset(&mut self, data: &mut Dataset) {
+        let n_rows = data.train.len();
+        data.train.clear(); // removes every element stored under the dataset's prefixes
+        data.target.clear();
+        data.label_sets.clear();
+        data.values.clear();
+        if let Some(tree) = &mut data.kd_tree {
+            tree.clear();
+        }
+        if let Some(mut usage) = self.account_usage.get(&data.creator) {
+            usage.rows = usage.rows.saturating_sub(n_rows);
+            usage.bytes = usage.bytes.saturating_sub(data.storage_bytes);
+            self.account_usage.insert(&data.creator, &usage);
+        }
+    }
+
+    // Callable from methods only (not user). Gives the cost of freed storage (bytes) back to the account that paid for it.
+    fn refund_storage(&mut self, creator: &AccountId, freed: u64) {
+        let refund = Balance::from(freed) * env::storage_byte_cost();
+        // toy datasets were paid for by the contract itself, registered creators are credited on their storage balance
+        if *creator != env::current_account_id() && !self.unlock_storage_balance(creator, refund) {
+            Promise::new(creator.clone()).transfer(refund);
+        }
+    }
+
+    // Callable from methods only (not user). Removes a dataset with its points, its reward pool and its model NFT, and refunds
+    // the freed storage to its creator. Panics for a snapshot, which cannot be removed.
+    fn delete_dataset_refunded(&mut self, name: &String, mut data: Dataset) {
+        if is_snapshot(name) {
+            KnnError::SnapshotImmutable { name: name.clone() }.panic();
+        }
+        let initial_storage = env::storage_usage();
+        self.clear_dataset(&mut data);
+        self.close_reward_pool(name, &data);
+        self.burn_model_nft(name);
+        self.delete_dataset(name);
+        self.gas_stats.remove(name);
+        self.live_accuracy.remove(name);
+        let freed = initial_storage.saturating_sub(env::storage_usage());
+        self.log(LogLevel::Info, "dataset_removed", json!({ "dataset": name, "freed_bytes": freed }));
+        self.refund_storage(&data.creator, freed);
+    }
+
+    // Callable from methods only (not user). Removes the entry of a dataset (its points must be cleared beforehand).
+    fn delete_dataset(&mut self, name: &str) {
+        match split_namespace(name) {
+            Some((account_id, local_name)) => {
+                if let Some(mut datasets) = self.account_datasets.get(&account_id) {
+                    datasets.remove(&local_name.to_string());
+                    self.account_datasets.insert(&account_id, &datasets);
+                }
+            }
+            None => {
+                self.datasets.remove(&name.to_string());
+            }
+        }
+    }
+
+    // Callable from methods only (not user). Dataset a new train point goes into (created if the name is not yet known), once the point matches its schema.
+    fn dataset_for_point(&self, name: &String, point: &[f64]) -> Result<Dataset, KnnError> {
+        if point.is_empty() { // a dataset needs at least one feature
+            return Err(KnnError::DimensionMismatch { expected: 1, actual: 0 });
+        }
+        if is_snapshot(name) && self.get_dataset(name).is_err() { // '@' is reserved for the names of snapshots
+            return Err(KnnError::InvalidDatasetName { name: name.clone() });
+        }
+        if self.offchain_datasets.contains_key(name) { // taken by an off-chain dataset (see offchain.rs)
+            return Err(KnnError::DatasetExists { name: name.clone() });
+        }
+        let dataset = self.get_dataset(name).unwrap_or_else(|_| Dataset::new(name, point.len() as u32, env::predecessor_account_id()));
+        check_dimension(&dataset, point)?;
+        Ok(dataset)
+    }
+
+    // Callable from methods only (not user). Point as it is stored: rounded to the fixed-point grid in the fixed-point mode.
+    // Its features must be finite (checked before rounding, which would turn infinite features into finite ones).
+    fn stored_point(&self, point: Vec<f64>) -> Result<Vec<f64>, KnnError> {
+        check_finite(&point)?;
+        Ok(match self.numeric_mode {
+            NumericMode::Float => point,
+            NumericMode::FixedPoint => fixed::quantize(&point),
+        })
+    }
+
+    // Callable from methods only (not user). Checks the test point against the dataset's schema and classifies it with the stored data.
+    // Metric given in the call takes precedence over the dataset's metric, which takes precedence over the contract's metric (likewise for k).
+    // A k given in the call must be valid and cannot exceed the number of train points in the dataset.
+    fn classify_dataset(&self, name: &str, dataset: &Dataset, test_point: &[f64], metric: Option<DistanceMetric>, k_override: Option<u8>) -> Result<PredictionResult, KnnError> {
+        self.classify_dataset_metered(name, dataset, test_point, metric, k_override, &mut StageGas::default())
+    }
+
+    // Callable from methods only (not user). Same as classify_dataset, adds the gas used by each stage to gas. Classifies with the
+    // dataset's default model (see classifier.rs).
+    fn classify_dataset_metered(&self, name: &str, dataset: &Dataset, test_point: &[f64], metric: Option<DistanceMetric>, k_override: Option<u8>, gas: &mut StageGas) -> Result<PredictionResult, KnnError> {
+        dataset.default_classifier().classify(self, name, dataset, &Query { test_point, metric, k_override }, gas)
+    }
+
+    // Callable from methods only (not user). KNN classification of the test point with the dataset, see classify_dataset_metered.
+    pub(crate) fn classify_knn(&self, name: &str, dataset: &Dataset, test_point: &[f64], metric: Option<DistanceMetric>, k_override: Option<u8>, gas: &mut StageGas) -> Result<PredictionResult, KnnError> {
+        let metric = self.classification_metric(name, dataset, test_point, metric)?;
+        let k = match k_override {
+            Some(k) => {
+                validate_k(k)?;
+                if u64::from(k) > dataset.train.len() {
+                    return Err(KnnError::KTooLarge { k, n_points: dataset.train.len() });
+                }
+                k
+            }
+            None => dataset.k.unwrap_or(self.param_k),
+        };
+        let (indices, distances) = self.dataset_neighbors(dataset, test_point, &metric, k as usize, gas);
+        let start = env::used_gas().0;
+        let labels: Vec<u8> = indices.iter().filter_map(|&ii| dataset.target.get(ii as u64)).collect();
+        let ans = self.vote(labels, indices, distances, dataset.vote_class_counts(), dataset.voting_rule().as_ref()).map(|ans| dataset.named(ans));
+        gas.vote += env::used_gas().0 - start;
+        ans
+    }
+
+    // Callable from methods only (not user). The k nearest train points of the dataset (nearest first) and their distances, computed
+    // with the dataset's scaling and feature weights (so distances are in scaled units). Searches the k-d tree if the dataset has one.
+    // The gas used by the stages is added to gas.
+    fn dataset_neighbors(&self, dataset: &Dataset, test_point: &[f64], metric: &PointMetric, k: usize, gas: &mut StageGas) -> (Vec<usize>, Vec<f64>) {
+        let start = env::used_gas().0;
+        if let Some(tree) = dataset.kd_tree.as_ref().filter(|_| metric.indexable()) {
+            let nearest = dataset.indexed_nearest(tree, test_point, metric, self.numeric_mode, k);
+            gas.distance += env::used_gas().0 - start;
+            return nearest;
+        }
+        let rank = self.calc_rank_dist(&dataset.transformed_train(metric), &dataset.transform(test_point, metric), metric);
+        let after_distances = env::used_gas().0;
+        let (indices, rank) = knn_core::select_k_smallest(&rank, k);
+        let distances = rank.into_iter().map(|r| metric.rank_to_distance(self.numeric_mode, r)).collect();
+        gas.distance += after_distances - start;
+        gas.sort += env::used_gas().0 - after_distances;
+        (indices, distances)
+    }
+
+    // Callable from methods only (not user). Checks the metric is a valid distance that can be computed in the contract's numeric mode.
+    fn check_metric(&self, metric: &DistanceMetric) -> Result<(), KnnError> {
+        metric.validate()?;
+        match metric {
+            DistanceMetric::Minkowski(p) if self.numeric_mode == NumericMode::FixedPoint && !fixed::supports(metric) => Err(KnnError::UnsupportedFixedPointMetric { p: *p }),
+            _ => Ok(()),
+        }
+    }
+
+    // Callable from methods only (not user). Checks the test point can be classified with the dataset and returns the metric to use
+    // (given in the call, else the dataset's, else the contract's).
+    fn classification_metric(&self, name: &str, dataset: &Dataset, test_point: &[f64], metric: Option<DistanceMetric>) -> Result<PointMetric, KnnError> {
+        check_dimension(dataset, test_point)?;
+        check_finite(test_point)?;
+        if dataset.train.is_empty() {
+            return Err(KnnError::EmptyDataset { name: name.to_string() });
+        }
+        dataset.check_range(test_point)?;
+        if !dataset.values.is_empty() { // holds numeric values, use predict_value
+            return Err(KnnError::TargetMismatch { name: name.to_string() });
+        }
+        let metric = metric.or(dataset.metric).unwrap_or(self.distance_metric);
+        self.check_metric(&metric)?;
+        dataset.point_metric(metric)
+    }
+
+    // Callable from methods only (not user). Predicted classes of a labelled test set (one label per point, at least one point).
+    fn predict_test_set(&self, name: &String, test_points: &[Vec<f64>], test_labels: &[u8]) -> Result<Vec<u8>, KnnError> {
+        if test_points.len() != test_labels.len() || test_points.is_empty() {
+            return Err(KnnError::InvalidTestSet { n_points: test_points.len() as u32, n_labels: test_labels.len() as u32 });
+        }
+        let dataset = self.get_dataset(name)?;
+        test_points.iter().map(|point| Ok(self.classify_dataset(name, &dataset, point, None, None)?.label)).collect()
+    }
+
+    // Callable from methods only (not user). Classification dataset to evaluate and the metric to use, once k is checked against the
+    // number of train points left to vote when the largest fold is held out (n_folds None: leave-one-out).
+    fn evaluation_data(&self, name: &String, k: u8, n_folds: Option<u64>) -> Result<(Dataset, PointMetric), KnnError> {
+        let dataset = self.get_dataset(name)?;
+        if !dataset.values.is_empty() { // holds numeric values, not classes
+            return Err(KnnError::TargetMismatch { name: name.clone() });
+        }
+        let n_holdout = match n_folds {
+            Some(n_folds) if n_folds < 2 || n_folds > dataset.train.len() => {
+                return Err(KnnError::InvalidFolds { n_folds, n_points: dataset.train.len() });
+            }
+            Some(n_folds) => dataset.train.len().div_ceil(n_folds), // folds differ in size by at most 1 point
+            None => 1,
+        };
+        if dataset.train.len() <= n_holdout {
+            return Err(KnnError::EmptyDataset { name: name.clone() }); // nothing left to train on
+        }
+        validate_k(k)?;
+        let n_points = dataset.train.len() - n_holdout;
+        if u64::from(k) > n_points {
+            return Err(KnnError::KTooLarge { k, n_points });
+        }
+        let metric = dataset.metric.unwrap_or(self.distance_metric);
+        self.check_metric(&metric)?;
+        let metric = dataset.point_metric(metric)?;
+        Ok((dataset, metric))
+    }
+
+    // Callable from methods only (not user). Share of the held out points (by index) classified correctly by the k nearest of the other points
+    // (a point the vote abstains on counts as misclassified).
+    fn holdout_accuracy(&self, points: &[Vec<f64>], targets: &[u8], holdout: &[usize], metric: &PointMetric, k: usize) -> f64 {
+        let is_holdout = |ii: &usize| holdout.contains(ii);
+        let rest: Vec<usize> = (0..points.len()).filter(|ii| !is_holdout(ii)).collect();
+        let (train, target): (Vec<&Vec<f64>>, Vec<u8>) = rest.iter().map(|&ii| (&points[ii], targets[ii])).unzip();
+        let n_correct = holdout
+            .iter()
+            .filter(|&&ii| self.classify_test_point(&train, &target, &points[ii], metric, k).is_ok_and(|ans| ans.label == targets[ii]))
+            .count();
+        n_correct as f64 / holdout.len() as f64
+    }
+
+    // Callable from methods only (not user). Stores the toy datasets so that they can be used (and extended) like any other dataset.
+    fn seed_toy_datasets(&mut self) {
+        for (name, arr_train, arr_target) in [("cancer", TOY_CANCER_TRAIN, TOY_CANCER_TARGET), ("customer", TOY_CUSTOMER_TRAIN, TOY_CUSTOMER_TARGET)] {
+            let mut dataset = Dataset::new(name, 2, env::current_account_id());
+            for (obs, label) in arr_train.iter().zip(arr_target) {
+                dataset.push_point(obs);
+                dataset.push_label(*label);
+                dataset.hash_row(obs, *label);
+            }
+            self.datasets.insert(&name.to_string(), &dataset);
+        }
+    }
+
+    // Callable from methods only (not user). Shares out the prediction fee paid on a dataset.
+    fn collect_fee(&mut self, data_set: &str, fee: Balance) {
+        let fee = self.distribute_fee(data_set, fee); // the contributors' share goes to the dataset
+        self.fees_collected += self.pay_model_holder(data_set, fee); // the rest to the holder of the dataset's NFT, if minted
+    }
+
+    // Callable from methods only (not user). Requires the attached deposit to pay for the storage used since initial_storage
+    // (plus a fee kept by the contract), refunds the rest. If the caller is registered for storage (see storage.rs), what the attached
+    // deposit does not cover is drawn from their storage balance.
+    fn charge_storage(&mut self, initial_storage: u64, fee: Balance) {
+        let required: Balance = Balance::from(env::storage_usage().saturating_sub(initial_storage)) * env::storage_byte_cost() + fee;
+        let attached: Balance = env::attached_deposit();
+        if attached < required {
+            let caller = env::predecessor_account_id();
+            assert!(self.storage_accounts.contains_key(&caller), "Must attach {} yoctoNEAR to cover storage.", required);
+            self.lock_storage_balance(&caller, required - attached);
+            return;
+        }
+        let refund = attached - required;
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+    }
+    
+    // Fn callable from inside contract methods only, not by user. Parameters: array nxd, array nx1, array dx1.
+    fn classify_test_point<T: AsRef<[f64]>>(&self, arr_train: &[T], arr_target: &[u8], pt: &[f64], metric: &PointMetric, k: usize) -> Result<PredictionResult, KnnError> {
+        let (indices, distances) = self.k_nearest(arr_train, pt, metric, k);
+        // Based on indices of the k nearest neighbours obtain their classes
+        let first_k: Vec<u8> = indices.iter().map(|&x| arr_target[x]).collect();
+        self.vote(first_k, indices, distances, None, DecisionRule::Weighted.rule().as_ref())
+    }
+
+    // Callable from methods only (not user). Classifies by the vote of the neighbours given by their classes, indices in the train set and distances (nearest first).
+    // class_counts: train points per class when votes are class weighted (see set_class_weighting). rule: decides the class from the votes.
+    fn vote(&self, first_k: Vec<u8>, indices: Vec<usize>, distances: Vec<f64>, class_counts: Option<&BTreeMap<u8, u64>>, rule: &dyn VotingRule) -> Result<PredictionResult, KnnError> {
+        // Weigh the votes for each class label, the rule decides
+        let weights = self.vote_weights(&first_k, &distances, class_counts);
+        let (label, confidence) = rule.decide(&first_k, &weights, self.tie_break)?;
+        Ok(PredictionResult {
+            label,
+            label_name: None, // see Dataset::named
+            confidence,
+            neighbor_indices: indices.into_iter().map(|x| x as u64).collect(),
+            neighbor_distances: distances,
+        })
+    }
+
+    // Callable from methods only (not user). Weight of the vote of each neighbour given its class and distance: the weight of the voting scheme,
+    // divided by the number of train points of the class when class_counts is given.
+    fn vote_weights(&self, labels: &[u8], distances: &[f64], class_counts: Option<&BTreeMap<u8, u64>>) -> Vec<f64> {
+        labels
+            .iter()
+            .zip(distances)
+            .map(|(label, &d)| {
+                let class_size = class_counts.and_then(|counts| counts.get(label)).map_or(1.0, |count| *count as f64);
+                self.voting_scheme.weight(d) / class_size
+            })
+            .collect()
+    }
+
+    // Fn callable from inside contract methods only, not by user. Parameters: array nxd, array nx1 of numeric targets, array dx1.
+    fn regress_test_point<T: AsRef<[f64]>>(&self, arr_train: &[T], arr_values: &[f64], pt: &[f64], metric: &PointMetric, k: usize) -> f64 {
+        let (indices, distances) = self.k_nearest(arr_train, pt, metric, k);
+        let first_k: Vec<f64> = indices.into_iter().map(|x| arr_values[x]).collect();
+        let weights: Vec<f64> = distances.iter().map(|&d| self.voting_scheme.weight(d)).collect(); // exact matches (d = 0) have infinite weight
+        knn_core::weighted_average(&first_k, &weights)
+    }
+
+    // Callable from methods only (not user). Returns the indices of the k nearest train points to pt (nearest first) and their distances.
+    fn k_nearest<T: AsRef<[f64]>>(&self, arr_train: &[T], pt: &[f64], metric: &PointMetric, k: usize) -> (Vec<usize>, Vec<f64>) {
+        // Get rank distances (e.g. squared Euclidean distances, no square root) from test point to all train data points
+        let rank = self.calc_rank_dist(arr_train, pt, metric);
+        // Select the k smallest in ascending order together with their indices (keep train point distances and their indices aligned).
+        let (indices, rank) = knn_core::select_k_smallest(&rank, k);
+        // True distances of the k nearest only
+        (indices, rank.into_iter().map(|r| metric.rank_to_distance(self.numeric_mode, r)).collect())
+    }
+
+    // Callable from methods only (not user). Params: array nxd, array dx1, metric to measure the distance with.
+    fn calc_dist<T: AsRef<[f64]>>(&self, arr_train: &[T], pt: &[f64], metric: &PointMetric) -> Vec<f64> {
+        let mut dist: Vec<f64> = Vec::new(); // store distanes 
+        for obs in arr_train { // for each observation in train dataset i.e. obs=[x, y]
+            dist.push(metric.distance_in(self.numeric_mode, obs.as_ref(), pt));
+        }
+        dist
+    }
+
+    // Callable from methods only (not user). Same as calc_dist with rank distances, which order the train points like the distances
+    // at a lower cost (no square root per train point for Euclidean).
+    fn calc_rank_dist<T: AsRef<[f64]>>(&self, arr_train: &[T], pt: &[f64], metric: &PointMetric) -> Vec<f64> {
+        arr_train.iter().map(|obs| metric.rank_distance(self.numeric_mode, obs.as_ref(), pt)).collect()
+    }
+}
+
+
+// ---------------------------------------------- TESTS ----------------------------------------------------------
+#[cfg(test)]
+mod tests { // start of unit tests
+    use super::*;
+    use crate::analytics::DailyUsage;
+    use crate::cache::CacheConfig;
+    use crate::governance::AdminAction;
+    use crate::token_gate::GateToken;
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApproval;
+    use near_contract_standards::non_fungible_token::core::NonFungibleTokenCore;
+    use near_contract_standards::non_fungible_token::enumeration::NonFungibleTokenEnumeration;
+    use near_contract_standards::non_fungible_token::metadata::NonFungibleTokenMetadataProvider;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::json_types::{Base64VecU8, U128};
+    use near_sdk::{serde_json, testing_env, AccountId, Gas, PromiseOrValue, PromiseResult};
+
+    // Set up a mock context. Provide a `predecessor` here, it'll modify the default context.
+    fn get_context(predecessor: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder.predecessor_account_id(predecessor);
+        builder
+    }
+
+    // Pre-pays the storage of the predictions of the accounts (see storage.rs), so that they are made without a deposit attached.
+    fn prepay_storage(contract: &mut KnnMachineLearning, context: &mut VMContextBuilder, account_ids: &[AccountId]) {
+        let saved = context.context.clone();
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        for account_id in account_ids {
+            contract.storage_deposit(Some(account_id.clone()), None);
+        }
+        context.context = saved;
+        testing_env!(context.build());
+    }
+
+    // Distance between 2 points of numeric features, with f64.
+    fn distance(metric: DistanceMetric, a: &[f64], b: &[f64]) -> f64 {
+        PointMetric::from(metric).distance_in(NumericMode::Float, a, b)
+    }
+
+    // TESTS HERE
+    #[test] 
+    fn test_default_k() { // Check that the default k value is 5
+        let contract = KnnMachineLearning::default(); 
+        assert_eq!(contract.param_k, 5, "Expected default value for k=5") 
+    }
+    
+    #[test]
+    fn test_new_k() { // Check that initialisation of k upon deployment satisfies requirements of being +ve, odd number between 1 and 15
+        KnnMachineLearning::new(3, None, None); // assert present inside new code
+    }
+
+    #[test]
+    fn test_set_k() { // k can be tuned after deployment, with the same validation as upon initialisation
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        assert_eq!(contract.set_k(7), Ok(()), "Expected equality.");
+        assert_eq!(contract.get_k(), 7, "Expected equality.");
+        assert_eq!(contract.set_k(0), Err(KnnError::InvalidK { k: 0 }), "Expected equality.");
+        assert_eq!(contract.get_k(), 7, "Expected k to be unchanged.");
+    }
+
+    #[test]
+    fn test_owner() { // the deployer owns the contract, ownership is transferred by proposing and accepting
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        assert_eq!(contract.get_owner(), accounts(1), "Expected equality.");
+        contract.propose_owner(Some(accounts(2)));
+        assert_eq!(contract.get_owner(), accounts(1), "Expected the owner to be unchanged until the proposal is accepted.");
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.accept_owner();
+        assert_eq!((contract.get_owner(), contract.get_proposed_owner()), (accounts(2), None), "Expected equality.");
+        assert_eq!(contract.set_k(5), Ok(()), "Expected the new owner to configure the contract.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Account charlie is not allowed to call this method.")]
+    fn test_not_owner() { // configuration is reserved to the owner
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let _ = contract.set_k(5);
+    }
+
+    #[test]
+    fn test_run_analysis() { // run_analysis is the top level method. Here will test that datset name was correctly specified
+        let mut context = VMContextBuilder::new(); // the default context
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &["bob.near".parse().unwrap()]);
+        let test_point: Vec<f64> = vec![2.2, 14.0]; // vector with 2 entries
+        assert!(contract.run_analysis("cancer".to_string(), test_point.clone(), None, None, None).is_ok());
+        assert!(contract.run_analysis("customer".to_string(), test_point.clone(), None, None, None).is_ok());
+        assert_eq!(
+            contract.run_analysis("wrong dataset".to_string(), test_point, None, None, None),
+            Err(KnnError::UnknownDataset { name: "wrong dataset".to_string() }),
+            "Expected a structured error."
+        );
+        let logs = get_logs();
+        assert_eq!( //Asserts that two expressions are equal to each other 
+            [logs[0].as_str(), logs[2].as_str()], 
+            [r#"LOG_JSON:{"level":"Info","event":"dataset_selected","fields":{"dataset":"cancer"}}"#, r#"LOG_JSON:{"level":"Info","event":"dataset_selected","fields":{"dataset":"customer"}}"#],
+            "Expected a successful log."
+        );
+        assert_eq!(
+            logs[1],
+            r#"EVENT_JSON:{"standard":"knn","version":"1.0.0","event":"knn_prediction","data":[{"dataset":"cancer","test_point":[2.2,14.0],"predicted_class":0,"caller":"bob.near"}]}"#,
+            "Expected a NEP-297 prediction event."
+        );
+    }
+
+    #[test]
+    fn test_prediction_history() { // every run_analysis call is recorded and can be paged through, in total and per caller
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(42).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(1), accounts(2)]);
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.run_analysis("customer".to_string(), vec![2.2, 14.0], None, None, None).unwrap();
+        contract.run_analysis("cancer".to_string(), vec![2.2, 14.0], None, None, None).unwrap();
+        let all = contract.get_predictions(0, 10);
+        assert_eq!(all.len(), 3, "Expected equality.");
+        assert_eq!((all[0].caller.clone(), all[0].result.label, all[0].timestamp), (accounts(1), 1, U64(42)), "Expected equality.");
+        let page = contract.get_predictions_by_account(accounts(2), 1, 10);
+        assert_eq!(page.iter().map(|r| (r.id, r.dataset.as_str())).collect::<Vec<_>>(), vec![(2, "cancer")], "Expected the second prediction of the caller.");
+        assert!(contract.get_predictions_by_account(accounts(3), 0, 10).is_empty(), "Expected no predictions.");
+    }
+
+    #[test]
+    fn test_report_outcome() { // the caller of a prediction reports its true class once, which updates the live accuracy of the dataset
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(2)]);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let ans = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        contract.run_analysis("cancer".to_string(), vec![2.2, 14.0], None, None, None).unwrap();
+        assert_eq!(contract.get_live_accuracy("cancer".to_string()), None, "Expected no outcomes yet.");
+        assert_eq!(contract.report_outcome(0, ans.label), Ok(true), "Expected equality.");
+        assert!(get_logs().last().unwrap().contains(r#""event":"knn_outcome""#), "Expected an outcome event.");
+        assert_eq!(contract.report_outcome(0, ans.label), Err(KnnError::OutcomeReported { prediction_id: 0 }), "Expected equality.");
+        assert_eq!(contract.report_outcome(2, 0), Err(KnnError::UnknownPrediction { prediction_id: 2 }), "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        assert_eq!(contract.report_outcome(1, 0), Err(KnnError::Unauthorized { account_id: accounts(3) }), "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(1)).build()); // the owner
+        let wrong = 1 - contract.get_predictions(1, 1)[0].result.label;
+        assert_eq!(contract.report_outcome(1, wrong), Ok(false), "Expected a disputed prediction.");
+        assert_eq!(contract.get_outcome(1), Some(wrong), "Expected equality.");
+        let live = contract.get_live_accuracy("cancer".to_string()).unwrap();
+        assert_eq!((live.n_reported, live.n_correct, live.accuracy), (2, 1, 0.5), "Expected equality.");
+    }
+
+    #[test]
+    fn test_usage_stats() { // predictions are counted in total, per dataset, per class, per caller and per day
+        let mut context = get_context(accounts(1));
+        let day = 86_400 * 1_000_000_000;
+        testing_env!(context.block_timestamp(40 * day).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(1), accounts(2)]);
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(2)).block_timestamp(75 * day).build());
+        contract.run_analysis("cancer".to_string(), vec![2.2, 14.0], None, None, None).unwrap();
+        testing_env!(context.block_timestamp(80 * day).build());
+        let label = contract.run_analysis("customer".to_string(), vec![2.2, 14.0], None, None, None).unwrap().label;
+        let stats = contract.get_usage_stats();
+        assert_eq!((stats.total_predictions, stats.unique_callers), (3, 2), "Expected equality.");
+        assert_eq!(stats.per_dataset, vec![("cancer".to_string(), 2), ("customer".to_string(), 1)], "Expected equality.");
+        assert_eq!(stats.per_class.iter().map(|(_, n)| n).sum::<u64>(), 3, "Expected every prediction counted by class.");
+        assert!(stats.per_class.iter().any(|(l, _)| *l == label), "Expected the class of the last prediction.");
+        assert_eq!(stats.daily, vec![DailyUsage { day: 75, predictions: 1 }, DailyUsage { day: 80, predictions: 1 }], "Expected the last 30 days only.");
+    }
+
+    #[test]
+    fn test_result_cache() { // repeated (rounded) queries are answered from the cache until it evicts them or the dataset changes
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build()); // attach 1 NEAR to cover storage
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_result_cache(Some(CacheConfig { decimals: 1, capacity: 2 }));
+        contract.set_log_level(LogLevel::Debug);
+        let hits = || get_logs().iter().filter(|log| log.contains(r#""event":"cache_hit""#)).count();
+        let first = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        assert_eq!(hits(), 0, "Expected a fresh classification.");
+        assert_eq!(contract.run_analysis("cancer".to_string(), vec![13.91, 1.94], None, None, None).unwrap(), first, "Expected equality.");
+        assert_eq!(hits(), 1, "Expected the rounded point to be answered from the cache.");
+        assert_eq!(contract.get_predictions(0, 10).len(), 2, "Expected cached answers to be recorded too.");
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], Some(DistanceMetric::Manhattan), None, None).unwrap();
+        assert_eq!(hits(), 1, "Expected another metric to be another query.");
+        contract.run_analysis("cancer".to_string(), vec![1.4, 14.2], None, None, None).unwrap();
+        assert_eq!(contract.get_cache_size(), 2, "Expected the capacity to bound the cache.");
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        assert_eq!(hits(), 1, "Expected the oldest result to be evicted.");
+        contract.add_training_point("cancer".to_string(), vec![14.0, 2.0], 0);
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        assert_eq!(hits(), 1, "Expected a change of the dataset to invalidate its results.");
+        contract.set_result_cache(None);
+        assert_eq!((contract.get_result_cache(), contract.get_cache_size()), (None, 0), "Expected equality.");
+    }
+
+    #[test]
+    fn test_commit_reveal() { // a committed test point is only classified (and recorded) when its request is revealed
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_index(7).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let hash = contract.commitment_hash("cancer".to_string(), vec![13.9, 1.9], "s3cret".to_string());
+        assert_eq!(contract.commit_prediction(Base64VecU8(vec![1, 2, 3])), Err(KnnError::InvalidCommitment), "Expected equality.");
+        assert_eq!(contract.commit_prediction(hash.clone()), Ok(()), "Expected equality.");
+        assert!(get_logs()[0].starts_with(r#"EVENT_JSON:{"standard":"knn","version":"1.0.0","event":"knn_commit""#), "Expected a commit event.");
+        assert_eq!(contract.get_commitment(accounts(1)).map(|c| c.block_height), Some(U64(7)), "Expected equality.");
+        assert!(contract.get_predictions(0, 10).is_empty(), "Expected the test point to stay private until revealed.");
+        testing_env!(context.block_index(8).build());
+        let wrong_salt = contract.reveal_and_classify("cancer".to_string(), vec![13.9, 1.9], "guess".to_string());
+        assert_eq!(wrong_salt, Err(KnnError::CommitmentMismatch), "Expected equality.");
+        testing_env!(context.attached_deposit(10u128.pow(22)).build()); // paying the fee: an error must revert to return it
+        let paid_wrong_salt = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.reveal_and_classify("cancer".to_string(), vec![13.9, 1.9], "guess".to_string())));
+        assert!(paid_wrong_salt.is_err(), "Expected the call to panic.");
+        let ans = contract.reveal_and_classify("cancer".to_string(), vec![13.9, 1.9], "s3cret".to_string()).unwrap();
+        assert_eq!(ans.label, 1, "Expected equality.");
+        assert_eq!(contract.get_predictions(0, 10)[0].test_point, vec![13.9, 1.9], "Expected the revealed prediction to be recorded.");
+        assert!(get_logs().last().unwrap().contains(r#""event":"knn_reveal","data":[{"caller":"bob","#), "Expected a reveal event.");
+        assert_eq!(contract.get_commitment(accounts(1)), None, "Expected the commitment to be consumed.");
+        let paid_again = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.reveal_and_classify("cancer".to_string(), vec![13.9, 1.9], "s3cret".to_string())));
+        assert!(paid_again.is_err(), "Expected the call to panic.");
+        testing_env!(context.attached_deposit(0).build());
+        let again = contract.reveal_and_classify("cancer".to_string(), vec![13.9, 1.9], "s3cret".to_string());
+        assert_eq!(again, Err(KnnError::NoCommitment { account_id: accounts(1) }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_privacy_noise() { // with privacy noise, confidences and class counts are perturbed (reproducibly within a block) and neighbours withheld
+        let mut context = get_context(accounts(1));
+        testing_env!(context.random_seed(vec![7; 32]).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let exact = contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap();
+        let exact_counts = contract.class_distribution("cancer".to_string()).unwrap();
+        assert_eq!(contract.set_privacy_noise(Some(0.0)), Err(KnnError::InvalidEpsilon { epsilon: 0.0 }), "Expected equality.");
+        assert_eq!(contract.set_privacy_noise(Some(1.0)), Ok(()), "Expected equality.");
+        let noisy = contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap();
+        assert_eq!(noisy.label, exact.label, "Expected the vote itself to be unchanged.");
+        assert!(noisy.confidence != exact.confidence && (0.0..=1.0).contains(&noisy.confidence), "Expected a noisy confidence between 0 and 1.");
+        assert!(noisy.neighbor_indices.is_empty() && noisy.neighbor_distances.is_empty(), "Expected the neighbours to be withheld.");
+        assert_eq!(contract.nearest_neighbors("cancer".to_string(), vec![13.9, 1.9], 3), Ok(Vec::new()), "Expected the neighbours to be withheld.");
+        assert_eq!(contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap(), noisy, "Expected the same noise for the same query in the block.");
+        assert!(contract.predict_proba("cancer".to_string(), vec![13.9, 1.9]).unwrap().iter().all(|(_, p)| (0.0..=1.0).contains(p)), "Expected probabilities between 0 and 1.");
+        contract.set_privacy_noise(Some(0.1)).unwrap(); // counts: noise of scale 10
+        let noisy_counts = contract.class_distribution("cancer".to_string()).unwrap();
+        assert!(noisy_counts != exact_counts, "Expected noisy class counts.");
+        let description = contract.describe_dataset("cancer".to_string()).unwrap();
+        assert_eq!(description.class_counts, noisy_counts.iter().map(|c| (c.label, c.count)).collect::<Vec<_>>(), "Expected the same noisy counts.");
+        contract.set_privacy_noise(Some(1e12)).unwrap(); // negligible noise
+        let almost_exact = contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap();
+        assert!((almost_exact.confidence - exact.confidence).abs() < 1e-9, "Expected the noise to shrink with a larger budget.");
+        contract.set_privacy_noise(None).unwrap();
+        assert_eq!(contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap(), exact, "Expected equality.");
+        assert_eq!(contract.nearest_neighbors("cancer".to_string(), vec![13.9, 1.9], 3).unwrap().len(), 3, "Expected equality.");
+    }
+
+    #[test]
+    fn test_mint_prediction_nft() { // a prediction can be minted as an NFT owned by the caller, with the prediction in its metadata
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.run_analysis("customer".to_string(), vec![2.2, 14.0], None, None, None).unwrap(); // not minted
+        let ans = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, Some(true)).unwrap();
+        assert!(contract.nft_token("0".to_string()).is_none(), "Expected no token for a prediction that was not minted.");
+        let token = contract.nft_token("1".to_string()).unwrap(); // token id is the id of the prediction in the history
+        assert_eq!(token.owner_id, accounts(1), "Expected equality.");
+        let metadata = token.metadata.unwrap();
+        assert_eq!(metadata.title.unwrap(), "KNN prediction #1", "Expected equality.");
+        assert_eq!(metadata.description.unwrap(), "Class 1 predicted for the test point [13.9, 1.9] of the 'cancer' dataset, with confidence 0.67.", "Expected equality.");
+        assert_eq!(contract.nft_metadata().spec, "nft-1.0.0", "Expected equality.");
+        let extra = metadata.extra.unwrap();
+        assert_eq!(extra, format!(r#"{{"dataset":"cancer","test_point":[13.9,1.9],"predicted_class":{},"confidence":{}}}"#, ans.label, ans.confidence), "Expected equality.");
+    }
+
+    #[test]
+    fn test_nft_enumeration() { // holders can list their prediction NFTs page by page
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for test_point in [vec![13.9, 1.9], vec![2.2, 14.0], vec![7.0, 9.0]] {
+            contract.run_analysis("cancer".to_string(), test_point, None, None, Some(true)).unwrap();
+        }
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, Some(true)).unwrap();
+        assert_eq!(contract.nft_total_supply(), U128(4), "Expected equality.");
+        assert_eq!(contract.nft_supply_for_owner(accounts(1)), U128(3), "Expected equality.");
+        let page = contract.nft_tokens_for_owner(accounts(1), Some(U128(1)), Some(5));
+        assert_eq!(page.len(), 2, "Expected the 2 tokens after the first one.");
+        assert!(page.iter().all(|token| token.owner_id == accounts(1)), "Expected tokens of the owner only.");
+        assert_eq!(contract.nft_tokens(Some(U128(3)), Some(5))[0].owner_id, accounts(2), "Expected equality.");
+    }
+
+    #[test]
+    fn test_nft_approvals() { // holders can let a marketplace transfer their prediction NFTs
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, Some(true)).unwrap(); // token "0"
+        contract.run_analysis("cancer".to_string(), vec![2.2, 14.0], None, None, Some(true)).unwrap(); // token "1"
+        testing_env!(context.attached_deposit(10u128.pow(22)).build()); // covers the storage of the approval
+        contract.nft_approve("0".to_string(), accounts(2), None);
+        contract.nft_approve("1".to_string(), accounts(2), None);
+        contract.nft_approve("1".to_string(), accounts(3), None);
+        assert!(contract.nft_is_approved("0".to_string(), accounts(2), Some(1)), "Expected the first approval of the token to have id 1.");
+        assert!(!contract.nft_is_approved("0".to_string(), accounts(3), None), "Expected no approval.");
+        testing_env!(context.attached_deposit(1).build());
+        contract.nft_revoke("1".to_string(), accounts(2));
+        assert!(!contract.nft_is_approved("1".to_string(), accounts(2), None), "Expected the approval to be revoked.");
+        contract.nft_revoke_all("1".to_string());
+        assert!(!contract.nft_is_approved("1".to_string(), accounts(3), None), "Expected every approval to be revoked.");
+        testing_env!(context.predecessor_account_id(accounts(2)).build()); // the marketplace sells token "0" to accounts(4)
+        contract.nft_transfer(accounts(4), "0".to_string(), Some(1), None);
+        let token = contract.nft_token("0".to_string()).unwrap();
+        assert_eq!((token.owner_id, token.approved_account_ids.unwrap().len()), (accounts(4), 0), "Expected the transfer to clear the approvals.");
+    }
+
+    #[test]
+    fn test_nft_royalties() { // resales of a prediction NFT pay royalties to the dataset creator and the contract owner
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let royalties = Royalties { dataset_creator_bps: 500, owner_bps: 250 };
+        assert_eq!(contract.set_royalties(Royalties { dataset_creator_bps: 5000, owner_bps: 1 }), Err(KnnError::InvalidRoyalties { total_bps: 5001 }), "Expected equality.");
+        assert_eq!(contract.set_royalties(Royalties { dataset_creator_bps: u16::MAX, owner_bps: u16::MAX }), Err(KnnError::InvalidRoyalties { total_bps: 131_070 }), "Expected an error rather than an overflow.");
+        contract.set_royalties(royalties).unwrap();
+        assert_eq!(contract.get_royalties(), royalties, "Expected equality.");
+        contract.grant_role(accounts(2), Role::DataProvider);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.add_training_point("iris".to_string(), vec![1.0, 1.0], 0);
+        contract.add_training_point("iris".to_string(), vec![2.0, 2.0], 1);
+        contract.add_training_point("iris".to_string(), vec![3.0, 3.0], 1);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.run_analysis("iris".to_string(), vec![2.5, 2.5], None, None, Some(true)).unwrap(); // token "0"
+        let payout = contract.nft_payout("0".to_string(), U128(10_000), Some(3)).payout;
+        assert_eq!((payout[&accounts(2)], payout[&accounts(1)], payout[&accounts(3)]), (U128(500), U128(250), U128(9250)), "Expected the royalties and the rest to the holder.");
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, Some(true)).unwrap(); // toy dataset: both shares to the owner
+        let payout = contract.nft_payout("1".to_string(), U128(10_000), None).payout;
+        assert_eq!((payout.len(), payout[&accounts(1)]), (2, U128(750)), "Expected equality.");
+        testing_env!(context.attached_deposit(1).build());
+        let payout = contract.nft_transfer_payout(accounts(4), "0".to_string(), None, None, U128(1000), None).payout;
+        assert_eq!(payout[&accounts(3)], U128(925), "Expected the sale to be paid to the previous holder.");
+        assert_eq!(contract.nft_token("0".to_string()).unwrap().owner_id, accounts(4), "Expected equality.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Payout to 3 accounts exceeds max_len_payout of 2.")]
+    fn test_nft_payout_too_long() { // marketplaces can bound the number of accounts they pay
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_royalties(Royalties { dataset_creator_bps: 500, owner_bps: 250 }).unwrap();
+        contract.grant_role(accounts(2), Role::DataProvider);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        for label in [0, 1, 1] {
+            contract.add_training_point("iris".to_string(), vec![1.0, 1.0], label);
+        }
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.run_analysis("iris".to_string(), vec![1.0, 1.0], None, None, Some(true)).unwrap();
+        contract.nft_payout("0".to_string(), U128(10_000), Some(2));
+    }
+
+    #[test]
+    fn test_model_nft() { // the holder of a dataset's NFT receives its prediction fees, and transferring the token transfers them
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_prediction_fee(U128(1000));
+        contract.grant_role(accounts(2), Role::DataProvider);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        for (point, label) in [(vec![1.0, 1.0], 0), (vec![2.0, 2.0], 1), (vec![3.0, 3.0], 1)] {
+            contract.add_training_point("iris".to_string(), point, label);
+        }
+        assert_eq!(contract.mint_model_nft("iris".to_string()), "model:iris", "Expected equality.");
+        assert_eq!(contract.get_model_holder("iris".to_string()), Some(accounts(2)), "Expected the creator to hold the model.");
+        let metadata = contract.nft_token("model:iris".to_string()).unwrap().metadata.unwrap();
+        assert_eq!(metadata.title.unwrap(), "KNN model 'iris'", "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.run_analysis("iris".to_string(), vec![2.5, 2.5], None, None, None).unwrap();
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap(); // no NFT: the fee goes to the treasury
+        assert_eq!((contract.get_rewards(accounts(2)), contract.get_treasury_balance()), (U128(1000), U128(1000)), "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1).build());
+        contract.nft_transfer(accounts(4), "model:iris".to_string(), None, None);
+        testing_env!(context.predecessor_account_id(accounts(3)).attached_deposit(10u128.pow(24)).build());
+        contract.run_analysis("iris".to_string(), vec![2.5, 2.5], None, None, None).unwrap();
+        assert_eq!((contract.get_rewards(accounts(2)), contract.get_rewards(accounts(4))), (U128(1000), U128(1000)), "Expected the new holder to receive the fees.");
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.remove_dataset("iris".to_string()).unwrap();
+        assert!(contract.nft_token("model:iris".to_string()).is_none(), "Expected the NFT to be burnt with its dataset.");
+        assert_eq!(contract.nft_supply_for_owner(accounts(4)), U128(0), "Expected equality.");
+    }
+
+    #[test]
+    #[should_panic(expected = "The NFT of the cancer dataset was already minted.")]
+    fn test_model_nft_minted_once() { // a dataset has a single NFT
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.mint_model_nft("cancer".to_string()); // toy dataset: minted by the owner
+        contract.mint_model_nft("cancer".to_string());
+    }
+
+    #[test]
+    fn test_prediction_fee() { // once a fee is set run_analysis collects it
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(1)]);
+        assert!(contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).is_ok(), "Expected free predictions by default.");
+        contract.set_prediction_fee(U128(1000));
+        assert_eq!(contract.get_prediction_fee(), U128(1000), "Expected equality.");
+        testing_env!(context.attached_deposit(1500).build()); // surplus of 500 is refunded
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        assert_eq!(contract.get_treasury_balance(), U128(2000), "Expected equality.");
+    }
+
+    #[test]
+    fn test_ft_payment() { // fees can be paid in a whitelisted fungible token with ft_transfer_call, the surplus is refunded
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(2)]);
+        contract.set_ft_fee(accounts(5), Some(U128(100)));
+        assert_eq!(contract.get_ft_fee(accounts(5)), Some(U128(100)), "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(5)).build()); // the token contract
+        let msg = r#"{"data_set": "cancer", "test_point": [13.9, 1.9], "k_override": 5}"#.to_string();
+        match contract.ft_on_transfer(accounts(2), U128(150), msg) {
+            PromiseOrValue::Value(unused) => assert_eq!(unused, U128(50), "Expected the surplus to be refunded."),
+            PromiseOrValue::Promise(_) => panic!("Expected a value."),
+        }
+        let record = contract.get_predictions_by_account(accounts(2), 0, 1).pop().unwrap();
+        assert_eq!((record.test_point, record.result.neighbor_indices.len()), (vec![13.9, 1.9], 5), "Expected the prediction of the sender.");
+        assert_eq!(contract.get_ft_treasury_balance(accounts(5)), U128(100), "Expected equality.");
+        let balance = contract.storage_balance_of(accounts(2)).unwrap();
+        assert!(balance.available.0 < balance.total.0 - contract.storage_balance_bounds().min.0, "Expected the storage of the prediction to be drawn from the sender's balance.");
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        assert_eq!(
+            contract.withdraw_ft_fees(accounts(5), U128(101), accounts(1)),
+            Err(KnnError::InsufficientTreasury { requested: U128(101), available: U128(100) }),
+            "Expected equality."
+        );
+        contract.withdraw_ft_fees(accounts(5), U128(100), accounts(1)).unwrap();
+        assert_eq!(get_created_receipts().len(), 2, "Expected the ft_transfer and its callback.");
+        assert_eq!(contract.get_ft_treasury_balance(accounts(5)), U128(0), "Expected equality.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Storage balance too low")]
+    fn test_ft_payment_storage() { // the storage of a prediction paid in tokens is paid from the sender's storage balance
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_ft_fee(accounts(5), Some(U128(100)));
+        testing_env!(context.predecessor_account_id(accounts(5)).build());
+        let _ = contract.ft_on_transfer(accounts(2), U128(100), r#"{"data_set": "cancer", "test_point": [13.9, 1.9]}"#.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_prediction_storage() { // the caller of run_analysis pays for the storage of the prediction record, even without a fee
+        testing_env!(get_context(accounts(2)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let _ = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token charlie is not accepted for the prediction fees.")]
+    fn test_ft_payment_unsupported_token() { // transfers of other tokens are refunded in full
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_ft_fee(accounts(5), Some(U128(100)));
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let _ = contract.ft_on_transfer(accounts(3), U128(100), r#"{"data_set": "cancer", "test_point": [13.9, 1.9]}"#.to_string());
+    }
+
+    #[test]
+    fn test_classify_for() { // another contract gets the prediction as the value of its call, including structured errors
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(5)]);
+        contract.set_prediction_fee(U128(1000));
+        testing_env!(context.predecessor_account_id(accounts(5)).attached_deposit(1000).build()); // the calling contract
+        let ans = contract.classify_for("cancer".to_string(), vec![13.9, 1.9], None).unwrap();
+        assert_eq!(ans, contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap(), "Expected the same prediction as classify.");
+        assert_eq!(contract.get_predictions_by_account(accounts(5), 0, 10).len(), 1, "Expected the prediction to be recorded for the calling contract.");
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(0).build());
+        contract.set_prediction_fee(U128(0));
+        testing_env!(context.predecessor_account_id(accounts(5)).build());
+        let value = serde_json::to_string(&contract.classify_for("cancer".to_string(), vec![13.9, 1.9, 0.0], None)).unwrap();
+        assert_eq!(value, r#"{"Err":{"DimensionMismatch":{"expected":2,"actual":3}}}"#, "Expected the error as the value of the promise.");
+    }
+
+    #[test]
+    fn test_oracle_features() { // the test point can be read from an oracle, the fee is only kept when the prediction is made
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_prediction_fee(U128(1000));
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1500).prepaid_gas(Gas(300_000_000_000_000)).build());
+        contract.run_analysis_from_oracle("cancer".to_string(), accounts(5), "patient-42".to_string(), None);
+        assert_eq!(get_created_receipts().len(), 2, "Expected the oracle call and its callback.");
+        let request = || OracleRequest { dataset: "cancer".to_string(), oracle_account: accounts(5), caller: accounts(2), deposit: U128(1500), k_override: None };
+        let mut oracle_returned = |result: PromiseResult| testing_env!(context.predecessor_account_id(accounts(0)).build(), Default::default(), Default::default(), Default::default(), vec![result]);
+        oracle_returned(PromiseResult::Successful(b"[13.9, 1.9]".to_vec()));
+        let ans = contract.on_oracle_features(request());
+        assert_eq!(ans, contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None), "Expected the prediction of the oracle's point.");
+        assert_eq!(contract.get_predictions_by_account(accounts(2), 0, 10).len(), 1, "Expected the prediction to be recorded for the caller.");
+        assert_eq!((contract.get_treasury_balance(), get_created_receipts().len()), (U128(1000), 1), "Expected the fee to be kept and the surplus refunded.");
+        oracle_returned(PromiseResult::Failed);
+        let ans = contract.on_oracle_features(request());
+        assert_eq!(ans, Err(KnnError::OracleFailed { oracle_account: accounts(5) }), "Expected equality.");
+        assert_eq!((contract.get_treasury_balance(), get_created_receipts().len()), (U128(1000), 1), "Expected the deposit to be refunded.");
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1500).prepaid_gas(Gas(80_000_000_000_000)).build());
+        let starved = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.run_analysis_from_oracle("cancer".to_string(), accounts(5), "patient-42".to_string(), None)));
+        assert!(starved.is_err(), "Expected too little gas for the prediction to be rejected.");
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(0).build());
+        contract.set_subscription_tier("pack".to_string(), Some(SubscriptionTier { price: U128(1500), predictions: Some(2), duration_days: None })).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(10u128.pow(22)).build());
+        contract.subscribe("pack".to_string());
+        testing_env!(context.attached_deposit(0).prepaid_gas(Gas(300_000_000_000_000)).build());
+        contract.run_analysis_from_oracle("cancer".to_string(), accounts(5), "patient-42".to_string(), None); // no fee attached
+        testing_env!(context.predecessor_account_id(accounts(0)).build(), Default::default(), Default::default(), Default::default(), vec![PromiseResult::Successful(b"[13.9, 1.9]".to_vec())]);
+        contract.on_oracle_features(OracleRequest { deposit: U128(0), ..request() }).unwrap();
+        assert_eq!(contract.get_treasury_balance(), U128(2500), "Expected the price of the subscription only.");
+        assert_eq!(contract.get_subscription(accounts(2)).unwrap().predictions_left, Some(1), "Expected the subscription to be used.");
+    }
+
+    #[test]
+    fn test_withdraw_fees() { // collected fees can be withdrawn, but not more than the treasury holds
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.fees_collected = 2000;
+        assert_eq!(
+            contract.withdraw_fees(U128(2001), accounts(2)),
+            Err(KnnError::InsufficientTreasury { requested: U128(2001), available: U128(2000) }),
+            "Expected a structured error."
+        );
+        contract.withdraw_fees(U128(1500), accounts(2)).unwrap();
+        assert_eq!(contract.get_treasury_balance(), U128(500), "Expected equality.");
+    }
+
+    #[test]
+    fn test_contribution_rewards() { // a share of the prediction fees is credited to the contributors in proportion to the rows they added
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let fee = 13 * 10u128.pow(21);
+        contract.set_prediction_fee(U128(fee));
+        assert_eq!(contract.set_reward_share(10001), Err(KnnError::InvalidRewardShare { share_bps: 10001 }), "Expected equality.");
+        contract.set_reward_share(5000).unwrap(); // half of the fees
+        contract.grant_role(accounts(2), Role::DataProvider);
+        contract.grant_role(accounts(3), Role::DataProvider);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.add_training_point("cancer".to_string(), vec![13.0, 2.0], 0);
+        contract.add_training_point("cancer".to_string(), vec![3.0, 12.0], 0);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.add_training_point("cancer".to_string(), vec![5.0, 5.0], 1); // 10 toy rows + 3 contributed
+        testing_env!(context.predecessor_account_id(accounts(4)).build());
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        let per_row = fee / 2 / 13;
+        assert_eq!((contract.get_rewards(accounts(2)), contract.get_rewards(accounts(3))), (U128(2 * per_row), U128(per_row)), "Expected rewards per row contributed.");
+        assert_eq!(contract.get_treasury_balance(), U128(fee - 3 * per_row), "Expected the rest of the fee, including the share of the toy rows.");
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.add_training_point("cancer".to_string(), vec![6.0, 6.0], 1); // settles the earlier rewards before counting the new row
+        testing_env!(context.predecessor_account_id(accounts(4)).build());
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        let per_row_2 = fee / 2 / 14;
+        assert_eq!(contract.get_rewards(accounts(3)), U128(per_row + 2 * per_row_2), "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        assert_eq!(contract.claim_rewards(), U128(2 * per_row + 2 * per_row_2), "Expected equality.");
+        assert_eq!(contract.get_rewards(accounts(2)), U128(0), "Expected the rewards to be paid out.");
+        assert_eq!(get_created_receipts().len(), 2, "Expected the transfer and its callback.");
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.remove_dataset("cancer".to_string()).unwrap();
+        assert_eq!(contract.get_rewards(accounts(3)), U128(per_row + 2 * per_row_2), "Expected the rewards to outlive the dataset.");
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        assert_eq!(contract.claim_rewards(), U128(per_row + 2 * per_row_2), "Expected equality.");
+        assert!(contract.contributed_datasets.get(&accounts(3)).unwrap().is_empty(), "Expected the removed dataset to be forgotten.");
+    }
+
+    #[test]
+    fn test_subscription() { // subscribers make predictions without fees until their predictions are used up or the subscription expires
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(2)]);
+        contract.set_prediction_fee(U128(1000));
+        let pack = SubscriptionTier { price: U128(1500), predictions: Some(2), duration_days: None };
+        assert_eq!(contract.set_subscription_tier("unlimited".to_string(), Some(SubscriptionTier { predictions: None, ..pack.clone() })), Err(KnnError::InvalidTier { name: "unlimited".to_string() }), "Expected equality.");
+        contract.set_subscription_tier("pack".to_string(), Some(pack)).unwrap();
+        contract.set_subscription_tier("month".to_string(), Some(SubscriptionTier { price: U128(20_000), predictions: None, duration_days: Some(30) })).unwrap();
+        assert_eq!(contract.get_subscription_tiers().len(), 2, "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(10u128.pow(22)).build());
+        contract.subscribe("pack".to_string());
+        testing_env!(context.attached_deposit(0).build());
+        for _ in 0..2 {
+            contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        }
+        assert_eq!(contract.get_treasury_balance(), U128(1500), "Expected the price of the subscription only.");
+        assert!(contract.get_subscription(accounts(2)).is_none(), "Expected the subscription to be used up.");
+        testing_env!(context.attached_deposit(10u128.pow(22)).build());
+        let month = contract.subscribe("month".to_string());
+        assert_eq!(month.expires_at, Some(U64(30 * 86_400 * 1_000_000_000)), "Expected equality.");
+        testing_env!(context.attached_deposit(0).block_timestamp(29 * 86_400 * 1_000_000_000).build());
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        assert_eq!(contract.get_subscription(accounts(2)).unwrap().predictions_left, None, "Expected unlimited predictions.");
+        testing_env!(context.block_timestamp(30 * 86_400 * 1_000_000_000).build());
+        assert!(contract.get_subscription(accounts(2)).is_none(), "Expected the subscription to expire.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach at least 1000 yoctoNEAR, got 0.")]
+    fn test_subscription_expired() { // once expired, predictions need the fee again
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_prediction_fee(U128(1000));
+        contract.set_subscription_tier("day".to_string(), Some(SubscriptionTier { price: U128(0), predictions: None, duration_days: Some(1) })).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(10u128.pow(22)).build());
+        contract.subscribe("day".to_string());
+        testing_env!(context.attached_deposit(0).block_timestamp(86_400 * 1_000_000_000).build());
+        let _ = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None);
+    }
+
+    #[test]
+    fn test_token_gate() { // predictions need a verified access pass, which is cached until it expires or the gate changes
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(1), accounts(2)]);
+        contract.set_token_gate(Some(TokenGate { token: GateToken::Ft { contract_id: accounts(5), min_balance: U128(100) }, cache_hours: 1 }));
+        assert!(contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).is_ok(), "Expected the owner not to be gated.");
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let ans = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None);
+        assert_eq!(ans, Err(KnnError::AccessPassRequired { account_id: accounts(2) }), "Expected equality.");
+        contract.verify_access();
+        assert_eq!(get_created_receipts().len(), 2, "Expected the balance query and its callback.");
+        let mut token_returned = |balance: &str| testing_env!(context.predecessor_account_id(accounts(0)).build(), Default::default(), Default::default(), Default::default(), vec![PromiseResult::Successful(balance.as_bytes().to_vec())]);
+        token_returned("\"99\"");
+        assert!(!contract.on_access_verified(accounts(2), 1), "Expected the balance to be too low.");
+        token_returned("\"100\"");
+        assert!(contract.on_access_verified(accounts(2), 1), "Expected the pass to be verified.");
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        assert!(contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).is_ok(), "Expected the cached pass to give access.");
+        testing_env!(context.block_timestamp(3_600 * 1_000_000_000).build());
+        assert!(!contract.has_access_pass(accounts(2)), "Expected the pass to expire.");
+    }
+
+    #[test]
+    fn test_token_gate_changed() { // a pass verified for a previous gate does not give access
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let gate = TokenGate { token: GateToken::Nft { contract_id: accounts(5) }, cache_hours: 24 };
+        contract.set_token_gate(Some(gate.clone()));
+        testing_env!(context.predecessor_account_id(accounts(0)).build(), Default::default(), Default::default(), Default::default(), vec![PromiseResult::Successful(b"\"1\"".to_vec())]);
+        assert!(contract.on_access_verified(accounts(2), 1), "Expected the pass to be verified.");
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_token_gate(Some(gate));
+        assert!(!contract.has_access_pass(accounts(2)), "Expected the pass to be voided.");
+        contract.set_token_gate(None);
+        assert!(contract.has_access_pass(accounts(2)), "Expected no gate without a token.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach at least 1000 yoctoNEAR, got 999.")]
+    fn test_prediction_fee_insufficient() { // calls that do not pay the fee are rejected
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_prediction_fee(U128(1000));
+        testing_env!(context.attached_deposit(999).build());
+        let _ = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None);
+    }
+
+    #[test]
+    fn test_errors() { // wrong dimensionality, wrong kind of targets and invalid metrics are reported as structured errors
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        assert_eq!(
+            contract.classify("cancer".to_string(), vec![1.0, 2.0, 3.0], None, None),
+            Err(KnnError::DimensionMismatch { expected: 2, actual: 3 }),
+            "Expected equality."
+        );
+        assert_eq!(contract.predict_value("cancer".to_string(), vec![1.0, 2.0], None), Err(KnnError::TargetMismatch { name: "cancer".to_string() }), "Expected equality.");
+        assert_eq!(contract.set_distance_metric(DistanceMetric::Minkowski(0.5)), Err(KnnError::InvalidMetric { p: 0.5 }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_k_override() { // k given in the call is used for that call only and is validated against the dataset size
+        let mut context = VMContextBuilder::new(); // the default context
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &["bob.near".parse().unwrap()]);
+        let ans = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, Some(1), None).unwrap();
+        assert_eq!((ans.label, ans.neighbor_indices), (1, vec![2]), "Expected the single nearest neighbour.");
+        assert_eq!(contract.param_k, 3, "Expected the contract's k to be unchanged.");
+        assert_eq!(contract.classify("cancer".to_string(), vec![13.9, 1.9], None, Some(4)), Err(KnnError::InvalidK { k: 4 }), "Expected equality.");
+        assert_eq!(contract.classify("cancer".to_string(), vec![13.9, 1.9], None, Some(11)), Err(KnnError::KTooLarge { k: 11, n_points: 10 }), "Expected equality.");
+    }
+
+    #[test]
+    #[should_panic(expected = "k must be positive and odd between 1 and 15!")]
+    fn test_new_invalid_k() { // init cannot return an error, so an even k panics
+        KnnMachineLearning::new(4, None, None);
+    }
+
+    #[test]
+    fn test_classify() { // view method gives the same prediction as run_analysis
+        let mut context = VMContextBuilder::new(); // the default context
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &["bob.near".parse().unwrap()]);
+        let test_point: Vec<f64> = vec![13.9, 1.9];
+        let view_ans = contract.classify("cancer".to_string(), test_point.clone(), None, None).unwrap();
+        assert_eq!(view_ans, contract.run_analysis("cancer".to_string(), test_point, None, None, None).unwrap(), "Expected equality.");
+        assert_eq!(view_ans.label, 1, "Expected equality.");
+    }
+
+    #[test]
+    fn test_calc_dist() { // check knn algo's sub-tasks work correctly
+        let contract = KnnMachineLearning::new(3, None, None);
+        let test_point: [f64; 2] = [15.8, 2.0]; // vector with 2 entries
+        let d = contract.calc_dist(TOY_CANCER_TRAIN, &test_point, &DistanceMetric::Euclidean.into());
+        let mut rounded_d = Vec::new();
+        for elem in d {
+            rounded_d.push((elem * 100.0).round() / 100.0);
+        }
+        assert_eq!(rounded_d, vec![18.87, 8.65, 0.00, 11.31, 4.16, 0.81, 3.40, 11.92, 3.90, 14.02], "Expected equality."); // Correct answer obtained from the correct code in Python.
+    }
+
+    #[test]
+    fn test_distance_metrics() { // distances between [1, 2] and [4, 6] (differences 3 and 4) for every metric
+        let (a, b) = ([1.0, 2.0], [4.0, 6.0]);
+        assert_eq!(distance(DistanceMetric::Euclidean, &a, &b), 5.0, "Expected equality.");
+        assert_eq!(distance(DistanceMetric::Manhattan, &a, &b), 7.0, "Expected equality.");
+        assert_eq!(distance(DistanceMetric::Chebyshev, &a, &b), 4.0, "Expected equality.");
+        assert_eq!(distance(DistanceMetric::Minkowski(2.0), &a, &b), 5.0, "Expected Minkowski p=2 to equal Euclidean.");
+        assert_eq!(distance(DistanceMetric::Minkowski(1.0), &a, &b), 7.0, "Expected Minkowski p=1 to equal Manhattan.");
+    }
+
+    #[test]
+    fn test_custom_metric() { // a Metric implemented outside metric.rs is searched and voted with like the built-in ones
+        use metric::Metric;
+        struct FirstFeature; // |a_0 - b_0|: only the first feature counts
+        impl Metric for FirstFeature {
+            fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+                (a[0] - b[0]).abs()
+            }
+        }
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let arr_train = [vec![0.0, 5.0], vec![1.0, 0.0]];
+        let ans = contract.classify_test_point(&arr_train, &[0, 1], &[0.2, 0.0], &PointMetric(Box::new(FirstFeature)), 1).unwrap();
+        assert_eq!((ans.label, ans.neighbor_distances), (0, vec![0.2]), "Expected equality.");
+        assert_eq!(contract.classify_test_point(&arr_train, &[0, 1], &[0.2, 0.0], &DistanceMetric::Euclidean.into(), 1).unwrap().label, 1, "Expected equality.");
+        // the default metric of a dataset is part of its metadata
+        contract.set_dataset_metric("cancer".to_string(), Some(DistanceMetric::Manhattan)).unwrap();
+        assert_eq!(contract.get_dataset_meta("cancer".to_string()).unwrap().metric, Some(DistanceMetric::Manhattan), "Expected equality.");
+    }
+
+    #[test]
+    fn test_voting_rules() { // each rule decides from the classes and vote weights alone, per dataset or per call
+        let (labels, weights) = ([0, 1, 1], [3.0, 1.0, 1.0]);
+        assert_eq!(DecisionRule::Majority.rule().decide(&labels, &weights, TieBreak::LowestLabel), Ok((1, 2.0 / 3.0)), "Expected equality.");
+        assert_eq!(DecisionRule::Weighted.rule().decide(&labels, &weights, TieBreak::LowestLabel), Ok((0, 0.6)), "Expected equality.");
+        let abstained = KnnError::Abstained { label: 0, confidence: 0.6, threshold: 0.7 };
+        assert_eq!(DecisionRule::Threshold(0.7).rule().decide(&labels, &weights, TieBreak::LowestLabel), Err(abstained), "Expected equality.");
+        assert_eq!(DecisionRule::Weighted.rule().decide(&[0, 1], &[1.0, 1.0], TieBreak::LowestLabel), Ok((0, 0.5)), "Expected equality.");
+        assert_eq!(DecisionRule::Abstain.rule().decide(&[0, 1], &[1.0, 1.0], TieBreak::LowestLabel), Err(KnnError::TiedVote { labels: vec![0, 1] }), "Expected equality.");
+        assert_eq!(DecisionRule::Threshold(1.5).validate(), Err(KnnError::InvalidThreshold { threshold: 1.5 }), "Expected equality.");
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, Some(VotingScheme::InverseDistance), None);
+        for (x, label) in [(0.0, 0), (1.0, 1), (1.1, 1)] {
+            contract.add_training_point("bob/votes".to_string(), vec![x], label);
+        }
+        let label = |contract: &KnnMachineLearning| contract.classify("bob/votes".to_string(), vec![0.1], None, None).unwrap().label;
+        assert_eq!(label(&contract), 0, "Expected the nearest point to outweigh the others.");
+        contract.set_decision_rule("bob/votes".to_string(), Some(DecisionRule::Majority)).unwrap();
+        assert_eq!(label(&contract), 1, "Expected equality.");
+        assert_eq!(contract.classify_by_rule("bob/votes".to_string(), vec![0.1], DecisionRule::Weighted).map(|ans| ans.label), Ok(0), "Expected equality.");
+    }
+
+    #[test]
+    fn test_model_registry() { // KNN is always registered, and classify_with selects a registered model at call time
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        assert_eq!(contract.get_models("cancer".to_string()), Ok(vec![Algorithm::Knn]), "Expected equality.");
+        assert_eq!(contract.register_model("cancer".to_string(), Algorithm::Knn), Ok(()), "Expected equality.");
+        assert_eq!(contract.set_default_model("cancer".to_string(), Some(Algorithm::Knn)), Ok(()), "Expected equality.");
+        assert_eq!(contract.get_models("cancer".to_string()), Ok(vec![Algorithm::Knn]), "Expected KNN only once.");
+        let knn = contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None);
+        assert_eq!(contract.classify_with("cancer".to_string(), vec![13.9, 1.9], Algorithm::Knn), knn, "Expected equality.");
+        contract.add_regression_point("bob/prices".to_string(), vec![1.0], 10.0);
+        assert_eq!(contract.register_model("bob/prices".to_string(), Algorithm::Knn), Err(KnnError::TargetMismatch { name: "bob/prices".to_string() }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_nearest_centroid() { // the class of the nearest class mean, kept up to date as rows are uploaded
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for (point, label) in [([0.0, 0.0], 0), ([1.0, 0.0], 0), ([0.0, 1.0], 0), ([10.0, 10.0], 1), ([10.0, 11.0], 1), ([1.5, 1.5], 1)] {
+            contract.add_training_point("bob/blobs".to_string(), point.to_vec(), label);
+        }
+        let unregistered = KnnError::UnregisteredModel { dataset: "bob/blobs".to_string(), algorithm: Algorithm::NearestCentroid };
+        assert_eq!(contract.classify_with("bob/blobs".to_string(), vec![1.4, 1.4], Algorithm::NearestCentroid), Err(unregistered), "Expected equality.");
+        contract.register_model("bob/blobs".to_string(), Algorithm::NearestCentroid).unwrap();
+        assert_eq!(contract.classify("bob/blobs".to_string(), vec![1.4, 1.4], None, Some(1)).unwrap().label, 1, "Expected the class of the nearest point.");
+        let ans = contract.classify_with("bob/blobs".to_string(), vec![1.4, 1.4], Algorithm::NearestCentroid).unwrap();
+        assert_eq!((ans.label, ans.neighbor_indices.len()), (0, 0), "Expected the class of the nearest mean.");
+        assert!((ans.neighbor_distances[0] - 2f64.sqrt() * (1.4 - 1.0 / 3.0)).abs() < 1e-9, "Expected the distance to the mean of class 0.");
+        contract.add_training_point("bob/blobs".to_string(), vec![20.0, 20.0], 0); // class 0 now averages (5.25, 5.25)
+        contract.set_default_model("bob/blobs".to_string(), Some(Algorithm::NearestCentroid)).unwrap();
+        let ans = contract.classify("bob/blobs".to_string(), vec![1.4, 1.4], None, None).unwrap();
+        assert!((ans.neighbor_distances[0] - 2f64.sqrt() * 3.85).abs() < 1e-9, "Expected the distance to the updated mean of class 0.");
+    }
+
+    #[test]
+    fn test_naive_bayes() { // the class of the highest posterior, from the mean and variance of the features of every class
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.register_model("bob/spread".to_string(), Algorithm::NaiveBayes).unwrap_err(); // no such dataset yet
+        for (x, label) in [(0.0, 0), (0.2, 0), (-0.2, 0), (3.0, 1), (5.0, 1)] {
+            contract.add_training_point("bob/spread".to_string(), vec![x], label);
+        }
+        contract.register_model("bob/spread".to_string(), Algorithm::NaiveBayes).unwrap();
+        contract.add_training_point("bob/spread".to_string(), vec![7.0], 1); // class 1 now has mean 5 and variance 8/3
+        assert_eq!(contract.classify("bob/spread".to_string(), vec![1.2], None, Some(1)).unwrap().label, 0, "Expected the class of the nearest point.");
+        let ans = contract.classify_with("bob/spread".to_string(), vec![1.2], Algorithm::NaiveBayes).unwrap();
+        let log_density = |x: f64, mean: f64, variance: f64| -0.5 * (2.0 * std::f64::consts::PI * variance).ln() - (x - mean).powi(2) / (2.0 * variance);
+        let odds = (log_density(1.2, 0.0, 0.08 / 3.0) - log_density(1.2, 5.0, 8.0 / 3.0)).exp(); // equal priors
+        assert_eq!((ans.label, ans.neighbor_indices.len()), (1, 0), "Expected the class of the wider distribution.");
+        assert!((ans.confidence - 1.0 / (1.0 + odds)).abs() < 1e-6, "Expected the posterior of class 1.");
+        assert_eq!(contract.classify_with("bob/spread".to_string(), vec![0.1], Algorithm::NaiveBayes).unwrap().label, 0, "Expected equality.");
+    }
+
+    #[test]
+    fn test_logistic_regression() { // weights trained on chain, one gradient descent step per train_epoch call
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for (x, label) in [(0.0, 0), (1.0, 0), (3.0, 1), (4.0, 1)] {
+            contract.add_training_point("bob/line".to_string(), vec![x], label);
+        }
+        let unregistered = KnnError::UnregisteredModel { dataset: "bob/line".to_string(), algorithm: Algorithm::LogisticRegression };
+        assert_eq!(contract.train_epoch("bob/line".to_string(), 0.5), Err(unregistered), "Expected equality.");
+        contract.register_model("bob/line".to_string(), Algorithm::LogisticRegression).unwrap();
+        assert_eq!(contract.classify_with("bob/line".to_string(), vec![3.5], Algorithm::LogisticRegression).unwrap().confidence, 0.5, "Expected equally likely classes before training.");
+        assert_eq!(contract.train_epoch("bob/line".to_string(), 0.0), Err(KnnError::InvalidLearningRate { learning_rate: 0.0 }), "Expected equality.");
+        assert!((contract.train_epoch("bob/line".to_string(), 0.5).unwrap() - 2f64.ln()).abs() < 1e-12, "Expected the cross-entropy of weights of 0.");
+        let mut loss = f64::INFINITY;
+        for _ in 0..100 {
+            let next = contract.train_epoch("bob/line".to_string(), 0.5).unwrap();
+            assert!(next < loss, "Expected the loss to decrease.");
+            loss = next;
+        }
+        assert_eq!(contract.get_logistic_model("bob/line".to_string()).unwrap().unwrap().epochs, 101, "Expected equality.");
+        let ans = contract.classify_with("bob/line".to_string(), vec![3.5], Algorithm::LogisticRegression).unwrap();
+        assert!(ans.label == 1 && ans.confidence > 0.9, "Expected a confident class 1.");
+        assert_eq!(contract.classify_with("bob/line".to_string(), vec![0.5], Algorithm::LogisticRegression).unwrap().label, 0, "Expected equality.");
+    }
+
+    #[test]
+    fn test_kmeans() { // clusters of the train points whatever their labels
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for point in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [10.0, 10.0], [11.0, 10.0], [10.0, 11.0]] {
+            contract.add_training_point("bob/shops".to_string(), point.to_vec(), 0);
+        }
+        assert_eq!(contract.assign_cluster("bob/shops".to_string(), vec![2.0, 2.0]), Err(KnnError::NoClusters { dataset: "bob/shops".to_string() }), "Expected equality.");
+        assert_eq!(contract.fit_kmeans("bob/shops".to_string(), 7, 10), Err(KnnError::InvalidClusterCount { k: 7, n_points: 6 }), "Expected equality.");
+        let kmeans = contract.fit_kmeans("bob/shops".to_string(), 2, 10).unwrap();
+        assert_eq!(kmeans.sizes, vec![3, 3], "Expected equality.");
+        assert!((kmeans.centroids[1][0] - 31.0 / 3.0).abs() < 1e-12 && (kmeans.inertia - 8.0 / 3.0).abs() < 1e-12, "Expected the means of the clusters.");
+        assert!(kmeans.iterations < 10, "Expected the assignments to settle.");
+        assert_eq!(contract.assign_cluster("bob/shops".to_string(), vec![2.0, 2.0]), Ok(0), "Expected equality.");
+        assert_eq!(contract.assign_cluster("bob/shops".to_string(), vec![9.0, 9.0]), Ok(1), "Expected equality.");
+        assert_eq!(contract.get_kmeans("bob/shops".to_string()), Ok(Some(kmeans)), "Expected equality.");
+    }
+
+    #[test]
+    fn test_anomaly_score() { // mean distance to the k nearest train points
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for point in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]] {
+            contract.add_training_point("bob/square".to_string(), point.to_vec(), 0);
+        }
+        contract.set_range_check("bob/square".to_string(), Some(0.0)).unwrap(); // outliers are scored, not rejected
+        assert!((contract.anomaly_score("bob/square".to_string(), vec![0.0, 0.0]).unwrap() - 2.0 / 3.0).abs() < 1e-12, "Expected equality.");
+        let far = 162f64.sqrt() + 2.0 * 181f64.sqrt(); // to (1, 1), (1, 0) and (0, 1)
+        assert!((contract.anomaly_score("bob/square".to_string(), vec![10.0, 10.0]).unwrap() - far / 3.0).abs() < 1e-12, "Expected equality.");
+        assert_eq!(contract.is_outlier("bob/square".to_string(), vec![10.0, 10.0], 5.0), Ok(true), "Expected an outlier.");
+        assert_eq!(contract.is_outlier("bob/square".to_string(), vec![0.5, 0.5], 5.0), Ok(false), "Expected an inlier.");
+        assert_eq!(contract.anomaly_score("bob/square".to_string(), vec![0.5]), Err(KnnError::DimensionMismatch { expected: 2, actual: 1 }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_lof_score() { // density around the point relative to the density around its neighbours
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.add_training_point("bob/square".to_string(), vec![0.0, 0.0], 0);
+        assert_eq!(contract.lof_score("bob/square".to_string(), vec![0.5, 0.5]), Err(KnnError::KTooLarge { k: 1, n_points: 1 }), "Expected equality.");
+        for point in [[1.0, 0.0], [0.0, 1.0], [1.0, 1.0]] {
+            contract.add_training_point("bob/square".to_string(), point.to_vec(), 0);
+        }
+        // every corner has a k-distance of sqrt(2), the reachability distance of the centre to each one
+        assert!((contract.lof_score("bob/square".to_string(), vec![0.5, 0.5]).unwrap() - 1.0).abs() < 1e-6, "Expected the density of the neighbours.");
+        let far = (162f64.sqrt() + 2.0 * 181f64.sqrt()) / (3.0 * 2f64.sqrt());
+        assert!((contract.lof_score("bob/square".to_string(), vec![10.0, 10.0]).unwrap() - far).abs() < 1e-6, "Expected equality.");
+    }
+
+    #[test]
+    fn test_condense_dataset() { // prototypes classifying every row like its nearest prototype
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for (x, label) in [(0.0, 0), (1.0, 0), (2.0, 0), (3.0, 0), (4.0, 0), (10.0, 1), (11.0, 1), (12.0, 1), (13.0, 1), (14.0, 1)] {
+            contract.add_training_point("bob/line".to_string(), vec![x], label);
+        }
+        assert_eq!(contract.condense_dataset("bob/line".to_string()), 2, "Expected the first row of each class.");
+        let condensed = contract.describe_dataset("bob/line-condensed".to_string()).unwrap();
+        assert_eq!(condensed.class_counts, vec![(0, 1), (1, 1)], "Expected equality.");
+        for x in [3.0, 11.0, 14.0] {
+            assert_eq!(contract.classify("bob/line-condensed".to_string(), vec![x], None, None).unwrap().label, contract.classify("bob/line".to_string(), vec![x], None, None).unwrap().label, "Expected the class of the full dataset.");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "already exists")]
+    fn test_condense_dataset_twice() {
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.add_training_point("bob/line".to_string(), vec![0.0], 0);
+        contract.condense_dataset("bob/line".to_string());
+        contract.condense_dataset("bob/line".to_string());
+    }
+
+    #[test]
+    fn test_sort_and_argsort() { // check knn algo's sub-tasks work correctly
+        let v = vec![1.1, 7.1, 4.1, 2.1]; // vector of floats
+        let (i, v_sorted) = knn_core::sort_and_argsort(&v);
+        assert_eq!(i, vec![0, 3, 2, 1], "Expected equality."); //Asserts that two expressions are equal to each other 
+        assert_eq!(v_sorted, vec![1.1, 2.1, 4.1, 7.1], "Expected equality."); // Correct answer can be obtained by visual inspection.
+    }
+
+    #[test]
+    fn test_sort_and_argsort_duplicates() { // equal values must each keep their own index (previously the first index was returned twice)
+        let contract = KnnMachineLearning::new(3, None, None);
+        let (i, v_sorted) = knn_core::sort_and_argsort(&[2.0, 1.0, 2.0, 1.0]);
+        assert_eq!(i, vec![1, 3, 0, 2], "Expected equality.");
+        assert_eq!(v_sorted, vec![1.0, 1.0, 2.0, 2.0], "Expected equality.");
+        assert_eq!(knn_core::select_k_smallest(&[2.0, 1.0, 2.0, 1.0], 3).0, vec![1, 3, 0], "Expected equality.");
+        // All 3 train points are at distance 1 from the test point: labels 0, 1, 1 must each be counted once
+        let arr_train = [[1.0, 0.0], [-1.0, 0.0], [0.0, 1.0]];
+        let ans = contract.classify_test_point(&arr_train, &[0, 1, 1], &[0.0, 0.0], &DistanceMetric::Euclidean.into(), 3).unwrap();
+        assert_eq!((ans.label, ans.neighbor_indices), (1, vec![0, 1, 2]), "Expected equality.");
+    }
+
+    #[test]
+    fn test_select_k_smallest() { // partial selection gives the same k nearest as a full sort
+        let v = vec![5.5, 1.1, 9.9, 3.3, 0.4, 7.7, 2.2];
+        assert_eq!(knn_core::select_k_smallest(&v, 3), (vec![4, 1, 6], vec![0.4, 1.1, 2.2]), "Expected equality.");
+        assert_eq!(knn_core::select_k_smallest(&v, 7), knn_core::sort_and_argsort(&v), "Expected the full sort when k covers all elements.");
+    }
+
+    #[test]
+    fn test_classify_gas() { // a prediction on a 500 point dataset stays well within the 300 Tgas transaction limit
+        // (the mocked blockchain only meters host calls such as storage reads, not arithmetic: this guards against extra reads per
+        // prediction, not against a slower selection of the k nearest, which benches/knn_core.rs times)
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let mut dataset = Dataset::new("large", 2, accounts(1));
+        for ii in 0..500 {
+            dataset.push_point(&[(ii % 23) as f64, (ii % 37) as f64]);
+            dataset.push_label((ii % 2) as u8);
+        }
+        contract.datasets.insert(&"large".to_string(), &dataset);
+        testing_env!(get_context(accounts(1)).prepaid_gas(Gas(300 * 10u64.pow(12))).build()); // fresh gas counter
+        contract.classify("large".to_string(), vec![11.0, 18.0], None, None).unwrap();
+        let used_tgas = env::used_gas().0 / 10u64.pow(12);
+        assert!(used_tgas < 100, "Prediction used {} Tgas.", used_tgas);
+    }
+
+    #[test]
+    fn test_classify_test_point(){ // check single test data point and 10 test data points for class results.
+        let contract = KnnMachineLearning::new(3, None, None);
+        // Test a single data point
+        let test_point: [f64; 2] = [13.9, 1.9]; // vector with 2 entries
+        let ans = contract.classify_test_point(TOY_CANCER_TRAIN, TOY_CANCER_TARGET, &test_point, &DistanceMetric::Euclidean.into(), 3).unwrap();
+        assert_eq!(ans.label, 1, "Expected equality."); // This data point should be classified as 1, established from Python code.
+        // Test 10 data points: the data points from the training set (note: they will not ALL be classified correctly as algo has some error; expected result given below as tested in Python)
+        let test_points = *TOY_CANCER_TRAIN; // array with 10 entries   
+        let mut pred_class = vec![0; test_points.len()]; // store predicted class labels.
+        for (count, pt) in test_points.iter().enumerate() { // go over test points (note each is 2x1)
+            let ans = contract.classify_test_point(TOY_CANCER_TRAIN, TOY_CANCER_TARGET, pt, &DistanceMetric::Euclidean.into(), 3).unwrap();
+            pred_class[count] = ans.label; // store predicted class one at a time (for each test point)
+        }
+        assert_eq!(pred_class, vec![0, 1, 1, 1, 1, 1, 1, 0, 1, 0], "Expected equality."); // correct classes (obtained with code in Python)
+    }
+
+    #[test]
+    fn test_prediction_result() { // check confidence and neighbour details of a prediction
+        let contract = KnnMachineLearning::new(3, None, None);
+        let ans = contract.classify_test_point(TOY_CANCER_TRAIN, TOY_CANCER_TARGET, &[13.9, 1.9], &DistanceMetric::Euclidean.into(), 3).unwrap();
+        assert_eq!(ans.neighbor_indices, vec![2, 8, 5], "Expected equality."); // nearest: [15.8, 2.0], [11.9, 1.9], [16.6, 2.1]
+        assert_eq!(ans.neighbor_distances[1], 2.0, "Expected equality.");
+        assert_eq!((ans.label, ans.confidence), (1, 2.0 / 3.0), "Expected 2 of the 3 votes for class 1.");
+    }
+
+    #[test]
+    fn test_majority_vote() { // check voting works for more than 2 classes and that ties go to the lowest label
+        let contract = KnnMachineLearning::new(3, None, None);
+        assert_eq!(knn_core::majority_vote(&[2, 0, 2, 1, 2]), 2, "Expected equality.");
+        assert_eq!(knn_core::majority_vote(&[3, 1, 3, 1, 0]), 1, "Expected the lowest label to win the tie.");
+        // 3 class dataset: the 3 nearest neighbours of the test point all belong to class 2
+        let arr_train = [[0.0, 0.0], [0.1, 0.2], [5.0, 5.0], [5.1, 4.8], [9.0, 9.0], [9.2, 8.9], [8.8, 9.1]];
+        let arr_target = [0, 0, 1, 1, 2, 2, 2];
+        assert_eq!(contract.classify_test_point(&arr_train, &arr_target, &[9.0, 8.8], &DistanceMetric::Euclidean.into(), 3).unwrap().label, 2, "Expected equality.");
+    }
+
+    #[test]
+    fn test_weighted_vote() { // 2 far away class 0 neighbours are outvoted by 1 close class 1 neighbour when votes are weighted by distance
+        let mut contract = KnnMachineLearning::new(3, Some(VotingScheme::InverseDistance), None);
+        let arr_train = [[1.0, 0.0], [4.0, 0.0], [0.0, 4.0]];
+        let arr_target = [1, 0, 0];
+        assert_eq!(contract.classify_test_point(&arr_train, &arr_target, &[0.0, 0.0], &DistanceMetric::Euclidean.into(), 3).unwrap().label, 1, "Expected equality."); // weights: 1/1 vs 1/4 + 1/4
+        contract.set_voting_scheme(VotingScheme::Uniform);
+        assert_eq!(contract.get_voting_scheme(), VotingScheme::Uniform, "Expected equality.");
+        assert_eq!(contract.classify_test_point(&arr_train, &arr_target, &[0.0, 0.0], &DistanceMetric::Euclidean.into(), 3).unwrap().label, 0, "Expected equality."); // flat votes: 1 vs 2
+    }
+
+    #[test]
+    fn test_predict_value() { // regression: average of the numeric targets of the 3 nearest neighbours
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for (point, value) in [([1.0], 10.0), ([2.0], 20.0), ([3.0], 30.0), ([10.0], 100.0)] {
+            contract.add_regression_point("prices".to_string(), point.to_vec(), value);
+        }
+        assert_eq!(contract.predict_value("prices".to_string(), vec![2.1], None), Ok(20.0), "Expected equality."); // (10 + 20 + 30) / 3
+        contract.set_voting_scheme(VotingScheme::InverseDistance);
+        assert_eq!(contract.predict_value("prices".to_string(), vec![3.0], None), Ok(30.0), "Expected the exact match to decide.");
+    }
+
+    #[test]
+    fn test_add_training_point() { // check points can be appended to a toy dataset and that a new dataset is created with the schema of its first point
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build()); // attach 1 NEAR to cover storage
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.add_training_point("cancer".to_string(), vec![13.0, 2.0], 0);
+        contract.add_training_point("iris".to_string(), vec![5.1, 3.5, 1.4, 0.2], 2);
+        assert_eq!(contract.datasets.get(&"cancer".to_string()).unwrap().train.len(), 11, "Expected the point to be appended.");
+        let iris = contract.datasets.get(&"iris".to_string()).unwrap();
+        assert_eq!((iris.n_features, iris.target.to_vec()), (4, vec![2]), "Expected a new 4 feature dataset.");
+    }
+
+    #[test]
+    fn test_named_labels() { // classes can be given by name, and predictions return the name of the predicted class
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(1)]);
+        contract.set_dataset_schema("cancer".to_string(), Vec::new(), vec!["benign".to_string(), "malignant".to_string()]).unwrap();
+        let ans = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        assert_eq!((ans.label, ans.label_name), (1, Some("malignant".to_string())), "Expected equality.");
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        for (point, name) in [(1.0, "apple"), (2.0, "pear"), (1.1, "apple")] {
+            contract.add_named_training_point("bob/fruit".to_string(), vec![point], name.to_string());
+        }
+        assert_eq!(contract.get_label("bob/fruit".to_string(), "pear".to_string()), Ok(1), "Expected new names to get the next label.");
+        let ans = contract.classify("bob/fruit".to_string(), vec![1.05], None, None).unwrap();
+        assert_eq!((ans.label, ans.label_name), (0, Some("apple".to_string())), "Expected equality.");
+        let unknown = contract.get_label("bob/fruit".to_string(), "plum".to_string());
+        assert_eq!(unknown, Err(KnnError::UnknownLabel { dataset: "bob/fruit".to_string(), label_name: "plum".to_string() }), "Expected equality.");
+        assert_eq!(contract.classify("customer".to_string(), vec![13.9, 1.9], None, None).unwrap().label_name, None, "Expected no name for unnamed classes.");
+    }
+
+    #[test]
+    fn test_multilabel() { // classes whose share of the votes exceeds the threshold, each neighbour voting for all its classes
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.add_training_point("bob/docs".to_string(), vec![1.0], 1);
+        contract.add_multilabel_training_point("bob/docs".to_string(), vec![0.0], vec![1, 0, 1]);
+        contract.add_multilabel_training_point("bob/docs".to_string(), vec![2.0], vec![2, 1]);
+        for point in [10.0, 11.0] {
+            contract.add_training_point("bob/docs".to_string(), vec![point], 2);
+        }
+        let shares = contract.classify_multilabel("bob/docs".to_string(), vec![0.5], 0.3).unwrap();
+        assert_eq!(shares, vec![(0, 1.0 / 3.0), (1, 1.0), (2, 1.0 / 3.0)], "Expected equality.");
+        assert_eq!(contract.classify_multilabel("bob/docs".to_string(), vec![0.5], 0.5), Ok(vec![(1, 1.0)]), "Expected equality.");
+        assert_eq!(contract.classify("bob/docs".to_string(), vec![0.5], None, None).unwrap().label, 1, "Expected the lowest classes to vote in classify.");
+        let data = contract.get_dataset(&"bob/docs".to_string()).unwrap();
+        assert_eq!(data.label_sets.to_vec(), vec![vec![1], vec![0, 1], vec![1, 2], vec![2], vec![2]], "Expected equality.");
+        assert_eq!(data.compute_content_hash(), data.content_hash, "Expected the label sets in the content hash.");
+        assert_eq!(contract.classify_multilabel("bob/docs".to_string(), vec![0.5], 1.5), Err(KnnError::InvalidThreshold { threshold: 1.5 }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_dataset_snapshot() { // a snapshot keeps classifying like the version it was taken of, whatever rows are added since
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        assert_eq!(contract.snapshot_dataset("cancer".to_string()), 0, "Expected equality.");
+        for _ in 0..3 {
+            contract.add_training_point("cancer".to_string(), vec![13.9, 1.9], 0);
+        }
+        let meta = contract.get_dataset_meta("cancer".to_string()).unwrap();
+        assert_eq!((meta.version, meta.snapshots), (3, vec![0]), "Expected a version per change of the rows.");
+        assert_eq!(contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap().label, 0, "Expected equality.");
+        assert_eq!(contract.classify("cancer@0".to_string(), vec![13.9, 1.9], None, None).unwrap().label, 1, "Expected the snapshot to be unchanged.");
+        assert_eq!(contract.get_dataset_meta("cancer@0".to_string()).map(|meta| meta.n_rows), Ok(10), "Expected equality.");
+        assert_eq!(contract.snapshot_dataset("cancer".to_string()), 3, "Expected equality.");
+        assert_eq!(contract.classify("cancer@1".to_string(), vec![13.9, 1.9], None, None), Err(KnnError::UnknownDataset { name: "cancer@1".to_string() }), "Expected equality.");
+        assert_eq!(contract.begin_upload("iris@1".to_string(), 1), Err(KnnError::InvalidDatasetName { name: "iris@1".to_string() }), "Expected equality.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Snapshot 'cancer@0' cannot be changed.")]
+    fn test_dataset_snapshot_immutable() { // rows cannot be added to a snapshot
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.snapshot_dataset("cancer".to_string());
+        contract.add_training_point("cancer@0".to_string(), vec![13.9, 1.9], 0);
+    }
+
+    #[test]
+    fn test_freeze_dataset() { // a frozen dataset keeps its rows, and reports their content hash
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let expected = TOY_CANCER_TRAIN.iter().zip(TOY_CANCER_TARGET).fold(env::sha256(&[]), |hash, (point, label)| {
+            env::sha256(&[hash, (point.to_vec(), *label).try_to_vec().unwrap()].concat())
+        });
+        assert_eq!(contract.freeze_dataset("cancer".to_string()), Ok(Base64VecU8(expected.clone())), "Expected equality.");
+        let meta = contract.get_dataset_meta("cancer".to_string()).unwrap();
+        assert_eq!((meta.frozen, meta.content_hash), (true, Base64VecU8(expected)), "Expected equality.");
+        assert_eq!(contract.remove_dataset("cancer".to_string()), Err(KnnError::DatasetFrozen { name: "cancer".to_string() }), "Expected equality.");
+        assert_eq!(contract.freeze_dataset("cancer".to_string()), Err(KnnError::DatasetFrozen { name: "cancer".to_string() }), "Expected equality.");
+        assert!(contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).is_ok(), "Expected predictions to be served.");
+    }
+
+    #[test]
+    fn test_verify_dataset() { // the content hash of the rows does not depend on how they were uploaded
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let rows = vec![(vec![1.0, 1.0], 1), (vec![9.0, 9.0], 0), (vec![1.5, 0.5], 1)];
+        let expected = rows.iter().fold(env::sha256(&[]), |hash, row| env::sha256(&[hash, row.try_to_vec().unwrap()].concat()));
+        contract.begin_upload("bob/chunked".to_string(), 3).unwrap();
+        contract.upload_chunk(rows[..2].to_vec());
+        contract.upload_chunk(rows[2..].to_vec());
+        contract.commit_upload().unwrap();
+        for (point, label) in &rows {
+            contract.add_training_point("bob/points".to_string(), point.clone(), *label);
+        }
+        assert_eq!(contract.verify_dataset("bob/chunked".to_string(), Base64VecU8(expected.clone())), Ok(true), "Expected equality.");
+        assert_eq!(contract.get_dataset_meta("bob/points".to_string()).unwrap().content_hash, Base64VecU8(expected.clone()), "Expected equality.");
+        contract.add_training_point("bob/points".to_string(), vec![5.0, 5.0], 0);
+        assert_eq!(contract.verify_dataset("bob/points".to_string(), Base64VecU8(expected)), Ok(false), "Expected a changed hash.");
+        let unknown = contract.verify_dataset("iris".to_string(), Base64VecU8(vec![]));
+        assert_eq!(unknown, Err(KnnError::UnknownDataset { name: "iris".to_string() }), "Expected equality.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Dataset 'cancer' is frozen.")]
+    fn test_freeze_dataset_add_rows() { // rows cannot be added to a frozen dataset
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.freeze_dataset("cancer".to_string()).unwrap();
+        contract.add_training_point("cancer".to_string(), vec![13.9, 1.9], 0);
+    }
+
+    #[test]
+    fn test_non_finite_features() { // NaN and infinite features are rejected with a structured error instead of meaningless distances
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let nan = contract.run_analysis("cancer".to_string(), vec![f64::NAN, 1.9], None, None, None);
+        assert_eq!(nan, Err(KnnError::NonFiniteFeature { feature: 0 }), "Expected equality.");
+        let inf = contract.classify("cancer".to_string(), vec![13.9, f64::INFINITY], None, None);
+        assert_eq!(inf, Err(KnnError::NonFiniteFeature { feature: 1 }), "Expected equality.");
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        contract.add_regression_point("prices".to_string(), vec![1.0], 2.5);
+        assert_eq!(contract.predict_value("prices".to_string(), vec![f64::NEG_INFINITY], None), Err(KnnError::NonFiniteFeature { feature: 0 }), "Expected equality.");
+        assert!(contract.get_predictions(0, 10).is_empty(), "Expected no prediction recorded.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Feature 1 is not a finite number.")]
+    fn test_non_finite_training_row() { // a train point with a NaN feature is not stored
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.add_training_point("cancer".to_string(), vec![13.0, f64::NAN], 0);
+    }
+
+    #[test]
+    fn test_range_check() { // with a range check, test points far outside the range of the train points are rejected
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        assert!(contract.classify("cancer".to_string(), vec![25.0, 5.0], None, None).is_ok(), "Expected no check by default.");
+        assert_eq!(contract.set_range_check("cancer".to_string(), Some(-1.0)), Err(KnnError::InvalidTolerance { tolerance: -1.0 }), "Expected equality.");
+        contract.set_range_check("cancer".to_string(), Some(0.1)).unwrap(); // feature 0 of the toy points is within [1.4, 18.1]
+        assert!(contract.classify("cancer".to_string(), vec![19.0, 5.0], None, None).is_ok(), "Expected the tolerance to widen the range.");
+        let far = contract.run_analysis("cancer".to_string(), vec![25.0, 5.0], None, None, None);
+        assert_eq!(far, Err(KnnError::OutOfRange { feature: 0, value: 25.0, min: 1.4, max: 18.1 }), "Expected equality.");
+        contract.set_range_check("cancer".to_string(), None).unwrap();
+        assert!(contract.classify("cancer".to_string(), vec![25.0, 5.0], None, None).is_ok(), "Expected the check to be disabled.");
+    }
+
+    #[test]
+    fn test_labeling_bounty() { // labels of the accepted labelers are appended by majority, and the bounty split between them
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let id = contract.create_bounty("cancer".to_string(), vec![vec![13.0, 2.0], vec![2.0, 13.0]], 1, 1);
+        let reward = contract.get_bounty(id).unwrap().reward.0;
+        assert!(reward > 0 && reward < 10u128.pow(24), "Expected the storage of the bounty to be paid from the deposit.");
+        for (labeler, labels) in [(2, vec![1, 0]), (3, vec![1, 1]), (4, vec![0, 0])] {
+            testing_env!(context.predecessor_account_id(accounts(labeler)).attached_deposit(10u128.pow(22)).build());
+            contract.submit_labels(id, labels);
+        }
+        let hour = 3_600 * 1_000_000_000;
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(0).block_timestamp(hour).build());
+        assert_eq!(contract.settle_bounty(id), Err(KnnError::ChallengeWindowOpen { bounty_id: id, ends_at: U64(2 * hour) }), "Expected equality.");
+        assert_eq!(contract.challenge_labels(id, accounts(5)), Err(KnnError::NoSubmission { labeler: accounts(5) }), "Expected equality.");
+        contract.challenge_labels(id, accounts(4)).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(5)).block_timestamp(2 * hour).build());
+        assert_eq!(contract.challenge_labels(id, accounts(2)), Err(KnnError::Unauthorized { account_id: accounts(5) }), "Expected equality.");
+        assert_eq!(contract.settle_bounty(id), Ok(2), "Expected equality.");
+        let cancer = contract.datasets.get(&"cancer".to_string()).unwrap();
+        assert_eq!(cancer.target.to_vec()[10..], [1, 0], "Expected the majority labels, the lowest on a tie.");
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.iter().filter(|receipt| receipt.receiver_id != accounts(1)).count(), 2, "Expected the two accepted labelers to be paid.");
+        assert!(receipts.iter().filter(|receipt| receipt.receiver_id == accounts(1)).all(|receipt| transferred(std::slice::from_ref(receipt)) < 2), "Expected the creator to get the remainder only.");
+        assert_eq!(contract.get_bounty(id), None, "Expected the bounty to be closed.");
+    }
+
+    // Sum of the deposits transferred by the receipts.
+    fn transferred(receipts: &[near_sdk::mock::Receipt]) -> u128 {
+        receipts
+            .iter()
+            .flat_map(|receipt| &receipt.actions)
+            .map(|action| match action {
+                near_sdk::mock::VmAction::Transfer { deposit } => *deposit,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_labeling_bounty_over_limit() { // points the dataset cannot take are not appended, the labelers are paid all the same
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let id = contract.create_bounty("cancer".to_string(), vec![vec![13.0, 2.0]], 1, 0);
+        let reward = contract.get_bounty(id).unwrap().reward.0;
+        for labeler in [2, 3] {
+            testing_env!(context.predecessor_account_id(accounts(labeler)).attached_deposit(10u128.pow(22)).build());
+            contract.submit_labels(id, vec![1]);
+        }
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(0).block_timestamp(3_600 * 1_000_000_000).build());
+        contract.set_storage_limits(StorageLimits { max_rows_per_dataset: Some(10), max_bytes_per_dataset: None, max_rows_per_account: None, max_bytes_per_account: None });
+        let before = contract.datasets.get(&"cancer".to_string()).unwrap();
+        assert_eq!(contract.settle_bounty(id), Ok(0), "Expected no point appended.");
+        let cancer = contract.datasets.get(&"cancer".to_string()).unwrap();
+        assert_eq!((cancer.train.len(), cancer.version, &cancer.content_hash), (10, before.version, &before.content_hash), "Expected the dataset as it was.");
+        assert_eq!(transferred(&get_created_receipts()), reward, "Expected the whole reward paid out.");
+        assert_eq!(contract.get_bounty(id), None, "Expected the bounty to be closed.");
+    }
+
+    #[test]
+    fn test_cancel_bounty() { // the creator gets the reward and the storage back, until labels are submitted
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let (open, labeled) = (contract.create_bounty("cancer".to_string(), vec![vec![13.0, 2.0]], 1, 1), contract.create_bounty("cancer".to_string(), vec![vec![2.0, 13.0]], 1, 1));
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(10u128.pow(22)).build());
+        contract.submit_labels(labeled, vec![0]);
+        testing_env!(context.attached_deposit(0).build());
+        assert_eq!(contract.cancel_bounty(open), Err(KnnError::Unauthorized { account_id: accounts(2) }), "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        assert_eq!(contract.cancel_bounty(labeled), Err(KnnError::BountyHasSubmissions { bounty_id: labeled }), "Expected equality.");
+        let reward = contract.get_bounty(open).unwrap().reward;
+        assert_eq!(contract.cancel_bounty(open), Ok(reward), "Expected equality.");
+        assert_eq!(transferred(&get_created_receipts()), 10u128.pow(24), "Expected the whole deposit refunded.");
+        assert_eq!(contract.get_bounty(open), None, "Expected the bounty to be closed.");
+    }
+
+    #[test]
+    fn test_labeling_bounty_refunded() { // without accepted labels, the creator is refunded and no point is appended
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let id = contract.create_bounty("cancer".to_string(), vec![vec![13.0, 2.0]], 0, 1);
+        testing_env!(context.attached_deposit(0).block_timestamp(3_600 * 1_000_000_000).build());
+        assert_eq!(contract.settle_bounty(id), Ok(0), "Expected equality.");
+        assert_eq!((contract.datasets.get(&"cancer".to_string()).unwrap().train.len(), get_created_receipts().len()), (10, 1), "Expected the refund only.");
+        assert_eq!(contract.settle_bounty(id), Err(KnnError::UnknownBounty { bounty_id: id }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_staked_contribution() { // a slashed batch loses its stake and its rows, the stake of the others is released after the window
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let stake = 10u128.pow(23);
+        contract.set_stake_policy(Some(StakePolicy { stake: U128(stake), window_hours: 1 }));
+        contract.grant_role(accounts(2), Role::DataProvider);
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(10u128.pow(24)).build());
+        contract.add_training_point("cancer".to_string(), vec![50.0, 50.0], 0); // garbage
+        contract.add_training_point("cancer".to_string(), vec![13.0, 2.0], 1);
+        assert_eq!(contract.get_staked_batches(accounts(2)).iter().map(|(id, batch)| (*id, batch.start)).collect::<Vec<_>>(), vec![(0, 10), (1, 11)], "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(0).build());
+        assert_eq!(contract.slash_batch(0), Ok(1), "Expected equality.");
+        let cancer = contract.datasets.get(&"cancer".to_string()).unwrap();
+        assert_eq!((cancer.train.len(), cancer.train.get(10), cancer.class_counts.get(&0).copied()), (11, Some(vec![13.0, 2.0]), Some(5)), "Expected the garbage row removed.");
+        assert_eq!((contract.get_staked_batch(1).unwrap().start, contract.get_treasury_balance()), (10, U128(stake)), "Expected the stake slashed.");
+        assert_eq!(contract.release_stake(1), Err(KnnError::SlashWindowOpen { batch_id: 1, ends_at: U64(3_600 * 1_000_000_000) }), "Expected equality.");
+        testing_env!(context.block_timestamp(3_600 * 1_000_000_000).build());
+        assert_eq!(contract.slash_batch(1), Err(KnnError::SlashWindowOver { batch_id: 1 }), "Expected equality.");
+        assert_eq!(contract.release_stake(1), Ok(U128(stake)), "Expected equality.");
+        assert_eq!(contract.release_stake(1), Err(KnnError::UnknownBatch { batch_id: 1 }), "Expected equality.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach at least 100000000000000000000000 yoctoNEAR, got 1000.")]
+    fn test_staked_contribution_insufficient() { // rows are not appended without their stake
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_stake_policy(Some(StakePolicy { stake: U128(10u128.pow(23)), window_hours: 1 }));
+        contract.grant_role(accounts(2), Role::DataProvider);
+        testing_env!(context.predecessor_account_id(accounts(2)).attached_deposit(1000).build());
+        contract.add_training_point("cancer".to_string(), vec![13.0, 2.0], 1);
+    }
+
+    #[test]
+    fn test_add_dataset_csv() { // a CSV upload creates a dataset with the header's feature names, rows are appended to it
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let csv = "diagnosis,radius,texture\n1,1.0,1.5\n0, 9.0, 9.5\n1,2.0,1.0\n";
+        contract.add_dataset_csv("tumours".to_string(), csv.to_string(), "diagnosis".to_string());
+        let data = contract.datasets.get(&"tumours".to_string()).unwrap();
+        assert_eq!((data.n_features, data.feature_names.clone()), (2, vec!["radius".to_string(), "texture".to_string()]), "Expected equality.");
+        assert_eq!((data.train.get(1), data.target.to_vec()), (Some(vec![9.0, 9.5]), vec![1, 0, 1]), "Expected equality.");
+        contract.add_dataset_csv("tumours".to_string(), "radius,texture,diagnosis\n8.0,8.0,0\n".to_string(), "diagnosis".to_string());
+        assert_eq!(contract.classify("tumours".to_string(), vec![8.5, 8.5], None, Some(1)).unwrap().label, 0, "Expected the appended row.");
+        let err = |line, reason: &str| Some(KnnError::InvalidCsv { line, reason: reason.to_string() });
+        assert_eq!(upload::parse_csv("a,b\n1,2\n", "label").err(), err(1, "no column named 'label'"), "Expected equality.");
+        assert_eq!(upload::parse_csv("a,label\n1,2\nx,1\n", "label").err(), err(3, "feature 'x' is not a number"), "Expected equality.");
+        assert_eq!(upload::parse_csv("a,label\n1,-1\n", "label").err(), err(2, "label '-1' is not an integer between 0 and 255"), "Expected equality.");
+        assert_eq!(upload::parse_csv("a,label\n", "label").err(), err(2, "no data rows"), "Expected equality.");
+        assert!(matches!(upload::parse_csv("a,label\n1,2,3\n", "label"), Err(KnnError::InvalidCsv { line: 2, .. })), "Expected a row of the wrong length to fail.");
+    }
+
+    #[test]
+    fn test_add_dataset_borsh() { // a Borsh upload appends the decoded rows, and is much smaller than the same rows as JSON
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let rows: Vec<(Vec<f64>, u8)> = (0..100).map(|ii| (vec![ii as f64 / 3.0, 100.0 - ii as f64 / 7.0], (ii % 3) as u8)).collect();
+        let payload = Base64VecU8(rows.try_to_vec().unwrap());
+        assert!(serde_json::to_string(&payload).unwrap().len() < serde_json::to_string(&rows).unwrap().len(), "Expected a more compact payload than JSON.");
+        contract.add_dataset_borsh("blobs".to_string(), payload);
+        let data = contract.datasets.get(&"blobs".to_string()).unwrap();
+        assert_eq!((data.n_features, data.train.len()), (2, 100), "Expected equality.");
+        assert_eq!((data.train.get(7), data.target.get(7)), (Some(rows[7].0.clone()), Some(1)), "Expected equality.");
+        assert!(matches!(upload::parse_borsh(&[1, 0, 0]), Err(KnnError::InvalidPayload { .. })), "Expected truncated bytes to fail.");
+        assert_eq!(upload::parse_borsh(&[0, 0, 0, 0]).err(), Some(KnnError::InvalidPayload { reason: "no rows".to_string() }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_chunked_upload() { // staged rows become a dataset only once the upload is committed, an aborted upload frees its storage
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let name = "bob/blobs".to_string();
+        assert_eq!(contract.begin_upload(name.clone(), 3), Ok(()), "Expected equality.");
+        assert_eq!(contract.begin_upload("bob/other".to_string(), 3), Err(KnnError::UploadInProgress { name: name.clone() }), "Expected equality.");
+        assert_eq!(contract.upload_chunk(vec![(vec![1.0, 1.0], 1), (vec![9.0, 9.0], 0)]), 2, "Expected equality.");
+        assert_eq!(contract.classify(name.clone(), vec![1.0, 1.0], None, Some(1)), Err(KnnError::UnknownDataset { name: name.clone() }), "Expected the dataset to be staged only.");
+        assert_eq!(contract.commit_upload(), Err(KnnError::UploadRowCount { expected: 3, actual: 2 }), "Expected equality.");
+        assert_eq!(contract.upload_chunk(vec![(vec![1.5, 0.5], 1)]), 3, "Expected equality.");
+        let status = upload::UploadStatus { name: name.clone(), total_rows: 3, uploaded_rows: 3 };
+        assert_eq!(contract.get_upload(accounts(1)), Some(status), "Expected equality.");
+        assert_eq!(contract.commit_upload(), Ok(()), "Expected equality.");
+        assert_eq!(contract.get_upload(accounts(1)), None, "Expected equality.");
+        assert_eq!(contract.classify(name.clone(), vec![1.2, 0.9], None, None).unwrap().label, 1, "Expected equality.");
+        assert_eq!(contract.get_dataset(&name).unwrap().train.len(), 3, "Expected equality.");
+        assert_eq!(contract.begin_upload(name.clone(), 1), Err(KnnError::DatasetExists { name }), "Expected equality.");
+        let storage_before = env::storage_usage();
+        assert_eq!(contract.begin_upload("bob/tmp".to_string(), 10), Ok(()), "Expected equality.");
+        contract.upload_chunk(vec![(vec![1.0], 1), (vec![2.0], 0)]);
+        assert_eq!(contract.abort_upload(), Ok(()), "Expected equality.");
+        assert_eq!(env::storage_usage(), storage_before, "Expected the staged rows to be deleted.");
+        assert_eq!(contract.commit_upload(), Err(KnnError::NoUploadInProgress { account_id: accounts(1) }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_chunked_upload_offchain_name() { // an upload can neither start nor be committed under the name of an off-chain dataset
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let rows: Vec<(Vec<f64>, u8)> = vec![(vec![1.0, 1.0], 1), (vec![9.0, 9.0], 0)];
+        let root = contract.compute_merkle_root(rows.clone()).unwrap();
+        contract.register_offchain_dataset("bob/offchain".to_string(), root.clone(), 2, 2, None).unwrap();
+        assert_eq!(contract.begin_upload("bob/offchain".to_string(), 2), Err(KnnError::DatasetExists { name: "bob/offchain".to_string() }), "Expected equality.");
+        let name = "bob/later".to_string();
+        contract.begin_upload(name.clone(), 2).unwrap();
+        contract.upload_chunk(rows);
+        contract.register_offchain_dataset(name.clone(), root, 2, 2, None).unwrap();
+        assert_eq!(contract.commit_upload(), Err(KnnError::DatasetExists { name: name.clone() }), "Expected equality.");
+        assert_eq!(contract.get_dataset(&name).map(|_| ()), Err(KnnError::UnknownDataset { name }), "Expected the upload not to be stored as a dataset.");
+    }
+
+    #[test]
+    fn test_offchain_dataset() { // predictions on a committed off-chain dataset only use rows proven to belong to it
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for n in 1..=9 { // every proof of trees of any shape (odd levels carry a node up) leads to the root
+            let rows: Vec<(Vec<f64>, u8)> = (0..n).map(|ii| (vec![ii as f64], (ii % 2) as u8)).collect();
+            let root = contract.compute_merkle_root(rows.clone()).unwrap();
+            for (ii, (point, label)) in rows.iter().enumerate() {
+                let proof = contract.compute_merkle_proof(rows.clone(), ii as u64).unwrap();
+                assert!(offchain::verify_proof(&root.0, n, ii as u64, offchain::leaf_hash(ii as u64, point, *label), &proof), "Expected a valid proof.");
+                assert!(!offchain::verify_proof(&root.0, n, ii as u64, offchain::leaf_hash(ii as u64, point, 1 - *label), &proof), "Expected a tampered row to fail.");
+            }
+        }
+        let rows: Vec<(Vec<f64>, u8)> = vec![(vec![1.0, 1.0], 1), (vec![9.0, 9.0], 0), (vec![1.5, 0.5], 1), (vec![8.0, 9.5], 0), (vec![0.0, 2.0], 0)];
+        let root = contract.compute_merkle_root(rows.clone()).unwrap();
+        let name = "bob/offchain".to_string();
+        let attempt = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.register_offchain_dataset(name.clone(), Base64VecU8(vec![0; 31]), 5, 2, None)));
+        assert!(attempt.is_err(), "Expected an error to revert the call when a deposit is attached.");
+        testing_env!(context.attached_deposit(0).build());
+        assert_eq!(contract.register_offchain_dataset(name.clone(), Base64VecU8(vec![0; 31]), 5, 2, None), Err(KnnError::InvalidCommitment), "Expected equality.");
+        let long_uri = Some("x".repeat(513));
+        assert_eq!(contract.register_offchain_dataset(name.clone(), root.clone(), 5, 2, long_uri), Err(KnnError::UriTooLong { length: 513, max_length: 512 }), "Expected equality.");
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        assert_eq!(contract.register_offchain_dataset(name.clone(), root, 5, 2, Some("ipfs://bafy".to_string())), Ok(()), "Expected equality.");
+        assert_eq!(get_created_receipts().len(), 1, "Expected the surplus of the storage deposit to be refunded.");
+        let taken = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.add_training_point(name.clone(), vec![1.0, 1.0], 1)));
+        assert!(taken.is_err(), "Expected the name of the off-chain dataset to be taken for on-chain datasets.");
+        let proven = |ii: usize| offchain::ProvenRow { index: ii as u64, point: rows[ii].0.clone(), label: rows[ii].1, proof: contract.compute_merkle_proof(rows.clone(), ii as u64).unwrap() };
+        let supplied = vec![proven(3), proven(0), proven(2), proven(4)];
+        let ans = contract.classify_offchain(name.clone(), vec![1.0, 0.9], supplied.clone(), None).unwrap();
+        assert_eq!((ans.label, ans.neighbor_indices), (1, vec![0, 2, 4]), "Expected the indices of the rows in the dataset.");
+        let mut forged = supplied;
+        forged[0].label = 1;
+        assert_eq!(contract.classify_offchain(name.clone(), vec![1.0, 0.9], forged, None), Err(KnnError::InvalidProof { index: 3 }), "Expected equality.");
+        assert_eq!(contract.classify_offchain(name, vec![1.0, 0.9], vec![proven(0)], None), Err(KnnError::KTooLarge { k: 3, n_points: 1 }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_remove_dataset() { // the creator can remove their dataset, all points are deleted from storage
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.grant_role(accounts(2), Role::DataProvider);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.add_training_point("iris".to_string(), vec![5.1, 3.5, 1.4, 0.2], 2);
+        let storage_before = env::storage_usage();
+        assert_eq!(contract.remove_dataset("iris".to_string()), Ok(()), "Expected equality.");
+        assert!(env::storage_usage() < storage_before, "Expected storage to be freed.");
+        assert!(get_logs()[0].starts_with(r#"LOG_JSON:{"level":"Info","event":"dataset_removed","fields":{"dataset":"iris","freed_bytes":"#), "Expected a log of the freed storage.");
+        assert_eq!(contract.remove_dataset("iris".to_string()), Err(KnnError::UnknownDataset { name: "iris".to_string() }), "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        assert_eq!(contract.remove_dataset("cancer".to_string()), Ok(()), "Expected the owner to remove any dataset.");
+    }
+
+    #[test]
+    fn test_account_namespace() { // any account can host datasets in its own namespace, others cannot write to them
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build()); // no role needed
+        contract.add_training_point("charlie/cancer".to_string(), vec![1.0, 1.0], 1);
+        contract.add_training_point("charlie/cancer".to_string(), vec![9.0, 9.0], 0);
+        assert_eq!(contract.get_dataset(&"charlie/cancer".to_string()).unwrap().train.len(), 2, "Expected the second point to be appended.");
+        let ans = contract.classify("charlie/cancer".to_string(), vec![0.0, 0.0], None, Some(1)).unwrap();
+        assert_eq!(ans.label, 1, "Expected the point of the namespaced dataset, not the toy cancer dataset.");
+        assert_eq!(contract.get_dataset(&"cancer".to_string()).unwrap().train.len(), 10, "Expected the toy dataset to be unchanged.");
+        assert_eq!(split_namespace("cancer"), None, "Expected a shared dataset.");
+        assert_eq!(contract.remove_dataset("charlie/cancer".to_string()), Ok(()), "Expected equality.");
+        assert!(contract.get_dataset(&"charlie/cancer".to_string()).is_err(), "Expected the dataset to be removed.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Account danny is not allowed to call this method.")]
+    fn test_account_namespace_unauthorized() { // only the account itself writes to its namespace, whatever its role
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.grant_role(accounts(3), Role::Admin);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.add_training_point("charlie/cancer".to_string(), vec![1.0, 1.0], 1);
+    }
+
+    #[test]
+    fn test_dataset_meta() { // every dataset is described by its schema, creator, creation time and size
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(7).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.add_training_point("iris".to_string(), vec![5.1, 3.5], 2);
+        contract.add_training_point("bob/iris".to_string(), vec![5.1, 3.5], 2);
+        let names = vec!["length".to_string(), "width".to_string()];
+        assert_eq!(contract.set_dataset_schema("iris".to_string(), names.clone(), vec!["setosa".to_string()]), Ok(()), "Expected equality.");
+        assert_eq!(
+            contract.set_dataset_schema("iris".to_string(), vec!["length".to_string()], Vec::new()),
+            Err(KnnError::DimensionMismatch { expected: 2, actual: 1 }),
+            "Expected a structured error."
+        );
+        let meta = contract.get_dataset_meta("iris".to_string()).unwrap();
+        assert_eq!((meta.feature_names, meta.creator, meta.created_at, meta.n_rows), (names, accounts(1), U64(7), 1), "Expected equality.");
+        let listed: Vec<String> = contract.list_datasets(1, 10, None).into_iter().map(|meta| meta.name).collect();
+        assert_eq!(listed, vec!["customer", "iris"], "Expected the shared datasets after the first one.");
+        assert_eq!(contract.list_datasets(0, 10, Some(accounts(1)))[0].name, "bob/iris", "Expected equality.");
+    }
+
+    #[test]
+    fn test_scaling() { // a feature with a large range no longer dominates the distances once scaled
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for (point, label) in [(vec![0.0, 0.0], 0), (vec![1.0, 1000.0], 1)] {
+            contract.add_training_point("income".to_string(), point, label);
+        }
+        let test_point = vec![0.9, 300.0]; // close to [1, 1000] relative to the range of the features, but not in raw units
+        let raw = contract.classify("income".to_string(), test_point.clone(), None, Some(1)).unwrap();
+        assert_eq!(raw.label, 0, "Expected the large range feature to decide.");
+        contract.set_dataset_scaling("income".to_string(), Scaling::MinMax).unwrap();
+        let scaled = contract.classify("income".to_string(), test_point, None, Some(1)).unwrap(); // [0.9, 0.3] vs [0, 0] and [1, 1]
+        assert_eq!((scaled.label, scaled.neighbor_distances), (1, vec![0.7071067811865475]), "Expected equality.");
+        let stats = contract.get_dataset(&"income".to_string()).unwrap().stats;
+        assert_eq!(stats.params(Scaling::ZScore, 2), vec![(0.5, 2.0), (500.0, 0.002)], "Expected means and 1 / standard deviations.");
+    }
+
+    #[test]
+    fn test_feature_weights() { // weighted distances, e.g. sqrt(1 * 3^2 + 4 * 4^2) = sqrt(73) for Euclidean
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.add_training_point("iris".to_string(), vec![1.0, 2.0], 0);
+        assert_eq!(contract.set_feature_weights("iris".to_string(), Some(vec![1.0, 4.0])), Ok(()), "Expected equality.");
+        let dist = |metric| contract.classify("iris".to_string(), vec![4.0, 6.0], Some(metric), Some(1)).unwrap().neighbor_distances[0];
+        assert_eq!(dist(DistanceMetric::Euclidean), 73f64.sqrt(), "Expected equality.");
+        assert_eq!(dist(DistanceMetric::Manhattan), 19.0, "Expected equality.");
+        assert_eq!(dist(DistanceMetric::Chebyshev), 16.0, "Expected equality.");
+        for weights in [vec![1.0], vec![-1.0, 1.0], vec![0.0, 0.0], vec![f64::NAN, 1.0]] {
+            assert!(contract.set_feature_weights("iris".to_string(), Some(weights)).is_err(), "Expected invalid weights to be rejected.");
+        }
+    }
+
+    #[test]
+    fn test_categorical_features() { // a categorical feature differs by 0 or 1, however far apart its codes are
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.add_training_point("bob/cars".to_string(), vec![7.0, 0.5], 0); // colour 7
+        contract.add_training_point("bob/cars".to_string(), vec![0.0, 1.5], 1); // colour 0
+        let nearest = |contract: &KnnMachineLearning, point: Vec<f64>, metric| {
+            let ans = contract.classify("bob/cars".to_string(), point, metric, Some(1)).unwrap();
+            (ans.label, ans.neighbor_distances[0])
+        };
+        assert_eq!(nearest(&contract, vec![0.0, 0.0], None), (1, 1.5), "Expected equality.");
+        assert_eq!(contract.set_categorical_features("bob/cars".to_string(), vec![0, 0]), Ok(()), "Expected equality.");
+        contract.build_index("bob/cars".to_string()); // not searched with categorical features
+        assert_eq!(nearest(&contract, vec![0.0, 0.0], None), (0, 1.25f64.sqrt()), "Expected equality.");
+        assert_eq!(nearest(&contract, vec![0.0, 0.0], Some(DistanceMetric::Hamming)), (1, 1.0), "Expected equality.");
+        assert_eq!(nearest(&contract, vec![0.0, 1.0], Some(DistanceMetric::Gower)), (1, 0.25), "Expected equality."); // (0 + 0.5 / 1) / 2
+        assert_eq!(contract.get_dataset_meta("bob/cars".to_string()).unwrap().categorical_features, vec![0], "Expected equality.");
+        let unknown = contract.set_categorical_features("bob/cars".to_string(), vec![2]);
+        assert_eq!(unknown, Err(KnnError::UnknownFeature { feature: 2, n_features: 2 }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_haversine() { // great-circle distances: a degree of longitude is shorter away from the equator
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let london_paris = distance(DistanceMetric::Haversine, &[51.5074, -0.1278], &[48.8566, 2.3522]);
+        assert!((london_paris - 343.5).abs() < 0.5, "Expected about 343.5 km, got {}.", london_paris);
+        contract.add_training_point("bob/stores".to_string(), vec![60.0, 10.0], 0); // about 556 km East of the test point
+        contract.add_training_point("bob/stores".to_string(), vec![53.0, 0.0], 1); // about 778 km South
+        let nearest = |metric| contract.classify("bob/stores".to_string(), vec![60.0, 0.0], Some(metric), Some(1)).unwrap().label;
+        assert_eq!((nearest(DistanceMetric::Euclidean), nearest(DistanceMetric::Haversine)), (1, 0), "Expected equality.");
+        contract.add_training_point("bob/iris".to_string(), vec![5.1, 3.5, 1.4], 0);
+        let wrong_dimension = KnnError::MetricDimension { expected: 2, actual: 3 };
+        assert_eq!(contract.set_dataset_metric("bob/iris".to_string(), Some(DistanceMetric::Haversine)), Err(wrong_dimension.clone()), "Expected equality.");
+        assert_eq!(contract.classify("bob/iris".to_string(), vec![5.0, 3.0, 1.0], Some(DistanceMetric::Haversine), None), Err(wrong_dimension), "Expected equality.");
+    }
+
+    #[test]
+    fn test_cosine() { // directions rather than norms
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let cosine = |a: &[f64], b: &[f64]| distance(DistanceMetric::Cosine, a, b);
+        assert_eq!((cosine(&[1.0, 1.0], &[2.0, 2.0]), cosine(&[1.0, 0.0], &[0.0, 3.0]), cosine(&[1.0, 2.0], &[-1.0, -2.0])), (0.0, 1.0, 2.0), "Expected equality.");
+        assert_eq!(cosine(&[0.0, 0.0], &[1.0, 2.0]), 1.0, "Expected equality.");
+        contract.add_training_point("bob/embeddings".to_string(), vec![10.0, 0.0], 0); // same direction as the test point, but far
+        contract.add_training_point("bob/embeddings".to_string(), vec![1.0, 1.0], 1); // close, but 45 degrees away
+        let nearest = |metric| contract.classify("bob/embeddings".to_string(), vec![3.0, 0.2], Some(metric), Some(1)).unwrap().label;
+        assert_eq!((nearest(DistanceMetric::Euclidean), nearest(DistanceMetric::Cosine)), (1, 0), "Expected equality.");
+    }
+
+    #[test]
+    fn test_mahalanobis() { // the train points spread along the diagonal: a point off it is further than its Euclidean distance
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for (point, label) in [([0.0, 0.0], 0), ([1.0, 1.1], 0), ([2.0, 1.9], 0), ([3.0, 3.0], 0)] {
+            contract.add_training_point("bob/diagonal".to_string(), point.to_vec(), label);
+        }
+        let nearest = |contract: &KnnMachineLearning, metric| contract.classify("bob/diagonal".to_string(), vec![4.0, 4.0], Some(metric), Some(1)).map(|ans| ans.label);
+        assert_eq!(nearest(&contract, DistanceMetric::Mahalanobis), Err(KnnError::NoInverseCovariance), "Expected equality.");
+        assert_eq!(contract.set_mahalanobis("bob/diagonal".to_string(), true), Ok(()), "Expected equality.");
+        contract.add_training_point("bob/diagonal".to_string(), vec![4.5, 3.5], 1); // the inverse covariance is updated with the upload
+        assert_eq!((nearest(&contract, DistanceMetric::Euclidean), nearest(&contract, DistanceMetric::Mahalanobis)), (Ok(1), Ok(0)), "Expected equality.");
+        let inverse = contract.get_inverse_covariance("bob/diagonal".to_string()).unwrap().unwrap();
+        assert!((inverse[0][1] - inverse[1][0]).abs() < 1e-9 && inverse[0][1] < 0.0, "Expected a symmetric inverse of positively correlated features.");
+        contract.add_training_point("bob/line".to_string(), vec![0.0, 0.0], 0);
+        contract.add_training_point("bob/line".to_string(), vec![1.0, 1.0], 1);
+        contract.set_mahalanobis("bob/line".to_string(), true).unwrap(); // 2 points: the covariance is singular
+        assert_eq!(contract.get_inverse_covariance("bob/line".to_string()), Ok(None), "Expected equality.");
+    }
+
+    #[test]
+    fn test_evaluate_loocv() { // every toy point classified by its 3 nearest other points
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        assert_eq!(contract.evaluate_loocv("cancer".to_string(), 3), Ok(0.5), "Expected equality.");
+        assert_eq!(contract.evaluate_loocv("cancer".to_string(), 11), Err(KnnError::KTooLarge { k: 11, n_points: 9 }), "Expected equality.");
+        // 2 well separated clusters are classified perfectly
+        let mut dataset = Dataset::new("clusters", 1, accounts(1));
+        for (x, label) in [(0.0, 0), (0.1, 0), (0.2, 0), (5.0, 1), (5.1, 1), (5.2, 1)] {
+            dataset.push_point(&[x]);
+            dataset.push_label(label);
+        }
+        contract.datasets.insert(&"clusters".to_string(), &dataset);
+        assert_eq!(contract.evaluate_loocv("clusters".to_string(), 1), Ok(1.0), "Expected equality.");
+    }
+
+    #[test]
+    fn test_evaluate_kfold() { // folds are deterministic for a given seed and cover every point once
+        let contract = KnnMachineLearning::new(3, None, None);
+        let result = contract.evaluate_kfold("cancer".to_string(), 3, 5, Some(U64(42))).unwrap();
+        assert_eq!(result, contract.evaluate_kfold("cancer".to_string(), 3, 5, Some(U64(42))).unwrap(), "Expected the same folds for the same seed.");
+        assert_eq!(result.fold_accuracies.len(), 5, "Expected equality.");
+        assert!((result.mean_accuracy - result.fold_accuracies.iter().sum::<f64>() / 5.0).abs() < 1e-12, "Expected the mean of the folds.");
+        // with as many folds as points, k-fold is leave-one-out
+        let loocv = contract.evaluate_loocv("cancer".to_string(), 3).unwrap();
+        assert_eq!(contract.evaluate_kfold("cancer".to_string(), 3, 10, None).unwrap().mean_accuracy, loocv, "Expected equality.");
+        assert_eq!(contract.evaluate_kfold("cancer".to_string(), 3, 1, None), Err(KnnError::InvalidFolds { n_folds: 1, n_points: 10 }), "Expected equality.");
+        let mut order: Vec<usize> = (0..10).collect();
+        shuffle(&mut order, 7);
+        order.sort_unstable();
+        assert_eq!(order, (0..10).collect::<Vec<usize>>(), "Expected a permutation.");
+    }
+
+    #[test]
+    fn test_tune_k() { // best k by leave-one-out accuracy is stored for the dataset and reported in an event
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let scores: Vec<f64> = [1, 3, 5].iter().map(|&k| contract.evaluate_loocv("cancer".to_string(), k).unwrap()).collect();
+        let best_k = contract.tune_k("cancer".to_string(), vec![5, 3, 1]).unwrap();
+        let expected = [1u8, 3, 5].iter().zip(&scores).fold((0, -1.0), |best, (&k, &acc)| if acc > best.1 { (k, acc) } else { best }).0;
+        assert_eq!(best_k, expected, "Expected the smallest k with the best accuracy.");
+        assert_eq!(contract.get_dataset_meta("cancer".to_string()).unwrap().k, Some(best_k), "Expected the k to be stored for the dataset.");
+        assert!(get_logs()[0].starts_with(r#"EVENT_JSON:{"standard":"knn","version":"1.0.0","event":"knn_tune_k","data":[{"dataset":"cancer","scores":[[5,"#), "Expected an event.");
+        let ans = contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap();
+        assert_eq!(ans.neighbor_indices.len(), best_k as usize, "Expected the dataset's k to be used.");
+        assert_eq!(contract.tune_k("cancer".to_string(), vec![]), Err(KnnError::NoCandidateK), "Expected equality.");
+    }
+
+    #[test]
+    fn test_evaluate() { // metrics of the predictions on a test set, checked against the predictions of classify
+        let contract = KnnMachineLearning::new(3, None, None);
+        let test_points = vec![vec![13.9, 1.9], vec![2.2, 14.0], vec![7.0, 9.0], vec![16.0, 3.0]];
+        let test_labels = vec![1, 0, 1, 0];
+        let predicted: Vec<u8> = test_points.iter().map(|pt| contract.classify("cancer".to_string(), pt.clone(), None, None).unwrap().label).collect();
+        assert_eq!(predicted, vec![1, 0, 1, 1], "Expected equality.");
+        let metrics = contract.evaluate("cancer".to_string(), test_points.clone(), test_labels).unwrap();
+        assert_eq!(metrics.accuracy, 0.75, "Expected equality.");
+        let class_0 = ClassMetrics { label: 0, precision: 1.0, recall: 0.5, f1: 2.0 / 3.0, support: 2 };
+        let class_1 = ClassMetrics { label: 1, precision: 2.0 / 3.0, recall: 1.0, f1: 0.8, support: 2 };
+        assert_eq!(metrics.per_class, vec![class_0, class_1], "Expected equality.");
+        assert_eq!(
+            contract.evaluate("cancer".to_string(), test_points, vec![1]),
+            Err(KnnError::InvalidTestSet { n_points: 4, n_labels: 1 }),
+            "Expected a structured error."
+        );
+    }
+
+    #[test]
+    fn test_confusion_matrix() { // rows are the actual classes, columns the predicted ones (predictions as in test_evaluate)
+        let contract = KnnMachineLearning::new(3, None, None);
+        let test_points = vec![vec![13.9, 1.9], vec![2.2, 14.0], vec![7.0, 9.0], vec![16.0, 3.0]];
+        let matrix = contract.confusion_matrix("cancer".to_string(), test_points.clone(), vec![1, 0, 1, 0]).unwrap();
+        assert_eq!(matrix, vec![vec![1, 1], vec![0, 2]], "Expected equality.");
+        let matrix = contract.confusion_matrix("cancer".to_string(), test_points, vec![2, 0, 1, 0]).unwrap(); // class 2 is never predicted
+        assert_eq!(matrix, vec![vec![1, 1, 0], vec![0, 1, 0], vec![0, 1, 0]], "Expected equality.");
+    }
+
+    #[test]
+    fn test_predict_proba() { // vote shares of the classes, the most likely class is the predicted one
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let proba = contract.predict_proba("cancer".to_string(), vec![13.9, 1.9]).unwrap();
+        assert_eq!(proba, vec![(0, 1.0 / 3.0), (1, 2.0 / 3.0)], "Expected equality.");
+        contract.set_voting_scheme(VotingScheme::InverseDistance);
+        let proba = contract.predict_proba("cancer".to_string(), vec![13.9, 1.9]).unwrap();
+        assert!((proba.iter().map(|(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-12, "Expected the probabilities to sum to 1.");
+        let ans = contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap();
+        assert_eq!(proba.iter().find(|(label, _)| *label == ans.label).unwrap().1, ans.confidence, "Expected the confidence of the prediction.");
+    }
+
+    #[test]
+    fn test_tie_break() { // 3 neighbours of 3 different classes: each class has one vote
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let arr_train = [[3.0, 0.0], [0.0, 1.0], [2.0, 0.0]];
+        let arr_target = [0, 2, 1]; // nearest neighbour is of class 2
+        let classify = |contract: &KnnMachineLearning| contract.classify_test_point(&arr_train, &arr_target, &[0.0, 0.0], &DistanceMetric::Euclidean.into(), 3);
+        assert_eq!(contract.get_tie_break(), TieBreak::LowestLabel, "Expected equality.");
+        assert_eq!(classify(&contract).unwrap().label, 0, "Expected the lowest label to win the tie.");
+        contract.set_tie_break(TieBreak::NearestNeighbor);
+        let ans = classify(&contract).unwrap();
+        assert_eq!((ans.label, ans.confidence), (2, 1.0 / 3.0), "Expected the class of the nearest neighbour.");
+        contract.set_tie_break(TieBreak::Random);
+        assert!([0, 1, 2].contains(&classify(&contract).unwrap().label), "Expected one of the tied classes.");
+        contract.set_tie_break(TieBreak::Abstain);
+        assert_eq!(classify(&contract), Err(KnnError::TiedVote { labels: vec![0, 1, 2] }), "Expected equality.");
+        assert_eq!(contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap().label, 1, "Expected no tie: 2 of the 3 votes for class 1.");
+    }
+
+    #[test]
+    fn test_classify_radius() { // train points within the radius vote, however many they are
+        let contract = KnnMachineLearning::new(3, None, None);
+        let ans = contract.classify_radius("cancer".to_string(), vec![13.9, 1.9], 2.0).unwrap();
+        assert_eq!((ans.label, ans.confidence, ans.neighbor_indices), (1, 1.0, vec![2, 8]), "Expected equality."); // [15.8, 2.0] and [11.9, 1.9]
+        let ans = contract.classify_radius("cancer".to_string(), vec![13.9, 1.9], 3.0).unwrap();
+        assert_eq!(ans, contract.classify("cancer".to_string(), vec![13.9, 1.9], None, Some(3)).unwrap(), "Expected the same 3 neighbours as with k = 3.");
+        assert_eq!(contract.classify_radius("cancer".to_string(), vec![13.9, 1.9], 1.0), Err(KnnError::NoNeighborsInRadius { radius: 1.0 }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_nearest_neighbors() { // the neighbours that vote in classify, with their coordinates and classes
+        let contract = KnnMachineLearning::new(3, None, None);
+        let neighbors = contract.nearest_neighbors("cancer".to_string(), vec![13.9, 1.9], 3).unwrap();
+        let ans = contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap();
+        assert_eq!(neighbors.iter().map(|n| n.index).collect::<Vec<_>>(), ans.neighbor_indices, "Expected equality.");
+        assert_eq!(neighbors.iter().map(|n| n.distance).collect::<Vec<_>>(), ans.neighbor_distances, "Expected equality.");
+        assert_eq!(neighbors[1], Neighbor { index: 8, point: vec![11.9, 1.9], label: 1, distance: 2.0 }, "Expected equality.");
+        assert_eq!(contract.nearest_neighbors("cancer".to_string(), vec![13.9, 1.9], 20).unwrap().len(), 10, "Expected all train points.");
+    }
+
+    #[test]
+    fn test_kd_tree() { // the k-d tree finds the same neighbours as a scan of all the train points
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for ii in 0..40 {
+            let (x, y) = (((ii * 37) % 23) as f64, ((ii * 11) % 7) as f64 * 3.0); // some duplicate coordinates
+            contract.add_training_point("grid".to_string(), vec![x, y], (ii % 3) as u8);
+        }
+        let before = contract.classify("grid".to_string(), vec![10.5, 9.0], None, Some(5)).unwrap();
+        assert_eq!(contract.build_index("grid".to_string()), 40, "Expected equality.");
+        assert!(contract.get_dataset_meta("grid".to_string()).unwrap().indexed, "Expected the dataset to be indexed.");
+        assert_eq!(contract.classify("grid".to_string(), vec![10.5, 9.0], None, Some(5)).unwrap(), before, "Expected equality.");
+        contract.add_training_point("grid".to_string(), vec![10.0, 9.0], 2); // inserted into the tree
+        contract.set_dataset_scaling("grid".to_string(), Scaling::ZScore).unwrap();
+        contract.set_feature_weights("grid".to_string(), Some(vec![1.0, 0.0])).unwrap();
+        let data = contract.get_dataset(&"grid".to_string()).unwrap();
+        for metric in [DistanceMetric::Euclidean, DistanceMetric::Manhattan, DistanceMetric::Chebyshev] {
+            let metric = data.point_metric(metric).unwrap();
+            for pt in [[10.5, 9.0], [0.0, 0.0], [22.0, 18.0], [7.0, 3.0]] {
+                for k in [1, 5, 15, 50] {
+                    testing_env!(context.build()); // fresh gas budget, the storage is kept
+                    let scan = contract.k_nearest(&data.transformed_train(&metric), &data.transform(&pt, &metric), &metric, k);
+                    assert_eq!(contract.dataset_neighbors(&data, &pt, &metric, k, &mut StageGas::default()), scan, "Expected equality.");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_classify_chunked() { // the k nearest of the chunks' nearest points are the k nearest of the dataset
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = KnnMachineLearning::new(3, None, None);
+        let chunks: Vec<Vec<(u64, f64)>> = [(0, 4), (4, 8), (8, 10)]
+            .iter()
+            .map(|&range| contract.process_chunk("cancer".to_string(), vec![13.9, 1.9], range, 3))
+            .collect();
+        assert_eq!(chunks[2], vec![(8, 2.0), (9, 13.843771162512041)], "Expected the 2 points of the last chunk.");
+        let results = |failed: bool| {
+            let mut results: Vec<PromiseResult> = chunks.iter().map(|chunk| PromiseResult::Successful(serde_json::to_vec(chunk).unwrap())).collect();
+            if failed {
+                results[1] = PromiseResult::Failed;
+            }
+            results
+        };
+        testing_env!(context.build(), Default::default(), Default::default(), Default::default(), results(false));
+        let expected = contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None);
+        assert_eq!(contract.on_chunks_processed("cancer".to_string(), 3), expected, "Expected the same prediction as classify.");
+        testing_env!(context.build(), Default::default(), Default::default(), Default::default(), results(true));
+        assert_eq!(contract.on_chunks_processed("cancer".to_string(), 3), Err(KnnError::ChunkFailed { chunk: 1 }), "Expected equality.");
+        testing_env!(context.prepaid_gas(Gas(300_000_000_000_000)).build());
+        contract.classify_chunked("cancer".to_string(), vec![13.9, 1.9], 4);
+        assert_eq!(get_created_receipts().len(), 4, "Expected 3 chunks and the callback.");
+    }
+
+    #[test]
+    fn test_gas_report() { // gas of the stages of the latest prediction, and average gas of the predictions per dataset
+        let mut context = VMContextBuilder::new(); // the default context
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &["bob.near".parse().unwrap()]);
+        assert_eq!(contract.last_gas_report(), None, "Expected no report before the first prediction.");
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        let first = contract.last_gas_report().unwrap();
+        contract.run_analysis("cancer".to_string(), vec![2.2, 14.0], None, None, None).unwrap();
+        let report = contract.last_gas_report().unwrap();
+        assert_eq!(report.dataset, "cancer", "Expected equality.");
+        assert!(report.distance_gas.0 > 0, "Expected the distances to use gas.");
+        assert!(report.total_gas.0 >= report.distance_gas.0 + report.sort_gas.0 + report.vote_gas.0, "Expected the stages within the total.");
+        let stats = contract.get_gas_stats("cancer".to_string()).unwrap();
+        assert_eq!((stats.n_predictions, stats.total_gas.0), (2, first.total_gas.0 + report.total_gas.0), "Expected equality.");
+        assert_eq!(stats.average_gas.0, stats.total_gas.0 / 2, "Expected equality.");
+        assert_eq!(contract.get_gas_stats("customer".to_string()), None, "Expected equality.");
+    }
+
+    #[test]
+    fn test_fixed_point() { // fixed-point distances match the f64 ones to 1e-6, and give the same predictions
+        testing_env!(get_context(accounts(1)).attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        assert_eq!((fixed::to_fixed(1.5), fixed::from_fixed(-2_250_000)), (1_500_000, -2.25), "Expected equality.");
+        let (a, b) = ([0.1234567, -3.5, 7.25], [10.0, 2.0000004, -1.0]);
+        for metric in [DistanceMetric::Euclidean, DistanceMetric::Manhattan, DistanceMetric::Chebyshev, DistanceMetric::Minkowski(2.0)] {
+            let (float, fixed_point) = (distance(metric, &a, &b), fixed::distance(&metric, &a, &b, &[]).unwrap());
+            assert!((float - fixed_point).abs() <= 3e-6, "Expected {} and {} to agree for {:?}.", float, fixed_point, metric);
+        }
+        let float_preds: Vec<u8> = TOY_CANCER_TRAIN.iter().map(|pt| contract.classify("cancer".to_string(), pt.to_vec(), None, None).unwrap().label).collect();
+        let float_accuracy = contract.evaluate_loocv("cancer".to_string(), 3).unwrap();
+        contract.set_numeric_mode(NumericMode::FixedPoint);
+        assert_eq!(contract.get_numeric_mode(), NumericMode::FixedPoint, "Expected equality.");
+        let fixed_preds: Vec<u8> = TOY_CANCER_TRAIN.iter().map(|pt| contract.classify("cancer".to_string(), pt.to_vec(), None, None).unwrap().label).collect();
+        assert_eq!((fixed_preds, contract.evaluate_loocv("cancer".to_string(), 3).unwrap()), (float_preds, float_accuracy), "Expected equality.");
+        let ans = contract.classify("cancer".to_string(), vec![13.9, 1.9], None, None).unwrap();
+        assert_eq!(ans.neighbor_distances, vec![1.902629, 2.0, 2.707397], "Expected distances rounded down to 1e-6.");
+        assert_eq!(
+            contract.classify("cancer".to_string(), vec![13.9, 1.9], Some(DistanceMetric::Minkowski(3.0)), None),
+            Err(KnnError::UnsupportedFixedPointMetric { p: 3.0 }),
+            "Expected equality."
+        );
+        contract.add_training_point("fine".to_string(), vec![0.1234567, 2.0], 1);
+        assert_eq!(contract.get_dataset(&"fine".to_string()).unwrap().train.get(0), Some(vec![0.123457, 2.0]), "Expected the point rounded to 1e-6.");
+        contract.build_index("cancer".to_string());
+        let data = contract.get_dataset(&"cancer".to_string()).unwrap();
+        let scan = contract.k_nearest(&data.transformed_train(&DistanceMetric::Euclidean.into()), &[13.9, 1.9], &DistanceMetric::Euclidean.into(), 5);
+        assert_eq!(contract.dataset_neighbors(&data, &[13.9, 1.9], &DistanceMetric::Euclidean.into(), 5, &mut StageGas::default()), scan, "Expected equality.");
+    }
+
+    #[test]
+    fn test_rank_distance() { // neighbours ranked by squared distances are the ones ranked by distances, with the same distances
+        let contract = KnnMachineLearning::new(3, None, None);
+        assert_eq!(PointMetric::from(DistanceMetric::Euclidean).rank_distance(NumericMode::Float, &[0.0, 0.0], &[3.0, 4.0]), 25.0, "Expected the squared distance.");
+        for metric in [DistanceMetric::Euclidean, DistanceMetric::Manhattan, DistanceMetric::Minkowski(3.0)] {
+            let metric = PointMetric::from(metric);
+            for pt in TOY_CUSTOMER_TRAIN {
+                let dist = contract.calc_dist(TOY_CANCER_TRAIN, pt, &metric);
+                assert_eq!(contract.k_nearest(TOY_CANCER_TRAIN, pt, &metric, 5), knn_core::select_k_smallest(&dist, 5), "Expected equality.");
+            }
+        }
+    }
+
+    #[test]
+    fn test_describe_dataset() { // statistics of the toy cancer dataset
+        let contract = KnnMachineLearning::new(3, None, None);
+        let description = contract.describe_dataset("cancer".to_string()).unwrap();
+        assert_eq!((description.n_rows, description.class_counts), (10, vec![(0, 5), (1, 5)]), "Expected equality.");
+        let feature = &description.features[0];
+        assert_eq!((feature.name.clone(), feature.min, feature.max), (None, 1.4, 18.1), "Expected equality.");
+        let xs: Vec<f64> = TOY_CANCER_TRAIN.iter().map(|pt| pt[0]).collect();
+        let mean = xs.iter().sum::<f64>() / 10.0;
+        let std = (xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 10.0).sqrt();
+        assert!((feature.mean - mean).abs() < 1e-12 && (feature.std - std).abs() < 1e-12, "Expected the mean and standard deviation of the feature.");
+        assert_eq!(contract.describe_dataset("iris".to_string()), Err(KnnError::UnknownDataset { name: "iris".to_string() }), "Expected equality.");
+    }
+
+    #[test]
+    fn test_class_imbalance() { // a warning once a class holds more than 90% of the points, class weighting lets the minority class win
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build()); // attach 1 NEAR to cover storage
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        for x in 0..9 {
+            contract.add_training_point("fraud".to_string(), vec![x as f64], 0);
+        }
+        contract.add_training_point("fraud".to_string(), vec![10.0], 1); // 90% of class 0: not above the threshold
+        assert!(get_logs().iter().all(|log| !log.contains("knn_imbalance")), "Expected no warning.");
+        contract.add_training_point("fraud".to_string(), vec![9.0], 0);
+        contract.add_training_point("fraud".to_string(), vec![9.1], 0); // still imbalanced: no second warning
+        let warnings: Vec<String> = get_logs().into_iter().filter(|log| log.contains("knn_imbalance")).collect();
+        assert_eq!(warnings.len(), 1, "Expected one warning.");
+        assert!(warnings[0].ends_with(r#""data":[{"dataset":"fraud","label":0,"share":0.9090909090909091,"n_rows":11}]}"#), "Expected equality.");
+        let distribution = contract.class_distribution("fraud".to_string()).unwrap();
+        assert_eq!(distribution[1], ClassShare { label: 1, count: 1, share: 1.0 / 12.0 }, "Expected equality.");
+        // neighbours of 9.5: 9.1 and 9.0 (class 0), 10.0 (class 1)
+        assert_eq!(contract.classify("fraud".to_string(), vec![9.5], None, None).unwrap().label, 0, "Expected the majority class.");
+        contract.set_class_weighting("fraud".to_string(), true).unwrap();
+        let ans = contract.classify("fraud".to_string(), vec![9.5], None, None).unwrap();
+        assert_eq!(ans.label, 1, "Expected the minority class: its vote weighs 1, the others 1/11 each.");
+        assert!((ans.confidence - 11.0 / 13.0).abs() < 1e-12, "Expected a share of 1 / (1 + 2/11) of the votes.");
+        assert_eq!(contract.predict_proba("fraud".to_string(), vec![9.5]).unwrap()[1], (1, ans.confidence), "Expected equality.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Storage limit max_rows_per_account of 3 exceeded: 4 after the upload.")]
+    fn test_storage_limits() { // the datasets of an account are counted against the account limits, uploads beyond a limit are rejected
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build()); // attach 1 NEAR to cover storage
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let limits = StorageLimits { max_rows_per_account: Some(3), ..Default::default() };
+        contract.set_storage_limits(limits);
+        assert_eq!(contract.get_storage_limits(), limits, "Expected equality.");
+        contract.add_training_point("bob/churn".to_string(), vec![1.0, 2.0], 0);
+        contract.add_training_point("bob/churn".to_string(), vec![2.0, 3.0], 1);
+        let usage = contract.get_account_usage(accounts(1));
+        assert_eq!(usage.rows, 2, "Expected equality.");
+        assert!(usage.bytes > 0 && usage.bytes == contract.get_dataset_meta("bob/churn".to_string()).unwrap().storage_bytes, "Expected the storage of the dataset.");
+        contract.remove_dataset("bob/churn".to_string()).unwrap();
+        assert_eq!(contract.get_account_usage(accounts(1)), AccountUsage::default(), "Expected the usage to be freed.");
+        for x in 0..4 {
+            contract.add_training_point("bob/churn".to_string(), vec![x as f64, 0.0], 0);
+        }
+    }
+
+    #[test]
+    fn test_storage_management() { // uploads without a deposit are paid from the pre-paid storage balance, removals credit it back
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        let min = contract.storage_balance_bounds().min.0;
+        let balance = contract.storage_deposit(None, None);
+        assert_eq!((balance.total.0, balance.available.0), (10u128.pow(24), 10u128.pow(24) - min), "Expected equality.");
+        testing_env!(context.attached_deposit(0).build());
+        contract.add_training_point("bob/churn".to_string(), vec![1.0, 2.0], 0);
+        let balance = contract.storage_balance_of(accounts(1)).unwrap();
+        let spent = 10u128.pow(24) - min - balance.available.0;
+        assert!(balance.total.0 == 10u128.pow(24) && spent >= Balance::from(contract.get_account_usage(accounts(1)).bytes) * env::storage_byte_cost(), "Expected the storage to be paid from the balance.");
+        contract.remove_dataset("bob/churn".to_string()).unwrap();
+        let available = contract.storage_balance_of(accounts(1)).unwrap().available.0;
+        assert!(available > 10u128.pow(24) - min - spent, "Expected the freed storage to be credited.");
+        testing_env!(context.attached_deposit(1).build());
+        let balance = contract.storage_withdraw(None);
+        assert_eq!(balance.available.0, 0, "Expected equality.");
+        assert_eq!(get_created_receipts().len(), 1, "Expected a transfer of the available balance.");
+        assert!(contract.storage_unregister(None), "Expected the account to be unregistered.");
+        assert!(contract.storage_balance_of(accounts(1)).is_none(), "Expected no balance.");
+    }
+
+    #[test]
+    fn test_call_quota() { // accounts can make at most max_calls predictions per period, the count restarts with the next period
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(2), accounts(3)]);
+        let quota = CallQuota { max_calls: 2, period: quota::QuotaPeriod::Day };
+        contract.set_call_quota(Some(quota));
+        assert_eq!(contract.get_call_quota(), Some(quota), "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        for _ in 0..2 {
+            contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        }
+        assert_eq!(contract.get_calls_remaining(accounts(2)), Some(0), "Expected equality.");
+        assert_eq!(
+            contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None),
+            Err(KnnError::QuotaExceeded { max_calls: 2, period: quota::QuotaPeriod::Day }),
+            "Expected equality."
+        );
+        assert_eq!(contract.get_predictions_by_account(accounts(2), 0, 10).len(), 2, "Expected no record beyond the quota.");
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        assert!(contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).is_ok(), "Expected a quota per account.");
+        testing_env!(context.predecessor_account_id(accounts(2)).block_timestamp(86_400 * 1_000_000_000).build()); // next day
+        assert_eq!(contract.get_calls_remaining(accounts(2)), Some(2), "Expected equality.");
+        assert!(contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).is_ok(), "Expected the quota to restart.");
+    }
+
+    #[test]
+    fn test_call_quota_failed_prediction() { // a prediction that fails does not use up the quota
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(2)]);
+        contract.set_call_quota(Some(CallQuota { max_calls: 1, period: quota::QuotaPeriod::Day }));
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        assert_eq!(
+            contract.run_analysis("unknown".to_string(), vec![13.9, 1.9], None, None, None),
+            Err(KnnError::UnknownDataset { name: "unknown".to_string() }),
+            "Expected equality."
+        );
+        assert!(contract.run_analysis("cancer".to_string(), vec![13.9], None, None, None).is_err(), "Expected a dimension mismatch.");
+        assert_eq!(contract.get_calls_remaining(accounts(2)), Some(1), "Expected the failed predictions not to be counted.");
+        assert!(contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).is_ok(), "Expected the quota to be left.");
+        assert_eq!(contract.get_calls_remaining(accounts(2)), Some(0), "Expected equality.");
+    }
+
+    #[test]
+    fn test_access_lists() { // the owner can ban accounts, or only serve the accounts of an allowlist
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(3)]);
+        contract.deny_account(accounts(2));
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let denied = Err(KnnError::AccessDenied { account_id: accounts(2) });
+        assert_eq!(contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None), denied, "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.remove_denied_account(accounts(2));
+        contract.set_allowlist_enabled(true);
+        contract.allow_account(accounts(3));
+        assert_eq!(
+            (contract.is_account_allowed(accounts(1)), contract.is_account_allowed(accounts(2)), contract.is_account_allowed(accounts(3))),
+            (true, false, true),
+            "Expected the owner and the listed accounts only."
+        );
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        assert!(contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).is_ok(), "Expected the listed account to be served.");
+        testing_env!(context.predecessor_account_id(accounts(5)).build()); // e.g. a contract calling for an unlisted account
+        assert_eq!(contract.classify_for("cancer".to_string(), vec![13.9, 1.9], None), Err(KnnError::AccessDenied { account_id: accounts(5) }), "Expected equality.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Account charlie is not allowed to use the contract.")]
+    fn test_access_lists_datasets() { // denied accounts cannot write to datasets either
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.deny_account(accounts(1)); // the owner cannot be denied
+        contract.grant_role(accounts(2), Role::DataProvider);
+        contract.deny_account(accounts(2));
+        contract.add_training_point("cancer".to_string(), vec![1.0, 1.0], 0);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.add_training_point("cancer".to_string(), vec![1.0, 1.0], 0);
+    }
+
+    #[test]
+    fn test_governance() { // under a council, sensitive changes are carried out once enough members approved them
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        assert_eq!(contract.set_council(Council { members: vec![accounts(2)], threshold: 2 }), Err(KnnError::InvalidCouncil { members: 1, threshold: 2 }), "Expected equality.");
+        contract.set_council(Council { members: vec![accounts(2), accounts(3), accounts(4)], threshold: 2 }).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        assert_eq!(contract.propose_action(AdminAction::SetK { k: 4 }), Err(KnnError::InvalidK { k: 4 }), "Expected equality.");
+        let id = contract.propose_action(AdminAction::SetPredictionFee { fee: U128(1000) }).unwrap();
+        assert_eq!((contract.get_prediction_fee(), contract.get_proposal(id).unwrap().approvals), (U128(0), vec![accounts(2)]), "Expected the proposer's approval only.");
+        testing_env!(context.predecessor_account_id(accounts(5)).build());
+        assert_eq!(contract.approve_proposal(id), Err(KnnError::NotCouncilMember { account_id: accounts(5) }), "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        assert_eq!(contract.approve_proposal(id), Ok(true), "Expected equality.");
+        assert!(get_logs().last().unwrap().contains(r#""event":"knn_proposal_executed""#), "Expected an execution event.");
+        assert_eq!(contract.get_prediction_fee(), U128(1000), "Expected the fee to be changed.");
+        assert_eq!(contract.approve_proposal(id), Err(KnnError::ProposalExecuted { proposal_id: id }), "Expected equality.");
+        let withdrawal = AdminAction::WithdrawFees { amount: U128(1), to: accounts(2) };
+        assert_eq!(contract.propose_action(withdrawal), Err(KnnError::InsufficientTreasury { requested: U128(1), available: U128(0) }), "Expected equality.");
+        assert_eq!(contract.propose_action(AdminAction::SetConfidenceThreshold { threshold: 2.0 }), Err(KnnError::InvalidThreshold { threshold: 2.0 }), "Expected equality.");
+        let id = contract.propose_action(AdminAction::SetTieBreak { tie_break: TieBreak::Abstain }).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(4)).build());
+        contract.approve_proposal(id).unwrap();
+        assert_eq!(contract.get_tie_break(), TieBreak::Abstain, "Expected the tie break to be changed.");
+        let tier = SubscriptionTier { price: U128(10), predictions: None, duration_days: None };
+        assert_eq!(contract.propose_action(AdminAction::SetSubscriptionTier { name: "gold".to_string(), tier: Some(tier) }), Err(KnnError::InvalidTier { name: "gold".to_string() }), "Expected equality.");
+        let ft_withdrawal = AdminAction::WithdrawFtFees { token_id: accounts(5), amount: U128(1), to: accounts(2) };
+        assert_eq!(contract.propose_action(ft_withdrawal), Err(KnnError::InsufficientTreasury { requested: U128(1), available: U128(0) }), "Expected equality.");
+        let id = contract.propose_action(AdminAction::SetFtFee { token_id: accounts(5), fee: Some(U128(7)) }).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.approve_proposal(id).unwrap();
+        assert_eq!(contract.get_ft_fee(accounts(5)), Some(U128(7)), "Expected the fee in the token to be changed.");
+        let id = contract.propose_action(AdminAction::SetNumericMode { mode: NumericMode::FixedPoint }).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(4)).build());
+        contract.approve_proposal(id).unwrap();
+        assert_eq!(contract.get_numeric_mode(), NumericMode::FixedPoint, "Expected the numeric mode to be changed.");
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let id = contract.propose_action(AdminAction::SetCouncil { council: None }).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(4)).build());
+        contract.approve_proposal(id).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        assert_eq!(contract.set_k(5), Ok(()), "Expected the owner to configure the contract again.");
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is governed by a council: propose this change with propose_action.")]
+    fn test_governance_owner() { // the owner cannot bypass the council
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_council(Council { members: vec![accounts(2)], threshold: 1 }).unwrap();
+        contract.set_prediction_fee(U128(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is governed by a council: propose this change with propose_action.")]
+    fn test_governance_owner_withdraw() { // the owner cannot withdraw the fees without the council
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.fees_collected = 2000;
+        contract.set_council(Council { members: vec![accounts(2)], threshold: 1 }).unwrap();
+        let _ = contract.withdraw_fees(U128(2000), accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is governed by a council: propose this change with propose_action.")]
+    fn test_governance_owner_ft_fee() { // the owner cannot change the fees in tokens without the council
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_council(Council { members: vec![accounts(2)], threshold: 1 }).unwrap();
+        contract.set_ft_fee(accounts(5), Some(U128(7)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Changes are timelocked: schedule this change with schedule_action.")]
+    fn test_timelock_owner() { // the owner cannot bypass the timelock
+        testing_env!(get_context(accounts(1)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_timelock(10);
+        let _ = contract.set_royalties(Royalties { dataset_creator_bps: 100, owner_bps: 100 });
+    }
+
+    #[test]
+    fn test_timelock() { // timelocked changes are executed once their delay has passed, unless cancelled before
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_timelock(10);
+        assert_eq!(contract.schedule_action(AdminAction::SetK { k: 4 }), Err(KnnError::InvalidK { k: 4 }), "Expected equality.");
+        let fee = contract.schedule_action(AdminAction::SetPredictionFee { fee: U128(1000) }).unwrap();
+        assert!(get_logs()[0].contains(r#""event":"knn_action_scheduled""#), "Expected a scheduling event.");
+        let removal = contract.schedule_action(AdminAction::RemoveDataset { name: "customer".to_string() }).unwrap();
+        assert_eq!(contract.get_scheduled_actions().len(), 2, "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(2)).block_index(9).build());
+        assert_eq!(contract.execute_action(fee), Err(KnnError::ActionNotReady { action_id: fee, ready_at: U64(10) }), "Expected equality.");
+        assert_eq!(contract.cancel_action(removal), Err(KnnError::Unauthorized { account_id: accounts(2) }), "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.cancel_action(removal).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(2)).block_index(10).build());
+        assert_eq!(contract.execute_action(removal), Err(KnnError::UnknownAction { action_id: removal }), "Expected equality.");
+        assert_eq!(contract.execute_action(fee), Ok(()), "Expected anyone to execute a ready action.");
+        assert_eq!((contract.get_prediction_fee(), contract.datasets.get(&"customer".to_string()).is_some()), (U128(1000), true), "Expected equality.");
+    }
+
+    #[test]
+    fn test_timelock_council() { // under a council and a timelock, approved proposals are scheduled
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.set_council(Council { members: vec![accounts(2)], threshold: 1 }).unwrap();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.propose_action(AdminAction::SetTimelock { blocks: 5 }).unwrap();
+        contract.propose_action(AdminAction::SetK { k: 7 }).unwrap();
+        assert_eq!((contract.get_timelock(), contract.get_k(), contract.get_scheduled_actions().len()), (5, 3, 1), "Expected the change to be scheduled.");
+        testing_env!(context.block_index(5).build());
+        contract.execute_action(0).unwrap();
+        assert_eq!(contract.get_k(), 7, "Expected equality.");
+    }
+
+    #[test]
+    fn test_log_level() { // diagnostics are logged as JSON lines, down to the verbosity level set by the owner
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &[accounts(1)]);
+        assert_eq!(contract.get_log_level(), LogLevel::Info, "Expected equality.");
+        contract.pause();
+        let line: serde_json::Value = serde_json::from_str(get_logs()[0].strip_prefix("LOG_JSON:").unwrap()).unwrap();
+        assert_eq!(line, serde_json::json!({ "level": "Warn", "event": "contract_paused", "fields": {} }), "Expected a structured log.");
+        contract.set_log_level(LogLevel::Error);
+        contract.unpause();
+        contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap();
+        assert!(get_logs().iter().all(|log| !log.contains("contract_unpaused") && !log.contains("dataset_selected")), "Expected the less severe lines to be dropped.");
+        assert!(get_logs().iter().any(|log| log.contains(r#""event":"knn_prediction""#)), "Expected the events to be emitted regardless.");
+        contract.set_log_level(LogLevel::Debug);
+        let label = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap().label;
+        let line = json!({ "level": "Debug", "event": "prediction", "fields": { "dataset": "cancer", "label": label } }).to_string();
+        assert!(get_logs().contains(&format!("LOG_JSON:{}", line)), "Expected the predicted class to be logged at the Debug level.");
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is paused.")]
+    fn test_pause() { // while paused the owner can still configure the contract, predictions and uploads are rejected
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.pause();
+        assert!(contract.is_paused(), "Expected the contract to be paused.");
+        contract.set_k(5).unwrap();
+        contract.unpause();
+        contract.add_training_point("cancer".to_string(), vec![13.0, 2.0], 0);
+        contract.pause();
+        let _ = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None);
+    }
+
+    #[test]
+    fn test_migrate() { // the state of the first deployed version (k only) is converted to the current layout, keeping k
+        testing_env!(get_context(accounts(1)).build());
+        env::state_write(&migrate::ContractV1 { param_k: 7 });
+        let mut contract = KnnMachineLearning::migrate();
+        assert_eq!(contract.get_k(), 7, "Expected k to be kept.");
+        assert_eq!(contract.get_dataset_meta("cancer".to_string()).unwrap().n_rows, 10, "Expected the toy datasets to be stored.");
+        contract.param_k = 9;
+        env::state_write(&contract);
+        assert_eq!(KnnMachineLearning::migrate().get_k(), 9, "Expected a state with the current layout to be unchanged.");
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract has already been initialized.")]
+    fn test_reinit() { // new cannot be called again once the state is stored
+        testing_env!(get_context(accounts(1)).build());
+        let contract = KnnMachineLearning::new(3, None, None);
+        env::state_write(&contract);
+        KnnMachineLearning::new(5, None, None);
+    }
+
+    #[test]
+    fn test_contract_source_metadata() { // version and source of the code, and the standards it implements (NEP-330)
+        let contract = KnnMachineLearning::new(3, None, None);
+        let metadata = contract.contract_source_metadata();
+        assert_eq!(metadata.version, Some("0.1.0".to_string()), "Expected the crate version.");
+        assert_eq!(metadata.link, Some("https://github.com/EkaterinaAbramova/knn_nft".to_string()), "Expected the repository.");
+        assert!(metadata.standards.iter().any(|s| s.standard == "nep330" && s.version == "1.2.0"), "Expected NEP-330 in the standards.");
+    }
+
+    #[test]
+    fn test_knn_invariants() { // properties of the classification checked on 100 random datasets (seeded, so failures can be replayed)
+        let contract = KnnMachineLearning::new(3, None, None);
+        let metric = DistanceMetric::Euclidean;
+        for seed in 0..100 {
+            let mut state = seed;
+            let mut random = || (splitmix64(&mut state) % 1_000_000) as f64 / 10_000.0; // coordinates between 0 and 100 (ties are unlikely)
+            let n_features = 1 + seed as usize % 4;
+            let train: Vec<Vec<f64>> = (0..20).map(|_| (0..n_features).map(|_| random()).collect()).collect();
+            let target: Vec<u8> = (0..20).map(|_| (random() as u8) % 3).collect();
+            let pt: Vec<f64> = (0..n_features).map(|_| random()).collect();
+            let label = contract.classify_test_point(&train, &target, &pt, &metric.into(), 3).unwrap().label;
+            // the order of the train points does not matter
+            let mut order: Vec<usize> = (0..20).collect();
+            shuffle(&mut order, seed);
+            let shuffled_train: Vec<Vec<f64>> = order.iter().map(|&ii| train[ii].clone()).collect();
+            let shuffled_target: Vec<u8> = order.iter().map(|&ii| target[ii]).collect();
+            assert_eq!(contract.classify_test_point(&shuffled_train, &shuffled_target, &pt, &metric.into(), 3).unwrap().label, label, "Expected the same class for seed {}.", seed);
+            // scaling all features by the same factor (a power of 2, so exactly) keeps the neighbours
+            let scale = |point: &Vec<f64>| point.iter().map(|x| x * 4.0).collect::<Vec<f64>>();
+            let scaled_train: Vec<Vec<f64>> = train.iter().map(scale).collect();
+            assert_eq!(contract.classify_test_point(&scaled_train, &target, &scale(&pt), &metric.into(), 3).unwrap().label, label, "Expected the same class for seed {}.", seed);
+            // k = 1 gives the class of the nearest train point
+            let nearest = (0..20).min_by(|&a, &b| cmp_pairs(&(a, distance(metric, &train[a], &pt)), &(b, distance(metric, &train[b], &pt)))).unwrap();
+            assert_eq!(contract.classify_test_point(&train, &target, &pt, &metric.into(), 1).unwrap().label, target[nearest], "Expected the class of the nearest point for seed {}.", seed);
+        }
+    }
+
+    #[test]
+    fn test_confidence_threshold() { // predictions with 2 of the 3 votes abstain when a confidence of 0.8 is required
+        let mut context = VMContextBuilder::new(); // the default context
+        testing_env!(context.build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        prepay_storage(&mut contract, &mut context, &["bob.near".parse().unwrap()]);
+        assert_eq!(contract.set_confidence_threshold(1.5), Err(KnnError::InvalidThreshold { threshold: 1.5 }), "Expected equality.");
+        contract.set_confidence_threshold(0.8).unwrap();
+        assert_eq!(contract.get_confidence_threshold(), 0.8, "Expected equality.");
+        let ans = contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None);
+        assert_eq!(ans, Err(KnnError::Abstained { label: 1, confidence: 2.0 / 3.0, threshold: 0.8 }), "Expected equality.");
+        assert!(contract.get_predictions(0, 10).is_empty(), "Expected no prediction to be recorded.");
+        contract.set_confidence_threshold(0.6).unwrap();
+        assert_eq!(contract.run_analysis("cancer".to_string(), vec![13.9, 1.9], None, None, None).unwrap().label, 1, "Expected equality.");
+    }
+
+    #[test]
+    fn test_roles() { // data providers (granted by the owner or an admin) can add training data
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.grant_role(accounts(2), Role::Admin);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.grant_role(accounts(3), Role::DataProvider); // by the admin
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.add_training_point("cancer".to_string(), vec![13.0, 2.0], 0);
+        assert_eq!(contract.get_role(accounts(3)), Some(Role::DataProvider), "Expected equality.");
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.revoke_role(accounts(3));
+        assert_eq!(contract.get_role(accounts(3)), None, "Expected the role to be revoked.");
+    }
+
+    #[test]
+    #[should_panic(expected = "Account danny is not allowed to call this method.")]
+    fn test_add_training_point_unauthorized() { // the public can predict but not add training data
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.grant_role(accounts(3), Role::Predictor);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.add_training_point("cancer".to_string(), vec![13.0, 2.0], 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Point must have 2 features to match the dataset, got 3.")]
+    fn test_add_training_point_wrong_dimension() { // schema of the dataset must be respected
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(10u128.pow(24)).build());
+        let mut contract = KnnMachineLearning::new(3, None, None);
+        contract.add_training_point("cancer".to_string(), vec![13.0, 2.0, 7.0], 0);
     }
 }
 
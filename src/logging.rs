@@ -0,0 +1,40 @@
+use crate::*;
+use near_sdk::serde_json::{self, Value};
+
+// ------------------------------------------ STRUCTURED LOGS ---------------------------------------------------
+// Diagnostics of the contract are logged as JSON lines: `LOG_JSON:{"level":"Info","event":"dataset_removed","fields":{...}}`,
+// so that indexers and debuggers can parse them instead of free-form strings (the usage of the model is tracked by the NEP-297
+// events of events.rs). Only the lines at least as severe as the verbosity level set by the owner are logged (Info by default).
+
+// Severity of a log line, from the most to the least severe.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can set the verbosity: e.g. Debug to trace predictions, Warn to only log what needs attention.
+    pub fn set_log_level(&mut self, level: LogLevel) {
+        self.assert_owner();
+        self.log_level = level;
+    }
+
+    pub fn get_log_level(&self) -> LogLevel {
+        self.log_level
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Logs the event with its fields (a JSON object) if the verbosity level allows it.
+    pub(crate) fn log(&self, level: LogLevel, event: &str, fields: Value) {
+        if level <= self.log_level {
+            let line = json!({ "level": level, "event": event, "fields": fields });
+            env::log_str(&format!("LOG_JSON:{}", serde_json::to_string(&line).unwrap()));
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::*;
+use crate::classifier::{check_registered, Classifier};
+
+// ------------------------------------------ LOGISTIC REGRESSION -----------------------------------------------
+// Unlike the lazy KNN model, which only reads the rows at prediction time, logistic regression learns weights from them, and
+// learns them on chain: once the model is registered for a dataset (see register_model), its owner or creator trains it with
+// train_epoch calls, each one gradient descent step on the cross-entropy of all the train points, so that the weights improve
+// call after call (and keep up with uploaded rows) without any off-chain training. With more than 2 classes it is the multinomial
+// (softmax) regression: every class has a weight per feature and a bias, and its probability is exp(score) / sum(exp(scores)).
+// The features go through the dataset's scaling (without the feature weights, which the model learns), so that one learning
+// rate suits all of them. The weights start at 0 (every class equally likely) and a new class starts with weights of 0 when its
+// first row is uploaded. The confidence is the probability of the predicted class; no neighbours are returned.
+
+// Weights of the logistic regression of a dataset.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LogisticModel {
+    pub epochs: u64, // number of train_epoch calls
+    pub weights: BTreeMap<u8, Vec<f64>>, // the weight of every feature, then the bias, of every class
+}
+
+impl LogisticModel {
+    // Probability of every class (ascending) for the features x (with the bias input, 1, last).
+    fn probabilities(&self, x: &[f64]) -> Vec<(u8, f64)> {
+        let scores: Vec<(u8, f64)> = self.weights.iter().map(|(label, w)| (*label, w.iter().zip(x).map(|(w, x)| w * x).sum())).collect();
+        let max = scores.iter().map(|(_, score)| *score).fold(f64::NEG_INFINITY, f64::max);
+        let total: f64 = scores.iter().map(|(_, score)| (score - max).exp()).sum();
+        scores.into_iter().map(|(label, score)| (label, (score - max).exp() / total)).collect()
+    }
+}
+
+pub(crate) struct LogisticRegression;
+
+impl Dataset {
+    // Inputs of the model for a point: its features with the dataset's scaling, and the bias input.
+    fn logistic_input(&self, point: &[f64]) -> Vec<f64> {
+        let mut x = apply_transform(point, &Some(self.stats.params(self.scaling, self.train.len())));
+        x.push(1.0);
+        x
+    }
+
+    // One gradient descent step of the logistic regression on all the train points, returning their mean cross-entropy
+    // before the step.
+    fn gradient_step(&mut self, learning_rate: f64) -> f64 {
+        let mut model = self.logistic.take().unwrap_or_default();
+        let mut gradients: BTreeMap<u8, Vec<f64>> = model.weights.iter().map(|(label, w)| (*label, vec![0.0; w.len()])).collect();
+        let mut loss = 0.0;
+        for (point, label) in self.train.iter().zip(self.target.iter()) {
+            let x = self.logistic_input(&point);
+            for (class, p) in model.probabilities(&x) {
+                let error = if class == label { p - 1.0 } else { p };
+                for (g, x) in gradients.get_mut(&class).unwrap().iter_mut().zip(&x) {
+                    *g += error * x;
+                }
+                if class == label {
+                    loss -= p.max(f64::MIN_POSITIVE).ln();
+                }
+            }
+        }
+        let n = self.train.len() as f64;
+        for (label, w) in model.weights.iter_mut() {
+            for (w, g) in w.iter_mut().zip(&gradients[label]) {
+                *w -= learning_rate * g / n;
+            }
+        }
+        model.epochs += 1;
+        self.logistic = Some(model);
+        loss / n
+    }
+}
+
+impl Classifier for LogisticRegression {
+    // Weights of 0 for the classes without any yet.
+    fn fit(&self, dataset: &mut Dataset) {
+        let n_inputs = dataset.n_features as usize + 1;
+        let mut model = dataset.logistic.take().unwrap_or_default();
+        for label in dataset.class_counts.keys() {
+            model.weights.entry(*label).or_insert_with(|| vec![0.0; n_inputs]);
+        }
+        dataset.logistic = Some(model);
+    }
+
+    fn classify(&self, contract: &KnnMachineLearning, name: &str, dataset: &Dataset, query: &Query, gas: &mut StageGas) -> Result<PredictionResult, KnnError> {
+        contract.classification_metric(name, dataset, query.test_point, query.metric)?; // checks the test point, the metric is not used
+        let start = env::used_gas().0;
+        let probabilities = dataset.logistic.as_ref().map(|model| model.probabilities(&dataset.logistic_input(query.test_point))).unwrap_or_default();
+        let (label, confidence) = probabilities.into_iter().reduce(|best, next| if next.1 > best.1 { next } else { best }).unwrap(); // a dataset to classify with has a class
+        gas.vote += env::used_gas().0 - start;
+        Ok(dataset.named(PredictionResult {
+            label,
+            label_name: None, // see Dataset::named
+            confidence,
+            neighbor_indices: Vec::new(),
+            neighbor_distances: Vec::new(),
+        }))
+    }
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner or the creator of a dataset can train its logistic regression (once registered), by one gradient descent
+    // step of this learning rate per call. Returns the mean cross-entropy of the train points before the step.
+    pub fn train_epoch(&mut self, dataset: String, learning_rate: f64) -> Result<f64, KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset)?;
+        self.assert_dataset_admin(&data);
+        check_registered(&dataset, &data, Algorithm::LogisticRegression)?;
+        if !(learning_rate > 0.0 && learning_rate.is_finite()) {
+            return Err(KnnError::InvalidLearningRate { learning_rate });
+        }
+        if data.train.is_empty() {
+            return Err(KnnError::EmptyDataset { name: dataset });
+        }
+        let loss = data.gradient_step(learning_rate);
+        self.store_dataset(&dataset, &data);
+        Ok(loss)
+    }
+
+    // Weights of the logistic regression of a dataset (None: not registered).
+    pub fn get_logistic_model(&self, dataset: String) -> Result<Option<LogisticModel>, KnnError> {
+        Ok(self.get_dataset(&dataset)?.logistic)
+    }
+}
@@ -0,0 +1,121 @@
+use crate::*;
+
+// ------------------------------------------ MAHALANOBIS DISTANCE ----------------------------------------------
+// Correlated features count the same difference several times in the Euclidean distance (e.g. height and weight), and features of
+// a large spread dominate it. The Mahalanobis distance sqrt((a - b)^T S^-1 (a - b)), with S the covariance matrix of the train
+// points, corrects for both: it is the Euclidean distance once the features are decorrelated and scaled to unit variance.
+// The owner or the creator of a dataset can have it keep the inverse covariance of its train points for the Mahalanobis metric:
+// the sums of the products of every pair of features are kept up to date as points are added, and the inverse is computed again
+// when rows are uploaded (or removed), so that predictions only read it. The metric takes the raw features: the covariance already
+// scales them, so the dataset's scaling and feature weights do not apply. A singular covariance (e.g. no more points than
+// features, or a feature that is a linear combination of others) has no inverse, and the metric then fails with NoInverseCovariance.
+const SINGULAR_TOLERANCE: f64 = 1e-12; // pivots below this share of the largest variance are taken as 0
+
+// Sums the covariance matrix of the train points derives from, with its inverse (matrices of n_features x n_features, row-major).
+#[derive(BorshDeserialize, BorshSerialize, Clone, PartialEq, Debug)]
+pub struct Covariance {
+    sum_products: Vec<f64>, // sum of x_i * x_j over the train points
+    inverse: Option<Vec<f64>>, // inverse of the covariance matrix as of the last upload (None: singular)
+}
+
+impl Covariance {
+    fn new(n_features: usize) -> Self {
+        Self { sum_products: vec![0.0; n_features * n_features], inverse: None }
+    }
+
+    pub(crate) fn add(&mut self, point: &[f64]) {
+        let n_features = point.len();
+        for (ii, x) in point.iter().enumerate() {
+            for (jj, y) in point.iter().enumerate() {
+                self.sum_products[ii * n_features + jj] += x * y;
+            }
+        }
+    }
+}
+
+impl Dataset {
+    // Computes the covariance sums of all the train points and the inverse covariance.
+    pub(crate) fn track_covariance(&mut self) {
+        let mut covariance = Covariance::new(self.n_features as usize);
+        for point in self.train.iter() {
+            covariance.add(&point);
+        }
+        self.covariance = Some(covariance);
+        self.update_inverse_covariance();
+    }
+
+    // Computes the inverse covariance again from the sums, once rows are added (if the dataset keeps it).
+    pub(crate) fn update_inverse_covariance(&mut self) {
+        let n = self.train.len() as f64;
+        let n_features = self.n_features as usize;
+        let means: Vec<f64> = self.stats.sum.iter().map(|sum| sum / n).collect();
+        if let Some(covariance) = &mut self.covariance {
+            let matrix: Vec<f64> = (0..n_features * n_features)
+                .map(|ij| covariance.sum_products[ij] / n - means[ij / n_features] * means[ij % n_features]) // population covariance
+                .collect();
+            covariance.inverse = invert(&matrix, n_features);
+        }
+    }
+
+    // Inverse covariance of the train points, for the Mahalanobis metric.
+    pub(crate) fn inverse_covariance(&self) -> Result<Vec<f64>, KnnError> {
+        self.covariance.as_ref().and_then(|covariance| covariance.inverse.clone()).ok_or(KnnError::NoInverseCovariance)
+    }
+}
+
+// Inverse of a square matrix (row-major) by Gauss-Jordan elimination with partial pivoting (None: the matrix is singular).
+fn invert(matrix: &[f64], n: usize) -> Option<Vec<f64>> {
+    let scale = (0..n).map(|ii| matrix[ii * n + ii].abs()).fold(0.0, f64::max);
+    if n == 0 || scale == 0.0 || !scale.is_finite() {
+        return None;
+    }
+    let mut a = matrix.to_vec();
+    let mut inverse: Vec<f64> = (0..n * n).map(|ij| if ij / n == ij % n { 1.0 } else { 0.0 }).collect();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|r1, r2| a[r1 * n + col].abs().total_cmp(&a[r2 * n + col].abs())).unwrap();
+        if a[pivot * n + col].abs() <= SINGULAR_TOLERANCE * scale {
+            return None;
+        }
+        for jj in 0..n {
+            a.swap(col * n + jj, pivot * n + jj);
+            inverse.swap(col * n + jj, pivot * n + jj);
+        }
+        let p = a[col * n + col];
+        for jj in 0..n {
+            a[col * n + jj] /= p;
+            inverse[col * n + jj] /= p;
+        }
+        for row in (0..n).filter(|row| *row != col) {
+            let factor = a[row * n + col];
+            for jj in 0..n {
+                a[row * n + jj] -= factor * a[col * n + jj];
+                inverse[row * n + jj] -= factor * inverse[col * n + jj];
+            }
+        }
+    }
+    Some(inverse)
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner or the creator of a dataset can have it keep (or stop keeping) the inverse covariance of its train points,
+    // which the Mahalanobis metric needs.
+    pub fn set_mahalanobis(&mut self, dataset: String, enabled: bool) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset)?;
+        self.assert_dataset_admin(&data);
+        if enabled {
+            data.track_covariance();
+        } else {
+            data.covariance = None;
+        }
+        self.store_dataset(&dataset, &data);
+        Ok(())
+    }
+
+    // Inverse covariance matrix of the train points of a dataset, by rows (None: not kept, or singular).
+    pub fn get_inverse_covariance(&self, dataset: String) -> Result<Option<Vec<Vec<f64>>>, KnnError> {
+        let data = self.get_dataset(&dataset)?;
+        Ok(data.inverse_covariance().ok().map(|inverse| inverse.chunks(data.n_features as usize).map(<[f64]>::to_vec).collect()))
+    }
+}
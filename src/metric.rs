@@ -0,0 +1,230 @@
+use crate::*;
+
+// ------------------------------------------ PLUGGABLE METRICS -------------------------------------------------
+// The searches (classify_test_point, k_nearest, the k-d tree, the chunked and off-chain predictions) only compute distances
+// through the Metric trait, dispatched at runtime: the DistanceMetric of a prediction (given in the call, else the dataset's
+// default stored with it, see set_dataset_metric and get_dataset_meta, else the contract's) is built into a PointMetric for the
+// points of the dataset (see Dataset::point_metric). A new metric implements Metric and gets a DistanceMetric variant, built
+// into it by DistanceMetric::measure, without changing the searches or the voting.
+
+// Features a metric is computed on, see Dataset::feature_transform.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MetricInput {
+    Scaled, // with the dataset's scaling and feature weights
+    RangeScaled, // scaled by their range (MinMax) whatever the dataset's scaling, with its feature weights
+    Raw, // as they are stored
+}
+
+pub(crate) trait Metric {
+    // Distance between 2 points of the same dimensionality, with f64.
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64;
+
+    // Distance with integer arithmetic, see fixed.rs (None: computed with f64 in the fixed-point mode too).
+    fn fixed_distance(&self, _a: &[f64], _b: &[f64]) -> Option<f64> {
+        None
+    }
+
+    // Cheaper stand-in for the distance (with f64) that ranks points in the same order.
+    fn rank_distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        self.distance(a, b)
+    }
+
+    // Distance from its rank distance: the same value as computing the distance directly.
+    fn rank_to_distance(&self, rank: f64) -> f64 {
+        rank
+    }
+
+    fn input(&self) -> MetricInput {
+        MetricInput::Scaled
+    }
+
+    // Factor a feature with weight w is multiplied by, so that the metric gives the weighted distance.
+    fn weight_factor(&self, w: f64) -> f64 {
+        w
+    }
+
+    // Whether the k-d tree can be searched with the metric: its pruning needs distances at least as large as the difference
+    // along any axis.
+    fn indexable(&self) -> bool {
+        false
+    }
+}
+
+// Metrics of the absolute differences of the features: Euclidean, Manhattan, Chebyshev, Minkowski, Hamming and Gower. The features
+// flagged in categorical differ by 0 or 1 (see categorical.rs).
+struct Norm {
+    metric: DistanceMetric,
+    categorical: Vec<bool>, // empty: all the features are numeric
+}
+
+impl Metric for Norm {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        let diffs = knn_core::abs_diffs(a, b, &self.categorical);
+        match self.metric {
+            DistanceMetric::Euclidean => knn_core::euclidean(diffs),
+            DistanceMetric::Manhattan => knn_core::manhattan(diffs),
+            DistanceMetric::Chebyshev => knn_core::chebyshev(diffs),
+            DistanceMetric::Minkowski(p) => knn_core::minkowski(diffs, p),
+            DistanceMetric::Hamming => knn_core::hamming(diffs),
+            _ => knn_core::gower(diffs), // Gower, the other metrics are not built into a Norm
+        }
+    }
+
+    fn fixed_distance(&self, a: &[f64], b: &[f64]) -> Option<f64> {
+        fixed::distance(&self.metric, a, b, &self.categorical)
+    }
+
+    // The Minkowski distances (Euclidean included) without their root, i.e. sum(|a - b|^p) (the squared distance for Euclidean).
+    fn rank_distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        match self.metric {
+            DistanceMetric::Euclidean => knn_core::squared_euclidean(knn_core::abs_diffs(a, b, &self.categorical)),
+            DistanceMetric::Minkowski(p) => knn_core::minkowski_sum(knn_core::abs_diffs(a, b, &self.categorical), p),
+            _ => self.distance(a, b),
+        }
+    }
+
+    fn rank_to_distance(&self, rank: f64) -> f64 {
+        match self.metric {
+            DistanceMetric::Euclidean => rank.sqrt(),
+            DistanceMetric::Minkowski(p) => rank.powf(1.0 / p),
+            _ => rank,
+        }
+    }
+
+    // Gower scales every feature by its range, so that it differs by 0 to 1.
+    fn input(&self) -> MetricInput {
+        if self.metric == DistanceMetric::Gower { MetricInput::RangeScaled } else { MetricInput::Scaled }
+    }
+
+    // e.g. sqrt(sum(w * (a - b)^2)) for Euclidean, sum(w * |a - b|) for Manhattan and max(w * |a - b|) for Chebyshev.
+    fn weight_factor(&self, w: f64) -> f64 {
+        match self.metric {
+            DistanceMetric::Euclidean => w.sqrt(),
+            DistanceMetric::Minkowski(p) => w.powf(1.0 / p),
+            _ => w,
+        }
+    }
+
+    // The Minkowski distances of numeric features.
+    fn indexable(&self) -> bool {
+        !matches!(self.metric, DistanceMetric::Hamming | DistanceMetric::Gower) && !self.categorical.contains(&true)
+    }
+}
+
+// Great-circle distance in km between (latitude, longitude) points, see knn_core::haversine.
+struct Haversine;
+
+impl Metric for Haversine {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        knn_core::haversine(a, b)
+    }
+
+    // Scaled degrees are no longer angles.
+    fn input(&self) -> MetricInput {
+        MetricInput::Raw
+    }
+}
+
+// 1 - cos(angle between a and b), see knn_core::cosine.
+struct Cosine;
+
+impl Metric for Cosine {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        knn_core::cosine(a, b)
+    }
+
+    // sqrt(w) scales the products a_i * b_i by w.
+    fn weight_factor(&self, w: f64) -> f64 {
+        w.sqrt()
+    }
+}
+
+// sqrt((a - b)^T S^-1 (a - b)) for the inverse covariance S^-1 of the train points, see mahalanobis.rs.
+struct Mahalanobis {
+    inverse_covariance: Vec<f64>, // row-major (empty: the identity, i.e. the Euclidean distance)
+}
+
+impl Metric for Mahalanobis {
+    fn distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        self.rank_distance(a, b).sqrt()
+    }
+
+    // The squared distance.
+    fn rank_distance(&self, a: &[f64], b: &[f64]) -> f64 {
+        if self.inverse_covariance.is_empty() {
+            return knn_core::squared_euclidean(knn_core::abs_diffs(a, b, &[]));
+        }
+        knn_core::squared_mahalanobis(a, b, &self.inverse_covariance)
+    }
+
+    fn rank_to_distance(&self, rank: f64) -> f64 {
+        rank.sqrt()
+    }
+
+    // The covariance is of the raw features, and already scales them.
+    fn input(&self) -> MetricInput {
+        MetricInput::Raw
+    }
+}
+
+impl DistanceMetric {
+    // The Metric computing this distance, for points whose features flagged in categorical are categories and, for Mahalanobis,
+    // with the inverse covariance of the train points (empty: the identity).
+    pub(crate) fn measure(self, categorical: Vec<bool>, inverse_covariance: Vec<f64>) -> PointMetric {
+        PointMetric(match self {
+            DistanceMetric::Euclidean | DistanceMetric::Manhattan | DistanceMetric::Chebyshev | DistanceMetric::Minkowski(_) | DistanceMetric::Hamming | DistanceMetric::Gower => {
+                Box::new(Norm { metric: self, categorical })
+            }
+            DistanceMetric::Haversine => Box::new(Haversine),
+            DistanceMetric::Cosine => Box::new(Cosine),
+            DistanceMetric::Mahalanobis => Box::new(Mahalanobis { inverse_covariance }),
+        })
+    }
+}
+
+// Distance metric as applied to the points of a dataset (see Dataset::point_metric), computed in either numeric mode.
+pub struct PointMetric(pub(crate) Box<dyn Metric>);
+
+// The metric for numeric features (with the identity covariance for Mahalanobis).
+impl From<DistanceMetric> for PointMetric {
+    fn from(metric: DistanceMetric) -> Self {
+        metric.measure(Vec::new(), Vec::new())
+    }
+}
+
+impl PointMetric {
+    // The fixed-point mode ranks points by the distance itself.
+    pub(crate) fn rank_distance(&self, mode: NumericMode, a: &[f64], b: &[f64]) -> f64 {
+        match mode {
+            NumericMode::Float => self.0.rank_distance(a, b),
+            NumericMode::FixedPoint => self.distance_in(mode, a, b),
+        }
+    }
+
+    pub(crate) fn rank_to_distance(&self, mode: NumericMode, rank: f64) -> f64 {
+        match mode {
+            NumericMode::Float => self.0.rank_to_distance(rank),
+            NumericMode::FixedPoint => rank,
+        }
+    }
+
+    // Distance between 2 points in the numeric mode (metrics without fixed-point support are computed with f64, see check_metric).
+    pub(crate) fn distance_in(&self, mode: NumericMode, a: &[f64], b: &[f64]) -> f64 {
+        match mode {
+            NumericMode::Float => self.0.distance(a, b),
+            NumericMode::FixedPoint => self.0.fixed_distance(a, b).unwrap_or_else(|| self.0.distance(a, b)),
+        }
+    }
+
+    pub(crate) fn input(&self) -> MetricInput {
+        self.0.input()
+    }
+
+    pub(crate) fn weight_factor(&self, w: f64) -> f64 {
+        self.0.weight_factor(w)
+    }
+
+    pub(crate) fn indexable(&self) -> bool {
+        self.0.indexable()
+    }
+}
@@ -0,0 +1,64 @@
+use crate::*;
+
+// ------------------------------------------ STATE MIGRATION ---------------------------------------------------
+// The contract state is read with Borsh, so redeploying code with another layout of KnnMachineLearning over an existing account
+// makes the old state unreadable. The deployment is instead followed by a call to migrate, which reads the state with the layout
+// of its version and converts it to the current one.
+// The version of the state is stored under its own key (states deployed before versioning have none: version 1).
+// To change the layout: copy the current struct here as the layout of its version (e.g. ContractV2), add the variant to
+// VersionedContract with its conversion to the new layout, and bump STATE_VERSION.
+const STATE_VERSION: u8 = 2;
+const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
+
+// Layout of the first deployed version (knn_nft.drkat.testnet): only k, the toy datasets were constants of the code.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+    pub param_k: u8,
+}
+
+// The contract state as stored by each version.
+pub enum VersionedContract {
+    V1(ContractV1),
+    V2(Box<KnnMachineLearning>), // current layout
+}
+
+impl VersionedContract {
+    // Reads the stored state with the layout of its version.
+    fn read() -> Self {
+        let version = env::storage_read(STATE_VERSION_KEY).map_or(1, |bytes| bytes[0]);
+        match version {
+            1 => VersionedContract::V1(env::state_read().unwrap_or_else(|| env::panic_str("No contract state to migrate."))),
+            STATE_VERSION => VersionedContract::V2(Box::new(env::state_read().unwrap_or_else(|| env::panic_str("No contract state to migrate.")))),
+            _ => env::panic_str(&format!("Unknown state version {}.", version)),
+        }
+    }
+
+    // Converts the state to the current layout, version by version.
+    fn into_current(self) -> KnnMachineLearning {
+        match self {
+            VersionedContract::V1(v1) => KnnMachineLearning {
+                param_k: v1.param_k,
+                ..Default::default() // storage collections and toy datasets, the deployer's account owns the contract
+            },
+            VersionedContract::V2(contract) => *contract,
+        }
+    }
+}
+
+// Records that the stored state has the current layout.
+pub(crate) fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &[STATE_VERSION]);
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Called (by the contract account) right after deploying a new version of the code: converts the stored state to the current
+    // layout, keeping its data. Calling it on a state that already has the current layout changes nothing.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let contract = VersionedContract::read().into_current();
+        write_state_version();
+        contract
+    }
+}
@@ -0,0 +1,69 @@
+use crate::*;
+
+// ------------------------------------------ MULTI-LABEL CLASSIFICATION ----------------------------------------
+// A train point can belong to several classes at once (e.g. the topics of a document). Its classes are kept sorted, the first
+// (lowest) being its label for the single-label methods (classify, run_analysis, ...), and all of them in the label sets of the
+// dataset, which are only stored once the dataset has a point of several classes. classify_multilabel returns every class whose
+// share of the (possibly distance weighted) votes of the k nearest neighbours exceeds a threshold: each neighbour votes for all its
+// classes, so several classes can pass it.
+
+impl Dataset {
+    // Appends the classes (sorted, at least one) of a train point pushed with push_point, and chains the row into the content hash.
+    pub(crate) fn push_label_set(&mut self, point: &[f64], labels: &[u8]) {
+        if labels.len() == 1 {
+            self.push_label(labels[0]);
+            return self.hash_row(point, labels[0]);
+        }
+        if self.label_sets.is_empty() { // first point of several classes: the points so far have one class each
+            for label in self.target.iter() {
+                self.label_sets.push(&vec![label]);
+            }
+        }
+        self.label_sets.push(&labels.to_vec());
+        self.target.push(&labels[0]);
+        *self.class_counts.entry(labels[0]).or_insert(0) += 1;
+        self.hash_row(point, labels);
+    }
+
+    // All the classes of a train point.
+    fn row_labels(&self, index: u64) -> Vec<u8> {
+        self.label_sets.get(index).or_else(|| self.target.get(index).map(|label| vec![label])).unwrap_or_default()
+    }
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Same as add_training_point, with all the classes of the point (in any order, duplicates ignored).
+    #[payable]
+    pub fn add_multilabel_training_point(&mut self, dataset: String, point: Vec<f64>, labels: Vec<u8>) {
+        self.assert_not_paused();
+        self.assert_can_write(&dataset);
+        let mut labels = labels;
+        labels.sort_unstable();
+        labels.dedup();
+        if labels.is_empty() {
+            KnnError::EmptyLabelSet.panic();
+        }
+        self.append_training_rows(&dataset, TrainingRows { feature_names: Vec::new(), label_names: None, points: vec![point], labels: vec![labels[0]], label_sets: Some(vec![labels]) });
+    }
+
+    // Every class whose share of the votes of the k nearest neighbours exceeds threshold (between 0 and 1), with its share, by
+    // ascending label.
+    pub fn classify_multilabel(&self, dataset: String, point: Vec<f64>, threshold: f64) -> Result<Vec<(u8, f64)>, KnnError> {
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(KnnError::InvalidThreshold { threshold });
+        }
+        let data = self.get_dataset(&dataset)?;
+        let metric = self.classification_metric(&dataset, &data, &point, None)?;
+        let k = data.k.unwrap_or(self.param_k);
+        let (indices, distances) = self.dataset_neighbors(&data, &point, &metric, k as usize, &mut StageGas::default());
+        let label_sets: Vec<Vec<u8>> = indices.iter().map(|&ii| data.row_labels(ii as u64)).collect();
+        let weights: Vec<f64> = distances.iter().map(|&d| self.voting_scheme.weight(d)).collect();
+        let query = (&dataset, &point, k).try_to_vec().unwrap();
+        Ok(knn_core::label_set_shares(&label_sets, &weights)
+            .into_iter()
+            .map(|(label, share)| (label, self.noisy_share(share, indices.len(), false, &[&query[..], &[label]].concat())))
+            .filter(|(_, share)| *share > threshold)
+            .collect())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::*;
+use crate::classifier::Classifier;
+
+// ------------------------------------------ GAUSSIAN NAIVE BAYES ----------------------------------------------
+// Each class is modelled by a normal distribution per feature, of the mean and variance of the feature over the train points of
+// the class, the features taken as independent given the class. A test point is given the class of the highest posterior
+// probability P(class) * prod(P(x_i | class)), P(class) the share of the class in the train points: a prediction costs
+// O(classes x features) whatever the number of rows, to compare against KNN on the same datasets. Once the model is registered
+// for a dataset (see register_model), the dataset keeps the feature statistics of every class, which uploads extend with their
+// rows (removing rows computes them all again). The model takes the raw features (the normal distributions already scale
+// them), so the dataset's scaling, feature weights and metric do not apply. The confidence is the posterior probability of the
+// winning class; no neighbours are returned.
+const VAR_SMOOTHING: f64 = 1e-9; // share of the largest feature variance added to every variance, so that none is 0
+
+// Feature statistics of the train points of every class, over the first n_rows rows of the dataset.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default, PartialEq, Debug)]
+pub struct ClassStats {
+    n_rows: u64,
+    stats: BTreeMap<u8, FeatureStats>,
+}
+
+pub(crate) struct NaiveBayes;
+
+// Mean and variance of every feature of n points.
+fn moments(stats: &FeatureStats, n: f64) -> Vec<(f64, f64)> {
+    stats.sum.iter().zip(&stats.sum_sq).map(|(sum, sum_sq)| (sum / n, (sum_sq / n - (sum / n).powi(2)).max(0.0))).collect()
+}
+
+// Log density of the normal distribution of this mean and variance at x.
+fn log_density(x: f64, mean: f64, variance: f64) -> f64 {
+    -0.5 * (2.0 * std::f64::consts::PI * variance).ln() - (x - mean).powi(2) / (2.0 * variance)
+}
+
+impl Classifier for NaiveBayes {
+    fn fit(&self, dataset: &mut Dataset) {
+        let mut class_stats = dataset.class_stats.take().unwrap_or_default();
+        let n_features = dataset.n_features as usize;
+        for ii in class_stats.n_rows..dataset.train.len() {
+            let (point, label) = (dataset.train.get(ii).unwrap(), dataset.target.get(ii).unwrap());
+            class_stats.stats.entry(label).or_insert_with(|| FeatureStats::new(n_features)).add(&point);
+        }
+        class_stats.n_rows = dataset.train.len();
+        dataset.class_stats = Some(class_stats);
+    }
+
+    fn classify(&self, contract: &KnnMachineLearning, name: &str, dataset: &Dataset, query: &Query, gas: &mut StageGas) -> Result<PredictionResult, KnnError> {
+        contract.classification_metric(name, dataset, query.test_point, query.metric)?; // checks the test point, the metric is not used
+        let start = env::used_gas().0;
+        let n = dataset.train.len() as f64;
+        let smoothing = (VAR_SMOOTHING * moments(&dataset.stats, n).iter().map(|(_, variance)| *variance).fold(0.0, f64::max)).max(VAR_SMOOTHING);
+        let log_posteriors: Vec<(u8, f64)> = dataset
+            .class_stats
+            .iter()
+            .flat_map(|class_stats| &class_stats.stats)
+            .map(|(label, stats)| {
+                let count = dataset.class_counts[label] as f64;
+                let log_likelihood: f64 = moments(stats, count).iter().zip(query.test_point).map(|((mean, variance), x)| log_density(*x, *mean, variance + smoothing)).sum();
+                (*label, (count / n).ln() + log_likelihood)
+            })
+            .collect();
+        let (label, best) = log_posteriors.iter().copied().reduce(|best, next| if next.1 > best.1 { next } else { best }).unwrap(); // a dataset to classify with has a class
+        let total: f64 = log_posteriors.iter().map(|(_, log_posterior)| (log_posterior - best).exp()).sum();
+        gas.vote += env::used_gas().0 - start;
+        Ok(dataset.named(PredictionResult {
+            label,
+            label_name: None, // see Dataset::named
+            confidence: 1.0 / total, // exp(best - best) / total
+            neighbor_indices: Vec::new(),
+            neighbor_distances: Vec::new(),
+        }))
+    }
+}
@@ -0,0 +1,311 @@
+use crate::*;
+use near_contract_standards::non_fungible_token::metadata::{
+    NFTContractMetadata, NonFungibleTokenMetadataProvider, TokenMetadata, NFT_METADATA_SPEC,
+};
+use near_contract_standards::non_fungible_token::core::StorageKey as NftStorageKey;
+use near_contract_standards::non_fungible_token::{Token, TokenId};
+use near_sdk::collections::UnorderedSet;
+use near_sdk::serde_json;
+use near_sdk::{assert_one_yocto, PromiseOrValue};
+
+// ------------------------------------------ PREDICTION NFTs (NEP-171) ----------------------------------------
+// A caller of run_analysis can ask for the prediction to be minted as a non-fungible token owned by them.
+// The token id is the id of the prediction in the prediction history, and the token's metadata records
+// the dataset, test point, predicted class and confidence of the prediction.
+// Transfers (nft_transfer, nft_transfer_call) and nft_token come from the NEP-171 core implementation of near-contract-standards.
+near_contract_standards::impl_non_fungible_token_core!(KnnMachineLearning, tokens);
+// Listing of the tokens, in total and per owner, with pagination (NEP-181): nft_total_supply, nft_tokens,
+// nft_supply_for_owner and nft_tokens_for_owner.
+near_contract_standards::impl_non_fungible_token_enumeration!(KnnMachineLearning, tokens);
+// Approval management (NEP-178): holders can approve other accounts (e.g. a marketplace) to transfer their tokens with nft_approve,
+// and withdraw the approvals with nft_revoke and nft_revoke_all. The transfers check the approvals and clear them.
+near_contract_standards::impl_non_fungible_token_approval!(KnnMachineLearning, tokens);
+
+// Contract level metadata (NEP-177), used by wallets and marketplaces to display the collection.
+const NFT_NAME: &str = "KNN predictions";
+const NFT_SYMBOL: &str = "KNN";
+
+#[near_bindgen]
+impl NonFungibleTokenMetadataProvider for KnnMachineLearning {
+    fn nft_metadata(&self) -> NFTContractMetadata {
+        NFTContractMetadata {
+            spec: NFT_METADATA_SPEC.to_string(),
+            name: NFT_NAME.to_string(),
+            symbol: NFT_SYMBOL.to_string(),
+            icon: None,
+            base_uri: None,
+            reference: None,
+            reference_hash: None,
+        }
+    }
+}
+
+// Details of the prediction (also summarised in the token's title and description), stored as JSON in the `extra` field of the token metadata.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct PredictionExtra<'a> {
+    dataset: &'a str,
+    test_point: &'a [f64],
+    predicted_class: u8,
+    confidence: f64,
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Mints the NFT of prediction `id` to its caller.
+    // Storage of the token is not charged here: the caller (run_analysis) charges it together with the prediction fee,
+    // hence the token is written directly rather than with NonFungibleToken::internal_mint, which refunds the whole attached deposit.
+    pub(crate) fn mint_prediction(&mut self, id: u64, owner_id: AccountId, dataset: &str, test_point: &[f64], result: &PredictionResult) {
+        let extra = PredictionExtra {
+            dataset,
+            test_point,
+            predicted_class: result.label,
+            confidence: result.confidence,
+        };
+        let metadata = TokenMetadata {
+            title: Some(format!("KNN prediction #{}", id)),
+            description: Some(format!(
+                "Class {} predicted for the test point {:?} of the '{}' dataset, with confidence {:.2}.",
+                result.label, test_point, dataset, result.confidence
+            )),
+            media: None, // no image, wallets show the title and description
+            media_hash: None,
+            copies: Some(1), // every prediction is unique
+            issued_at: Some((env::block_timestamp() / 1_000_000).to_string()), // milliseconds since the Unix epoch
+            expires_at: None,
+            starts_at: None,
+            updated_at: None,
+            extra: Some(serde_json::to_string(&extra).unwrap()),
+            reference: None,
+            reference_hash: None,
+        };
+        let token_id: TokenId = id.to_string(); // prediction ids are unique, so are the token ids
+        self.record_royalties(&token_id, dataset);
+        self.insert_token(&token_id, &owner_id, &metadata);
+    }
+
+    // Callable from methods only (not user). Writes a new token, with the same layout as near-contract-standards so that
+    // transfers, approvals and the enumeration views keep working.
+    fn insert_token(&mut self, token_id: &TokenId, owner_id: &AccountId, metadata: &TokenMetadata) {
+        self.tokens.owner_by_id.insert(token_id, owner_id);
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            token_metadata_by_id.insert(token_id, metadata);
+        }
+        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
+            let mut token_ids = tokens_per_owner.get(owner_id).unwrap_or_else(|| {
+                UnorderedSet::new(NftStorageKey::TokensPerOwner { account_hash: env::sha256(owner_id.as_bytes()) })
+            });
+            token_ids.insert(token_id);
+            tokens_per_owner.insert(owner_id, &token_ids);
+        }
+    }
+
+    // Callable from methods only (not user). Deletes a token with its metadata, approvals and royalties.
+    fn burn_token(&mut self, token_id: &TokenId) {
+        let owner_id = match self.tokens.owner_by_id.remove(token_id) {
+            Some(owner_id) => owner_id,
+            None => return,
+        };
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            token_metadata_by_id.remove(token_id);
+        }
+        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
+            if let Some(mut token_ids) = tokens_per_owner.get(&owner_id) {
+                token_ids.remove(token_id);
+                if token_ids.is_empty() {
+                    tokens_per_owner.remove(&owner_id);
+                } else {
+                    tokens_per_owner.insert(&owner_id, &token_ids);
+                }
+            }
+        }
+        if let Some(approvals_by_id) = &mut self.tokens.approvals_by_id {
+            approvals_by_id.remove(token_id);
+        }
+        if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
+            next_approval_id_by_id.remove(token_id);
+        }
+        self.token_royalties.remove(token_id);
+    }
+}
+
+// ------------------------------------------ MODEL NFTs --------------------------------------------------------
+// Besides the predictions, a dataset (the model it defines) can be tokenized: its creator mints one NFT that represents the
+// ownership of the model. The holder of the token receives the prediction fees paid on the dataset (what remains once the
+// contributors are rewarded), credited to their claimable balance (see claim_rewards), so transferring or selling the token
+// transfers the revenue stream. The token id is "model:<dataset name>" and it is burnt when the dataset is removed.
+const MODEL_TOKEN_PREFIX: &str = "model:";
+
+pub(crate) fn model_token_id(dataset: &str) -> TokenId {
+    format!("{}{}", MODEL_TOKEN_PREFIX, dataset)
+}
+
+// Details of the model, stored as JSON in the `extra` field of the token metadata.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct ModelExtra<'a> {
+    dataset: &'a str,
+    n_features: u32,
+    created_at: u64, // block timestamp (nanoseconds) of the creation of the dataset
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Mints the NFT of a dataset to its creator, who must call it (the owner, for the toy datasets of the contract) attaching
+    // a deposit that covers the storage of the token (the surplus is refunded). Returns the token id.
+    #[payable]
+    pub fn mint_model_nft(&mut self, name: String) -> TokenId {
+        self.assert_not_paused();
+        let data = self.get_dataset(&name).unwrap_or_else(|e| e.panic());
+        let caller = env::predecessor_account_id();
+        let creator = if data.creator == env::current_account_id() { self.owner_id.clone() } else { data.creator.clone() };
+        if caller != creator {
+            KnnError::Unauthorized { account_id: caller }.panic();
+        }
+        let token_id = model_token_id(&name);
+        if self.tokens.owner_by_id.contains_key(&token_id) {
+            KnnError::ModelTokenExists { name }.panic();
+        }
+        let extra = ModelExtra { dataset: &name, n_features: data.n_features, created_at: data.created_at };
+        let metadata = TokenMetadata {
+            title: Some(format!("KNN model '{}'", name)),
+            description: Some(format!("Ownership of the '{}' dataset: the holder receives the prediction fees paid on it.", name)),
+            media: None,
+            media_hash: None,
+            copies: Some(1),
+            issued_at: Some((env::block_timestamp() / 1_000_000).to_string()),
+            expires_at: None,
+            starts_at: None,
+            updated_at: None,
+            extra: Some(serde_json::to_string(&extra).unwrap()),
+            reference: None,
+            reference_hash: None,
+        };
+        let initial_storage = env::storage_usage();
+        self.record_royalties(&token_id, &name);
+        self.insert_token(&token_id, &caller, &metadata);
+        self.charge_storage(initial_storage, 0);
+        token_id
+    }
+
+    // Holder of the NFT of a dataset, if it was minted.
+    pub fn get_model_holder(&self, name: String) -> Option<AccountId> {
+        self.tokens.owner_by_id.get(&model_token_id(&name))
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Credits the part of a prediction fee left to the dataset to the holder of its NFT,
+    // returns the part kept by the treasury (all of it when the dataset has no NFT).
+    pub(crate) fn pay_model_holder(&mut self, name: &str, fee: Balance) -> Balance {
+        match self.tokens.owner_by_id.get(&model_token_id(name)) {
+            Some(holder) => {
+                self.credit_rewards(&holder, fee);
+                0
+            }
+            None => fee,
+        }
+    }
+
+    // Callable from methods only (not user). Burns the NFT of a dataset being removed.
+    pub(crate) fn burn_model_nft(&mut self, name: &str) {
+        self.burn_token(&model_token_id(name));
+    }
+}
+
+// ------------------------------------------ ROYALTIES (NEP-199) ----------------------------------------------
+// When a prediction NFT is resold on a marketplace, the creator of the dataset it was predicted with and the contract owner
+// earn a share of the sale. Marketplaces ask for the split with nft_payout, or transfer the token with nft_transfer_payout
+// and pay out the returned amounts. The shares are set by the contract owner and recorded on each token when it is minted.
+const MAX_ROYALTY_BPS: u16 = 5_000; // royalties take at most half of a sale
+const ROYALTY_BPS_DENOMINATOR: u128 = 10_000;
+
+// Shares of a sale (in basis points) for the creator of the dataset and the contract owner.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Royalties {
+    pub dataset_creator_bps: u16,
+    pub owner_bps: u16,
+}
+
+impl Royalties {
+    pub(crate) fn validate(&self) -> Result<(), KnnError> {
+        let total_bps = u32::from(self.dataset_creator_bps) + u32::from(self.owner_bps); // u16 bps could overflow
+        if total_bps > u32::from(MAX_ROYALTY_BPS) {
+            return Err(KnnError::InvalidRoyalties { total_bps });
+        }
+        Ok(())
+    }
+}
+
+// Amounts (yoctoNEAR) of a sale to pay to each account, the token owner included (NEP-199).
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Payout {
+    pub payout: HashMap<AccountId, U128>,
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can set the royalties of the prediction NFTs minted from now on (at most 5000 basis points in total).
+    pub fn set_royalties(&mut self, royalties: Royalties) -> Result<(), KnnError> {
+        self.assert_owner();
+        self.assert_ungoverned();
+        royalties.validate()?;
+        self.royalties = royalties;
+        Ok(())
+    }
+
+    pub fn get_royalties(&self) -> Royalties {
+        self.royalties
+    }
+
+    // Split of a sale of the token for balance: royalties to their accounts, the rest to the token owner.
+    // Panics if the token is unknown or the payout has more than max_len_payout accounts.
+    pub fn nft_payout(&self, token_id: TokenId, balance: U128, max_len_payout: Option<u32>) -> Payout {
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect("Token not found");
+        self.payout(&token_id, owner_id, balance.0, max_len_payout)
+    }
+
+    // Transfers the token like nft_transfer (the caller must own it or be approved) and returns the payout of the sale (see nft_payout)
+    // to its previous owner. Requires 1 yoctoNEAR attached.
+    #[payable]
+    pub fn nft_transfer_payout(&mut self, receiver_id: AccountId, token_id: TokenId, approval_id: Option<u64>, memo: Option<String>, balance: U128, max_len_payout: Option<u32>) -> Payout {
+        assert_one_yocto();
+        let (previous_owner, _) = self.tokens.internal_transfer(&env::predecessor_account_id(), &receiver_id, &token_id, approval_id, memo);
+        self.payout(&token_id, previous_owner, balance.0, max_len_payout)
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Records the royalties of a newly minted token: the current shares, for the creator
+    // of its dataset and the contract owner (the owner's share if the dataset was created by the contract itself).
+    pub(crate) fn record_royalties(&mut self, token_id: &TokenId, dataset: &str) {
+        let creator = self.get_dataset(&dataset.to_string()).map_or_else(|_| self.owner_id.clone(), |data| data.creator);
+        let creator = if creator == env::current_account_id() { self.owner_id.clone() } else { creator };
+        let mut royalty: BTreeMap<AccountId, u16> = BTreeMap::new();
+        for (account_id, bps) in [(creator, self.royalties.dataset_creator_bps), (self.owner_id.clone(), self.royalties.owner_bps)] {
+            if bps > 0 {
+                *royalty.entry(account_id).or_insert(0) += bps;
+            }
+        }
+        if !royalty.is_empty() {
+            self.token_royalties.insert(token_id, &royalty);
+        }
+    }
+
+    fn payout(&self, token_id: &TokenId, owner_id: AccountId, balance: Balance, max_len_payout: Option<u32>) -> Payout {
+        let royalty = self.token_royalties.get(token_id).unwrap_or_default();
+        let mut payout: HashMap<AccountId, U128> = HashMap::new();
+        let mut paid: Balance = 0;
+        for (account_id, bps) in royalty {
+            let amount = balance * Balance::from(bps) / ROYALTY_BPS_DENOMINATOR;
+            paid += amount;
+            payout.entry(account_id).or_insert(U128(0)).0 += amount;
+        }
+        payout.entry(owner_id).or_insert(U128(0)).0 += balance - paid;
+        if let Some(max_len) = max_len_payout {
+            assert!(payout.len() <= max_len as usize, "Payout to {} accounts exceeds max_len_payout of {}.", payout.len(), max_len);
+        }
+        Payout { payout }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::*;
+use near_sdk::json_types::Base64VecU8;
+
+// ------------------------------------------ OFF-CHAIN DATASETS ------------------------------------------------
+// A dataset too large (or too costly) to store on chain is kept off chain, e.g. on IPFS, and only a commitment to it is stored:
+// the root of a Merkle tree over its rows. A prediction supplies the rows to compare against, each with the Merkle proof that it
+// is the row at its index of the committed dataset, so the contract only classifies with rows that belong to the dataset.
+// The contract cannot check that the supplied rows include the true nearest neighbours of the test point: the result is the
+// vote of the k nearest among the rows supplied, which anyone holding the dataset can verify by running the same query.
+//
+// Merkle tree: the leaf of the row at index i is sha256(0x00 | borsh(i as u64, point, label)), a node is sha256(0x01 | left | right).
+// Each level pairs its nodes from the left; the last node of a level with an odd number of nodes is carried up unpaired.
+// A proof lists the sibling hashes from the leaf up (levels where the node is carried up have no sibling).
+// The commitment is stored at the expense of its creator, like the rows of an on-chain dataset, and its name is then taken for
+// on-chain datasets too.
+const LEAF_TAG: u8 = 0x00;
+const NODE_TAG: u8 = 0x01;
+const HASH_LEN: usize = 32;
+const MAX_URI_LEN: usize = 512; // bytes
+
+// Commitment to a dataset stored off chain.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OffchainDataset {
+    pub merkle_root: Base64VecU8, // root of the Merkle tree over the rows (32 bytes)
+    pub n_rows: u64,
+    pub n_features: u32,
+    pub uri: Option<String>, // where the rows can be downloaded, e.g. "ipfs://<CID>"
+    pub creator: AccountId,
+}
+
+// A row of an off-chain dataset, with the proof that it is the row at index of the committed dataset.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProvenRow {
+    pub index: u64,
+    pub point: Vec<f64>,
+    pub label: u8,
+    pub proof: Vec<Base64VecU8>, // sibling hashes, from the leaf up
+}
+
+pub(crate) fn leaf_hash(index: u64, point: &[f64], label: u8) -> Vec<u8> {
+    let mut bytes = vec![LEAF_TAG];
+    bytes.extend((index, point.to_vec(), label).try_to_vec().unwrap());
+    env::sha256(&bytes)
+}
+
+fn row_leaves(rows: &[(Vec<f64>, u8)]) -> Vec<Vec<u8>> {
+    rows.iter().enumerate().map(|(ii, (point, label))| leaf_hash(ii as u64, point, *label)).collect()
+}
+
+fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    env::sha256(&[&[NODE_TAG], left, right].concat())
+}
+
+// Parent level of a level of the tree.
+fn parent_level(level: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    level.chunks(2).map(|pair| if pair.len() == 2 { node_hash(&pair[0], &pair[1]) } else { pair[0].clone() }).collect()
+}
+
+// Root of the tree over the leaves (at least one).
+fn merkle_root(leaves: &[Vec<u8>]) -> Vec<u8> {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = parent_level(&level);
+    }
+    level.swap_remove(0)
+}
+
+// Proof of the leaf at index.
+fn merkle_proof(leaves: &[Vec<u8>], index: usize) -> Vec<Vec<u8>> {
+    let (mut level, mut index, mut proof) = (leaves.to_vec(), index, Vec::new());
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            proof.push(level[sibling].clone());
+        }
+        level = parent_level(&level);
+        index /= 2;
+    }
+    proof
+}
+
+// Whether the proof leads from the leaf at index to the root of a tree of n_leaves leaves.
+pub(crate) fn verify_proof(root: &[u8], n_leaves: u64, index: u64, leaf: Vec<u8>, proof: &[Base64VecU8]) -> bool {
+    if index >= n_leaves {
+        return false;
+    }
+    let (mut hash, mut index, mut n, mut siblings) = (leaf, index, n_leaves, proof.iter());
+    while n > 1 {
+        if index % 2 == 1 || index + 1 < n { // the node has a sibling at this level
+            let sibling = match siblings.next() {
+                Some(sibling) if sibling.0.len() == HASH_LEN => &sibling.0,
+                _ => return false,
+            };
+            hash = if index % 2 == 0 { node_hash(&hash, sibling) } else { node_hash(sibling, &hash) };
+        }
+        index /= 2;
+        n = n.div_ceil(2);
+    }
+    siblings.next().is_none() && hash == root
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Registers a dataset stored off chain by the root of the Merkle tree over its n_rows rows of n_features features (and a uri of
+    // at most MAX_URI_LEN bytes). Written by the same accounts as on-chain datasets, under a name no on-chain dataset has.
+    // Registering again replaces the commitment. Payable: the caller covers the storage of the commitment (the surplus is
+    // refunded); errors panic if a deposit is attached, so that it is returned.
+    #[payable]
+    pub fn register_offchain_dataset(&mut self, name: String, merkle_root: Base64VecU8, n_rows: u64, n_features: u32, uri: Option<String>) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        self.assert_can_write(&name);
+        let creator = env::predecessor_account_id();
+        match self.check_offchain_dataset(&name, &merkle_root, n_rows, n_features, &uri, &creator) {
+            Err(e) if env::attached_deposit() > 0 => e.panic(),
+            result => result?,
+        }
+        let initial_storage = env::storage_usage();
+        self.offchain_datasets.insert(&name, &OffchainDataset { merkle_root, n_rows, n_features, uri, creator });
+        self.charge_storage(initial_storage, 0);
+        Ok(())
+    }
+
+    pub fn get_offchain_dataset(&self, name: String) -> Option<OffchainDataset> {
+        self.offchain_datasets.get(&name)
+    }
+
+    // Root of the Merkle tree over rows (points with their class, in the order of their indices), and the proof of the row at index:
+    // the reference for the hashing, to check an off-chain implementation against on small datasets (large ones exceed the gas of a view call).
+    pub fn compute_merkle_root(&self, rows: Vec<(Vec<f64>, u8)>) -> Option<Base64VecU8> {
+        if rows.is_empty() {
+            return None;
+        }
+        Some(Base64VecU8(merkle_root(&row_leaves(&rows))))
+    }
+
+    pub fn compute_merkle_proof(&self, rows: Vec<(Vec<f64>, u8)>, index: u64) -> Option<Vec<Base64VecU8>> {
+        if index >= rows.len() as u64 {
+            return None;
+        }
+        Some(merkle_proof(&row_leaves(&rows), index as usize).into_iter().map(Base64VecU8).collect())
+    }
+
+    // Classifies the test point by the vote of its k nearest among the supplied rows of an off-chain dataset, once every row is
+    // proven to belong to the dataset (rows are counted once per index). The neighbour indices are the rows' indices in the dataset.
+    // Uses the contract's metric and voting scheme; k defaults to the contract's k and cannot exceed the number of rows supplied.
+    pub fn classify_offchain(&self, name: String, test_point: Vec<f64>, rows: Vec<ProvenRow>, k: Option<u8>) -> Result<PredictionResult, KnnError> {
+        let dataset = self.offchain_datasets.get(&name).ok_or_else(|| KnnError::UnknownDataset { name: name.clone() })?;
+        if test_point.len() != dataset.n_features as usize {
+            return Err(KnnError::DimensionMismatch { expected: dataset.n_features, actual: test_point.len() as u32 });
+        }
+        check_finite(&test_point)?;
+        let mut verified: BTreeMap<u64, (Vec<f64>, u8)> = BTreeMap::new(); // index -> row
+        for row in rows {
+            if row.point.len() != dataset.n_features as usize {
+                return Err(KnnError::DimensionMismatch { expected: dataset.n_features, actual: row.point.len() as u32 });
+            }
+            let leaf = leaf_hash(row.index, &row.point, row.label);
+            if !verify_proof(&dataset.merkle_root.0, dataset.n_rows, row.index, leaf, &row.proof) {
+                return Err(KnnError::InvalidProof { index: row.index });
+            }
+            verified.insert(row.index, (self.stored_point(row.point)?, row.label));
+        }
+        let k = k.unwrap_or(self.param_k);
+        validate_k(k)?;
+        if usize::from(k) > verified.len() {
+            return Err(KnnError::KTooLarge { k, n_points: verified.len() as u64 });
+        }
+        let metric = self.distance_metric;
+        self.check_metric(&metric)?;
+        metric.check_dimension(dataset.n_features)?;
+        if metric == DistanceMetric::Mahalanobis { // no covariance of the rows is kept off chain
+            return Err(KnnError::NoInverseCovariance);
+        }
+        let metric = PointMetric::from(metric);
+        let indices: Vec<u64> = verified.keys().copied().collect();
+        let (points, labels): (Vec<Vec<f64>>, Vec<u8>) = verified.into_values().unzip();
+        let mut result = self.classify_test_point(&points, &labels, &test_point, &metric, k as usize)?;
+        result.neighbor_indices = result.neighbor_indices.iter().map(|ii| indices[*ii as usize]).collect(); // index in the dataset
+        self.check_confidence(result, false)
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Checks that the commitment can be registered under the name by the creator.
+    fn check_offchain_dataset(&self, name: &String, merkle_root: &Base64VecU8, n_rows: u64, n_features: u32, uri: &Option<String>, creator: &AccountId) -> Result<(), KnnError> {
+        if self.get_dataset(name).is_ok() {
+            return Err(KnnError::DatasetExists { name: name.clone() });
+        }
+        if merkle_root.0.len() != HASH_LEN {
+            return Err(KnnError::InvalidCommitment);
+        }
+        if n_rows == 0 {
+            return Err(KnnError::EmptyDataset { name: name.clone() });
+        }
+        if n_features == 0 {
+            return Err(KnnError::DimensionMismatch { expected: 1, actual: 0 });
+        }
+        if let Some(uri) = uri.as_ref().filter(|uri| uri.len() > MAX_URI_LEN) {
+            return Err(KnnError::UriTooLong { length: uri.len() as u32, max_length: MAX_URI_LEN as u32 });
+        }
+        if let Some(dataset) = self.offchain_datasets.get(name) {
+            if dataset.creator != *creator && *creator != self.owner_id {
+                return Err(KnnError::Unauthorized { account_id: creator.clone() });
+            }
+        }
+        Ok(())
+    }
+}
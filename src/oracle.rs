@@ -0,0 +1,92 @@
+use crate::*;
+use near_sdk::serde_json;
+use near_sdk::PromiseResult;
+
+// ------------------------------------------ ORACLE FEATURES ---------------------------------------------------
+// Instead of supplying the test point, a caller can have the features read from an oracle contract (e.g. live prices), so that
+// an on-chain pipeline does not depend on a client to report them. run_analysis_from_oracle calls get_feature_values(query)
+// on the oracle, which returns the features of the test point as a JSON array of numbers (an oracle with another interface
+// can be wrapped by an adapter contract exposing this method), and on_oracle_features classifies the point it returned.
+// The caller picks the oracle, hence decides which source of data it trusts; the oracle is named in the logs of the prediction.
+// The deposit is only refunded if on_oracle_features runs to the end, so the call is rejected up front unless the gas prepaid
+// leaves the callback at least GAS_FOR_PREDICTION; subscribers pay no fee, their subscription is used once the prediction is made.
+const GAS_FOR_ORACLE: Gas = Gas(20_000_000_000_000); // 20 Tgas for get_feature_values on the oracle
+const GAS_FOR_SCHEDULING: Gas = Gas(15_000_000_000_000); // 15 Tgas kept by run_analysis_from_oracle to create each promise
+const GAS_FOR_PREDICTION: Gas = Gas(50_000_000_000_000); // 50 Tgas at least for the prediction and the refund in on_oracle_features
+
+// Prediction waiting for the features from the oracle.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OracleRequest {
+    pub dataset: String,
+    pub oracle_account: AccountId,
+    pub caller: AccountId,
+    pub deposit: U128, // attached by the caller, to pay the prediction fee
+    pub k_override: Option<u8>,
+}
+
+#[ext_contract(ext_oracle)]
+trait FeatureOracle {
+    fn get_feature_values(&self, query: String) -> Vec<f64>;
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Classifies the point returned by the oracle for the query, like run_analysis (without NFT): the prediction fee must be
+    // attached, and is only collected once the prediction is made (the deposit is refunded otherwise, the surplus in any case).
+    // The gas attached (minus what the oracle needs, and at least GAS_FOR_PREDICTION) goes to the prediction. The result (a
+    // Result<PredictionResult, KnnError>) is the one of on_oracle_features.
+    #[payable]
+    pub fn run_analysis_from_oracle(&mut self, dataset: String, oracle_account: AccountId, query: String, k_override: Option<u8>) -> Promise {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        let attached = env::attached_deposit();
+        let fee = if self.is_subscribed(&caller) { 0 } else { self.prediction_fee };
+        if attached < fee {
+            KnnError::InsufficientDeposit { required: U128(fee), attached: U128(attached) }.panic();
+        }
+        let reserved = GAS_FOR_ORACLE.0 + 2 * GAS_FOR_SCHEDULING.0; // the oracle call and the callback
+        let required = env::used_gas().0 + reserved + GAS_FOR_PREDICTION.0;
+        if env::prepaid_gas().0 < required {
+            KnnError::InsufficientGas { required: U64(required), prepaid: U64(env::prepaid_gas().0) }.panic();
+        }
+        let gas_for_callback = Gas(env::prepaid_gas().0 - (env::used_gas().0 + reserved));
+        let request = OracleRequest { dataset, oracle_account: oracle_account.clone(), caller, deposit: U128(attached), k_override };
+        ext_oracle::get_feature_values(query, oracle_account, 0, GAS_FOR_ORACLE)
+            .then(ext_self::on_oracle_features(request, env::current_account_id(), 0, gas_for_callback))
+    }
+
+    // Callback of run_analysis_from_oracle: classifies the features returned by the oracle for the caller of the request.
+    #[private]
+    pub fn on_oracle_features(&mut self, request: OracleRequest) -> Result<PredictionResult, KnnError> {
+        let OracleRequest { dataset, oracle_account, caller, deposit, k_override } = request;
+        let point: Option<Vec<f64>> = match env::promise_result(0) {
+            PromiseResult::Successful(bytes) => serde_json::from_slice(&bytes).ok(),
+            _ => None,
+        };
+        match &point {
+            Some(point) => self.log(LogLevel::Debug, "oracle_features", json!({ "oracle_account": oracle_account, "point": point })),
+            None => self.log(LogLevel::Warn, "oracle_failed", json!({ "oracle_account": oracle_account })),
+        }
+        let result = point
+            .ok_or(KnnError::OracleFailed { oracle_account })
+            .and_then(|point| self.predict_and_record(&dataset, point, None, k_override, caller.clone()))
+            .map(|(_, ans)| ans);
+        let refund = match &result {
+            Ok(_) if self.is_subscribed(&caller) => {
+                self.use_subscription(&caller);
+                deposit.0
+            }
+            Ok(_) => {
+                let fee = std::cmp::min(self.prediction_fee, deposit.0); // the fee or the subscription may have changed since the call
+                self.collect_fee(&dataset, fee);
+                deposit.0 - fee
+            }
+            Err(_) => deposit.0,
+        };
+        if refund > 0 {
+            Promise::new(caller).transfer(refund);
+        }
+        result
+    }
+}
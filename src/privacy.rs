@@ -0,0 +1,76 @@
+use crate::*;
+
+// ------------------------------------------ DIFFERENTIAL PRIVACY ---------------------------------------------
+// Vote fractions and class counts reveal something about individual train points: whether a point near the query is in the
+// dataset shifts the confidence of a prediction by a whole vote. With privacy noise enabled, the owner sets a privacy budget
+// epsilon and Laplace noise of scale sensitivity / epsilon is added to the confidences and class shares returned by
+// predictions and to the class counts of the statistics views (smaller epsilon: more noise, more privacy). As the noise would
+// be averaged out by repeating a query, it is seeded from env::random_seed and the query, so the same query in the same
+// block gets the same noise. The lists of neighbours of a prediction (and of nearest_neighbors) are withheld, as they would
+// reveal the points themselves and the exact votes.
+// Note that, as all contract state, the stored train points (and the prediction history) can still be read from the blockchain.
+
+// Laplace noise of the given scale, from the block's random seed and the query (bytes identifying the call and the noisy value).
+fn laplace_noise(scale: f64, query: &[u8]) -> f64 {
+    let hash = env::sha256(&[&env::random_seed()[..], query].concat());
+    let bits = hash[..8].iter().fold(0u64, |acc, byte| (acc << 8) | u64::from(*byte)) >> 11; // 53 random bits
+    let u = (bits as f64 + 0.5) / (1u64 << 53) as f64 - 0.5; // uniform in (-0.5, 0.5)
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can enable (with a positive privacy budget epsilon) or disable (None) the privacy noise.
+    pub fn set_privacy_noise(&mut self, epsilon: Option<f64>) -> Result<(), KnnError> {
+        self.assert_owner();
+        if let Some(epsilon) = epsilon {
+            if !epsilon.is_finite() || epsilon <= 0.0 {
+                return Err(KnnError::InvalidEpsilon { epsilon });
+            }
+        }
+        self.privacy_epsilon = epsilon;
+        Ok(())
+    }
+
+    pub fn get_privacy_noise(&self) -> Option<f64> {
+        self.privacy_epsilon
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Share of the votes (between 0 and 1) with the privacy noise added, if enabled.
+    // A train point changes the share by at most one vote: 1/k of uniform votes, up to all of weighted (by distance, or by
+    // class size as with class_weighted) votes.
+    pub(crate) fn noisy_share(&self, share: f64, k: usize, class_weighted: bool, query: &[u8]) -> f64 {
+        match self.privacy_epsilon {
+            Some(epsilon) => {
+                let sensitivity = if self.voting_scheme == VotingScheme::Uniform && !class_weighted { 1.0 / k.max(1) as f64 } else { 1.0 };
+                (share + laplace_noise(sensitivity / epsilon, query)).clamp(0.0, 1.0)
+            }
+            None => share,
+        }
+    }
+
+    // Callable from methods only (not user). Number of train points with the privacy noise added (rounded, not below 0), if enabled.
+    // A train point changes a count by at most 1.
+    pub(crate) fn noisy_count(&self, count: u64, query: &[u8]) -> u64 {
+        match self.privacy_epsilon {
+            Some(epsilon) => (count as f64 + laplace_noise(1.0 / epsilon, query)).round().max(0.0) as u64,
+            None => count,
+        }
+    }
+
+    // Callable from methods only (not user). Prediction with a noisy confidence and without its neighbours, if the noise is enabled.
+    pub(crate) fn noisy_result(&self, ans: PredictionResult, class_weighted: bool) -> PredictionResult {
+        if self.privacy_epsilon.is_none() {
+            return ans;
+        }
+        let query = ans.try_to_vec().unwrap(); // the neighbours' distances identify the query
+        PredictionResult {
+            confidence: self.noisy_share(ans.confidence, ans.neighbor_indices.len(), class_weighted, &query),
+            neighbor_indices: Vec::new(),
+            neighbor_distances: Vec::new(),
+            ..ans
+        }
+    }
+}
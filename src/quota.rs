@@ -0,0 +1,90 @@
+use crate::*;
+
+// ------------------------------------------ CALL QUOTAS -------------------------------------------------------
+// Every prediction is stored in the history (at the caller's expense), so free (or cheap) predictions could still be spammed to grow it.
+// The owner can set a quota: each account can make at most max_calls predictions per period (an epoch of the blockchain,
+// about 12 hours, or a UTC day). The calls of an account are counted in the current period only: the count restarts with
+// each period. Predictions beyond the quota fail with QuotaExceeded; the owner is not limited. Only the predictions made are
+// counted: one that fails (e.g. an unknown dataset or a result below the confidence threshold) leaves the quota as it was.
+const NANOSECONDS_PER_DAY: u64 = 86_400 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum QuotaPeriod {
+    Epoch,
+    Day,
+}
+
+impl QuotaPeriod {
+    // Index of the current period.
+    fn current(&self) -> u64 {
+        match self {
+            QuotaPeriod::Epoch => env::epoch_height(),
+            QuotaPeriod::Day => env::block_timestamp() / NANOSECONDS_PER_DAY,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CallQuota {
+    pub max_calls: u32,
+    pub period: QuotaPeriod,
+}
+
+// Predictions of an account in a period.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct CallCount {
+    period: u64, // index of the period
+    calls: u32,
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can set the quota of predictions per account (None removes it).
+    pub fn set_call_quota(&mut self, quota: Option<CallQuota>) {
+        self.assert_owner();
+        self.call_quota = quota;
+    }
+
+    pub fn get_call_quota(&self) -> Option<CallQuota> {
+        self.call_quota
+    }
+
+    // Predictions the account can still make in the current period (None without a quota).
+    pub fn get_calls_remaining(&self, account_id: AccountId) -> Option<u32> {
+        self.call_quota.map(|quota| quota.max_calls.saturating_sub(self.calls_in_period(&account_id, &quota)))
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). QuotaExceeded if the account already made max_calls predictions in the period.
+    pub(crate) fn check_call_quota(&self, account_id: &AccountId) -> Result<(), KnnError> {
+        match self.quota_of(account_id) {
+            Some(quota) if self.calls_in_period(account_id, &quota) >= quota.max_calls => {
+                Err(KnnError::QuotaExceeded { max_calls: quota.max_calls, period: quota.period })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Callable from methods only (not user). Counts a prediction made by the account (checked with check_call_quota).
+    pub(crate) fn record_call(&mut self, account_id: &AccountId) {
+        if let Some(quota) = self.quota_of(account_id) {
+            let calls = self.calls_in_period(account_id, &quota);
+            self.call_counts.insert(account_id, &CallCount { period: quota.period.current(), calls: calls + 1 });
+        }
+    }
+
+    // Quota that applies to the account (None for the owner).
+    fn quota_of(&self, account_id: &AccountId) -> Option<CallQuota> {
+        self.call_quota.filter(|_| *account_id != self.owner_id)
+    }
+
+    fn calls_in_period(&self, account_id: &AccountId, quota: &CallQuota) -> u32 {
+        match self.call_counts.get(account_id) {
+            Some(count) if count.period == quota.period.current() => count.calls,
+            _ => 0,
+        }
+    }
+}
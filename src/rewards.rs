@@ -0,0 +1,190 @@
+use crate::*;
+
+// ------------------------------------------ CONTRIBUTION REWARDS ---------------------------------------------
+// Data contributors earn a share of the prediction fees paid on the datasets they contributed rows to. The owner sets the share
+// of the fee (in basis points) that goes to the dataset, the rest stays in the treasury. The dataset's share is split over its
+// train points: each contributor earns it for the rows they added, and the share of the rows nobody is rewarded for (the toy
+// datasets seeded at initialisation) goes back to the treasury.
+// So that a prediction does not update every contributor, a dataset accumulates the reward earned per row since its creation
+// (reward_per_row, scaled by REWARD_PRECISION); a contributor's rewards are settled (credited to their claimable balance) from
+// the growth of reward_per_row since their last settlement, whenever they add rows or claim.
+const REWARD_PRECISION: u128 = 1_000_000; // reward_per_row is in units of 1e-6 yoctoNEAR
+const MAX_BPS: u16 = 10_000;
+const GAS_FOR_CLAIM_CALLBACK: Gas = Gas(5_000_000_000_000); // 5 Tgas for on_rewards_claimed
+
+// Rows an account contributed to a dataset.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Contribution {
+    rows: u64,
+    reward_debt: u128, // rows x reward_per_row at the last settlement (scaled by REWARD_PRECISION)
+    dataset_created_at: u64, // identifies the dataset, in case it is removed and another one created under its name
+}
+
+// The share of the fees credited to the contributors is at most the whole fee.
+pub(crate) fn validate_reward_share(share_bps: u16) -> Result<(), KnnError> {
+    if share_bps > MAX_BPS {
+        return Err(KnnError::InvalidRewardShare { share_bps });
+    }
+    Ok(())
+}
+
+// Rewards earned by rows at the given reward per row.
+fn accrued(rows: u64, reward_per_row: u128) -> u128 {
+    reward_per_row * u128::from(rows) // floor division by REWARD_PRECISION is done on the differences
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can set the share of every prediction fee that is credited to the contributors of the dataset, in basis points (0 to 10000).
+    pub fn set_reward_share(&mut self, share_bps: u16) -> Result<(), KnnError> {
+        self.assert_owner();
+        self.assert_ungoverned();
+        validate_reward_share(share_bps)?;
+        self.reward_share_bps = share_bps;
+        Ok(())
+    }
+
+    pub fn get_reward_share(&self) -> u16 {
+        self.reward_share_bps
+    }
+
+    // Rewards (yoctoNEAR) the account can claim, over all the datasets it contributed to.
+    pub fn get_rewards(&self, account_id: AccountId) -> U128 {
+        let pending: Balance = self.contributed_datasets.get(&account_id).unwrap_or_default().iter().map(|name| self.pending_reward(name, &account_id)).sum();
+        U128(self.rewards.get(&account_id).unwrap_or(0) + pending)
+    }
+
+    // Sends the caller their rewards. Their balance is debited before the transfer and credited back by on_rewards_claimed if it fails.
+    // Returns the amount claimed.
+    pub fn claim_rewards(&mut self) -> U128 {
+        let caller = env::predecessor_account_id();
+        for name in self.contributed_datasets.get(&caller).unwrap_or_default() {
+            self.settle_rewards(&name, &caller);
+        }
+        let amount = self.rewards.remove(&caller).unwrap_or(0);
+        if amount > 0 {
+            Promise::new(caller.clone())
+                .transfer(amount)
+                .then(ext_self::on_rewards_claimed(caller, U128(amount), env::current_account_id(), 0, GAS_FOR_CLAIM_CALLBACK));
+        }
+        U128(amount)
+    }
+
+    // Callback of claim_rewards: returns the amount to the claimable balance when the transfer did not go through.
+    #[private]
+    pub fn on_rewards_claimed(&mut self, account_id: AccountId, amount: U128) {
+        if !is_promise_success() {
+            let balance = self.rewards.get(&account_id).unwrap_or(0);
+            self.rewards.insert(&account_id, &(balance + amount.0));
+        }
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Splits the contributors' share of a prediction fee paid on a dataset over its rows,
+    // returns the part kept by the treasury (the rest of the fee, and the share of the rows nobody is rewarded for).
+    pub(crate) fn distribute_fee(&mut self, name: &str, fee: Balance) -> Balance {
+        let reward = fee * Balance::from(self.reward_share_bps) / Balance::from(MAX_BPS);
+        let mut data = match self.get_dataset(&name.to_string()) {
+            Ok(data) if reward > 0 && data.contributed_rows > 0 => data,
+            _ => return fee,
+        };
+        let n_rows = Balance::from(data.train.len());
+        let per_row = reward * REWARD_PRECISION / n_rows;
+        data.reward_per_row += per_row;
+        self.store_dataset(name, &data);
+        fee - per_row * Balance::from(data.contributed_rows) / REWARD_PRECISION // contributors are credited at most this
+    }
+
+    // Callable from methods only (not user). Records rows added by account to a dataset, first settling the account's rewards.
+    // The caller stores the dataset afterwards.
+    pub(crate) fn record_contribution(&mut self, name: &str, data: &mut Dataset, account_id: &AccountId, rows: u64) {
+        if *account_id == env::current_account_id() { // e.g. toy datasets
+            return;
+        }
+        let key = (name.to_string(), account_id.clone());
+        let mut contribution = match self.contributions.get(&key) {
+            Some(contribution) if contribution.dataset_created_at == data.created_at => {
+                self.credit_rewards(account_id, (accrued(contribution.rows, data.reward_per_row) - contribution.reward_debt) / REWARD_PRECISION);
+                contribution
+            }
+            stale => {
+                if stale.is_some() { // rows of a removed dataset of the same name
+                    self.settle_rewards(name, account_id);
+                }
+                let mut datasets = self.contributed_datasets.get(account_id).unwrap_or_default();
+                datasets.push(name.to_string());
+                self.contributed_datasets.insert(account_id, &datasets);
+                Contribution { rows: 0, reward_debt: 0, dataset_created_at: data.created_at }
+            }
+        };
+        contribution.rows += rows;
+        contribution.reward_debt = accrued(contribution.rows, data.reward_per_row);
+        data.contributed_rows += rows;
+        self.contributions.insert(&key, &contribution);
+    }
+
+    // Callable from methods only (not user). Removes rows of account from its contribution to a dataset (e.g. slashed ones),
+    // first settling the account's rewards. The caller stores the dataset afterwards.
+    pub(crate) fn remove_contribution(&mut self, name: &str, data: &mut Dataset, account_id: &AccountId, rows: u64) {
+        let key = (name.to_string(), account_id.clone());
+        if let Some(mut contribution) = self.contributions.get(&key).filter(|contribution| contribution.dataset_created_at == data.created_at) {
+            self.credit_rewards(account_id, (accrued(contribution.rows, data.reward_per_row) - contribution.reward_debt) / REWARD_PRECISION);
+            let rows = std::cmp::min(rows, contribution.rows);
+            contribution.rows -= rows;
+            contribution.reward_debt = accrued(contribution.rows, data.reward_per_row);
+            data.contributed_rows -= rows;
+            self.contributions.insert(&key, &contribution);
+        }
+    }
+
+    // Callable from methods only (not user). Keeps the reward per row of a dataset being removed, so its contributors can still claim.
+    pub(crate) fn close_reward_pool(&mut self, name: &str, data: &Dataset) {
+        if data.contributed_rows > 0 {
+            self.closed_reward_pools.insert(&(name.to_string(), data.created_at), &data.reward_per_row);
+        }
+    }
+
+    // Reward per row of the dataset a contribution was made to (open, or closed when it was removed).
+    fn reward_per_row(&self, name: &str, contribution: &Contribution) -> u128 {
+        match self.get_dataset(&name.to_string()) {
+            Ok(data) if data.created_at == contribution.dataset_created_at => data.reward_per_row,
+            _ => self.closed_reward_pools.get(&(name.to_string(), contribution.dataset_created_at)).unwrap_or(0),
+        }
+    }
+
+    fn pending_reward(&self, name: &str, account_id: &AccountId) -> Balance {
+        self.contributions.get(&(name.to_string(), account_id.clone())).map_or(0, |contribution| {
+            accrued(contribution.rows, self.reward_per_row(name, &contribution)).saturating_sub(contribution.reward_debt) / REWARD_PRECISION
+        })
+    }
+
+    // Credits the rewards of account on a dataset to its claimable balance. The contribution to a removed dataset is deleted.
+    fn settle_rewards(&mut self, name: &str, account_id: &AccountId) {
+        let key = (name.to_string(), account_id.clone());
+        let mut contribution = match self.contributions.get(&key) {
+            Some(contribution) => contribution,
+            None => return,
+        };
+        self.credit_rewards(account_id, self.pending_reward(name, account_id));
+        match self.get_dataset(&name.to_string()) {
+            Ok(data) if data.created_at == contribution.dataset_created_at => {
+                contribution.reward_debt = accrued(contribution.rows, data.reward_per_row);
+                self.contributions.insert(&key, &contribution);
+            }
+            _ => { // removed: nothing more to earn
+                self.contributions.remove(&key);
+                let mut datasets = self.contributed_datasets.get(account_id).unwrap_or_default();
+                datasets.retain(|dataset| dataset != name);
+                self.contributed_datasets.insert(account_id, &datasets);
+            }
+        }
+    }
+
+    pub(crate) fn credit_rewards(&mut self, account_id: &AccountId, amount: Balance) {
+        if amount > 0 {
+            let balance = self.rewards.get(account_id).unwrap_or(0);
+            self.rewards.insert(account_id, &(balance + amount));
+        }
+    }
+}
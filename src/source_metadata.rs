@@ -0,0 +1,66 @@
+use crate::*;
+
+// ------------------------------------------ CONTRACT SOURCE METADATA (NEP-330) -------------------------------
+// Describes the code the contract runs: its version, where the source is, the standards it implements and how the wasm was built,
+// so that verifiers and explorers can match the deployed code with its source.
+const NEP330_VERSION: &str = "1.2.0";
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractSourceMetadata {
+    pub version: Option<String>, // version of the crate
+    pub link: Option<String>, // repository of the source code
+    pub standards: Vec<Standard>,
+    pub build_info: Option<BuildInfo>, // None unless built reproducibly (e.g. with cargo near), which sets the NEP330_BUILD_INFO_* variables
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Standard {
+    pub standard: String, // e.g. "nep171"
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BuildInfo {
+    pub build_environment: String, // docker image the wasm was built in
+    pub build_command: Vec<String>,
+    pub contract_path: String, // path of the contract in the source snapshot
+    pub source_code_snapshot: String, // e.g. "git+https://github.com/...?rev=<commit>"
+    pub output_wasm_path: Option<String>,
+}
+
+// Standards implemented by the contract, with their versions.
+const STANDARDS: &[(&str, &str)] = &[
+    ("nep145", "1.0.0"), // storage management, see storage.rs
+    ("nep171", "1.0.0"), // prediction NFTs, see nft.rs
+    ("nep177", "2.0.0"), // NFT metadata
+    ("nep178", "1.0.0"), // NFT approval management
+    ("nep181", "1.0.0"), // NFT enumeration
+    ("nep199", "2.0.0"), // NFT royalties and payouts
+    ("nep297", "1.0.0"), // events, see events.rs
+    ("nep330", NEP330_VERSION),
+];
+
+fn build_info() -> Option<BuildInfo> {
+    Some(BuildInfo {
+        build_environment: option_env!("NEP330_BUILD_INFO_BUILD_ENVIRONMENT")?.to_string(),
+        build_command: option_env!("NEP330_BUILD_INFO_BUILD_COMMAND")?.split_whitespace().map(String::from).collect(),
+        contract_path: option_env!("NEP330_BUILD_INFO_CONTRACT_PATH").unwrap_or("").to_string(),
+        source_code_snapshot: option_env!("NEP330_BUILD_INFO_SOURCE_CODE_SNAPSHOT")?.to_string(),
+        output_wasm_path: option_env!("NEP330_BUILD_INFO_OUTPUT_WASM_PATH").map(String::from),
+    })
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    pub fn contract_source_metadata(&self) -> ContractSourceMetadata {
+        ContractSourceMetadata {
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            link: option_env!("CARGO_PKG_REPOSITORY").filter(|link| !link.is_empty()).map(String::from),
+            standards: STANDARDS.iter().map(|(standard, version)| Standard { standard: standard.to_string(), version: version.to_string() }).collect(),
+            build_info: build_info(),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use crate::*;
+
+// ------------------------------------------ STAKED CONTRIBUTIONS ----------------------------------------------
+// Shared datasets can be poisoned with garbage rows. The owner can require data providers to stake NEAR on each batch of rows
+// they append (with add_training_point, add_dataset_csv, add_dataset_borsh or add_regression_point) to a dataset outside their
+// own namespace; the owner's batches are not staked. The stake is attached on top of the storage deposit and held by the
+// contract. Until the batch's slash window ends, the owner can flag it as garbage: the rows are removed from the dataset (the
+// rows after them move up) and the stake goes to the treasury. Once the window is over, anyone can release the stake back to
+// the contributor. Rows of chunked uploads create a dataset of their own, and are not staked.
+const NANOSECONDS_PER_HOUR: u64 = 3_600 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakePolicy {
+    pub stake: U128, // yoctoNEAR per batch
+    pub window_hours: u32, // how long a batch can be slashed
+}
+
+// Rows appended in one call, with the stake of their contributor.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakedBatch {
+    pub contributor: AccountId,
+    pub dataset: String,
+    pub dataset_created_at: U64, // identifies the dataset, in case it is removed and another one created under its name
+    pub start: u64, // index of the first row in the dataset
+    pub rows: u64,
+    pub stake: U128,
+    pub slashable_until: U64, // block timestamp (nanoseconds)
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can require a stake on the batches of rows (None: no stake). Batches already staked keep their terms.
+    pub fn set_stake_policy(&mut self, policy: Option<StakePolicy>) {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.stake_policy = policy;
+    }
+
+    pub fn get_stake_policy(&self) -> Option<StakePolicy> {
+        self.stake_policy
+    }
+
+    pub fn get_staked_batch(&self, batch_id: u64) -> Option<StakedBatch> {
+        self.staked_batches.get(&batch_id)
+    }
+
+    // Batches of the account whose stake is not released yet, as (batch id, batch) pairs.
+    pub fn get_staked_batches(&self, account_id: AccountId) -> Vec<(u64, StakedBatch)> {
+        self.staked_batches.iter().filter(|(_, batch)| batch.contributor == account_id).collect()
+    }
+
+    // Only the owner can slash a batch flagged as garbage, until its slash window ends (and unless its dataset was frozen since).
+    // Returns the number of rows removed (0 if its dataset was removed since).
+    pub fn slash_batch(&mut self, batch_id: u64) -> Result<u64, KnnError> {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.slash(batch_id)
+    }
+
+    // Sends the stake of a batch back to its contributor once the slash window is over. Returns the stake released.
+    pub fn release_stake(&mut self, batch_id: u64) -> Result<U128, KnnError> {
+        let batch = self.staked_batches.get(&batch_id).ok_or(KnnError::UnknownBatch { batch_id })?;
+        if env::block_timestamp() < batch.slashable_until.0 {
+            return Err(KnnError::SlashWindowOpen { batch_id, ends_at: batch.slashable_until });
+        }
+        self.staked_batches.remove(&batch_id);
+        Promise::new(batch.contributor).transfer(batch.stake.0);
+        Ok(batch.stake)
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Slashes a batch for slash_batch or a SlashBatch action.
+    pub(crate) fn slash(&mut self, batch_id: u64) -> Result<u64, KnnError> {
+        let (batch, data) = self.slashable_batch(batch_id)?;
+        self.staked_batches.remove(&batch_id);
+        self.fees_collected += batch.stake.0;
+        let mut data = match data {
+            Some(data) => data,
+            None => return Ok(0),
+        };
+        let initial_storage = env::storage_usage();
+        data.remove_rows(batch.start, batch.rows);
+        self.remove_contribution(&batch.dataset, &mut data, &batch.contributor, batch.rows);
+        let later: Vec<(u64, StakedBatch)> = self.staked_batches.iter()
+            .filter(|(_, other)| other.dataset == batch.dataset && other.dataset_created_at == batch.dataset_created_at && other.start > batch.start)
+            .collect();
+        for (id, mut other) in later {
+            other.start -= batch.rows;
+            self.staked_batches.insert(&id, &other);
+        }
+        let freed = initial_storage.saturating_sub(env::storage_usage());
+        data.storage_bytes = data.storage_bytes.saturating_sub(freed);
+        self.store_dataset(&batch.dataset, &data);
+        if let Some(mut usage) = self.account_usage.get(&data.creator) {
+            usage.rows = usage.rows.saturating_sub(batch.rows);
+            usage.bytes = usage.bytes.saturating_sub(freed);
+            self.account_usage.insert(&data.creator, &usage);
+        }
+        self.log(LogLevel::Warn, "batch_slashed", json!({ "batch_id": batch_id, "dataset": batch.dataset, "rows": batch.rows, "contributor": batch.contributor }));
+        self.refund_storage(&batch.contributor, freed); // the storage of the rows was paid by the contributor
+        Ok(batch.rows)
+    }
+
+    // Callable from methods only (not user). The batch and its dataset (None if removed since), if the batch can be slashed.
+    pub(crate) fn slashable_batch(&self, batch_id: u64) -> Result<(StakedBatch, Option<Dataset>), KnnError> {
+        let batch = self.staked_batches.get(&batch_id).ok_or(KnnError::UnknownBatch { batch_id })?;
+        if env::block_timestamp() >= batch.slashable_until.0 {
+            return Err(KnnError::SlashWindowOver { batch_id });
+        }
+        let data = self.get_dataset(&batch.dataset).ok().filter(|data| data.created_at == batch.dataset_created_at.0);
+        if let Some(data) = &data {
+            check_unfrozen(&batch.dataset, data)?;
+        }
+        Ok((batch, data))
+    }
+
+    // Callable from methods only (not user). Records the last `rows` rows of the dataset (just appended by the caller) as a staked
+    // batch if the stake policy requires it. Returns the stake, which the attached deposit must cover on top of the storage.
+    pub(crate) fn stake_batch(&mut self, name: &str, data: &Dataset, rows: u64) -> Balance {
+        let contributor = env::predecessor_account_id();
+        let policy = match self.stake_policy {
+            Some(policy) if contributor != self.owner_id && split_namespace(name).is_none_or(|(account_id, _)| account_id != contributor) => policy,
+            _ => return 0,
+        };
+        let attached = env::attached_deposit();
+        if attached < policy.stake.0 {
+            KnnError::InsufficientDeposit { required: policy.stake, attached: U128(attached) }.panic();
+        }
+        let batch_id = self.next_batch_id;
+        self.next_batch_id += 1;
+        self.staked_batches.insert(&batch_id, &StakedBatch {
+            contributor,
+            dataset: name.to_string(),
+            dataset_created_at: U64(data.created_at),
+            start: data.train.len() - rows,
+            rows,
+            stake: policy.stake,
+            slashable_until: U64(env::block_timestamp() + u64::from(policy.window_hours) * NANOSECONDS_PER_HOUR),
+        });
+        self.log(LogLevel::Info, "batch_staked", json!({ "batch_id": batch_id, "dataset": name, "rows": rows, "stake": policy.stake }));
+        policy.stake.0
+    }
+}
@@ -0,0 +1,148 @@
+use crate::*;
+use near_contract_standards::storage_management::{StorageBalance, StorageBalanceBounds, StorageManagement};
+use near_sdk::assert_one_yocto;
+
+// ------------------------------------------ STORAGE MANAGEMENT (NEP-145) -------------------------------------
+// Data contributors can pre-pay for the storage of their uploads with storage_deposit, rather than attaching a deposit to every call.
+// When the deposit attached to an upload (or a prediction minting an NFT) does not cover its storage, the rest is drawn from the
+// caller's storage balance. The part of the balance spent on storage is locked; the rest is available and can be withdrawn.
+// The storage freed when a dataset is removed is credited back to the balance of its creator (if registered) instead of transferred.
+
+// Bytes of the record of a registered account: 40 bytes of record overhead, the storage key (1 byte prefix, 4 + up to 64 bytes of
+// account id) and the balances (2 x 16 bytes).
+const STORAGE_ACCOUNT_BYTES: u64 = 40 + 1 + 4 + 64 + 32;
+
+// Storage balance of a registered account, in yoctoNEAR.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct StorageAccount {
+    available: Balance, // deposited and not spent, can be withdrawn
+    locked: Balance, // spent on the storage of uploads
+}
+
+impl StorageAccount {
+    fn balance(&self) -> StorageBalance {
+        StorageBalance { total: U128(self.available + self.locked), available: U128(self.available) }
+    }
+}
+
+fn min_storage_balance() -> Balance {
+    Balance::from(STORAGE_ACCOUNT_BYTES) * env::storage_byte_cost()
+}
+
+#[near_bindgen]
+impl StorageManagement for KnnMachineLearning {
+    // Adds the attached deposit to the storage balance of account_id (default: the caller), registering the account if needed.
+    // Registration needs at least the minimum balance, which is locked for the record of the account.
+    // registration_only: only registers, the deposit above the minimum (all of it for a registered account) is refunded.
+    #[payable]
+    fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>) -> StorageBalance {
+        self.assert_not_paused();
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let registration_only = registration_only.unwrap_or(false);
+        let (account, deposit) = match self.storage_accounts.get(&account_id) {
+            Some(account) if registration_only => (account, 0),
+            Some(account) => (StorageAccount { available: account.available + amount, ..account }, amount),
+            None => {
+                let min = min_storage_balance();
+                if amount < min {
+                    KnnError::InsufficientDeposit { required: U128(min), attached: U128(amount) }.panic();
+                }
+                let deposit = if registration_only { min } else { amount };
+                (StorageAccount { available: deposit - min, locked: min }, deposit)
+            }
+        };
+        let refund = amount - deposit;
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+        self.storage_accounts.insert(&account_id, &account);
+        account.balance()
+    }
+
+    // Withdraws amount (default: all) of the caller's available storage balance. Requires 1 yoctoNEAR attached.
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        self.assert_not_paused();
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = self.storage_account(&account_id);
+        let amount = amount.map_or(account.available, |amount| amount.0);
+        if amount > account.available {
+            KnnError::InsufficientStorageBalance { required: U128(amount), available: U128(account.available) }.panic();
+        }
+        account.available -= amount;
+        self.storage_accounts.insert(&account_id, &account);
+        if amount > 0 {
+            Promise::new(account_id).transfer(amount);
+        }
+        account.balance()
+    }
+
+    // Unregisters the caller and refunds their available balance and the minimum balance locked for their record. Accounts that
+    // still have datasets must remove them first (force is not supported, as it would leave their datasets without a payer).
+    // Requires 1 yoctoNEAR attached.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        self.assert_not_paused();
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let account = match self.storage_accounts.get(&account_id) {
+            Some(account) => account,
+            None => return false,
+        };
+        if force.unwrap_or(false) {
+            env::panic_str("Forced unregistration is not supported.");
+        }
+        if self.get_account_usage(account_id.clone()).rows > 0 {
+            KnnError::StorageInUse { account_id }.panic();
+        }
+        self.storage_accounts.remove(&account_id);
+        Promise::new(account_id).transfer(account.available + account.locked.min(min_storage_balance()));
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds { min: U128(min_storage_balance()), max: None }
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts.get(&account_id).map(|account| account.balance())
+    }
+}
+
+impl KnnMachineLearning {
+    fn storage_account(&self, account_id: &AccountId) -> StorageAccount {
+        self.storage_accounts.get(account_id).unwrap_or_else(|| KnnError::NotRegistered { account_id: account_id.clone() }.panic())
+    }
+
+    // Callable from methods only (not user). Spends amount of the storage balance of account_id on storage (locks it).
+    // Panics if the account is not registered or its available balance is too low, as the storage is not paid for.
+    pub(crate) fn lock_storage_balance(&mut self, account_id: &AccountId, amount: Balance) {
+        match self.storage_accounts.get(account_id) {
+            Some(mut account) if account.available >= amount => {
+                account.available -= amount;
+                account.locked += amount;
+                self.storage_accounts.insert(account_id, &account);
+            }
+            account => {
+                let available = account.map_or(0, |account| account.available);
+                KnnError::InsufficientStorageBalance { required: U128(amount), available: U128(available) }.panic();
+            }
+        }
+    }
+
+    // Callable from methods only (not user). Credits freed storage (amount in yoctoNEAR) to the storage balance of account_id.
+    // Returns false if the account is not registered (the caller then transfers the amount).
+    pub(crate) fn unlock_storage_balance(&mut self, account_id: &AccountId, amount: Balance) -> bool {
+        match self.storage_accounts.get(account_id) {
+            Some(mut account) => {
+                account.available += amount;
+                account.locked = account.locked.saturating_sub(amount);
+                self.storage_accounts.insert(account_id, &account);
+                true
+            }
+            None => false,
+        }
+    }
+}
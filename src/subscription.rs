@@ -0,0 +1,114 @@
+use crate::*;
+
+// ------------------------------------------ SUBSCRIPTIONS -----------------------------------------------------
+// An alternative to the per-call prediction fee: the owner offers subscription tiers, each with a price and the access it
+// grants, a number of predictions and/or a duration (e.g. 100 predictions, or unlimited predictions for 30 days). While an
+// account's subscription is active its run_analysis predictions (and classify_for, which uses it) need no fee attached, and
+// use up one of its predictions. A subscription ends once its predictions are used up or it expires; subscribing again
+// replaces it. Subscription payments go to the treasury.
+const NANOSECONDS_PER_DAY: u64 = 86_400 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SubscriptionTier {
+    pub price: U128, // yoctoNEAR
+    pub predictions: Option<u32>, // None: unlimited
+    pub duration_days: Option<u32>, // None: never expires
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Subscription {
+    pub tier: String,
+    pub predictions_left: Option<u32>, // None: unlimited
+    pub expires_at: Option<U64>, // block timestamp (nanoseconds), None: never
+}
+
+// A tier must limit the predictions, the duration or both.
+pub(crate) fn validate_tier(name: &str, tier: &SubscriptionTier) -> Result<(), KnnError> {
+    if tier.predictions.is_none() && tier.duration_days.is_none() {
+        return Err(KnnError::InvalidTier { name: name.to_string() });
+    }
+    Ok(())
+}
+
+impl Subscription {
+    fn is_active(&self) -> bool {
+        self.predictions_left != Some(0) && self.expires_at.is_none_or(|expires_at| env::block_timestamp() < expires_at.0)
+    }
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can offer a tier (or change it, which does not affect current subscriptions) or withdraw it (None).
+    // A tier must limit the predictions, the duration or both.
+    pub fn set_subscription_tier(&mut self, name: String, tier: Option<SubscriptionTier>) -> Result<(), KnnError> {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.store_subscription_tier(name, tier)
+    }
+
+    pub fn get_subscription_tiers(&self) -> Vec<(String, SubscriptionTier)> {
+        self.subscription_tiers.to_vec()
+    }
+
+    // Subscribes the caller to the tier, replacing their current subscription. The attached deposit must cover the price of the
+    // tier and the storage of the subscription, the surplus is refunded.
+    #[payable]
+    pub fn subscribe(&mut self, tier: String) -> Subscription {
+        self.assert_not_paused();
+        let caller = env::predecessor_account_id();
+        self.check_access(&caller).unwrap_or_else(|e| e.panic());
+        let offer = self.subscription_tiers.get(&tier).unwrap_or_else(|| KnnError::UnknownTier { name: tier.clone() }.panic());
+        let attached = env::attached_deposit();
+        if attached < offer.price.0 {
+            KnnError::InsufficientDeposit { required: offer.price, attached: U128(attached) }.panic();
+        }
+        let subscription = Subscription {
+            tier,
+            predictions_left: offer.predictions,
+            expires_at: offer.duration_days.map(|days| U64(env::block_timestamp() + u64::from(days) * NANOSECONDS_PER_DAY)),
+        };
+        let initial_storage = env::storage_usage();
+        self.subscriptions.insert(&caller, &subscription);
+        self.fees_collected += offer.price.0;
+        self.charge_storage(initial_storage, offer.price.0);
+        subscription
+    }
+
+    // Subscription of the account, if it is still active.
+    pub fn get_subscription(&self, account_id: AccountId) -> Option<Subscription> {
+        self.subscriptions.get(&account_id).filter(Subscription::is_active)
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Offers or withdraws a tier for set_subscription_tier or a SetSubscriptionTier action.
+    pub(crate) fn store_subscription_tier(&mut self, name: String, tier: Option<SubscriptionTier>) -> Result<(), KnnError> {
+        match tier {
+            Some(tier) => {
+                validate_tier(&name, &tier)?;
+                self.subscription_tiers.insert(&name, &tier);
+            }
+            None => {
+                self.subscription_tiers.remove(&name);
+            }
+        }
+        Ok(())
+    }
+
+    // Callable from methods only (not user). Whether the account's next prediction is covered by its subscription.
+    pub(crate) fn is_subscribed(&self, account_id: &AccountId) -> bool {
+        self.get_subscription(account_id.clone()).is_some()
+    }
+
+    // Callable from methods only (not user). Uses up one prediction of the account's active subscription.
+    pub(crate) fn use_subscription(&mut self, account_id: &AccountId) {
+        if let Some(mut subscription) = self.get_subscription(account_id.clone()) {
+            if let Some(left) = subscription.predictions_left.as_mut() {
+                *left -= 1;
+            }
+            self.subscriptions.insert(account_id, &subscription);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use crate::*;
+use events::ScheduledActionData;
+use governance::AdminAction;
+
+// ------------------------------------------ TIMELOCK ----------------------------------------------------------
+// So that the users of the model get notice of changes, the owner can set a delay of N blocks on the sensitive changes (the
+// same as under a council, see governance.rs, plus the delay itself). They are then queued with schedule_action, and can be
+// carried out by anyone with execute_action once N blocks have passed; until then they are listed by get_scheduled_actions
+// and the owner can cancel them. Under a council, approved proposals are queued instead of carried out, and any member can
+// cancel them. Each step emits an event (knn_action_scheduled, knn_action_executed, knn_action_cancelled).
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ScheduledAction {
+    pub action: AdminAction,
+    pub scheduled_by: AccountId,
+    pub ready_at: U64, // block height from which it can be executed
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can set the delay, after which changing it takes a scheduled (or, under a council, proposed) SetTimelock action.
+    pub fn set_timelock(&mut self, blocks: u64) {
+        self.assert_owner();
+        self.assert_ungoverned();
+        self.timelock_blocks = blocks;
+    }
+
+    pub fn get_timelock(&self) -> u64 {
+        self.timelock_blocks
+    }
+
+    // Only the owner can queue a sensitive change (under a council, it is proposed instead). Returns the id of the scheduled action.
+    pub fn schedule_action(&mut self, action: AdminAction) -> Result<u64, KnnError> {
+        self.assert_owner();
+        if self.council.is_some() {
+            return Err(KnnError::GovernanceRequired);
+        }
+        self.validate_action(&action)?;
+        Ok(self.schedule(action, env::predecessor_account_id()))
+    }
+
+    // Carries out a scheduled action once its delay has passed. Anyone can call it.
+    pub fn execute_action(&mut self, action_id: u64) -> Result<(), KnnError> {
+        let scheduled = self.scheduled_actions.get(&action_id).ok_or(KnnError::UnknownAction { action_id })?;
+        if env::block_height() < scheduled.ready_at.0 {
+            return Err(KnnError::ActionNotReady { action_id, ready_at: scheduled.ready_at });
+        }
+        self.apply_action(scheduled.action.clone())?; // stays scheduled if the change fails (e.g. the dataset was removed)
+        self.scheduled_actions.remove(&action_id);
+        let caller = env::predecessor_account_id();
+        KnnEvent::KnnActionExecuted(vec![ScheduledActionData { action_id, action: &scheduled.action, ready_at: scheduled.ready_at, account_id: &caller }]).emit();
+        Ok(())
+    }
+
+    // Only the owner (under a council, a member) can cancel a scheduled action.
+    pub fn cancel_action(&mut self, action_id: u64) -> Result<(), KnnError> {
+        let caller = env::predecessor_account_id();
+        let allowed = match &self.council {
+            Some(council) => council.members.contains(&caller),
+            None => caller == self.owner_id,
+        };
+        if !allowed {
+            return Err(KnnError::Unauthorized { account_id: caller });
+        }
+        let scheduled = self.scheduled_actions.remove(&action_id).ok_or(KnnError::UnknownAction { action_id })?;
+        KnnEvent::KnnActionCancelled(vec![ScheduledActionData { action_id, action: &scheduled.action, ready_at: scheduled.ready_at, account_id: &caller }]).emit();
+        Ok(())
+    }
+
+    // Actions waiting to be executed, as (action id, action) pairs.
+    pub fn get_scheduled_actions(&self) -> Vec<(u64, ScheduledAction)> {
+        self.scheduled_actions.to_vec()
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Queues an action for execution in timelock_blocks blocks.
+    pub(crate) fn schedule(&mut self, action: AdminAction, scheduled_by: AccountId) -> u64 {
+        let action_id = self.next_action_id;
+        self.next_action_id += 1;
+        let ready_at = U64(env::block_height() + self.timelock_blocks);
+        KnnEvent::KnnActionScheduled(vec![ScheduledActionData { action_id, action: &action, ready_at, account_id: &scheduled_by }]).emit();
+        self.scheduled_actions.insert(&action_id, &ScheduledAction { action, scheduled_by, ready_at });
+        action_id
+    }
+}
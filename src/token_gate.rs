@@ -0,0 +1,108 @@
+use crate::*;
+use near_sdk::serde_json;
+use near_sdk::PromiseResult;
+
+// ------------------------------------------ TOKEN-GATED PREDICTIONS -------------------------------------------
+// A project can sell access passes for the model: the owner gates the predictions on holding a token of an NFT contract
+// (NEP-171 with enumeration, read with nft_supply_for_owner) or a minimum balance of a fungible token (NEP-141, ft_balance_of).
+// The holdings are on another contract, so they are verified by a cross-contract call: an account calls verify_access, and
+// the callback caches the result for cache_hours. Predictions are made only for accounts with a valid cached pass (the owner is
+// not gated); the cache is also the limit of how long a pass outlives selling the token. Changing the gate voids the passes.
+const GAS_FOR_HOLDINGS: Gas = Gas(10_000_000_000_000); // 10 Tgas to read the holdings on the token contract
+const GAS_FOR_ACCESS_CALLBACK: Gas = Gas(10_000_000_000_000); // 10 Tgas for on_access_verified
+const NANOSECONDS_PER_HOUR: u64 = 3_600 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum GateToken {
+    Nft { contract_id: AccountId }, // at least one token of the contract
+    Ft { contract_id: AccountId, min_balance: U128 }, // in the smallest units of the token
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenGate {
+    pub token: GateToken,
+    pub cache_hours: u32, // how long a verification is valid
+}
+
+// Verified access of an account.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct AccessPass {
+    gate_id: u64, // gate it was verified for
+    expires_at: u64, // block timestamp (nanoseconds)
+}
+
+#[ext_contract(ext_token)]
+trait TokenHoldings {
+    fn nft_supply_for_owner(&self, account_id: AccountId) -> U128;
+    fn ft_balance_of(&self, account_id: AccountId) -> U128;
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner can gate the predictions on a token (None removes the gate).
+    pub fn set_token_gate(&mut self, gate: Option<TokenGate>) {
+        self.assert_owner();
+        self.token_gate = gate;
+        self.gate_id += 1;
+    }
+
+    pub fn get_token_gate(&self) -> Option<TokenGate> {
+        self.token_gate.clone()
+    }
+
+    // Verifies that the caller holds the gating token, and caches the result. The result (a bool) is the one of on_access_verified.
+    pub fn verify_access(&mut self) -> Promise {
+        let gate = self.token_gate.clone().unwrap_or_else(|| env::panic_str("Predictions are not token-gated."));
+        let account_id = env::predecessor_account_id();
+        let holdings = match &gate.token {
+            GateToken::Nft { contract_id } => ext_token::nft_supply_for_owner(account_id.clone(), contract_id.clone(), 0, GAS_FOR_HOLDINGS),
+            GateToken::Ft { contract_id, .. } => ext_token::ft_balance_of(account_id.clone(), contract_id.clone(), 0, GAS_FOR_HOLDINGS),
+        };
+        holdings.then(ext_self::on_access_verified(account_id, self.gate_id, env::current_account_id(), 0, GAS_FOR_ACCESS_CALLBACK))
+    }
+
+    // Callback of verify_access: caches the pass of the account if its holdings meet the gate it was verified for.
+    #[private]
+    pub fn on_access_verified(&mut self, account_id: AccountId, gate_id: u64) -> bool {
+        let held: Option<U128> = match env::promise_result(0) {
+            PromiseResult::Successful(bytes) => serde_json::from_slice(&bytes).ok(),
+            _ => None,
+        };
+        let gate = match &self.token_gate {
+            Some(gate) if gate_id == self.gate_id => gate,
+            _ => return false, // the gate changed meanwhile
+        };
+        let required = match &gate.token {
+            GateToken::Nft { .. } => 1,
+            GateToken::Ft { min_balance, .. } => min_balance.0,
+        };
+        let passed = held.is_some_and(|held| held.0 >= required);
+        if passed {
+            let expires_at = env::block_timestamp() + u64::from(gate.cache_hours) * NANOSECONDS_PER_HOUR;
+            self.access_passes.insert(&account_id, &AccessPass { gate_id, expires_at });
+        } else {
+            self.access_passes.remove(&account_id);
+        }
+        passed
+    }
+
+    // Whether the account can make predictions under the token gate (true without a gate).
+    pub fn has_access_pass(&self, account_id: AccountId) -> bool {
+        self.check_token_gate(&account_id).is_ok()
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). AccessPassRequired unless the account has a valid pass for the current gate.
+    pub(crate) fn check_token_gate(&self, account_id: &AccountId) -> Result<(), KnnError> {
+        if self.token_gate.is_none() || *account_id == self.owner_id {
+            return Ok(());
+        }
+        match self.access_passes.get(account_id) {
+            Some(pass) if pass.gate_id == self.gate_id && env::block_timestamp() < pass.expires_at => Ok(()),
+            _ => Err(KnnError::AccessPassRequired { account_id: account_id.clone() }),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use crate::*;
+use near_sdk::json_types::Base64VecU8;
+
+// ------------------------------------------ BULK UPLOADS ------------------------------------------------------
+// Uploads many train points in one call, from the formats datasets are usually kept in, instead of one add_training_point
+// call per point. The rows are checked and appended (and the storage charged) like the points of add_training_point.
+
+// Train points and their classes parsed from an upload.
+pub(crate) struct TrainingRows {
+    pub feature_names: Vec<String>, // names of the columns of the points (empty: unnamed)
+    pub label_names: Option<Vec<String>>, // names of the classes of the dataset once the rows are added (None: unchanged)
+    pub points: Vec<Vec<f64>>,
+    pub labels: Vec<u8>,
+    pub label_sets: Option<Vec<Vec<u8>>>, // all the classes of each point, the first being its label (None: one class per point)
+}
+
+// Parses CSV text: a header row naming the columns, then one row per train point. The label_column holds integer classes
+// (0 to 255), every other column a f64 feature.
+pub(crate) fn parse_csv(csv: &str, label_column: &str) -> Result<TrainingRows, KnnError> {
+    let invalid = |line: u64, reason: String| KnnError::InvalidCsv { line, reason };
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(csv.as_bytes());
+    let header: Vec<String> = reader.headers().map_err(|e| invalid(1, e.to_string()))?.iter().map(String::from).collect();
+    let label_index = header
+        .iter()
+        .position(|column| column == label_column)
+        .ok_or_else(|| invalid(1, format!("no column named '{}'", label_column)))?;
+    if header.len() < 2 { // a dataset needs at least one feature
+        return Err(invalid(1, "no feature column".to_string()));
+    }
+    let feature_names = header.iter().enumerate().filter(|(ii, _)| *ii != label_index).map(|(_, name)| name.clone()).collect();
+    let mut points = Vec::new();
+    let mut labels = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| invalid(e.position().map_or(0, |p| p.line()), e.to_string()))?;
+        let line = record.position().map_or(0, |p| p.line());
+        let mut point = Vec::with_capacity(header.len() - 1);
+        for (ii, field) in record.iter().enumerate() {
+            if ii == label_index {
+                labels.push(field.parse::<u8>().map_err(|_| invalid(line, format!("label '{}' is not an integer between 0 and 255", field)))?);
+            } else {
+                point.push(field.parse::<f64>().map_err(|_| invalid(line, format!("feature '{}' is not a number", field)))?);
+            }
+        }
+        points.push(point);
+    }
+    if points.is_empty() {
+        return Err(invalid(2, "no data rows".to_string()));
+    }
+    Ok(TrainingRows { feature_names, label_names: None, points, labels, label_sets: None })
+}
+
+// Decodes a Borsh-encoded Vec<(Vec<f64>, u8)>: train points and their classes, 8 bytes per feature and 1 per class (plus a
+// 4 byte length per point and for the list), a fraction of the size of the same rows as JSON arrays.
+pub(crate) fn parse_borsh(payload: &[u8]) -> Result<TrainingRows, KnnError> {
+    let rows: Vec<(Vec<f64>, u8)> = BorshDeserialize::try_from_slice(payload).map_err(|e| KnnError::InvalidPayload { reason: e.to_string() })?;
+    if rows.is_empty() {
+        return Err(KnnError::InvalidPayload { reason: "no rows".to_string() });
+    }
+    let (points, labels) = rows.into_iter().unzip();
+    Ok(TrainingRows { feature_names: Vec::new(), label_names: None, points, labels, label_sets: None })
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Appends the rows of CSV text (see parse_csv) to a classification dataset. A new dataset takes its schema and feature names
+    // from the header; the rows of an existing dataset must match its schema.
+    // Payable like add_training_point: the caller covers the storage of the rows. Errors panic so that nothing is stored.
+    #[payable]
+    pub fn add_dataset_csv(&mut self, name: String, csv: String, label_column: String) {
+        self.assert_not_paused();
+        self.assert_can_write(&name);
+        let rows = parse_csv(&csv, &label_column).unwrap_or_else(|e| e.panic());
+        self.append_training_rows(&name, rows);
+    }
+
+    // Appends the rows of a Borsh-encoded payload (see parse_borsh), passed as a base64 string, to a classification dataset.
+    // Payable and checked like add_dataset_csv (the dataset is created with the features unnamed).
+    #[payable]
+    pub fn add_dataset_borsh(&mut self, name: String, payload_base64: Base64VecU8) {
+        self.assert_not_paused();
+        self.assert_can_write(&name);
+        let rows = parse_borsh(&payload_base64.0).unwrap_or_else(|e| e.panic());
+        self.append_training_rows(&name, rows);
+    }
+}
+
+impl KnnMachineLearning {
+    // Callable from methods only (not user). Appends train points and their classes to a dataset (created if the name is not yet
+    // known, with the feature names of the rows), then checks the storage limits and charges the storage. Panics if a point does not match the schema.
+    pub(crate) fn append_training_rows(&mut self, name: &String, rows: TrainingRows) {
+        let initial_storage = env::storage_usage(); // bytes used before the points are stored
+        let points: Vec<Vec<f64>> = rows.points.into_iter().map(|point| self.stored_point(point).unwrap_or_else(|e| e.panic())).collect();
+        let mut data = self.dataset_for_point(name, &points[0]).unwrap_or_else(|e| e.panic());
+        if data.train.is_empty() && data.feature_names.is_empty() {
+            data.feature_names = rows.feature_names;
+        }
+        if let Some(label_names) = rows.label_names {
+            data.label_names = label_names;
+        }
+        push_training_rows(name, &mut data, &points, rows.labels, rows.label_sets);
+        self.record_contribution(name, &mut data, &env::predecessor_account_id(), points.len() as u64);
+        self.store_dataset(name, &data); // re-insert so that the updated Vector lengths are stored too
+        self.enforce_storage_limits(name, &mut data, points.len() as u64, initial_storage).unwrap_or_else(|e| e.panic());
+        let stake = self.stake_batch(name, &data, points.len() as u64);
+        self.charge_storage(initial_storage, stake);
+    }
+}
+
+// Appends train points (already in their stored form) and their classes (or sets of classes) to a classification dataset. Panics
+// if a point does not match the schema, or the dataset holds numeric values.
+pub(crate) fn push_training_rows(name: &String, data: &mut Dataset, points: &[Vec<f64>], labels: Vec<u8>, label_sets: Option<Vec<Vec<u8>>>) {
+    if !data.values.is_empty() { // holds numeric values, use add_regression_point
+        KnnError::TargetMismatch { name: name.clone() }.panic();
+    }
+    check_unfrozen(name, data).unwrap_or_else(|e| e.panic());
+    let was_imbalanced = data.dominant_class().is_some();
+    let mut label_sets = label_sets.map(Vec::into_iter);
+    for (point, label) in points.iter().zip(labels) {
+        check_dimension(data, point).unwrap_or_else(|e| e.panic());
+        data.push_point(point);
+        match label_sets.as_mut().and_then(Iterator::next) {
+            Some(labels) => data.push_label_set(point, &labels),
+            None => {
+                data.push_label(label);
+                data.hash_row(point, label);
+            }
+        }
+    }
+    data.update_inverse_covariance();
+    data.refit_models();
+    data.version += 1;
+    if let (false, Some((label, share))) = (was_imbalanced, data.dominant_class()) { // warn once, when the dataset becomes imbalanced
+        KnnEvent::KnnImbalance(vec![ImbalanceData { dataset: name, label, share, n_rows: data.target.len() }]).emit();
+    }
+}
+
+// ------------------------------------------ CHUNKED UPLOADS ---------------------------------------------------
+// A dataset too large for one transaction is uploaded in several: begin_upload announces the new dataset and its number of
+// rows, upload_chunk calls (each paying the storage of its rows) stage the rows, and commit_upload makes the complete dataset
+// available under its name at once, so predictions never see a partial dataset. Each account stages one upload at a time;
+// abort_upload deletes its staged rows and refunds their storage.
+
+// Dataset being uploaded in chunks by an account.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PendingUpload {
+    name: String, // name of the dataset once committed
+    total_rows: u64, // number of rows announced in begin_upload
+    dataset: Option<Dataset>, // rows staged so far (None before the first chunk, which sets the schema)
+}
+
+// Progress of a chunked upload, as seen by get_upload.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UploadStatus {
+    pub name: String,
+    pub total_rows: u64,
+    pub uploaded_rows: u64,
+}
+
+impl PendingUpload {
+    fn uploaded_rows(&self) -> u64 {
+        self.dataset.as_ref().map_or(0, |data| data.train.len())
+    }
+}
+
+// Hash of the prefixes of the rows staged by an account for a dataset. Names are valid UTF-8, which never contains the byte
+// 0xFF, so the prefixes cannot collide with those of a dataset created by name (Dataset::new).
+fn staging_hash(uploader: &AccountId, name: &str) -> Vec<u8> {
+    env::sha256(&[&[0xFF], uploader.as_bytes(), &[0xFF], name.as_bytes()].concat())
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Starts the chunked upload of a new classification dataset of total_rows rows, by an account allowed to write it.
+    pub fn begin_upload(&mut self, name: String, total_rows: u64) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        self.assert_can_write(&name);
+        let uploader = env::predecessor_account_id();
+        if let Some(upload) = self.uploads.get(&uploader) {
+            return Err(KnnError::UploadInProgress { name: upload.name });
+        }
+        if self.get_dataset(&name).is_ok() || self.offchain_datasets.contains_key(&name) {
+            return Err(KnnError::DatasetExists { name });
+        }
+        if is_snapshot(&name) { // '@' is reserved for the names of snapshots
+            return Err(KnnError::InvalidDatasetName { name });
+        }
+        if total_rows == 0 {
+            return Err(KnnError::EmptyDataset { name });
+        }
+        self.uploads.insert(&uploader, &PendingUpload { name, total_rows, dataset: None });
+        Ok(())
+    }
+
+    // Stages rows (each a point and its class) of the caller's upload. The first chunk sets the number of features.
+    // Payable like add_training_point: the caller covers the storage of the rows. Errors panic so that nothing is staged.
+    // Returns the number of rows staged so far.
+    #[payable]
+    pub fn upload_chunk(&mut self, rows: Vec<(Vec<f64>, u8)>) -> u64 {
+        self.assert_not_paused();
+        let uploader = env::predecessor_account_id();
+        let mut upload = self.pending_upload(&uploader).unwrap_or_else(|e| e.panic());
+        let initial_storage = env::storage_usage(); // bytes used before the rows are staged
+        let (points, labels): (Vec<Vec<f64>>, Vec<u8>) = rows.into_iter().map(|(point, label)| (self.stored_point(point).unwrap_or_else(|e| e.panic()), label)).unzip();
+        let n_features = points.first().map_or(0, |point| point.len() as u32);
+        if n_features == 0 { // an empty chunk, or a point without features
+            KnnError::DimensionMismatch { expected: 1, actual: 0 }.panic();
+        }
+        let mut data = upload.dataset.take().unwrap_or_else(|| Dataset::with_hash(staging_hash(&uploader, &upload.name), n_features, uploader.clone()));
+        push_training_rows(&upload.name, &mut data, &points, labels, None);
+        if data.train.len() > upload.total_rows {
+            KnnError::UploadRowCount { expected: upload.total_rows, actual: data.train.len() }.panic();
+        }
+        let uploaded_rows = data.train.len();
+        upload.dataset = Some(data);
+        self.uploads.insert(&uploader, &upload); // re-insert so that the updated Vector lengths are stored too
+        let data = upload.dataset.as_mut().unwrap();
+        self.check_storage_limits(data, points.len() as u64, initial_storage).unwrap_or_else(|e| e.panic());
+        self.uploads.insert(&uploader, &upload); // same size: only storage_bytes changed
+        self.charge_storage(initial_storage, 0);
+        uploaded_rows
+    }
+
+    // Makes the caller's upload available as a dataset, once all the announced rows are staged.
+    pub fn commit_upload(&mut self) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let uploader = env::predecessor_account_id();
+        let upload = self.pending_upload(&uploader)?;
+        if upload.uploaded_rows() != upload.total_rows {
+            return Err(KnnError::UploadRowCount { expected: upload.total_rows, actual: upload.uploaded_rows() });
+        }
+        if self.get_dataset(&upload.name).is_ok() || self.offchain_datasets.contains_key(&upload.name) { // created since begin_upload
+            return Err(KnnError::DatasetExists { name: upload.name });
+        }
+        self.uploads.remove(&uploader);
+        let mut data = upload.dataset.unwrap(); // at least one row was staged
+        self.record_contribution(&upload.name, &mut data, &uploader, upload.total_rows);
+        self.store_dataset(&upload.name, &data);
+        Ok(())
+    }
+
+    // Cancels the caller's upload: deletes the staged rows and refunds their storage. Available while the contract is paused.
+    pub fn abort_upload(&mut self) -> Result<(), KnnError> {
+        let uploader = env::predecessor_account_id();
+        let upload = self.pending_upload(&uploader)?;
+        let initial_storage = env::storage_usage();
+        if let Some(mut data) = upload.dataset {
+            self.clear_dataset(&mut data);
+        }
+        self.uploads.remove(&uploader);
+        let freed = initial_storage.saturating_sub(env::storage_usage());
+        self.refund_storage(&uploader, freed);
+        Ok(())
+    }
+
+    pub fn get_upload(&self, account_id: AccountId) -> Option<UploadStatus> {
+        self.uploads.get(&account_id).map(|upload| UploadStatus { uploaded_rows: upload.uploaded_rows(), name: upload.name, total_rows: upload.total_rows })
+    }
+}
+
+impl KnnMachineLearning {
+    fn pending_upload(&self, uploader: &AccountId) -> Result<PendingUpload, KnnError> {
+        self.uploads.get(uploader).ok_or_else(|| KnnError::NoUploadInProgress { account_id: uploader.clone() })
+    }
+}
@@ -0,0 +1,90 @@
+use crate::*;
+
+// ------------------------------------------ DATASET VERSIONS --------------------------------------------------
+// Every change of the rows of a dataset (points added, or removed by slash_batch) bumps its version. The owner or the creator of
+// the dataset can copy its current version into an immutable snapshot named "name@version" (e.g. "cancer@3"), which the views
+// and predictions accept wherever they take a dataset name, so that their results can be reproduced however the dataset evolves.
+// Snapshots cannot be changed or removed, and the caller pays for their storage. The fees of predictions made with a snapshot
+// go to the treasury.
+
+// Name of the snapshot of a version of a dataset.
+pub(crate) fn snapshot_name(name: &str, version: u64) -> String {
+    format!("{}@{}", name, version)
+}
+
+// Whether the name refers to a snapshot ('@' cannot appear in the names of datasets).
+pub(crate) fn is_snapshot(name: &str) -> bool {
+    name.contains('@')
+}
+
+impl Dataset {
+    // Copy of the dataset (rows, statistics, configuration and index) whose collections are stored under prefixes derived from dataset_hash.
+    fn snapshot(&self, dataset_hash: Vec<u8>) -> Dataset {
+        let mut copy = Dataset::with_hash(dataset_hash.clone(), self.n_features, self.creator.clone());
+        copy.train.extend(self.train.iter());
+        copy.target.extend(self.target.iter());
+        copy.label_sets.extend(self.label_sets.iter());
+        copy.values.extend(self.values.iter());
+        copy.kd_tree = self.kd_tree.as_ref().map(|tree| {
+            let mut copied = Vector::new(StorageKey::DatasetKdTree { dataset_hash });
+            copied.extend(tree.iter());
+            copied
+        });
+        Dataset {
+            metric: self.metric,
+            created_at: self.created_at,
+            feature_names: self.feature_names.clone(),
+            label_names: self.label_names.clone(),
+            scaling: self.scaling,
+            stats: self.stats.clone(),
+            feature_weights: self.feature_weights.clone(),
+            categorical_features: self.categorical_features.clone(),
+            covariance: self.covariance.clone(),
+            k: self.k,
+            class_counts: self.class_counts.clone(),
+            class_weighted: self.class_weighted,
+            decision_rule: self.decision_rule,
+            models: self.models.clone(),
+            default_model: self.default_model,
+            class_sums: self.class_sums.clone(),
+            class_stats: self.class_stats.clone(),
+            logistic: self.logistic.clone(),
+            kmeans: self.kmeans.clone(),
+            range_tolerance: self.range_tolerance,
+            version: self.version,
+            content_hash: self.content_hash.clone(),
+            ..copy
+        }
+    }
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner or the creator of a dataset can snapshot it. Payable: the caller covers the storage of the copy (the surplus is
+    // refunded). Returns the version, whose snapshot is named "name@version" (snapshotting the same version again changes nothing).
+    #[payable]
+    pub fn snapshot_dataset(&mut self, name: String) -> u64 {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&name).unwrap_or_else(|e| e.panic());
+        if is_snapshot(&name) {
+            KnnError::SnapshotImmutable { name }.panic();
+        }
+        self.assert_dataset_admin(&data);
+        let key = snapshot_name(&name, data.version);
+        let initial_storage = env::storage_usage();
+        match self.snapshots.get(&key) {
+            Some(snapshot) if snapshot.created_at == data.created_at => {}
+            Some(_) => KnnError::SnapshotExists { name: key }.panic(), // of a removed dataset that had the same name
+            None => {
+                let mut snapshot = data.snapshot(env::sha256(key.as_bytes()));
+                self.snapshots.insert(&key, &snapshot);
+                snapshot.storage_bytes = env::storage_usage().saturating_sub(initial_storage);
+                self.snapshots.insert(&key, &snapshot);
+                data.snapshots.push(data.version);
+                self.store_dataset(&name, &data);
+            }
+        }
+        self.charge_storage(initial_storage, 0);
+        data.version
+    }
+}
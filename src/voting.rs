@@ -0,0 +1,127 @@
+use crate::*;
+
+// ------------------------------------------ PLUGGABLE VOTING RULES --------------------------------------------
+// The vote of the k nearest neighbours is decided by a VotingRule, given their classes (nearest first) and the weights of their
+// votes (of the voting scheme, divided by the class sizes for class-weighted datasets): the rule returns the predicted class and
+// its share of the votes, or abstains with an error. The DecisionRule of a prediction (given in the call with classify_by_rule, else
+// the dataset's, see set_decision_rule, else Weighted) is built into its VotingRule at runtime, so that a new rule implements the
+// trait and gets a DecisionRule variant, and each rule can be tested on its own, without a dataset.
+
+pub(crate) trait VotingRule {
+    // Predicted class of the neighbours of these classes and vote weights, and its share of the votes (between 0 and 1). Classes
+    // sharing the top vote are decided by the tie-break policy.
+    fn decide(&self, labels: &[u8], weights: &[f64], tie_break: TieBreak) -> Result<(u8, f64), KnnError>;
+}
+
+// How the vote of the k nearest neighbours decides the class of the test point.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum DecisionRule {
+    Majority, // every neighbour has one vote, whatever the voting scheme and class weighting
+    Weighted, // the votes are weighted by the voting scheme (and class weighting): the default
+    Threshold(f64), // Weighted, abstaining (Abstained error) unless the class has at least this share of the votes
+    Abstain, // Weighted, abstaining (TiedVote error) on a tie whatever the tie-break policy
+}
+
+impl DecisionRule {
+    pub(crate) fn rule(self) -> Box<dyn VotingRule> {
+        match self {
+            DecisionRule::Majority => Box::new(Majority),
+            DecisionRule::Weighted => Box::new(Weighted),
+            DecisionRule::Threshold(threshold) => Box::new(Threshold { threshold }),
+            DecisionRule::Abstain => Box::new(Abstain),
+        }
+    }
+
+    // Checks the threshold is a share of the votes.
+    pub(crate) fn validate(&self) -> Result<(), KnnError> {
+        match self {
+            DecisionRule::Threshold(threshold) if !(0.0..=1.0).contains(threshold) => Err(KnnError::InvalidThreshold { threshold: *threshold }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl TieBreak {
+    // Decides between the tied labels (ascending) given the labels of the k nearest neighbours (nearest first).
+    pub(crate) fn decide(&self, tied: &[u8], neighbor_labels: &[u8]) -> Result<u8, KnnError> {
+        match self {
+            TieBreak::NearestNeighbor => Ok(*neighbor_labels.iter().find(|label| tied.contains(label)).unwrap()),
+            TieBreak::LowestLabel => Ok(tied[0]),
+            TieBreak::Random => Ok(tied[(block_random() % tied.len() as u64) as usize]),
+            TieBreak::Abstain => Err(KnnError::TiedVote { labels: tied.to_vec() }),
+        }
+    }
+}
+
+// Class with the highest sum of vote weights, its share of the votes, and the classes sharing that share (ascending).
+fn top_classes(labels: &[u8], weights: &[f64]) -> (u8, f64, Vec<u8>) {
+    let label = knn_core::weighted_vote(labels, weights);
+    let share = knn_core::vote_share(labels, weights, label);
+    let tied = knn_core::class_shares(labels, weights).into_iter().filter(|(_, s)| *s == share).map(|(label, _)| label).collect();
+    (label, share, tied)
+}
+
+struct Weighted;
+
+impl VotingRule for Weighted {
+    fn decide(&self, labels: &[u8], weights: &[f64], tie_break: TieBreak) -> Result<(u8, f64), KnnError> {
+        let (label, share, tied) = top_classes(labels, weights);
+        let label = if tied.len() > 1 { tie_break.decide(&tied, labels)? } else { label };
+        Ok((label, share))
+    }
+}
+
+struct Majority;
+
+impl VotingRule for Majority {
+    fn decide(&self, labels: &[u8], _weights: &[f64], tie_break: TieBreak) -> Result<(u8, f64), KnnError> {
+        Weighted.decide(labels, &vec![1.0; labels.len()], tie_break)
+    }
+}
+
+struct Threshold {
+    threshold: f64, // minimum share of the votes
+}
+
+impl VotingRule for Threshold {
+    fn decide(&self, labels: &[u8], weights: &[f64], tie_break: TieBreak) -> Result<(u8, f64), KnnError> {
+        let (label, confidence) = Weighted.decide(labels, weights, tie_break)?;
+        if confidence < self.threshold {
+            return Err(KnnError::Abstained { label, confidence, threshold: self.threshold });
+        }
+        Ok((label, confidence))
+    }
+}
+
+struct Abstain;
+
+impl VotingRule for Abstain {
+    fn decide(&self, labels: &[u8], weights: &[f64], _tie_break: TieBreak) -> Result<(u8, f64), KnnError> {
+        Weighted.decide(labels, weights, TieBreak::Abstain)
+    }
+}
+
+#[near_bindgen]
+impl KnnMachineLearning {
+    // Only the owner or the creator of a dataset can pick the decision rule of its predictions (None: Weighted).
+    pub fn set_decision_rule(&mut self, dataset: String, rule: Option<DecisionRule>) -> Result<(), KnnError> {
+        self.assert_not_paused();
+        let mut data = self.get_dataset(&dataset)?;
+        self.assert_dataset_admin(&data);
+        if let Some(rule) = &rule {
+            rule.validate()?;
+        }
+        data.decision_rule = rule;
+        self.store_dataset(&dataset, &data);
+        Ok(())
+    }
+
+    // Same as classify, with the decision rule of the call instead of the dataset's.
+    pub fn classify_by_rule(&self, data_set: String, test_point: Vec<f64>, rule: DecisionRule) -> Result<PredictionResult, KnnError> {
+        rule.validate()?;
+        let mut dataset = self.get_dataset(&data_set)?; // a copy, the stored dataset keeps its rule
+        dataset.decision_rule = Some(rule);
+        self.check_confidence(self.classify_dataset(&data_set, &dataset, &test_point, None, None)?, dataset.class_weighted)
+    }
+}